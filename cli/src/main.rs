@@ -1,17 +1,38 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::fs::{self, File};
-use std::io;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use std::{mem, thread};
 
 use anyhow::Context;
 use argh::FromArgs;
 use flexi_logger::{LevelFilter, LogSpecBuilder, Logger};
-use redscript::bundle::ScriptBundle;
-use redscript::definition::AnyDefinition;
+use redscript::asm::{disassemble, patch_function, qualified_name, symbol_name};
+use redscript::bundle::{ConstantPool, PoolIndex, ScriptBundle};
+use redscript::compact::compact_pool;
+use redscript::definition::{AnyDefinition, Definition};
+use redscript::export::{export_bundle, import_bundle};
+use redscript::package::{Linker, Package};
+use redscript::verify::{verify_pool, Verifier};
+use redscript::view::BundleView;
+use redscript::xref::{CrossReferences, Reference, ReferenceKind};
+use redscript_compiler::diagnostics::Diagnostic;
+use redscript_compiler::doc::Documentation;
+use redscript_compiler::line_map::LineMap;
 use redscript_compiler::source_map::{Files, SourceFilter};
-use redscript_compiler::unit::CompilationUnit;
+use redscript_compiler::unit::{CompilationUnit, Profiling};
+use redscript_decompiler::diff::diff_pools;
 use redscript_decompiler::files::FileIndex;
 use redscript_decompiler::print::{write_definition, OutputMode};
+use redscript_decompiler::stubs::StubFile;
+use redscript_vm::testing::PRELUDE;
+use redscript_vm::Vm;
 use vmap::Map;
+use walkdir::WalkDir;
+
+const WATCH_INTERVAL: Duration = Duration::from_millis(500);
 
 /// redscript command line interface
 #[derive(Debug, FromArgs)]
@@ -26,6 +47,20 @@ enum Command {
     Decompile(DecompileOpts),
     Compile(CompileOpts),
     Lint(LintOpts),
+    CheckTargets(CheckTargetsOpts),
+    Verify(VerifyOpts),
+    Diff(DiffOpts),
+    Asm(AsmOpts),
+    Export(ExportOpts),
+    Import(ImportOpts),
+    Package(PackageOpts),
+    Link(LinkOpts),
+    Find(FindOpts),
+    Test(TestOpts),
+    Symbolize(SymbolizeOpts),
+    Xref(XrefOpts),
+    Stubs(StubsOpts),
+    Doc(DocOpts),
 }
 
 /// decompile a .redscripts file
@@ -48,6 +83,10 @@ struct DecompileOpts {
     /// include implicit operations in the output (conversions etc.)
     #[argh(switch, short = 'v')]
     verbose: bool,
+    /// only decompile the definitions with this name, they are decoded on demand instead of
+    /// loading the whole file
+    #[argh(option, short = 'n')]
+    name: Option<String>,
 }
 
 /// compile redscript source code into a .redscripts file
@@ -63,6 +102,24 @@ struct CompileOpts {
     /// path to an output .redscripts file
     #[argh(option, short = 'o')]
     output: PathBuf,
+    /// verify the generated bytecode before saving
+    #[argh(switch)]
+    verify: bool,
+    /// remove unreachable definitions and strings before saving
+    #[argh(switch)]
+    compact: bool,
+    /// emit breakpoint instructions for debugging
+    #[argh(switch)]
+    breakpoints: bool,
+    /// functions to instrument with profiling instructions (one of: 'all', 'annotated' or a name pattern)
+    #[argh(option)]
+    profile: Option<Profiling>,
+    /// save a line map for symbolize next to the output file
+    #[argh(switch)]
+    line_map: bool,
+    /// keep running and recompile whenever the source files change
+    #[argh(switch)]
+    watch: bool,
 }
 
 /// lint redscript source code
@@ -75,6 +132,208 @@ struct LintOpts {
     /// path to a .redscripts file to use for incremental compilation
     #[argh(option, short = 'b')]
     bundle: Option<PathBuf>,
+    /// keep running and lint again whenever the source files change
+    #[argh(switch)]
+    watch: bool,
+}
+
+/// check whether the annotation targets of redscript source code can be resolved against a .redscripts file
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "check-targets")]
+struct CheckTargetsOpts {
+    /// path to an input source file or directory
+    #[argh(option, short = 's')]
+    src: Vec<PathBuf>,
+    /// path to a .redscripts file to check against
+    #[argh(option, short = 'b')]
+    bundle: PathBuf,
+}
+
+/// verify the bytecode of all functions in a .redscripts file
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "verify")]
+struct VerifyOpts {
+    /// path to an input .redscripts file
+    #[argh(option, short = 'i')]
+    input: PathBuf,
+}
+
+/// compare the definitions of two .redscripts files
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "diff")]
+struct DiffOpts {
+    /// path to the old .redscripts file
+    #[argh(positional)]
+    old: PathBuf,
+    /// path to the new .redscripts file
+    #[argh(positional)]
+    new: PathBuf,
+    /// also compare decompiled function bodies
+    #[argh(switch, short = 'b')]
+    bodies: bool,
+}
+
+/// print or patch the bytecode of a function in a .redscripts file
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "asm")]
+struct AsmOpts {
+    /// path to an input .redscripts file
+    #[argh(option, short = 'i')]
+    input: PathBuf,
+    /// qualified name of the function (e.g. 'Class::Method;Int32')
+    #[argh(option, short = 'f')]
+    function: String,
+    /// path to an assembly file to replace the body of the function with, the current body is printed when omitted
+    #[argh(option, short = 's')]
+    src: Option<PathBuf>,
+    /// path to an output .redscripts file when patching or to an output assembly file otherwise
+    #[argh(option, short = 'o')]
+    output: Option<PathBuf>,
+}
+
+/// export the contents of a .redscripts file to a document with symbolic references
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "export")]
+struct ExportOpts {
+    /// path to an input .redscripts file
+    #[argh(option, short = 'i')]
+    input: PathBuf,
+    /// path to an output file
+    #[argh(option, short = 'o')]
+    output: PathBuf,
+    /// output format, only 'json' is supported currently
+    #[argh(option, default = "String::from(\"json\")")]
+    format: String,
+}
+
+/// rebuild a .redscripts file from a document created with the export command
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "import")]
+struct ImportOpts {
+    /// path to an input document
+    #[argh(option, short = 'i')]
+    input: PathBuf,
+    /// path to an output .redscripts file
+    #[argh(option, short = 'o')]
+    output: PathBuf,
+    /// input format, only 'json' is supported currently
+    #[argh(option, default = "String::from(\"json\")")]
+    format: String,
+}
+
+/// compile redscript source code into a package that can be linked without its sources
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "package")]
+struct PackageOpts {
+    /// path to an input source file or directory
+    #[argh(option, short = 's')]
+    src: Vec<PathBuf>,
+    /// path to a .redscripts file to compile against
+    #[argh(option, short = 'b')]
+    bundle: PathBuf,
+    /// path to an output .redspkg file
+    #[argh(option, short = 'o')]
+    output: PathBuf,
+}
+
+/// link packages into a .redscripts file
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "link")]
+struct LinkOpts {
+    /// path to an input .redspkg file, packages are linked in the order they are listed
+    #[argh(option, short = 'p')]
+    package: Vec<PathBuf>,
+    /// path to a .redscripts file to link into
+    #[argh(option, short = 'b')]
+    bundle: PathBuf,
+    /// path to an output .redscripts file
+    #[argh(option, short = 'o')]
+    output: PathBuf,
+}
+
+/// list the definitions with a given name in a .redscripts file without loading all of it
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "find")]
+struct FindOpts {
+    /// path to an input .redscripts file
+    #[argh(option, short = 'i')]
+    input: PathBuf,
+    /// name of the definitions to find (e.g. 'PlayerPuppet' or 'OnGameAttached;')
+    #[argh(positional)]
+    name: String,
+}
+
+/// compile redscript source code with its tests and run them
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "test")]
+struct TestOpts {
+    /// path to an input source file or directory
+    #[argh(option, short = 's')]
+    src: Vec<PathBuf>,
+    /// path to a .redscripts file to compile against
+    #[argh(option, short = 'b')]
+    bundle: PathBuf,
+    /// path to an output .redscripts file to save the test bundle to
+    #[argh(option, short = 'o')]
+    output: Option<PathBuf>,
+}
+
+/// find the source location of a bytecode offset in a function using a line map
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "symbolize")]
+struct SymbolizeOpts {
+    /// path to a line map file saved along with a .redscripts file
+    #[argh(option, short = 'i')]
+    input: PathBuf,
+    /// qualified name of the function (e.g. 'Class::Method;Int32' or 'Class::Method')
+    #[argh(option, short = 'f')]
+    function: String,
+    /// bytecode offset in the function
+    #[argh(option, short = 'o')]
+    offset: u16,
+}
+
+/// list the references between functions, fields, classes and enum members in a .redscripts file
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "xref")]
+struct XrefOpts {
+    /// path to an input .redscripts file
+    #[argh(option, short = 'i')]
+    input: PathBuf,
+    /// qualified name of the definition to list the references of, the signature can be omitted
+    /// (e.g. 'Class::Method', 'Class.field' or 'Enum.Member'), all functions are listed when omitted
+    #[argh(positional)]
+    name: Option<String>,
+    /// output format (one of: 'text', 'json' or 'dot')
+    #[argh(option, default = "String::from(\"text\")")]
+    format: String,
+}
+
+/// write declarations of the classes, enums and global functions of a .redscripts file
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "stubs")]
+struct StubsOpts {
+    /// path to an input .redscripts file
+    #[argh(option, short = 'i')]
+    input: PathBuf,
+    /// path to an output directory
+    #[argh(option, short = 'o')]
+    output: PathBuf,
+}
+
+/// generate documentation of redscript source code from its doc comments
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "doc")]
+struct DocOpts {
+    /// path to an input source file or directory
+    #[argh(option, short = 's')]
+    src: Vec<PathBuf>,
+    /// path to an output directory
+    #[argh(option, short = 'o')]
+    output: PathBuf,
+    /// output format (one of: 'markdown' or 'html')
+    #[argh(option, default = "String::from(\"markdown\")")]
+    format: String,
 }
 
 fn main() -> anyhow::Result<()> {
@@ -100,20 +359,75 @@ fn run() -> anyhow::Result<()> {
         Command::Decompile(opts) => Ok(decompile(opts)?),
         Command::Compile(opts) => Ok(compile(opts)?),
         Command::Lint(opts) => Ok(lint(opts)?),
+        Command::CheckTargets(opts) => Ok(check_targets(opts)?),
+        Command::Verify(opts) => Ok(verify(opts)?),
+        Command::Diff(opts) => Ok(diff(opts)?),
+        Command::Asm(opts) => Ok(asm(opts)?),
+        Command::Export(opts) => Ok(export(opts)?),
+        Command::Import(opts) => Ok(import(opts)?),
+        Command::Package(opts) => Ok(package(opts)?),
+        Command::Link(opts) => Ok(link(opts)?),
+        Command::Find(opts) => Ok(find(opts)?),
+        Command::Test(opts) => Ok(test(opts)?),
+        Command::Symbolize(opts) => Ok(symbolize(opts)?),
+        Command::Xref(opts) => Ok(xref(opts)?),
+        Command::Stubs(opts) => Ok(stubs(opts)?),
+        Command::Doc(opts) => Ok(doc(opts)?),
     }
 }
 
 fn compile(opts: CompileOpts) -> anyhow::Result<()> {
     let mut bundle = load_bundle(&opts.bundle)?;
 
+    if opts.watch {
+        let base = bundle.pool.clone();
+        let mut seen = SeenDiagnostics::default();
+        return watch_sources(&opts.src, |files| {
+            bundle.pool = base.clone();
+            let res = compile_files(&opts, &mut bundle, files, |diagnostic| seen.is_new(diagnostic, files));
+            seen.finish_run();
+            res
+        });
+    }
+
     let files = Files::from_dirs(&opts.src, &SourceFilter::None)
         .map_err(|err| anyhow::anyhow!("Failed to load the source files: {err}"))?;
+    compile_files(&opts, &mut bundle, &files, |_| true)
+}
 
+fn compile_files(
+    opts: &CompileOpts,
+    bundle: &mut ScriptBundle,
+    files: &Files,
+    filter: impl FnMut(&Diagnostic) -> bool,
+) -> anyhow::Result<()> {
     match CompilationUnit::new_with_defaults(&mut bundle.pool)
         .map_err(|err| anyhow::anyhow!("Failed to create the compilation unit: {err}"))?
-        .compile_and_report(&files)
+        .with_bytecode_verification(opts.verify)
+        .with_breakpoints(opts.breakpoints)
+        .with_profiling(opts.profile.clone().unwrap_or_default())
+        .compile_and_report_filtered(files, filter)
     {
-        Ok(_) => {
+        Ok(output) => {
+            if opts.line_map {
+                let mut path = opts.output.clone().into_os_string();
+                path.push(".lines");
+                let line_map = output
+                    .line_map(&bundle.pool, files)
+                    .context("Failed to create the line map")?;
+                let file = File::create(&path).context("Failed to create the line map file")?;
+                line_map
+                    .save(&mut io::BufWriter::new(file))
+                    .context("Failed to write the line map")?;
+            }
+            if opts.compact {
+                let stats = compact_pool(&mut bundle.pool).context("Failed to compact the script cache")?;
+                log::info!(
+                    "Removed {} definitions and {} strings",
+                    stats.definitions,
+                    stats.names + stats.strings + stats.tweakdb_ids + stats.resources
+                );
+            }
             let file = File::create(&opts.output).context("Failed to create a file at the specified output path")?;
             bundle
                 .save(&mut io::BufWriter::new(file))
@@ -129,9 +443,6 @@ fn compile(opts: CompileOpts) -> anyhow::Result<()> {
 }
 
 fn decompile(opts: DecompileOpts) -> anyhow::Result<()> {
-    let bundle = load_bundle(&opts.input)?;
-    let pool = &bundle.pool;
-
     let mode = match opts.mode.as_str() {
         "ast" => OutputMode::SyntaxTree,
         "bytecode" => OutputMode::Bytecode,
@@ -139,6 +450,13 @@ fn decompile(opts: DecompileOpts) -> anyhow::Result<()> {
         _ => anyhow::bail!("Invalid output mode: {}", opts.mode),
     };
 
+    if let Some(name) = &opts.name {
+        return decompile_named(&opts, name, mode);
+    }
+
+    let bundle = load_bundle(&opts.input)?;
+    let pool = &bundle.pool;
+
     if opts.dump_files {
         for entry in FileIndex::from_pool(pool).iter() {
             let path = opts.output.as_path().join(entry.path);
@@ -171,24 +489,509 @@ fn decompile(opts: DecompileOpts) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn decompile_named(opts: &DecompileOpts, name: &str, mode: OutputMode) -> anyhow::Result<()> {
+    let (map, _) = Map::with_options()
+        .open(&opts.input)
+        .context("Failed to open the script cache")?;
+    let view = BundleView::new(map.as_ref()).context("Failed to load the script cache")?;
+
+    let found = view.find(name);
+    if found.is_empty() {
+        anyhow::bail!("No definitions named {name} found");
+    }
+    let pool = view.subset(found).context("Failed to decode the definitions")?;
+
+    let file = File::create(&opts.output).context("Failed to create a file at the specified output path")?;
+    let mut output = io::BufWriter::new(file);
+    for index in found {
+        if let Err(err) = write_definition(&mut output, pool.definition(*index)?, &pool, 0, mode) {
+            log::error!("Failed to process a definition: {err}");
+        }
+    }
+    log::info!("Output successfully saved to {}", opts.output.display());
+    Ok(())
+}
+
 fn lint(opts: LintOpts) -> anyhow::Result<()> {
-    match opts.bundle {
-        Some(bundle_path) => {
-            let mut bundle = load_bundle(&bundle_path)?;
-            let files = Files::from_dirs(&opts.src, &SourceFilter::None)
-                .map_err(|err| anyhow::anyhow!("Failed to load the source files: {err}"))?;
-
-            if CompilationUnit::new_with_defaults(&mut bundle.pool)
-                .map_err(|err| anyhow::anyhow!("Failed to create the compilation unit: {err}"))?
-                .compile_and_report(&files)
-                .is_ok()
-            {
-                log::info!("Lint successful");
+    let Some(bundle_path) = &opts.bundle else {
+        return Ok(());
+    };
+    let mut bundle = load_bundle(bundle_path)?;
+
+    if opts.watch {
+        let base = bundle.pool.clone();
+        let mut seen = SeenDiagnostics::default();
+        return watch_sources(&opts.src, |files| {
+            bundle.pool = base.clone();
+            let res = lint_files(&mut bundle.pool, files, |diagnostic| seen.is_new(diagnostic, files));
+            seen.finish_run();
+            res
+        });
+    }
+
+    let files = Files::from_dirs(&opts.src, &SourceFilter::None)
+        .map_err(|err| anyhow::anyhow!("Failed to load the source files: {err}"))?;
+    lint_files(&mut bundle.pool, &files, |_| true)
+}
+
+fn lint_files(pool: &mut ConstantPool, files: &Files, filter: impl FnMut(&Diagnostic) -> bool) -> anyhow::Result<()> {
+    if CompilationUnit::new_with_defaults(pool)
+        .map_err(|err| anyhow::anyhow!("Failed to create the compilation unit: {err}"))?
+        .compile_and_report_filtered(files, filter)
+        .is_ok()
+    {
+        log::info!("Lint successful");
+    }
+    Ok(())
+}
+
+/// Runs the callback with the source files and then again every time any of them changes.
+/// The files are polled, because the modification times are all that's needed to notice a change.
+fn watch_sources(src: &[PathBuf], mut run: impl FnMut(&Files) -> anyhow::Result<()>) -> anyhow::Result<()> {
+    let mut last_snapshot = None;
+    loop {
+        let snapshot = source_snapshot(src);
+        if last_snapshot.as_ref() != Some(&snapshot) {
+            last_snapshot = Some(snapshot);
+            match Files::from_dirs(src, &SourceFilter::None) {
+                Ok(files) => run(&files)?,
+                Err(err) => log::error!("Failed to load the source files: {err}"),
+            }
+            log::info!("Watching for changes in the source files");
+        }
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+fn source_snapshot(src: &[PathBuf]) -> Vec<(PathBuf, Option<SystemTime>)> {
+    let mut entries = src
+        .iter()
+        .flat_map(|path| WalkDir::new(path).follow_links(true).into_iter().filter_map(Result::ok))
+        .filter(|entry| entry.path().extension() == Some(OsStr::new("reds")))
+        .map(|entry| {
+            let modified = entry.metadata().ok().and_then(|meta| meta.modified().ok());
+            (entry.into_path(), modified)
+        })
+        .collect::<Vec<_>>();
+    entries.sort();
+    entries
+}
+
+/// Keeps track of the diagnostics reported by the last run in watch mode, so that only the new ones
+/// are printed. Diagnostics are identified by their rendered message.
+#[derive(Debug, Default)]
+struct SeenDiagnostics {
+    previous: HashSet<String>,
+    current: HashSet<String>,
+}
+
+impl SeenDiagnostics {
+    fn is_new(&mut self, diagnostic: &Diagnostic, files: &Files) -> bool {
+        let mut message = String::new();
+        if diagnostic.display(files, &mut message).is_err() {
+            return true;
+        }
+        let is_new = !self.previous.contains(&message);
+        self.current.insert(message);
+        is_new
+    }
+
+    fn finish_run(&mut self) {
+        self.previous = mem::take(&mut self.current);
+    }
+}
+
+fn check_targets(opts: CheckTargetsOpts) -> anyhow::Result<()> {
+    let mut bundle = load_bundle(&opts.bundle)?;
+    let files = Files::from_dirs(&opts.src, &SourceFilter::None)
+        .map_err(|err| anyhow::anyhow!("Failed to load the source files: {err}"))?;
+
+    if CompilationUnit::new_with_defaults(&mut bundle.pool)
+        .map_err(|err| anyhow::anyhow!("Failed to create the compilation unit: {err}"))?
+        .check_targets_and_report(&files)
+        .is_ok()
+    {
+        log::info!("All targets resolved successfully");
+    } else {
+        anyhow::bail!("Some targets could not be resolved");
+    }
+    Ok(())
+}
+
+fn verify(opts: VerifyOpts) -> anyhow::Result<()> {
+    let bundle = load_bundle(&opts.input)?;
+    let pool = &bundle.pool;
+
+    let errors = verify_pool(pool);
+    for err in &errors {
+        let name = pool.def_name(err.function).unwrap_or_else(|_| "<unknown>".into());
+        log::error!("{name}: {} at {}", err.cause, err.location);
+    }
+    if !errors.is_empty() {
+        anyhow::bail!("Verification failed for {} functions", errors.len());
+    }
+    log::info!("Verification successful");
+    Ok(())
+}
+
+fn diff(opts: DiffOpts) -> anyhow::Result<()> {
+    let old = load_bundle(&opts.old)?;
+    let new = load_bundle(&opts.new)?;
+
+    let entries = diff_pools(&old.pool, &new.pool, opts.bodies).context("Failed to compare the script caches")?;
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    for entry in &entries {
+        writeln!(out, "{entry}")?;
+    }
+    out.flush()?;
+    log::info!("Found {} differences", entries.len());
+    Ok(())
+}
+
+fn asm(opts: AsmOpts) -> anyhow::Result<()> {
+    let mut bundle = load_bundle(&opts.input)?;
+    let index = bundle
+        .pool
+        .definitions()
+        .filter(|(_, def)| matches!(def.value, AnyDefinition::Function(_)))
+        .find(|(idx, _)| qualified_name(*idx, &bundle.pool).map_or(false, |name| name == opts.function))
+        .map(|(idx, _)| idx.cast())
+        .ok_or_else(|| anyhow::anyhow!("Function {} not found", opts.function))?;
+
+    let Some(src) = opts.src else {
+        let listing = disassemble(bundle.pool.function(index)?, &bundle.pool)?;
+        match opts.output {
+            Some(output) => fs::write(output, listing).context("Failed to write the assembly file")?,
+            None => io::stdout().lock().write_all(listing.as_bytes())?,
+        }
+        return Ok(());
+    };
+    let output = opts
+        .output
+        .ok_or_else(|| anyhow::anyhow!("An output path is required when patching a function"))?;
+
+    let text = fs::read_to_string(&src).context("Failed to read the assembly file")?;
+    patch_function(&text, index, &mut bundle.pool)?;
+    if let Err(err) = Verifier::new(&bundle.pool).verify_function(index) {
+        log::warn!(
+            "The patched function has invalid bytecode: {} at {}",
+            err.cause,
+            err.location
+        );
+    }
+
+    let file = File::create(&output).context("Failed to create a file at the specified output path")?;
+    bundle
+        .save(&mut io::BufWriter::new(file))
+        .context("Failed to write the script cache")?;
+    log::info!("Output successfully saved to {}", output.display());
+    Ok(())
+}
+
+fn export(opts: ExportOpts) -> anyhow::Result<()> {
+    if opts.format != "json" {
+        anyhow::bail!("Invalid export format: {}", opts.format);
+    }
+    let bundle = load_bundle(&opts.input)?;
+    let doc = export_bundle(&bundle).context("Failed to export the script cache")?;
+
+    let file = File::create(&opts.output).context("Failed to create a file at the specified output path")?;
+    let mut output = io::BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut output, &doc).context("Failed to write the document")?;
+    output.flush()?;
+    log::info!("Output successfully saved to {}", opts.output.display());
+    Ok(())
+}
+
+fn import(opts: ImportOpts) -> anyhow::Result<()> {
+    if opts.format != "json" {
+        anyhow::bail!("Invalid import format: {}", opts.format);
+    }
+    let file = File::open(&opts.input).context("Failed to open the document")?;
+    let doc = serde_json::from_reader(io::BufReader::new(file)).context("Failed to parse the document")?;
+    let bundle = import_bundle(doc).context("Failed to import the document")?;
+
+    let file = File::create(&opts.output).context("Failed to create a file at the specified output path")?;
+    bundle
+        .save(&mut io::BufWriter::new(file))
+        .context("Failed to write the script cache")?;
+    log::info!("Output successfully saved to {}", opts.output.display());
+    Ok(())
+}
+
+fn package(opts: PackageOpts) -> anyhow::Result<()> {
+    let mut bundle = load_bundle(&opts.bundle)?;
+    let base = bundle.pool.clone();
+
+    let files = Files::from_dirs(&opts.src, &SourceFilter::None)
+        .map_err(|err| anyhow::anyhow!("Failed to load the source files: {err}"))?;
+
+    match CompilationUnit::new_with_defaults(&mut bundle.pool)
+        .map_err(|err| anyhow::anyhow!("Failed to create the compilation unit: {err}"))?
+        .compile_and_report(&files)
+    {
+        Ok(output) => {
+            let package = Package::new(&base, &bundle.pool, output.wrapped_functions())
+                .context("Failed to create the package")?;
+            let file = File::create(&opts.output).context("Failed to create a file at the specified output path")?;
+            package
+                .save(&mut io::BufWriter::new(file))
+                .context("Failed to write the package")?;
+
+            log::info!("Output successfully saved to {}", opts.output.display());
+        }
+        Err(_) => {
+            log::error!("Build failed");
+        }
+    }
+    Ok(())
+}
+
+fn link(opts: LinkOpts) -> anyhow::Result<()> {
+    let mut bundle = load_bundle(&opts.bundle)?;
+
+    let mut linker = Linker::new(&mut bundle.pool).context("Failed to index the script cache")?;
+    for path in &opts.package {
+        let file = File::open(path).context("Failed to open the package")?;
+        let package = Package::load(&mut io::BufReader::new(file)).context("Failed to load the package")?;
+        linker
+            .link(&package)
+            .with_context(|| format!("Failed to link {}", path.display()))?;
+    }
+
+    let file = File::create(&opts.output).context("Failed to create a file at the specified output path")?;
+    bundle
+        .save(&mut io::BufWriter::new(file))
+        .context("Failed to write the script cache")?;
+    log::info!("Output successfully saved to {}", opts.output.display());
+    Ok(())
+}
+
+fn find(opts: FindOpts) -> anyhow::Result<()> {
+    let (map, _) = Map::with_options()
+        .open(&opts.input)
+        .context("Failed to open the script cache")?;
+    let view = BundleView::new(map.as_ref()).context("Failed to load the script cache")?;
+
+    let found = view.find(&opts.name);
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    for index in found {
+        let header = view.definition_header(*index)?;
+        if header.parent.is_undefined() {
+            writeln!(out, "{index}: {:?} {}", header.type_, opts.name)?;
+        } else {
+            let parent = view.def_name(header.parent)?;
+            writeln!(out, "{index}: {:?} {} in {parent}", header.type_, opts.name)?;
+        }
+    }
+    out.flush()?;
+    log::info!("Found {} definitions", found.len());
+    Ok(())
+}
+
+fn test(opts: TestOpts) -> anyhow::Result<()> {
+    let mut bundle = load_bundle(&opts.bundle)?;
+
+    let mut files = Files::from_dirs(&opts.src, &SourceFilter::None)
+        .map_err(|err| anyhow::anyhow!("Failed to load the source files: {err}"))?;
+    files.add(PathBuf::from("<test prelude>"), PRELUDE.to_owned());
+
+    let Ok(output) = CompilationUnit::new_with_defaults(&mut bundle.pool)
+        .map_err(|err| anyhow::anyhow!("Failed to create the compilation unit: {err}"))?
+        .with_test_code(true)
+        .compile_and_report(&files)
+    else {
+        anyhow::bail!("Build failed");
+    };
+
+    if let Some(path) = &opts.output {
+        let file = File::create(path).context("Failed to create a file at the specified output path")?;
+        bundle
+            .save(&mut io::BufWriter::new(file))
+            .context("Failed to write the script cache")?;
+        log::info!("Test bundle saved to {}", path.display());
+    }
+
+    let mut vm = Vm::new(&bundle.pool);
+    vm.register_operators();
+    vm.register_assertions();
+    let report = vm.run_tests(output.test_functions())?;
+
+    let mut out = io::stdout().lock();
+    writeln!(out, "{report}")?;
+    out.flush()?;
+    if !report.is_success() {
+        anyhow::bail!("{} of {} tests failed", report.failed(), report.results.len());
+    }
+    Ok(())
+}
+
+fn symbolize(opts: SymbolizeOpts) -> anyhow::Result<()> {
+    let file = File::open(&opts.input).context("Failed to open the line map")?;
+    let line_map = LineMap::load(&mut io::BufReader::new(file)).context("Failed to load the line map")?;
+
+    let mut out = io::stdout().lock();
+    let mut found = false;
+    for loc in line_map.lookup(&opts.function, opts.offset) {
+        writeln!(out, "{} at {loc}", loc.function)?;
+        found = true;
+    }
+    out.flush()?;
+    if !found {
+        anyhow::bail!(
+            "No source location found for {} at offset {}",
+            opts.function,
+            opts.offset
+        );
+    }
+    Ok(())
+}
+
+fn xref(opts: XrefOpts) -> anyhow::Result<()> {
+    let bundle = load_bundle(&opts.input)?;
+    let pool = &bundle.pool;
+    let xrefs = CrossReferences::new(pool);
+
+    let selected: Vec<PoolIndex<Definition>> = match &opts.name {
+        Some(name) => {
+            let selected: Vec<_> = pool
+                .definitions()
+                .map(|(idx, _)| idx)
+                .filter(|idx| {
+                    symbol_name(*idx, pool).map_or(false, |symbol| {
+                        symbol == *name || symbol.split_once(';').map_or(false, |(symbol, _)| symbol == name)
+                    })
+                })
+                .collect();
+            if selected.is_empty() {
+                anyhow::bail!("Definition {name} not found");
+            }
+            selected
+        }
+        None => pool
+            .definitions()
+            .filter(|(_, def)| matches!(def.value, AnyDefinition::Function(_)))
+            .map(|(idx, _)| idx)
+            .collect(),
+    };
+    let references: Vec<&Reference> = if opts.name.is_some() {
+        xrefs
+            .references()
+            .iter()
+            .filter(|r| selected.contains(&r.target) || selected.contains(&r.source.cast()))
+            .collect()
+    } else {
+        xrefs.references().iter().collect()
+    };
+
+    let mut out = io::BufWriter::new(io::stdout().lock());
+    match opts.format.as_str() {
+        "text" => {
+            for &idx in &selected {
+                let incoming = xrefs.references_to(idx).collect::<Vec<_>>();
+                let outgoing = xrefs.references_from(idx.cast()).collect::<Vec<_>>();
+                if opts.name.is_none() && outgoing.is_empty() {
+                    continue;
+                }
+                writeln!(out, "{}", symbol_name(idx, pool)?)?;
+                for r in incoming {
+                    let verb = match r.kind {
+                        ReferenceKind::Call | ReferenceKind::VirtualCall => "called by",
+                        ReferenceKind::Read => "read by",
+                        ReferenceKind::Write => "written by",
+                        ReferenceKind::Construct => "constructed by",
+                        ReferenceKind::EnumValue => "used by",
+                    };
+                    writeln!(out, "  {verb} {} at {}", symbol_name(r.source, pool)?, r.location)?;
+                }
+                for r in outgoing {
+                    let verb = match r.kind {
+                        ReferenceKind::Call => "calls",
+                        ReferenceKind::VirtualCall => "may call",
+                        ReferenceKind::Read => "reads",
+                        ReferenceKind::Write => "writes",
+                        ReferenceKind::Construct => "constructs",
+                        ReferenceKind::EnumValue => "uses",
+                    };
+                    writeln!(out, "  {verb} {} at {}", symbol_name(r.target, pool)?, r.location)?;
+                }
             }
-            Ok(())
         }
-        None => Ok(()),
+        "json" => {
+            let mut entries = Vec::with_capacity(references.len());
+            for r in references {
+                entries.push(serde_json::json!({
+                    "source": symbol_name(r.source, pool)?,
+                    "target": symbol_name(r.target, pool)?,
+                    "kind": r.kind.as_str(),
+                    "location": r.location.value,
+                }));
+            }
+            serde_json::to_writer_pretty(&mut out, &entries).context("Failed to write the references")?;
+            writeln!(out)?;
+        }
+        "dot" => {
+            let mut edges = HashSet::new();
+            writeln!(out, "digraph xref {{")?;
+            for r in references {
+                if edges.insert((r.source.cast(), r.target, r.kind)) {
+                    let source = symbol_name(r.source, pool)?;
+                    let target = symbol_name(r.target, pool)?;
+                    writeln!(out, "  {source:?} -> {target:?} [label=\"{}\"];", r.kind)?;
+                }
+            }
+            writeln!(out, "}}")?;
+        }
+        _ => anyhow::bail!("Invalid xref format: {}", opts.format),
+    }
+    out.flush()?;
+    Ok(())
+}
+
+fn stubs(opts: StubsOpts) -> anyhow::Result<()> {
+    let bundle = load_bundle(&opts.input)?;
+    let pool = &bundle.pool;
+
+    let files = StubFile::from_pool(pool).context("Failed to collect the declarations")?;
+    for entry in &files {
+        let path = opts.output.join(&entry.path);
+        fs::create_dir_all(path.parent().expect("entry path should have at least one component"))?;
+
+        let file = File::create(&path).context("Failed to create a file at the specified output path")?;
+        let mut output = io::BufWriter::new(file);
+        entry
+            .write(&mut output, pool)
+            .with_context(|| format!("Failed to write the declarations to {}", path.display()))?;
+        output.flush()?;
     }
+    log::info!("Saved {} declaration files to {}", files.len(), opts.output.display());
+    Ok(())
+}
+
+fn doc(opts: DocOpts) -> anyhow::Result<()> {
+    let extension = match opts.format.as_str() {
+        "markdown" => "md",
+        "html" => "html",
+        _ => anyhow::bail!("Invalid documentation format: {}", opts.format),
+    };
+    let files = Files::from_dirs(&opts.src, &SourceFilter::None)
+        .map_err(|err| anyhow::anyhow!("Failed to load the source files: {err}"))?;
+    let doc = Documentation::from_files(&files).context("Failed to parse the source files")?;
+
+    fs::create_dir_all(&opts.output)?;
+    for module in doc.modules() {
+        let name = if module.is_empty() { "global" } else { module };
+        let path = opts.output.join(format!("{name}.{extension}"));
+        let file = File::create(&path).context("Failed to create a file at the specified output path")?;
+        let mut output = io::BufWriter::new(file);
+        match extension {
+            "md" => doc.write_markdown(module, &mut output)?,
+            _ => doc.write_html(module, &mut output)?,
+        }
+        output.flush()?;
+    }
+    log::info!("Output successfully saved to {}", opts.output.display());
+    Ok(())
 }
 
 fn load_bundle(path: &Path) -> anyhow::Result<ScriptBundle> {
@@ -198,3 +1001,53 @@ fn load_bundle(path: &Path) -> anyhow::Result<ScriptBundle> {
     let mut reader = io::Cursor::new(map.as_ref());
     ScriptBundle::load(&mut reader).context("Failed to load the script cache")
 }
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::prelude::*;
+    use redscript::ast::{Pos, Span};
+
+    use super::*;
+
+    #[test]
+    fn report_only_new_diagnostics() {
+        let mut files = Files::new();
+        files.add("test.reds".into(), "let a = 1;\nlet b = 2;\n".to_owned());
+        let first = Diagnostic::UnusedLocal(Span::new(Pos::new(4), Pos::new(5)));
+        let second = Diagnostic::UnusedLocal(Span::new(Pos::new(15), Pos::new(16)));
+
+        let mut seen = SeenDiagnostics::default();
+        assert!(seen.is_new(&first, &files));
+        seen.finish_run();
+
+        assert!(!seen.is_new(&first, &files));
+        assert!(seen.is_new(&second, &files));
+        seen.finish_run();
+
+        assert!(!seen.is_new(&second, &files));
+    }
+
+    #[test]
+    fn snapshot_changes_with_modification_time() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        let script = dir.child("test.reds");
+        script.write_str("func Test() {}").unwrap();
+        dir.child("notes.txt").write_str("ignored").unwrap();
+
+        let src = [dir.path().to_path_buf()];
+        let snapshot = source_snapshot(&src);
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(source_snapshot(&src), snapshot);
+
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(script.path())
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+        assert_ne!(source_snapshot(&src), snapshot);
+
+        dir.close().unwrap();
+    }
+}
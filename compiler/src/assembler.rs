@@ -1,7 +1,7 @@
 use itertools::Itertools;
 use redscript::ast::{Constant, Expr, Ident, Literal, Seq, Span, TypeName};
 use redscript::bundle::{ConstantPool, PoolIndex};
-use redscript::bytecode::{Code, Instr, IntrinsicOp, Label, Location, Offset};
+use redscript::bytecode::{Breakpoint, Code, Instr, IntrinsicOp, Label, Location, Offset, StartProfiling};
 use redscript::definition::{Function, Local};
 
 use crate::error::{Cause, Error, ResultSpan};
@@ -14,14 +14,18 @@ pub struct Assembler<'a> {
     files: &'a Files,
     instructions: Vec<Instr<Label>>,
     labels: usize,
+    breakpoints: bool,
+    statements: Vec<(usize, Span)>,
 }
 
 impl<'a> Assembler<'a> {
-    fn new(files: &'a Files) -> Self {
+    fn new(files: &'a Files, breakpoints: bool) -> Self {
         Self {
             files,
             instructions: Vec::new(),
             labels: 0,
+            breakpoints,
+            statements: Vec::new(),
         }
     }
 
@@ -193,10 +197,18 @@ impl<'a> Assembler<'a> {
                 }
                 self.emit_label(exit_label);
             }
-            Expr::If(condition, if_, else_, _) => {
+            Expr::If(condition, mut if_, else_, span) => {
+                let condition = match *condition {
+                    Expr::Declare(local, Some(type_), Some(init), _) => {
+                        let (guard, unwrap) = Self::variant_guard(local, *type_, *init, scope, pool).with_span(span)?;
+                        if_.exprs.insert(0, unwrap);
+                        guard
+                    }
+                    condition => condition,
+                };
                 let else_label = self.new_label();
                 self.emit(Instr::JumpIfFalse(else_label));
-                self.assemble(*condition, scope, pool, None)?;
+                self.assemble(condition, scope, pool, None)?;
                 self.assemble_seq(if_, scope, pool, exit)?;
                 if let Some(else_code) = else_ {
                     let exit_label = self.new_label();
@@ -295,6 +307,8 @@ impl<'a> Assembler<'a> {
                 return Err(Cause::UnsupportedFeature("InterpolatedString").with_span(span))
             }
             Expr::ForIn(_, _, _, span) => return Err(Cause::UnsupportedFeature("For-in").with_span(span)),
+            Expr::Match(_, _, _, span) => return Err(Cause::UnsupportedFeature("Match").with_span(span)),
+            Expr::NullSafe(_, span) => return Err(Cause::UnsupportedFeature("NullSafe").with_span(span)),
             Expr::BinOp(_, _, _, span) => return Err(Cause::UnsupportedFeature("BinOp").with_span(span)),
             Expr::UnOp(_, _, span) => return Err(Cause::UnsupportedFeature("UnOp").with_span(span)),
             Expr::Break(span) => return Err(Cause::UnsupportedFeature("Break").with_span(span)),
@@ -311,11 +325,36 @@ impl<'a> Assembler<'a> {
         exit: Option<Label>,
     ) -> Result<(), Error> {
         for expr in seq.exprs {
+            let span = expr.span();
+            // synthesized expressions have no location
+            if !matches!(expr, Expr::Seq(_)) && span.low != span.high {
+                self.statements.push((self.instructions.len(), span));
+                if self.breakpoints {
+                    self.emit_breakpoint(span);
+                }
+            }
             self.assemble(expr, scope, pool, exit)?;
         }
         Ok(())
     }
 
+    fn emit_breakpoint(&mut self, span: Span) {
+        let Some(loc) = self.files.lookup(span) else {
+            return;
+        };
+        let line_start = usize::from(loc.file.line_start(loc.start.line)) - usize::from(loc.file.byte_offset());
+        let length = usize::from(span.high) - usize::from(span.low);
+        let breakpoint = Breakpoint {
+            line: u16::try_from(loc.start.line + 1).unwrap_or(u16::MAX),
+            line_start: u32::try_from(line_start).unwrap_or(u32::MAX),
+            col: u16::try_from(loc.start.col + 1).unwrap_or(u16::MAX),
+            length: u16::try_from(length).unwrap_or(u16::MAX),
+            enabled: true,
+            padding: 0,
+        };
+        self.emit(Instr::Breakpoint(Box::new(breakpoint)));
+    }
+
     fn emit_initializer(
         &mut self,
         local: PoolIndex<Local>,
@@ -436,6 +475,51 @@ impl<'a> Assembler<'a> {
         Ok(())
     }
 
+    // lowers `if let x = FromVariant<T>(v)` into a type name check followed by an assignment
+    fn variant_guard(
+        local: PoolIndex<Local>,
+        type_: TypeId,
+        init: Expr<TypedAst>,
+        scope: &Scope,
+        pool: &mut ConstantPool,
+    ) -> Result<(Expr<TypedAst>, Expr<TypedAst>), Cause> {
+        let (subject, span) = match init {
+            Expr::Call(Callable::Intrinsic(IntrinsicOp::FromVariant, _), _, args, span) => match &args[..] {
+                [Expr::Ident(subject, _)] => (subject.clone(), span),
+                _ => return Err(Cause::UnsupportedFeature("type guard on a complex expression")),
+            },
+            _ => return Err(Cause::UnsupportedFeature("'if let' without FromVariant")),
+        };
+        let bool_type = scope.resolve_type(&TypeName::BOOL, pool)?;
+        let name_type = scope.resolve_type(&TypeName::CNAME, pool)?;
+        let type_name = type_.variant_name(pool)?;
+
+        let variant_type_name = Expr::Call(
+            Callable::Intrinsic(IntrinsicOp::VariantTypeName, name_type),
+            [].into(),
+            [Expr::Ident(subject.clone(), span)].into(),
+            span,
+        );
+        let expected_name = Expr::Constant(Constant::String(Literal::Name, type_name.to_heap()), span);
+        let guard = Expr::Call(
+            Callable::Intrinsic(IntrinsicOp::Equals, bool_type),
+            [].into(),
+            [variant_type_name, expected_name].into(),
+            span,
+        );
+        let unwrap = Expr::Assign(
+            Box::new(Expr::Ident(Reference::Value(Value::Local(local)), span)),
+            Box::new(Expr::Call(
+                Callable::Intrinsic(IntrinsicOp::FromVariant, type_),
+                [].into(),
+                [Expr::Ident(subject, span)].into(),
+                span,
+            )),
+            span,
+        );
+        Ok((guard, unwrap))
+    }
+
     fn is_rvalue_ref(expr: &Expr<TypedAst>, scope: &Scope, pool: &ConstantPool) -> Option<bool> {
         let typ = type_of(expr, scope, pool).ok()?;
         match typ {
@@ -582,31 +666,45 @@ impl<'a> Assembler<'a> {
         Ok(())
     }
 
-    fn into_code(self) -> Code<Offset> {
+    fn into_code(self) -> (Code<Offset>, Vec<(Location, Span)>) {
         let mut locations = Vec::with_capacity(self.labels);
         locations.resize(self.labels, Location::new(0));
 
+        let mut statements = self.statements.into_iter().peekable();
+        let mut spans = Vec::with_capacity(statements.len());
+
         let code = Code(self.instructions);
-        for (loc, instr) in code.iter() {
+        for (i, (loc, instr)) in code.iter().enumerate() {
             if let Instr::Target(label) = instr {
                 locations[label.index] = loc;
             }
+            while let Some((_, span)) = statements.next_if(|(index, _)| *index == i) {
+                spans.push((loc, span));
+            }
         }
 
         let mut resolved = Vec::with_capacity(code.0.len());
         for (loc, instr) in code.iter().filter(|(_, instr)| !matches!(instr, Instr::Target(_))) {
             resolved.push(instr.resolve_labels(loc, &locations));
         }
-        Code(resolved)
+        (Code(resolved), spans)
     }
 
+    /// Assembles a function body, when `breakpoints` is set a breakpoint is emitted before every statement.
+    /// When a profiling name is provided, the body starts with a profiling instruction with that name.
+    /// The code is returned along with the bytecode location and the span of every statement.
     pub fn from_body(
         seq: Seq<TypedAst>,
         files: &'a Files,
         scope: &mut Scope,
         pool: &mut ConstantPool,
-    ) -> Result<Code<Offset>, Error> {
-        let mut assembler = Self::new(files);
+        breakpoints: bool,
+        profiling: Option<String>,
+    ) -> Result<(Code<Offset>, Vec<(Location, Span)>), Error> {
+        let mut assembler = Self::new(files, breakpoints);
+        if let Some(name) = profiling {
+            assembler.emit(Instr::StartProfiling(Box::new(StartProfiling(name, 1))));
+        }
         assembler.assemble_seq(seq, scope, pool, None)?;
         assembler.emit(Instr::Nop);
         Ok(assembler.into_code())
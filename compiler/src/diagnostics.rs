@@ -11,14 +11,17 @@ use crate::typechecker::TypedAst;
 
 pub mod return_val;
 pub mod unused;
+pub mod variant;
 
 #[derive(Debug)]
 pub enum Diagnostic {
     MethodConflict(PoolIndex<Function>, Span),
+    AddedMethodConflict(Span),
     FieldConflict(Span),
     Deprecation(Deprecation, Span),
     UnusedLocal(Span),
     MissingReturn(Span),
+    UncheckedVariant(Span),
     SyntaxError(ExpectedSet, Span),
     CompileError(Cause, Span),
     CteError(&'static str, Span),
@@ -72,10 +75,12 @@ impl Diagnostic {
         !matches!(
             self,
             Self::MethodConflict(_, _)
+                | Self::AddedMethodConflict(_)
                 | Self::FieldConflict(_)
                 | Self::Deprecation(_, _)
                 | Self::UnusedLocal(_)
                 | Self::MissingReturn(_)
+                | Self::UncheckedVariant(_)
         )
     }
 
@@ -83,10 +88,12 @@ impl Diagnostic {
     pub fn span(&self) -> Span {
         match self {
             Self::MethodConflict(_, span)
+            | Self::AddedMethodConflict(span)
             | Self::FieldConflict(span)
             | Self::Deprecation(_, span)
             | Self::UnusedLocal(span)
             | Self::MissingReturn(span)
+            | Self::UncheckedVariant(span)
             | Self::CompileError(_, span)
             | Self::SyntaxError(_, span)
             | Self::CteError(_, span) => *span,
@@ -107,12 +114,16 @@ impl fmt::Display for Diagnostic {
             Self::MethodConflict(_, _) => {
                 f.write_str("this replacement overwrites a previous replacement of the same method")
             }
+            Self::AddedMethodConflict(_) => f.write_str("method with this signature is already defined in the class"),
             Self::FieldConflict(_) => {
                 f.write_str("field with this name is already defined in the class, this will have no effect")
             }
             Self::Deprecation(msg, _) => f.write_fmt(format_args!("{msg}")),
             Self::UnusedLocal(_) => f.write_str("unused variable"),
             Self::MissingReturn(_) => f.write_str("function might not return a value"),
+            Self::UncheckedVariant(_) => f.write_str(
+                "variant is unwrapped without checking its type, consider using 'if let x = FromVariant<T>(v)'",
+            ),
             Self::SyntaxError(set, _) => f.write_fmt(format_args!("syntax error, expected {set}")),
             Self::CompileError(cause, _) => f.write_fmt(format_args!("{cause}")),
             Self::CteError(msg, _) => f.write_fmt(format_args!("compile-time expression error: {msg}")),
@@ -0,0 +1,82 @@
+use redscript::ast::{Expr, Seq, Span};
+use redscript::bytecode::IntrinsicOp;
+
+use super::{Diagnostic, DiagnosticPass, FunctionMetadata};
+use crate::typechecker::{Callable, TypedAst};
+use crate::visit_expr;
+
+pub struct VariantGuardCheck;
+
+impl DiagnosticPass for VariantGuardCheck {
+    fn diagnose(&self, body: &Seq<TypedAst>, _meta: &FunctionMetadata) -> Vec<Diagnostic> {
+        let mut unwraps = VariantUnwraps::default();
+        for expr in &body.exprs {
+            unwraps.on_expr(expr);
+        }
+        unwraps.unchecked.into_iter().map(Diagnostic::UncheckedVariant).collect()
+    }
+}
+
+#[derive(Default)]
+struct VariantUnwraps {
+    guards: usize,
+    unchecked: Vec<Span>,
+}
+
+impl VariantUnwraps {
+    fn on_expr(&mut self, expr: &Expr<TypedAst>) {
+        match expr {
+            Expr::If(cond, if_, else_, _) => {
+                let is_guarded = match cond.as_ref() {
+                    Expr::Declare(_, _, Some(init), _) if is_from_variant(init) => {
+                        visit_expr!(self, on_expr, init.as_ref());
+                        true
+                    }
+                    cond => {
+                        self.on_expr(cond);
+                        does_check_type_name(cond)
+                    }
+                };
+                if is_guarded {
+                    self.guards += 1;
+                }
+                for expr in &if_.exprs {
+                    self.on_expr(expr);
+                }
+                if is_guarded {
+                    self.guards -= 1;
+                }
+                for expr in else_.iter().flat_map(|seq| &seq.exprs) {
+                    self.on_expr(expr);
+                }
+                return;
+            }
+            Expr::Call(Callable::Intrinsic(IntrinsicOp::FromVariant, _), _, _, span) if self.guards == 0 => {
+                self.unchecked.push(*span);
+            }
+            _ => {}
+        }
+        visit_expr!(self, on_expr, expr);
+    }
+}
+
+fn is_from_variant(expr: &Expr<TypedAst>) -> bool {
+    matches!(expr, Expr::Call(Callable::Intrinsic(IntrinsicOp::FromVariant, _), _, _, _))
+}
+
+fn does_check_type_name(expr: &Expr<TypedAst>) -> bool {
+    struct Finder(bool);
+
+    impl Finder {
+        fn on_expr(&mut self, expr: &Expr<TypedAst>) {
+            if let Expr::Call(Callable::Intrinsic(IntrinsicOp::VariantTypeName, _), _, _, _) = expr {
+                self.0 = true;
+            }
+            visit_expr!(self, on_expr, expr);
+        }
+    }
+
+    let mut finder = Finder(false);
+    finder.on_expr(expr);
+    finder.0
+}
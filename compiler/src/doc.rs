@@ -0,0 +1,223 @@
+//! Documentation of the declarations in source files generated from their doc comments.
+use std::collections::BTreeMap;
+use std::io;
+
+use itertools::Itertools;
+use redscript::ast::{Ident, TypeName};
+
+use crate::error::Error;
+use crate::parser::{
+    ClassSource, Declaration, EnumSource, FieldSource, FunctionSource, MemberSource, Qualifier, Qualifiers,
+    SourceEntry, SourceModule,
+};
+use crate::source_map::Files;
+use crate::unit::CompilationUnit;
+
+/// The documented declarations of source files grouped by the module they belong to.
+/// Private members and test code are left out.
+#[derive(Debug, Default)]
+pub struct Documentation {
+    modules: BTreeMap<String, Vec<Item>>,
+}
+
+impl Documentation {
+    pub fn new(modules: &[SourceModule]) -> Self {
+        let mut res = Self::default();
+        for module in modules {
+            let name = module
+                .path
+                .as_ref()
+                .map(|path| path.render().to_string())
+                .unwrap_or_default();
+            let items = res.modules.entry(name).or_default();
+            for entry in module.entries.iter().filter(|entry| !entry.is_test_only()) {
+                match entry {
+                    SourceEntry::Class(class) => items.push(Item::class(class, "class")),
+                    SourceEntry::Struct(class) => items.push(Item::class(class, "struct")),
+                    SourceEntry::Function(fun) => items.push(Item::function(fun)),
+                    SourceEntry::GlobalLet(field) => items.push(Item::field(field)),
+                    SourceEntry::Enum(enum_) => items.push(Item::enum_(enum_)),
+                }
+            }
+        }
+        res
+    }
+
+    pub fn from_files(files: &Files) -> Result<Self, Error> {
+        Ok(Self::new(&CompilationUnit::parse(files)?))
+    }
+
+    /// Returns the names of the documented modules, the name of the global module is empty.
+    pub fn modules(&self) -> impl Iterator<Item = &str> {
+        self.modules.keys().map(String::as_str)
+    }
+
+    pub fn items(&self, module: &str) -> &[Item] {
+        self.modules.get(module).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    pub fn write_markdown<W: io::Write>(&self, module: &str, out: &mut W) -> io::Result<()> {
+        writeln!(out, "# {}", display_module(module))?;
+        for item in self.items(module) {
+            write_markdown_item(out, item, 2)?;
+        }
+        Ok(())
+    }
+
+    pub fn write_html<W: io::Write>(&self, module: &str, out: &mut W) -> io::Result<()> {
+        let title = escape_html(display_module(module));
+        writeln!(out, "<!DOCTYPE html>")?;
+        writeln!(out, "<html>")?;
+        writeln!(out, "<head><meta charset=\"utf-8\"><title>{title}</title></head>")?;
+        writeln!(out, "<body>")?;
+        writeln!(out, "<h1>{title}</h1>")?;
+        for item in self.items(module) {
+            write_html_item(out, item, 2)?;
+        }
+        writeln!(out, "</body>")?;
+        writeln!(out, "</html>")
+    }
+}
+
+/// A declaration along with its doc comment and its documented members.
+#[derive(Debug)]
+pub struct Item {
+    pub signature: String,
+    pub doc: Option<String>,
+    pub members: Vec<Item>,
+}
+
+impl Item {
+    fn class(class: &ClassSource, keyword: &str) -> Self {
+        let base = class
+            .base
+            .as_ref()
+            .map(|base| format!(" extends {base}"))
+            .unwrap_or_default();
+        let signature = format!("{}{keyword} {}{base}", format_qualifiers(&class.qualifiers), class.name);
+        let members = class
+            .members
+            .iter()
+            .filter(|member| !member.is_test_only())
+            .filter_map(|member| match member {
+                MemberSource::Function(fun) if is_public(&fun.declaration) => Some(Self::function(fun)),
+                MemberSource::Field(field) if is_public(&field.declaration) => Some(Self::field(field)),
+                _ => None,
+            })
+            .collect();
+        Self {
+            signature,
+            doc: class.doc.clone(),
+            members,
+        }
+    }
+
+    fn function(fun: &FunctionSource) -> Self {
+        let params = fun
+            .parameters
+            .iter()
+            .map(|param| {
+                format!(
+                    "{}{}: {}",
+                    format_qualifiers(&param.qualifiers),
+                    param.name,
+                    param.type_.pretty()
+                )
+            })
+            .format(", ");
+        let return_type = fun
+            .type_
+            .as_ref()
+            .map_or_else(|| Ident::from_static("Void"), TypeName::pretty);
+        let signature = format!(
+            "{}func {}({params}) -> {return_type}",
+            format_qualifiers(&fun.declaration.qualifiers),
+            fun.declaration.name
+        );
+        Self {
+            signature,
+            doc: fun.declaration.doc.clone(),
+            members: vec![],
+        }
+    }
+
+    fn field(field: &FieldSource) -> Self {
+        let signature = format!(
+            "{}let {}: {}",
+            format_qualifiers(&field.declaration.qualifiers),
+            field.declaration.name,
+            field.type_.pretty()
+        );
+        Self {
+            signature,
+            doc: field.declaration.doc.clone(),
+            members: vec![],
+        }
+    }
+
+    fn enum_(enum_: &EnumSource) -> Self {
+        let members = enum_
+            .members
+            .iter()
+            .map(|member| Self {
+                signature: format!("{} = {}", member.name, member.value),
+                doc: None,
+                members: vec![],
+            })
+            .collect();
+        Self {
+            signature: format!("enum {}", enum_.name),
+            doc: enum_.doc.clone(),
+            members,
+        }
+    }
+}
+
+fn is_public(decl: &Declaration) -> bool {
+    !decl.qualifiers.contain(Qualifier::Private)
+}
+
+fn format_qualifiers(qualifiers: &Qualifiers) -> String {
+    qualifiers.iter().map(|qualifier| format!("{qualifier} ")).collect()
+}
+
+fn display_module(module: &str) -> &str {
+    if module.is_empty() {
+        "Global"
+    } else {
+        module
+    }
+}
+
+fn write_markdown_item<W: io::Write>(out: &mut W, item: &Item, depth: usize) -> io::Result<()> {
+    writeln!(out)?;
+    writeln!(out, "{} `{}`", "#".repeat(depth), item.signature)?;
+    if let Some(doc) = &item.doc {
+        writeln!(out)?;
+        writeln!(out, "{doc}")?;
+    }
+    for member in &item.members {
+        write_markdown_item(out, member, depth + 1)?;
+    }
+    Ok(())
+}
+
+fn write_html_item<W: io::Write>(out: &mut W, item: &Item, depth: usize) -> io::Result<()> {
+    let depth = depth.min(6);
+    writeln!(
+        out,
+        "<h{depth}><code>{}</code></h{depth}>",
+        escape_html(&item.signature)
+    )?;
+    if let Some(doc) = &item.doc {
+        writeln!(out, "<p>{}</p>", escape_html(doc).replace('\n', "<br>"))?;
+    }
+    for member in &item.members {
+        write_html_item(out, member, depth + 1)?;
+    }
+    Ok(())
+}
+
+fn escape_html(str: &str) -> String {
+    str.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -6,6 +6,7 @@ use peg::error::ExpectedSet;
 use redscript::ast::{Ident, Span};
 use redscript::bundle::PoolError;
 use redscript::bytecode::IntrinsicOp;
+use redscript::verify::VerifyError;
 use thiserror::Error;
 
 const MAX_RESOLUTION_ERRORS: usize = 6;
@@ -24,6 +25,8 @@ pub enum Error {
     MultipleErrors(Vec<(&'static str, Span)>),
     #[error("compile-time eval error: {0}")]
     CteError(&'static str, Span),
+    #[error("bytecode verification error: {0}")]
+    VerifyError(#[from] VerifyError),
 }
 
 #[derive(Debug, Error)]
@@ -38,6 +41,8 @@ pub enum Cause {
     MemberNotFound(Ident, Ident),
     #[error("method {0} not found on {1}")]
     MethodNotFound(Ident, Ident),
+    #[error("no existing definition of {0} matches this signature")]
+    SignatureMismatch(Ident),
     #[error("class {0} not found")]
     ClassNotFound(Ident),
     #[error("cannot instantiate {0} because it's abstract")]
@@ -94,6 +99,10 @@ pub enum Cause {
     UnsupportedPersistent(Ident),
     #[error(r#"this value must be a constant (e.g. 1, "string")"#)]
     InvalidConstant,
+    #[error("shift amount must be a constant smaller than the width of its type")]
+    InvalidShiftAmount,
+    #[error("match is not exhaustive, add a default arm or cover all members of {0}")]
+    NonExhaustiveMatch(Ident),
     #[error(
         "arguments passed to {0} do not match any of the overloads:\n{}{}",
         .1.iter().take(MAX_RESOLUTION_ERRORS).format("\n"),
@@ -119,6 +128,7 @@ impl Cause {
             | Self::UnificationFailed(_, _) => "TYPE_ERR",
             Self::FunctionNotFound(_) => "UNRESOLVED_FN",
             Self::MethodNotFound(_, _) => "UNRESOLVED_METHOD",
+            Self::SignatureMismatch(_) => "SIGNATURE_MISMATCH",
             Self::MemberNotFound(_, _) => "UNRESOLVED_MEMBER",
             Self::ClassNotFound(_) | Self::UnresolvedType(_) => "UNRESOLVED_TYPE",
             Self::UnresolvedReference(_) => "UNRESOLVED_REF",
@@ -138,7 +148,8 @@ impl Cause {
             Self::UnexpectedBody => "UNEXPECTED_BODY",
             Self::UnexpectedNative => "UNEXPECTED_NATIVE",
             Self::UnsupportedPersistent(_) => "INVALID_PERSISTENT",
-            Self::InvalidConstant => "INVALID_CONSTANT",
+            Self::InvalidConstant | Self::InvalidShiftAmount => "INVALID_CONSTANT",
+            Self::NonExhaustiveMatch(_) => "NON_EXHAUSTIVE_MATCH",
             Self::UnsupportedFeature(_) | Self::UnsupportedOperation(_, _) | Self::UnexpectedToken(_) => "UNSUPPORTED",
         }
     }
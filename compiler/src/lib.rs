@@ -1,7 +1,9 @@
 pub mod assembler;
 pub mod cte;
 pub mod diagnostics;
+pub mod doc;
 pub mod error;
+pub mod line_map;
 #[allow(clippy::redundant_closure_call)]
 pub mod parser;
 pub mod scope;
@@ -0,0 +1,191 @@
+//! Maps from the bytecode of compiled functions to their source locations.
+//!
+//! The game only reports the name of a function and a bytecode offset when a script fails, a line map
+//! saved next to the compiled bundle makes it possible to resolve them to a file, line and column.
+use std::path::Path;
+use std::{fmt, io};
+
+use hashbrown::HashMap;
+use itertools::Itertools;
+use redscript::asm::qualified_name;
+use redscript::ast::Span;
+use redscript::bundle::{ConstantPool, PoolError, PoolIndex};
+use redscript::bytecode::Location;
+use redscript::decode::{Decode, DecodeError, DecodeExt};
+use redscript::definition::Function;
+use redscript::encode::{Encode, EncodeExt};
+
+use crate::source_map::Files;
+
+#[derive(Debug, Default)]
+pub struct LineMap {
+    files: Vec<String>,
+    functions: Vec<FunctionLines>,
+}
+
+impl LineMap {
+    const MAGIC: [u8; 4] = *b"RLMP";
+    const VERSION: u32 = 1;
+
+    pub(crate) fn new(
+        statements: &HashMap<PoolIndex<Function>, Vec<(Location, Span)>>,
+        pool: &ConstantPool,
+        files: &Files,
+    ) -> Result<Self, PoolError> {
+        let mut paths = vec![];
+        let mut path_indexes: HashMap<&Path, u32> = HashMap::new();
+        let mut functions = Vec::with_capacity(statements.len());
+
+        for (&index, spans) in statements.iter().sorted_by_key(|(index, _)| u32::from(**index)) {
+            let mut path = None;
+            let mut lines = Vec::with_capacity(spans.len());
+            for &(location, span) in spans {
+                let Some(loc) = files.lookup(span) else {
+                    continue;
+                };
+                path.get_or_insert(loc.file.path());
+                lines.push(Line {
+                    offset: location.value,
+                    line: loc.start.line as u32 + 1,
+                    col: loc.start.col as u32 + 1,
+                });
+            }
+            let Some(path) = path else {
+                continue;
+            };
+            let file = *path_indexes.entry(path).or_insert_with(|| {
+                paths.push(path.to_string_lossy().into_owned());
+                paths.len() as u32 - 1
+            });
+            functions.push(FunctionLines {
+                name: qualified_name(index, pool)?,
+                file,
+                lines,
+            });
+        }
+
+        Ok(Self {
+            files: paths,
+            functions,
+        })
+    }
+
+    pub fn load<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        input.decode()
+    }
+
+    pub fn save<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
+        output.encode(self)
+    }
+
+    /// Finds the source location of a bytecode offset in each of the functions matching the name.
+    /// The name can either be the full qualified name of the function or the qualified name without
+    /// the signature, in which case all overloads are matched.
+    pub fn lookup<'a>(&'a self, function: &'a str, offset: u16) -> impl Iterator<Item = SourceLocation<'a>> + 'a {
+        self.functions
+            .iter()
+            .filter(move |fun| {
+                fun.name == function || fun.name.split_once(';').map_or(false, |(name, _)| name == function)
+            })
+            .filter_map(move |fun| {
+                let line = fun.lines.iter().take_while(|line| line.offset <= offset).last()?;
+                Some(SourceLocation {
+                    function: &fun.name,
+                    path: self.files.get(fun.file as usize)?,
+                    line: line.line,
+                    col: line.col,
+                })
+            })
+    }
+}
+
+impl Encode for LineMap {
+    fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
+        output.encode(&Self::MAGIC)?;
+        output.encode(&Self::VERSION)?;
+        output.encode(&(self.files.len() as u32))?;
+        for file in &self.files {
+            output.encode(&file.as_str())?;
+        }
+        output.encode_slice_prefixed::<u32, _>(&self.functions)
+    }
+}
+
+impl Decode for LineMap {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        let magic: [u8; 4] = input.decode()?;
+        let version: u32 = input.decode()?;
+        if magic != Self::MAGIC || version != Self::VERSION {
+            return Err(DecodeError::InvalidHeader);
+        }
+        Ok(Self {
+            files: input.decode_vec_prefixed::<u32, _>()?,
+            functions: input.decode_vec_prefixed::<u32, _>()?,
+        })
+    }
+}
+
+#[derive(Debug)]
+struct FunctionLines {
+    name: String,
+    file: u32,
+    lines: Vec<Line>,
+}
+
+impl Encode for FunctionLines {
+    fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
+        output.encode(&self.name.as_str())?;
+        output.encode(&self.file)?;
+        output.encode_slice_prefixed::<u32, _>(&self.lines)
+    }
+}
+
+impl Decode for FunctionLines {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(Self {
+            name: input.decode()?,
+            file: input.decode()?,
+            lines: input.decode_vec_prefixed::<u32, _>()?,
+        })
+    }
+}
+
+/// The bytecode offset at which a statement starts along with its line and column.
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    offset: u16,
+    line: u32,
+    col: u32,
+}
+
+impl Encode for Line {
+    fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
+        output.encode(&self.offset)?;
+        output.encode(&self.line)?;
+        output.encode(&self.col)
+    }
+}
+
+impl Decode for Line {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(Self {
+            offset: input.decode()?,
+            line: input.decode()?,
+            col: input.decode()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SourceLocation<'a> {
+    pub function: &'a str,
+    pub path: &'a str,
+    pub line: u32,
+    pub col: u32,
+}
+
+impl fmt::Display for SourceLocation<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.path, self.line, self.col)
+    }
+}
@@ -1,8 +1,12 @@
+use std::fmt;
 use std::str::FromStr;
 
+use itertools::Itertools;
 use peg::error::ParseError;
 use peg::str::LineCol;
-use redscript::ast::{BinOp, Constant, Expr, Ident, Literal, Pos, Seq, SourceAst, Span, SwitchCase, TypeName, UnOp};
+use redscript::ast::{
+    BinOp, Constant, Expr, Ident, Literal, MatchArm, Pos, Seq, SourceAst, Span, SwitchCase, TypeName, UnOp,
+};
 use redscript::definition::Visibility;
 use redscript::Ref;
 use strum::EnumString;
@@ -17,6 +21,31 @@ pub struct SourceModule {
     pub entries: Vec<SourceEntry>,
 }
 
+impl SourceModule {
+    // doc comments are skipped by the grammar like any other comments, so they're attached to
+    // the declarations that they precede after parsing
+    fn attach_doc_comments(&mut self, source: &str, offset: Pos) {
+        let doc = |span: Span| doc_comment(source, usize::from(span.low) - usize::from(offset));
+        for entry in &mut self.entries {
+            match entry {
+                SourceEntry::Class(class) | SourceEntry::Struct(class) => {
+                    class.doc = doc(class.span);
+                    for member in &mut class.members {
+                        let decl = match member {
+                            MemberSource::Function(fun) => &mut fun.declaration,
+                            MemberSource::Field(field) => &mut field.declaration,
+                        };
+                        decl.doc = doc(decl.span);
+                    }
+                }
+                SourceEntry::Function(fun) => fun.declaration.doc = doc(fun.declaration.span),
+                SourceEntry::GlobalLet(field) => field.declaration.doc = doc(field.declaration.span),
+                SourceEntry::Enum(enum_) => enum_.doc = doc(enum_.span),
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum SourceEntry {
     Class(ClassSource),
@@ -34,6 +63,16 @@ impl SourceEntry {
             _ => &[],
         }
     }
+
+    /// Returns whether the entry should only be compiled into test builds.
+    pub fn is_test_only(&self) -> bool {
+        match self {
+            Self::Class(class) | Self::Struct(class) => class.qualifiers.contain(Qualifier::TestOnly),
+            Self::Function(fun) => fun.declaration.is_test_only(),
+            Self::GlobalLet(field) => field.declaration.is_test_only(),
+            Self::Enum(_) => false,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -43,6 +82,7 @@ pub struct ClassSource {
     pub base: Option<Ident>,
     pub members: Vec<MemberSource>,
     pub span: Span,
+    pub doc: Option<String>,
 }
 
 #[derive(Debug)]
@@ -51,6 +91,15 @@ pub enum MemberSource {
     Field(FieldSource),
 }
 
+impl MemberSource {
+    pub fn is_test_only(&self) -> bool {
+        match self {
+            Self::Function(fun) => fun.declaration.is_test_only(),
+            Self::Field(field) => field.declaration.is_test_only(),
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct FieldSource {
     pub declaration: Declaration,
@@ -79,6 +128,7 @@ pub struct EnumSource {
     pub name: Ident,
     pub members: Vec<EnumMember>,
     pub span: Span,
+    pub doc: Option<String>,
 }
 
 #[derive(Debug)]
@@ -104,6 +154,31 @@ pub enum Qualifier {
     Quest,
     ImportOnly,
     Persistent,
+    TestOnly,
+}
+
+impl fmt::Display for Qualifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let res = match self {
+            Self::Public => "public",
+            Self::Protected => "protected",
+            Self::Private => "private",
+            Self::Abstract => "abstract",
+            Self::Static => "static",
+            Self::Final => "final",
+            Self::Const => "const",
+            Self::Native => "native",
+            Self::Exec => "exec",
+            Self::Callback => "cb",
+            Self::Out => "out",
+            Self::Optional => "opt",
+            Self::Quest => "quest",
+            Self::ImportOnly => "importonly",
+            Self::Persistent => "persistent",
+            Self::TestOnly => "testonly",
+        };
+        f.write_str(res)
+    }
 }
 
 #[derive(Debug)]
@@ -122,6 +197,10 @@ impl Qualifiers {
     pub fn contain(&self, qualifier: Qualifier) -> bool {
         self.0.contains(&qualifier)
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Qualifier> {
+        self.0.iter()
+    }
 }
 
 #[derive(Debug)]
@@ -130,6 +209,18 @@ pub struct Declaration {
     pub qualifiers: Qualifiers,
     pub name: Ident,
     pub span: Span,
+    pub doc: Option<String>,
+}
+
+impl Declaration {
+    pub fn has_annotation(&self, kind: AnnotationKind) -> bool {
+        self.annotations.iter().any(|ann| ann.kind == kind)
+    }
+
+    /// Returns whether the declaration is a test or is marked with the `testonly` qualifier.
+    pub fn is_test_only(&self) -> bool {
+        self.has_annotation(AnnotationKind::Test) || self.qualifiers.contain(Qualifier::TestOnly)
+    }
 }
 
 #[derive(Debug)]
@@ -149,14 +240,48 @@ pub enum AnnotationKind {
     AddField,
     If,
     RuntimeProperty,
+    Test,
+    Profile,
 }
 
 pub fn parse_file(file: &File) -> Result<SourceModule, ParseError<LineCol>> {
-    lang::module(file.source(), file.byte_offset())
+    let mut module = lang::module(file.source(), file.byte_offset())?;
+    module.attach_doc_comments(file.source(), file.byte_offset());
+    Ok(module)
 }
 
 pub fn parse_str(str: &str) -> Result<SourceModule, ParseError<LineCol>> {
-    lang::module(str, Pos::ZERO)
+    let mut module = lang::module(str, Pos::ZERO)?;
+    module.attach_doc_comments(str, Pos::ZERO);
+    Ok(module)
+}
+
+/// Returns the contents of the `///` line comments or the `/** */` block comment that immediately
+/// precede a position in the source.
+fn doc_comment(source: &str, pos: usize) -> Option<String> {
+    let before = source.get(..pos)?.trim_end();
+    let doc = if let Some(block) = before.strip_suffix("*/") {
+        let start = block.rfind("/*")?;
+        block[start..]
+            .strip_prefix("/**")?
+            .lines()
+            .map(|line| {
+                let line = line.trim();
+                line.strip_prefix('*').map_or(line, str::trim_start)
+            })
+            .join("\n")
+    } else {
+        let mut lines = vec![];
+        for line in before.lines().rev() {
+            match line.trim_start().strip_prefix("///") {
+                Some(text) if !text.starts_with('/') => lines.push(text.strip_prefix(' ').unwrap_or(text)),
+                _ => break,
+            }
+        }
+        lines.iter().rev().join("\n")
+    };
+    let doc = doc.trim();
+    (!doc.is_empty()).then(|| doc.to_owned())
 }
 
 peg::parser! {
@@ -193,6 +318,7 @@ peg::parser! {
             / keyword("quest") { Qualifier::Quest }
             / keyword("importonly") { Qualifier::ImportOnly }
             / keyword("persistent") { Qualifier::Persistent }
+            / keyword("testonly") { Qualifier::TestOnly }
 
         rule literal_type() -> Literal
             = "n" { Literal::Name }
@@ -200,9 +326,9 @@ peg::parser! {
             / "t" { Literal::TweakDbId }
 
         rule annotation() -> Annotation
-            = pos:pos() "@" ident:ident() _ "(" _ args:commasep(<expr()>) _ ")" end:pos() {?
+            = pos:pos() "@" ident:ident() args:(_ "(" _ args:commasep(<expr()>) _ ")" { args })? end:pos() {?
                 AnnotationKind::from_str(ident.as_ref()).map(|kind| {
-                    Annotation { kind, args, span: Span::new(pos, end) }
+                    Annotation { kind, args: args.unwrap_or_default(), span: Span::new(pos, end) }
                 }).map_err(|_| "valid annotation")
             }
 
@@ -278,7 +404,7 @@ peg::parser! {
 
         rule decl(inner: rule<()>) -> Declaration
             = pos:pos() annotations:(annotation() ** _) _ qualifiers:qualifiers() _ inner() _ name:ident() end:pos()
-            { Declaration { annotations, qualifiers, name, span: Span::new(pos, end) } }
+            { Declaration { annotations, qualifiers, name, span: Span::new(pos, end), doc: None } }
 
         rule field() -> FieldSource
             = declaration:decl(<keyword("let")>) _ type_:let_type() _ default:initializer()? _ ";"
@@ -299,11 +425,11 @@ peg::parser! {
 
         pub rule class() -> ClassSource
             = pos:pos() qualifiers:qualifiers() _ keyword("class") _ name:ident() _ base:extends()? _ "{" _ members:member()**_ _ "}" end:pos()
-            { ClassSource { qualifiers, name, base, members, span: Span::new(pos, end) } }
+            { ClassSource { qualifiers, name, base, members, span: Span::new(pos, end), doc: None } }
 
         pub rule struct_() -> ClassSource
             = pos:pos() qualifiers:qualifiers() _ keyword("struct") _ name:ident() _ "{" _ members:member()**_ _ "}" end:pos()
-            { ClassSource { qualifiers, name, base: None, members, span: Span::new(pos, end) } }
+            { ClassSource { qualifiers, name, base: None, members, span: Span::new(pos, end), doc: None } }
 
         rule member() -> MemberSource
             = fun:function() { MemberSource::Function(fun) }
@@ -312,7 +438,7 @@ peg::parser! {
 
         pub rule enum_() -> EnumSource
             = pos:pos() keyword("enum") _ name:ident() _ "{" _ members:commasep(<enum_member()>) _ ","? _ "}" end:pos()
-            { EnumSource { name, members, span: Span::new(pos, end) } }
+            { EnumSource { name, members, span: Span::new(pos, end), doc: None } }
 
         rule enum_member() -> EnumMember
             = name:ident() _ "=" _ value:number()
@@ -357,6 +483,17 @@ peg::parser! {
         rule default() -> Seq<SourceAst>
             = keyword("default") _ ":" _ body:seq() { body }
 
+        rule match_() -> Expr<SourceAst>
+            = pos:pos() keyword("match") _ matched:expr() _ "{" _ arms:(match_arm() ** ("," _)) _ ","? _ default:match_default()? _ "}" end:pos()
+            { Expr::Match(Box::new(matched), arms, default.map(Box::new), Span::new(pos, end)) }
+
+        rule match_arm() -> MatchArm<SourceAst>
+            = !keyword("default") pattern:expr() _ "=>" _ body:expr() _
+            { MatchArm { pattern, body } }
+
+        rule match_default() -> Expr<SourceAst>
+            = keyword("default") _ "=>" _ body:expr() _ ","? { body }
+
         rule while_() -> Expr<SourceAst>
             = pos:pos() keyword("while") _ cond:expr() _ "{" _ body:seq() _ "}" _ ";"? end:pos()
             { Expr::While(Box::new(cond), body, Span::new(pos, end)) }
@@ -366,8 +503,11 @@ peg::parser! {
             { Expr::ForIn(ident, Box::new(array), body, Span::new(pos, end)) }
 
         rule if_() -> Expr<SourceAst>
-            = pos:pos() keyword("if") _ cond:expr() _ "{" _ if_:seq() _ "}" _ else_:else_()? _ ";"? end:pos()
+            = pos:pos() keyword("if") _ cond:(if_let() / expr()) _ "{" _ if_:seq() _ "}" _ else_:else_()? _ ";"? end:pos()
             { Expr::If(Box::new(cond), if_, else_, Span::new(pos, end)) }
+        rule if_let() -> Expr<SourceAst>
+            = pos:pos() keyword("let") _ name:ident() _ val:initializer() end:pos()
+            { Expr::Declare(name, None, Some(Box::new(val)), Span::new(pos, end)) }
         rule else_() -> Seq<SourceAst>
             = keyword("else") _ "{" _ body:seq() _ "}" { body }
             / keyword("else") _ body:if_() { Seq::new(vec![body]) }
@@ -377,6 +517,7 @@ peg::parser! {
             / for_: for_() { for_ }
             / if_: if_() { if_ }
             / switch: switch() { switch }
+            / match_: match_() _ ";"? { match_ }
             / pos:pos() keyword("return") _ val:expr()? _ end_of_stmt() end:pos() { Expr::Return(val.map(Box::new), Span::new(pos, end)) }
             / pos:pos() keyword("break") _ end_of_stmt() end:pos() { Expr::Break(Span::new(pos, end)) }
             / let_:let() { let_ }
@@ -402,6 +543,12 @@ peg::parser! {
             x:@ _ pos:pos() "/=" end:pos() _ y:(@) { binop(x, y, BinOp::AssignDivide) }
             x:@ _ pos:pos() "|=" end:pos() _ y:(@) { binop(x, y, BinOp::AssignOr) }
             x:@ _ pos:pos() "&=" end:pos() _ y:(@) { binop(x, y, BinOp::AssignAnd) }
+            x:@ _ pos:pos() "^=" end:pos() _ y:(@) { binop(x, y, BinOp::AssignXor) }
+            x:@ _ pos:pos() "%=" end:pos() _ y:(@) { binop(x, y, BinOp::AssignModulo) }
+            x:@ _ pos:pos() "<<=" end:pos() _ y:(@) { binop(x, y, BinOp::AssignShiftLeft) }
+            x:@ _ pos:pos() ">>=" end:pos() _ y:(@) { binop(x, y, BinOp::AssignShiftRight) }
+            --
+            x:@ _ pos:pos() "??" end:pos() _ y:(@) { binop(x, y, BinOp::Coalesce) }
             --
             x:(@) _ pos:pos() "||" end:pos() _ y:@ { binop(x, y, BinOp::LogicOr) }
             --
@@ -421,6 +568,9 @@ peg::parser! {
             x:(@) _ pos:pos() ">" end:pos() _ y:@ { binop(x, y, BinOp::Greater) }
             x:(@) _ pos:pos() ">=" end:pos() _ y:@ { binop(x, y, BinOp::GreaterEqual) }
             --
+            x:(@) _ pos:pos() "<<" end:pos() _ y:@ { binop(x, y, BinOp::ShiftLeft) }
+            x:(@) _ pos:pos() ">>" end:pos() _ y:@ { binop(x, y, BinOp::ShiftRight) }
+            --
             x:(@) _ pos:pos() "+" end:pos() _ y:@ { binop(x, y, BinOp::Add) }
             x:(@) _ pos:pos() "-" end:pos() _ y:@ { binop(x, y, BinOp::Subtract) }
             --
@@ -440,6 +590,14 @@ peg::parser! {
                 let span = expr.span();
                 Expr::ArrayElem(Box::new(expr), Box::new(idx), Span { high, ..span })
             }
+            expr:(@) _ "?." _ ident:ident() _ "(" _ params:commasep(<expr()>) _ ")" high:pos() {
+                let span = Span { high, ..expr.span() };
+                Expr::NullSafe(Box::new(Expr::MethodCall(Box::new(expr), ident, params, span)), span)
+            }
+            expr:(@) _ "?." _ ident:ident() high:pos() {
+                let span = Span { high, ..expr.span() };
+                Expr::NullSafe(Box::new(Expr::Member(Box::new(expr), ident, span)), span)
+            }
             expr:(@) _ "." _ ident:ident() _ "(" _ params:commasep(<expr()>) _ ")" high:pos() {
                 let span = expr.span();
                 Expr::MethodCall(Box::new(expr), ident, params, Span { high, ..span })
@@ -455,6 +613,7 @@ peg::parser! {
             pos:pos() "[" _ exprs:commasep(<expr()>)_ "]" end:pos() {
                 Expr::ArrayLit(exprs.into_boxed_slice(), None, Span::new(pos, end))
             }
+            match_:match_() { match_ }
             "(" _ v:expr() _ ")" { v }
             pos:pos() keyword("null") end:pos() {
                 Expr::Null(Span::new(pos, end))
@@ -520,7 +679,7 @@ mod tests {
         .unwrap();
         assert_eq!(
             format!("{:?}", module.entries),
-            r#"[Class(ClassSource { qualifiers: Qualifiers([Public]), name: "A", base: Some("IScriptable"), members: [Field(FieldSource { declaration: Declaration { annotations: [], qualifiers: Qualifiers([Private, Const]), name: "m_field", span: Span { low: Pos(53), high: Pos(78) } }, type_: TypeName { name: "Int32", arguments: None }, default: None }), Function(FunctionSource { declaration: Declaration { annotations: [], qualifiers: Qualifiers([Public]), name: "GetField", span: Span { low: Pos(104), high: Pos(124) } }, type_: Some(TypeName { name: "Int32", arguments: None }), parameters: [], body: Some(Seq { exprs: [Return(Some(Member(This(Span { low: Pos(165), high: Pos(169) }), "m_field", Span { low: Pos(165), high: Pos(177) })), Span { low: Pos(158), high: Pos(178) })] }), span: Span { low: Pos(104), high: Pos(196) } })], span: Span { low: Pos(0), high: Pos(211) } })]"#
+            r#"[Class(ClassSource { qualifiers: Qualifiers([Public]), name: "A", base: Some("IScriptable"), members: [Field(FieldSource { declaration: Declaration { annotations: [], qualifiers: Qualifiers([Private, Const]), name: "m_field", span: Span { low: Pos(53), high: Pos(78) }, doc: None }, type_: TypeName { name: "Int32", arguments: None }, default: None }), Function(FunctionSource { declaration: Declaration { annotations: [], qualifiers: Qualifiers([Public]), name: "GetField", span: Span { low: Pos(104), high: Pos(124) }, doc: None }, type_: Some(TypeName { name: "Int32", arguments: None }), parameters: [], body: Some(Seq { exprs: [Return(Some(Member(This(Span { low: Pos(165), high: Pos(169) }), "m_field", Span { low: Pos(165), high: Pos(177) })), Span { low: Pos(158), high: Pos(178) })] }), span: Span { low: Pos(104), high: Pos(196) } })], span: Span { low: Pos(0), high: Pos(211) }, doc: None })]"#
         );
     }
 
@@ -535,7 +694,7 @@ mod tests {
         .unwrap();
         assert_eq!(
             format!("{:?}", module.entries),
-            r#"[Function(FunctionSource { declaration: Declaration { annotations: [], qualifiers: Qualifiers([Public, Static]), name: "GetField", span: Span { low: Pos(0), high: Pos(27) } }, type_: Some(TypeName { name: "Uint64", arguments: None }), parameters: [ParameterSource { qualifiers: Qualifiers([]), name: "optimum", type_: TypeName { name: "Uint64", arguments: None } }], body: Some(Seq { exprs: [Return(Some(Conditional(BinOp(Member(This(Span { low: Pos(80), high: Pos(84) }), "m_field", Span { low: Pos(80), high: Pos(92) }), Ident("optimum", Span { low: Pos(95), high: Pos(102) }), Greater, Span { low: Pos(80), high: Pos(102) }), Member(This(Span { low: Pos(105), high: Pos(109) }), "m_field", Span { low: Pos(105), high: Pos(117) }), Ident("optimum", Span { low: Pos(120), high: Pos(127) }), Span { low: Pos(80), high: Pos(127) })), Span { low: Pos(73), high: Pos(128) })] }), span: Span { low: Pos(0), high: Pos(143) } })]"#
+            r#"[Function(FunctionSource { declaration: Declaration { annotations: [], qualifiers: Qualifiers([Public, Static]), name: "GetField", span: Span { low: Pos(0), high: Pos(27) }, doc: None }, type_: Some(TypeName { name: "Uint64", arguments: None }), parameters: [ParameterSource { qualifiers: Qualifiers([]), name: "optimum", type_: TypeName { name: "Uint64", arguments: None } }], body: Some(Seq { exprs: [Return(Some(Conditional(BinOp(Member(This(Span { low: Pos(80), high: Pos(84) }), "m_field", Span { low: Pos(80), high: Pos(92) }), Ident("optimum", Span { low: Pos(95), high: Pos(102) }), Greater, Span { low: Pos(80), high: Pos(102) }), Member(This(Span { low: Pos(105), high: Pos(109) }), "m_field", Span { low: Pos(105), high: Pos(117) }), Ident("optimum", Span { low: Pos(120), high: Pos(127) }), Span { low: Pos(80), high: Pos(127) })), Span { low: Pos(73), high: Pos(128) })] }), span: Span { low: Pos(0), high: Pos(143) } })]"#
         );
     }
 
@@ -630,7 +789,7 @@ mod tests {
         .unwrap();
         assert_eq!(
             format!("{:?}", module.entries),
-            r#"[Class(ClassSource { qualifiers: Qualifiers([]), name: "Test", base: None, members: [Field(FieldSource { declaration: Declaration { annotations: [], qualifiers: Qualifiers([Private]), name: "m_field", span: Span { low: Pos(130), high: Pos(149) } }, type_: TypeName { name: "String", arguments: None }, default: None })], span: Span { low: Pos(101), high: Pos(189) } })]"#
+            r#"[Class(ClassSource { qualifiers: Qualifiers([]), name: "Test", base: None, members: [Field(FieldSource { declaration: Declaration { annotations: [], qualifiers: Qualifiers([Private]), name: "m_field", span: Span { low: Pos(130), high: Pos(149) }, doc: None }, type_: TypeName { name: "String", arguments: None }, default: None })], span: Span { low: Pos(101), high: Pos(189) }, doc: None })]"#
         );
     }
 
@@ -648,7 +807,7 @@ mod tests {
         .unwrap();
         assert_eq!(
             format!("{:?}", module.entries),
-            r#"[Class(ClassSource { qualifiers: Qualifiers([]), name: "Test", base: None, members: [Field(FieldSource { declaration: Declaration { annotations: [], qualifiers: Qualifiers([Private]), name: "m_field", span: Span { low: Pos(114), high: Pos(133) } }, type_: TypeName { name: "String", arguments: None }, default: None })], span: Span { low: Pos(13), high: Pos(156) } })]"#
+            r#"[Class(ClassSource { qualifiers: Qualifiers([]), name: "Test", base: None, members: [Field(FieldSource { declaration: Declaration { annotations: [], qualifiers: Qualifiers([Private]), name: "m_field", span: Span { low: Pos(114), high: Pos(133) }, doc: None }, type_: TypeName { name: "String", arguments: None }, default: None })], span: Span { low: Pos(13), high: Pos(156) }, doc: None })]"#
         );
     }
 
@@ -698,4 +857,109 @@ mod tests {
             r#"BinOp(BinOp(Constant(Bool(true), Span { low: Pos(1), high: Pos(5) }), BinOp(Constant(Bool(false), Span { low: Pos(9), high: Pos(14) }), Constant(Bool(false), Span { low: Pos(18), high: Pos(23) }), LogicAnd, Span { low: Pos(9), high: Pos(23) }), LogicOr, Span { low: Pos(1), high: Pos(23) }), BinOp(BinOp(Constant(Bool(true), Span { low: Pos(30), high: Pos(34) }), Constant(Bool(false), Span { low: Pos(38), high: Pos(43) }), LogicOr, Span { low: Pos(30), high: Pos(43) }), Constant(Bool(true), Span { low: Pos(48), high: Pos(52) }), LogicAnd, Span { low: Pos(30), high: Pos(52) }), LogicAnd, Span { low: Pos(1), high: Pos(52) })"#
         );
     }
+
+    #[test]
+    fn parse_if_let() {
+        let stmt = lang::stmt("if let x = FromVariant<Int32>(v) {}", Pos::ZERO).unwrap();
+        assert_eq!(
+            format!("{:?}", stmt),
+            r#"If(Declare("x", None, Some(Call("FromVariant", [TypeName { name: "Int32", arguments: None }], [Ident("v", Span { low: Pos(30), high: Pos(31) })], Span { low: Pos(11), high: Pos(32) })), Span { low: Pos(3), high: Pos(32) }), Seq { exprs: [] }, None, Span { low: Pos(0), high: Pos(35) })"#
+        );
+    }
+
+    #[test]
+    fn parse_match() {
+        let expr = lang::expr(r#"match s { "a" => 1, "b" => 2, default => 3 }"#, Pos::ZERO).unwrap();
+        assert_eq!(
+            format!("{:?}", expr),
+            r#"Match(Ident("s", Span { low: Pos(6), high: Pos(7) }), [MatchArm { pattern: Constant(String(String, "a"), Span { low: Pos(10), high: Pos(13) }), body: Constant(I32(1), Span { low: Pos(17), high: Pos(18) }) }, MatchArm { pattern: Constant(String(String, "b"), Span { low: Pos(20), high: Pos(23) }), body: Constant(I32(2), Span { low: Pos(27), high: Pos(28) }) }], Some(Constant(I32(3), Span { low: Pos(41), high: Pos(42) })), Span { low: Pos(0), high: Pos(44) })"#
+        );
+    }
+
+    #[test]
+    fn parse_null_safe_ops() {
+        let expr = lang::expr("a?.b?.Get() ?? c", Pos::ZERO).unwrap();
+        assert_eq!(
+            format!("{:?}", expr),
+            r#"BinOp(NullSafe(MethodCall(NullSafe(Member(Ident("a", Span { low: Pos(0), high: Pos(1) }), "b", Span { low: Pos(0), high: Pos(4) }), Span { low: Pos(0), high: Pos(4) }), "Get", [], Span { low: Pos(0), high: Pos(11) }), Span { low: Pos(0), high: Pos(11) }), Ident("c", Span { low: Pos(15), high: Pos(16) }), Coalesce, Span { low: Pos(0), high: Pos(16) })"#
+        );
+    }
+
+    #[test]
+    fn parse_shift_ops() {
+        let stmt = lang::stmt("x <<= a << 1 + 2 >> b;", Pos::ZERO).unwrap();
+        assert_eq!(
+            format!("{:?}", stmt),
+            r#"BinOp(Ident("x", Span { low: Pos(0), high: Pos(1) }), BinOp(BinOp(Ident("a", Span { low: Pos(6), high: Pos(7) }), BinOp(Constant(I32(1), Span { low: Pos(11), high: Pos(12) }), Constant(I32(2), Span { low: Pos(15), high: Pos(16) }), Add, Span { low: Pos(11), high: Pos(16) }), ShiftLeft, Span { low: Pos(6), high: Pos(16) }), Ident("b", Span { low: Pos(20), high: Pos(21) }), ShiftRight, Span { low: Pos(6), high: Pos(21) }), AssignShiftLeft, Span { low: Pos(0), high: Pos(21) })"#
+        );
+    }
+
+    #[test]
+    fn parse_test_annotations() {
+        let module = lang::module(
+            "@test
+            func TestAdd() {}
+
+            @if(ModuleExists(\"Test\")) @test
+            func TestSub() {}
+
+            testonly class Fixture {}",
+            Pos::ZERO,
+        )
+        .unwrap();
+        let [SourceEntry::Function(add), SourceEntry::Function(sub), fixture] = &module.entries[..] else {
+            panic!("unexpected entries: {:?}", module.entries);
+        };
+        assert_eq!(add.declaration.annotations[0].kind, AnnotationKind::Test);
+        assert!(add.declaration.annotations[0].args.is_empty());
+        assert_eq!(
+            sub.declaration
+                .annotations
+                .iter()
+                .map(|ann| &ann.kind)
+                .collect::<Vec<_>>(),
+            [&AnnotationKind::If, &AnnotationKind::Test]
+        );
+        assert!(add.declaration.is_test_only());
+        assert!(fixture.is_test_only());
+    }
+
+    #[test]
+    fn parse_doc_comments() {
+        let module = parse_str(
+            "/// Keeps track of a count.
+            /// Starts at zero.
+            class Counter {
+                /** The current count. */
+                let count: Int32;
+
+                // not a doc comment
+                func Get() -> Int32 = this.count;
+
+                /**
+                 * Increments the count.
+                 */
+                @if(true)
+                func Increment() {}
+            }
+
+            //// not a doc comment either
+            enum Mode { A = 0 }",
+        )
+        .unwrap();
+        let [SourceEntry::Class(class), SourceEntry::Enum(enum_)] = &module.entries[..] else {
+            panic!("unexpected entries: {:?}", module.entries);
+        };
+        assert_eq!(class.doc.as_deref(), Some("Keeps track of a count.\nStarts at zero."));
+        let docs = class
+            .members
+            .iter()
+            .map(|member| match member {
+                MemberSource::Function(fun) => fun.declaration.doc.as_deref(),
+                MemberSource::Field(field) => field.declaration.doc.as_deref(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(docs, [Some("The current count."), None, Some("Increments the count.")]);
+        assert_eq!(enum_.doc, None);
+    }
 }
@@ -306,6 +306,18 @@ impl TypeId {
         }
     }
 
+    // Used for matching against the type name stored in a variant
+    pub fn variant_name(&self, pool: &ConstantPool) -> Result<Ident, PoolError> {
+        match self {
+            Self::Ref(idx) => Ok(str_fmt!("handle:{}", idx.variant_name(pool)?)),
+            Self::WeakRef(idx) => Ok(str_fmt!("whandle:{}", idx.variant_name(pool)?)),
+            Self::Array(idx) => Ok(str_fmt!("array:{}", idx.variant_name(pool)?)),
+            Self::StaticArray(idx, size) => Ok(str_fmt!("[{}]{}", size, idx.variant_name(pool)?)),
+            Self::ScriptRef(idx) => Ok(str_fmt!("script_ref:{}", idx.variant_name(pool)?)),
+            other => other.repr(pool),
+        }
+    }
+
     pub fn pretty(&self, pool: &ConstantPool) -> Result<Ident, PoolError> {
         match self {
             Self::Prim(idx) => Ok(Ident::from_heap(pool.def_name(*idx)?)),
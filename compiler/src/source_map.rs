@@ -153,12 +153,17 @@ impl File {
         Some(loc)
     }
 
-    pub fn enclosing_line(&self, line: usize) -> &str {
-        let low = if line == 0 {
+    /// Returns the position of the first character of a line.
+    pub fn line_start(&self, line: usize) -> Pos {
+        if line == 0 {
             self.lines.0
         } else {
             self.lines.1[line - 1]
-        };
+        }
+    }
+
+    pub fn enclosing_line(&self, line: usize) -> &str {
+        let low = self.line_start(line);
         let high = self.lines.1.get(line).copied().unwrap_or(self.high);
         let span = Span { low, high };
         self.source_slice(span)
@@ -1,6 +1,6 @@
 use std::vec;
 
-use redscript::ast::{BinOp, Constant, Expr, Ident, Literal, Seq, Span, TypeName};
+use redscript::ast::{BinOp, Constant, Expr, Ident, Literal, MatchArm, Seq, Span, SwitchCase, TypeName};
 use redscript::bundle::{ConstantPool, PoolIndex};
 use redscript::bytecode::IntrinsicOp;
 use redscript::definition::{Definition, Local, LocalFlags};
@@ -10,7 +10,7 @@ use crate::error::{Cause, Error, ResultSpan};
 use crate::scope::{Reference, Scope, TypeId, Value};
 use crate::symbol::{FunctionSignature, FunctionSignatureBuilder};
 use crate::transform::ExprTransformer;
-use crate::typechecker::{type_of, Callable, TypedAst};
+use crate::typechecker::{lub, match_type, type_of, Callable, TypedAst};
 
 pub struct Desugar<'a> {
     pool: &'a mut ConstantPool,
@@ -60,6 +60,127 @@ impl<'a> Desugar<'a> {
         self.name_count += 1;
         Ok(Reference::Value(Value::Local(idx)))
     }
+
+    // lowers a match into a switch when the VM supports it for the matched type, otherwise into an if-else chain
+    fn lower_match(
+        &mut self,
+        matched: Expr<TypedAst>,
+        arms: Vec<MatchArm<TypedAst>>,
+        default: Option<Expr<TypedAst>>,
+        result: Option<&Reference>,
+        span: Span,
+    ) -> Result<Expr<TypedAst>, Error> {
+        let matched = self.on_expr(matched)?;
+        let matched_type = type_of(&matched, self.scope, self.pool)?;
+
+        if self.is_switchable(&matched_type)? {
+            let mut cases = Vec::with_capacity(arms.len());
+            for arm in arms {
+                let mut body = self.lower_match_arm(arm.body, result)?;
+                body.push(Expr::Break(span));
+                cases.push(SwitchCase {
+                    matcher: arm.pattern,
+                    body: Seq::new(body),
+                });
+            }
+            let default = default.map(|expr| self.lower_match_arm(expr, result)).transpose()?;
+            return Ok(Expr::Switch(Box::new(matched), cases, default.map(Seq::new), span));
+        }
+
+        let subject = match matched {
+            Expr::Ident(subject, _) => subject,
+            matched => {
+                let subject = self.fresh_local(&matched_type).with_span(span)?;
+                self.add_prefix(Expr::Assign(
+                    Box::new(Expr::Ident(subject.clone(), span)),
+                    Box::new(matched),
+                    span,
+                ));
+                subject
+            }
+        };
+        let bool_type = self.scope.resolve_type(&TypeName::BOOL, self.pool).with_span(span)?;
+
+        let mut branches = Vec::with_capacity(arms.len());
+        for arm in arms {
+            let cond = Expr::Call(
+                Callable::Intrinsic(IntrinsicOp::Equals, bool_type.clone()),
+                [].into(),
+                [Expr::Ident(subject.clone(), span), arm.pattern].into(),
+                span,
+            );
+            branches.push((cond, self.lower_match_arm(arm.body, result)?));
+        }
+        let mut else_ = default.map(|expr| self.lower_match_arm(expr, result)).transpose()?;
+        for (cond, body) in branches.into_iter().rev() {
+            else_ = Some(vec![Expr::If(
+                Box::new(cond),
+                Seq::new(body),
+                else_.map(Seq::new),
+                span,
+            )]);
+        }
+        Ok(Expr::Seq(Seq::new(else_.unwrap_or_default())))
+    }
+
+    fn lower_match_arm(
+        &mut self,
+        body: Expr<TypedAst>,
+        result: Option<&Reference>,
+    ) -> Result<Vec<Expr<TypedAst>>, Error> {
+        let (mut exprs, body) = self.on_branch(body)?;
+        let span = body.span();
+        match result {
+            Some(result) => exprs.push(Expr::Assign(
+                Box::new(Expr::Ident(result.clone(), span)),
+                Box::new(body),
+                span,
+            )),
+            None => exprs.push(body),
+        }
+        Ok(exprs)
+    }
+
+    // branches are evaluated conditionally, so their prefix expressions are returned instead of being hoisted
+    fn on_branch(&mut self, expr: Expr<TypedAst>) -> Result<(Vec<Expr<TypedAst>>, Expr<TypedAst>), Error> {
+        let outer_prefix = std::mem::take(&mut self.prefix_exprs);
+        let expr = self.on_expr(expr);
+        let prefix = std::mem::replace(&mut self.prefix_exprs, outer_prefix);
+        Ok((prefix, expr?))
+    }
+
+    fn is_defined(&mut self, reference: Reference, span: Span) -> Result<Expr<TypedAst>, Error> {
+        let bool_type = self.scope.resolve_type(&TypeName::BOOL, self.pool).with_span(span)?;
+        Ok(Expr::Call(
+            Callable::Intrinsic(IntrinsicOp::IsDefined, bool_type),
+            [].into(),
+            [Expr::Ident(reference, span)].into(),
+            span,
+        ))
+    }
+
+    fn is_switchable(&self, type_: &TypeId) -> Result<bool, Error> {
+        let res = match type_ {
+            TypeId::Enum(_) => true,
+            TypeId::Prim(_) => {
+                let name = type_.pretty(self.pool)?;
+                [
+                    TypeName::INT8,
+                    TypeName::INT16,
+                    TypeName::INT32,
+                    TypeName::INT64,
+                    TypeName::UINT8,
+                    TypeName::UINT16,
+                    TypeName::UINT32,
+                    TypeName::UINT64,
+                ]
+                .iter()
+                .any(|int| name == int.name())
+            }
+            _ => false,
+        };
+        Ok(res)
+    }
 }
 
 impl<'a> ExprTransformer<TypedAst> for Desugar<'a> {
@@ -215,10 +336,166 @@ impl<'a> ExprTransformer<TypedAst> for Desugar<'a> {
         Ok(Expr::While(Box::new(condition), Seq::new(body), span))
     }
 
+    fn on_if(
+        &mut self,
+        cond: Expr<TypedAst>,
+        if_: Seq<TypedAst>,
+        else_: Option<Seq<TypedAst>>,
+        span: Span,
+    ) -> Result<Expr<TypedAst>, Error> {
+        let if_ = self.on_seq(if_)?;
+        let else_ = else_.map_or_else(|| Ok(None), |seq| self.on_seq(seq).map(Some))?;
+
+        let cond = match cond {
+            // the variant is accessed twice by the type guard, so it's stored in a local unless it's already one
+            Expr::Declare(local, type_, Some(init), decl_span) => match *init {
+                Expr::Call(callable @ Callable::Intrinsic(IntrinsicOp::FromVariant, _), type_args, args, call_span) => {
+                    let mut args = args.into_vec();
+                    let subject = match self.on_expr(args.remove(0))? {
+                        subject @ Expr::Ident(_, _) => subject,
+                        subject => {
+                            let variant = self.fresh_local(&TypeId::Variant).with_span(call_span)?;
+                            self.add_prefix(Expr::Assign(
+                                Box::new(Expr::Ident(variant.clone(), call_span)),
+                                Box::new(subject),
+                                call_span,
+                            ));
+                            Expr::Ident(variant, call_span)
+                        }
+                    };
+                    let init = Expr::Call(callable, type_args, [subject].into(), call_span);
+                    Expr::Declare(local, type_, Some(Box::new(init)), decl_span)
+                }
+                init => self.on_declare(local, type_, Some(init), decl_span)?,
+            },
+            cond => self.on_expr(cond)?,
+        };
+        Ok(Expr::If(Box::new(cond), if_, else_, span))
+    }
+
+    fn on_binop(
+        &mut self,
+        lhs: Expr<TypedAst>,
+        rhs: Expr<TypedAst>,
+        op: BinOp,
+        span: Span,
+    ) -> Result<Expr<TypedAst>, Error> {
+        if !matches!(op, BinOp::Coalesce) {
+            let lhs = self.on_expr(lhs)?;
+            let rhs = self.on_expr(rhs)?;
+            return Ok(Expr::BinOp(Box::new(lhs), Box::new(rhs), op, span));
+        }
+
+        let type_ = lub(
+            type_of(&lhs, self.scope, self.pool)?,
+            type_of(&rhs, self.scope, self.pool)?,
+            self.pool,
+        )
+        .with_span(span)?;
+        let lhs = self.on_expr(lhs)?;
+        let result = self.fresh_local(&type_).with_span(span)?;
+        self.add_prefix(Expr::Assign(
+            Box::new(Expr::Ident(result.clone(), span)),
+            Box::new(lhs),
+            span,
+        ));
+
+        let (mut fallback, rhs) = self.on_branch(rhs)?;
+        fallback.push(Expr::Assign(
+            Box::new(Expr::Ident(result.clone(), span)),
+            Box::new(rhs),
+            span,
+        ));
+        let cond = self.is_defined(result.clone(), span)?;
+        self.add_prefix(Expr::If(
+            Box::new(cond),
+            Seq::new(vec![]),
+            Some(Seq::new(fallback)),
+            span,
+        ));
+        Ok(Expr::Ident(result, span))
+    }
+
+    // the receiver is stored in a local and the access is skipped when it's null
+    fn on_null_safe(&mut self, expr: Expr<TypedAst>, span: Span) -> Result<Expr<TypedAst>, Error> {
+        let type_ = type_of(&expr, self.scope, self.pool)?;
+        let (receiver, mut access) = match expr {
+            Expr::MethodCall(receiver, fun, args, pos) => {
+                (receiver, Expr::MethodCall(Box::new(Expr::Null(pos)), fun, args, pos))
+            }
+            Expr::Member(receiver, member, pos) => (receiver, Expr::Member(Box::new(Expr::Null(pos)), member, pos)),
+            _ => return Err(Cause::UnsupportedFeature("null-safe access on this expression").with_span(span)),
+        };
+
+        let receiver = self.on_expr(*receiver)?;
+        let receiver_type = type_of(&receiver, self.scope, self.pool)?;
+        let local = self.fresh_local(&receiver_type).with_span(span)?;
+        self.add_prefix(Expr::Assign(
+            Box::new(Expr::Ident(local.clone(), span)),
+            Box::new(receiver),
+            span,
+        ));
+        if let Expr::MethodCall(receiver, _, _, _) | Expr::Member(receiver, _, _) = &mut access {
+            **receiver = Expr::Ident(local.clone(), span);
+        }
+
+        let (mut body, access) = self.on_branch(access)?;
+        let cond = self.is_defined(local, span)?;
+        if type_ == TypeId::Void {
+            body.push(access);
+            self.add_prefix(Expr::If(Box::new(cond), Seq::new(body), None, span));
+            return Ok(Expr::EMPTY);
+        }
+
+        let result = self.fresh_local(&type_).with_span(span)?;
+        body.push(Expr::Assign(
+            Box::new(Expr::Ident(result.clone(), span)),
+            Box::new(access),
+            span,
+        ));
+        let null = match &type_ {
+            TypeId::WeakRef(_) => Expr::Call(
+                Callable::Intrinsic(IntrinsicOp::RefToWeakRef, type_.clone()),
+                [].into(),
+                [Expr::Null(span)].into(),
+                span,
+            ),
+            _ => Expr::Null(span),
+        };
+        let reset = Expr::Assign(Box::new(Expr::Ident(result.clone(), span)), Box::new(null), span);
+        self.add_prefix(Expr::If(
+            Box::new(cond),
+            Seq::new(body),
+            Some(Seq::new(vec![reset])),
+            span,
+        ));
+        Ok(Expr::Ident(result, span))
+    }
+
+    fn on_match(
+        &mut self,
+        matched: Expr<TypedAst>,
+        arms: Vec<MatchArm<TypedAst>>,
+        default: Option<Expr<TypedAst>>,
+        span: Span,
+    ) -> Result<Expr<TypedAst>, Error> {
+        let type_ = match_type(&arms, default.as_ref(), self.scope, self.pool, span)?;
+        let result = self.fresh_local(&type_).with_span(span)?;
+        let lowered = self.lower_match(matched, arms, default, Some(&result), span)?;
+        self.add_prefix(lowered);
+        Ok(Expr::Ident(result, span))
+    }
+
     fn on_seq(&mut self, seq: Seq<TypedAst>) -> Result<Seq<TypedAst>, Error> {
         let mut processed = Vec::with_capacity(seq.exprs.len());
         for expr in seq.exprs {
-            let done = self.on_expr(expr)?;
+            let done = match expr {
+                // a match in statement position doesn't produce a value
+                Expr::Match(matched, arms, default, span) => {
+                    self.lower_match(*matched, arms, default.map(|e| *e), None, span)?
+                }
+                expr => self.on_expr(expr)?,
+            };
             if !self.prefix_exprs.is_empty() {
                 processed.append(&mut self.prefix_exprs);
             }
@@ -1,6 +1,6 @@
 use std::fmt::Debug;
 
-use redscript::ast::{BinOp, Constant, Expr, NameKind, Seq, Span, SwitchCase, Target, UnOp};
+use redscript::ast::{BinOp, Constant, Expr, MatchArm, NameKind, Seq, Span, SwitchCase, Target, UnOp};
 use redscript::Ref;
 
 use crate::error::Error;
@@ -105,6 +105,11 @@ where
         Ok(Expr::Member(Box::new(context), name, pos))
     }
 
+    fn on_null_safe(&mut self, expr: Expr<N>, pos: Span) -> Result<Expr<N>, Error> {
+        let expr = self.on_expr(expr)?;
+        Ok(Expr::NullSafe(Box::new(expr), pos))
+    }
+
     fn on_array_elem(&mut self, expr: Expr<N>, index: Expr<N>, pos: Span) -> Result<Expr<N>, Error> {
         let expr = self.on_expr(expr)?;
         let index = self.on_expr(index)?;
@@ -142,6 +147,24 @@ where
         Ok(Expr::Switch(Box::new(matched), processed, default, pos))
     }
 
+    fn on_match(
+        &mut self,
+        matched: Expr<N>,
+        arms: Vec<MatchArm<N>>,
+        default: Option<Expr<N>>,
+        pos: Span,
+    ) -> Result<Expr<N>, Error> {
+        let mut processed = Vec::with_capacity(arms.len());
+        for arm in arms {
+            let pattern = self.on_expr(arm.pattern)?;
+            let body = self.on_expr(arm.body)?;
+            processed.push(MatchArm { pattern, body });
+        }
+        let default = default.map_or_else(|| Ok(None), |expr| self.on_expr(expr).map(Some))?;
+        let matched = self.on_expr(matched)?;
+        Ok(Expr::Match(Box::new(matched), processed, default.map(Box::new), pos))
+    }
+
     fn on_goto(&mut self, target: Target, pos: Span) -> Result<Expr<N>, Error> {
         Ok(Expr::Goto(target, pos))
     }
@@ -216,11 +239,13 @@ where
             Expr::Call(callable, type_args, args, pos) => self.on_call(callable, type_args, args, pos),
             Expr::MethodCall(context, name, args, pos) => self.on_method_call(*context, name, args, pos),
             Expr::Member(context, name, pos) => self.on_member(*context, name, pos),
+            Expr::NullSafe(expr, pos) => self.on_null_safe(*expr, pos),
             Expr::ArrayElem(expr, index, pos) => self.on_array_elem(*expr, *index, pos),
             Expr::New(name, args, pos) => self.on_new(name, args, pos),
             Expr::Return(expr, pos) => self.on_return(expr.map(|e| *e), pos),
             Expr::Seq(seq) => self.on_seq(seq).map(Expr::Seq),
             Expr::Switch(matched, cases, default, pos) => self.on_switch(*matched, cases, default, pos),
+            Expr::Match(matched, arms, default, pos) => self.on_match(*matched, arms, default.map(|e| *e), pos),
             Expr::Goto(target, pos) => self.on_goto(target, pos),
             Expr::If(cond, if_, else_, pos) => self.on_if(*cond, if_, else_, pos),
             Expr::Conditional(cond, true_, false_, pos) => self.on_conditional(*cond, *true_, *false_, pos),
@@ -282,6 +307,9 @@ macro_rules! visit_expr {
             Expr::Member(context, _, _) => {
                 $self.$fun(context);
             }
+            Expr::NullSafe(expr, _) => {
+                $self.$fun(expr);
+            }
             Expr::ArrayElem(expr, index, _) => {
                 $self.$fun(expr);
                 $self.$fun(index);
@@ -307,6 +335,16 @@ macro_rules! visit_expr {
                     $crate::transform::visit_seq(&seq, |e| $self.$fun(e));
                 }
             }
+            Expr::Match(matched, arms, default, _) => {
+                $self.$fun(matched);
+                for arm in arms {
+                    $self.$fun(&arm.pattern);
+                    $self.$fun(&arm.body);
+                }
+                if let Some(expr) = default {
+                    $self.$fun(expr);
+                }
+            }
             Expr::If(cond, if_, else_, _) => {
                 $self.$fun(cond);
                 $crate::transform::visit_seq(if_, |e| $self.$fun(e));
@@ -1,11 +1,14 @@
 use std::iter;
 use std::str::FromStr;
 
+use hashbrown::{HashMap, HashSet};
 use itertools::{izip, Itertools};
-use redscript::ast::{Constant, Expr, Ident, Literal, NameKind, Seq, SourceAst, Span, SwitchCase, TypeName};
+use redscript::ast::{
+    BinOp, Constant, Expr, Ident, Literal, MatchArm, NameKind, Seq, SourceAst, Span, SwitchCase, TypeName,
+};
 use redscript::bundle::{ConstantPool, PoolError, PoolIndex};
 use redscript::bytecode::IntrinsicOp;
-use redscript::definition::{Class, Definition, Enum, Field, Function, Local, LocalFlags};
+use redscript::definition::{Class, Definition, Enum, Field, Function, Local, LocalFlags, Type};
 use redscript::Ref;
 use thiserror::Error;
 
@@ -17,6 +20,8 @@ use crate::symbol::Symbol;
 pub struct TypeChecker<'a> {
     pool: &'a mut ConstantPool,
     locals: Vec<PoolIndex<Local>>,
+    // locals declared without a type, they get one inferred from their first assignment
+    deferred_locals: HashMap<PoolIndex<Local>, Option<TypeId>>,
     diagnostics: Vec<Diagnostic>,
     permissive: bool,
 }
@@ -26,6 +31,7 @@ impl<'a> TypeChecker<'a> {
         TypeChecker {
             pool,
             locals: vec![],
+            deferred_locals: HashMap::new(),
             diagnostics: vec![],
             permissive,
         }
@@ -39,6 +45,9 @@ impl<'a> TypeChecker<'a> {
     ) -> Result<Expr<TypedAst>, Error> {
         let res = match expr {
             Expr::Ident(name, span) => match scope.resolve_reference(name.clone()) {
+                Ok(Reference::Value(Value::Local(local))) if !self.is_local_type_known(local) => {
+                    return Err(Cause::TypeAnnotationRequired.with_span(*span));
+                }
                 Ok(reference) => Expr::Ident(reference, *span),
                 Err(err) if self.permissive => {
                     self.report(err.with_span(*span))?;
@@ -129,6 +138,14 @@ impl<'a> TypeChecker<'a> {
                 }
             }
             Expr::Assign(lhs, rhs, span) => {
+                if let Some(local) = self.resolve_deferred_local(lhs, scope) {
+                    let rhs_typed = self.check(rhs, None, scope)?;
+                    let rhs_type = type_of(&rhs_typed, scope, self.pool)?;
+                    let type_ = self.infer_local_type(local, rhs_type, *span, scope)?;
+                    let rhs_typed = self.convert(rhs_typed, &type_, scope)?;
+                    let lhs_typed = Expr::Ident(Reference::Value(Value::Local(local)), lhs.span());
+                    return Ok(Expr::Assign(Box::new(lhs_typed), Box::new(rhs_typed), *span));
+                }
                 let lhs_typed = self.check(lhs, None, scope)?;
                 let type_ = type_of(&lhs_typed, scope, self.pool)?;
                 let rhs_typed = self.check_and_convert(rhs, &type_, scope)?;
@@ -170,17 +187,35 @@ impl<'a> TypeChecker<'a> {
                 };
                 Expr::MethodCall(Box::new(converted_context), match_.index, match_.args, *span)
             }
+            Expr::BinOp(lhs, rhs, BinOp::Coalesce, span) => {
+                let checked_lhs = self.check(lhs, expected, scope)?;
+                let lhs_type = match type_of(&checked_lhs, scope, self.pool)? {
+                    TypeId::Ref(inner) | TypeId::WeakRef(inner) => TypeId::Ref(inner),
+                    type_ => {
+                        let cause = Cause::UnsupportedOperation("null-coalescing", type_.pretty(self.pool)?);
+                        return Err(cause.with_span(*span));
+                    }
+                };
+                let checked_rhs = self.check(rhs, Some(&lhs_type), scope)?;
+                let rhs_type = match type_of(&checked_rhs, scope, self.pool)? {
+                    TypeId::WeakRef(inner) => TypeId::Ref(inner),
+                    type_ => type_,
+                };
+                let type_ = lub(lhs_type, rhs_type, self.pool).with_span(*span)?;
+                let converted_lhs = self.convert(checked_lhs, &type_, scope)?;
+                let converted_rhs = self.convert(checked_rhs, &type_, scope)?;
+                Expr::BinOp(Box::new(converted_lhs), Box::new(converted_rhs), BinOp::Coalesce, *span)
+            }
             Expr::BinOp(lhs, rhs, op, span) => {
-                let name = Ident::from_static(op.into());
-                let args = IntoIterator::into_iter([lhs.as_ref(), rhs.as_ref()]);
-                let candidates = scope.resolve_function(name.clone()).with_span(*span)?;
-                let match_ = self.resolve_overload(name, candidates, args, expected, scope, *span)?;
-                Expr::Call(
-                    Callable::Function(match_.index),
-                    [].into(),
-                    match_.args.into_boxed_slice(),
-                    *span,
-                )
+                let result = self.check_operator(lhs, rhs, *op, expected, *span, scope);
+                match (result, op.compound_operand()) {
+                    (Ok(expr), _) => expr,
+                    // compound assignments without a matching operator are expanded to `lhs = lhs op rhs`
+                    (Err(err), Some(operand)) if is_side_effect_free(lhs) => self
+                        .check_compound_assign(lhs, rhs, operand, *span, scope)
+                        .map_err(|inner| if operand.is_shift() { inner } else { err })?,
+                    (Err(err), _) => return Err(err),
+                }
             }
             Expr::UnOp(expr, op, span) => {
                 let name = Ident::from_static(op.into());
@@ -222,6 +257,28 @@ impl<'a> TypeChecker<'a> {
                 };
                 Expr::Member(Box::new(converted_context), member, *span)
             }
+            Expr::NullSafe(expr, span) => {
+                let checked = self.check(expr, expected, scope)?;
+                let receiver = match &checked {
+                    Expr::MethodCall(receiver, _, _, _) | Expr::Member(receiver, _, _) => receiver,
+                    _ => return Err(Cause::UnsupportedFeature("null-safe access on this expression").with_span(*span)),
+                };
+                match type_of(receiver, scope, self.pool)? {
+                    TypeId::Ref(_) => {}
+                    type_ => {
+                        let cause = Cause::UnsupportedOperation("null-safe access", type_.pretty(self.pool)?);
+                        return Err(cause.with_span(*span));
+                    }
+                }
+                match type_of(&checked, scope, self.pool)? {
+                    TypeId::Void | TypeId::Ref(_) | TypeId::WeakRef(_) => {}
+                    type_ => {
+                        let cause = Cause::UnsupportedOperation("null-safe access returning", type_.pretty(self.pool)?);
+                        return Err(cause.with_span(*span));
+                    }
+                }
+                Expr::NullSafe(Box::new(checked), *span)
+            }
             Expr::ArrayElem(expr, idx, span) => {
                 let idx_type = scope.resolve_type(&TypeName::INT32, self.pool).with_span(*span)?;
                 let checked_expr = self.check(expr, None, scope)?;
@@ -295,11 +352,19 @@ impl<'a> TypeChecker<'a> {
 
                 Expr::Switch(Box::new(checked_matched), checked_cases, default, *span)
             }
+            Expr::Match(matched, arms, default, span) => {
+                self.check_match(matched, arms, default.as_deref(), expected, false, *span, scope)?
+            }
             Expr::Goto(target, span) => Expr::Goto(*target, *span),
             Expr::If(cond, if_, else_, span) => {
-                let cond_type = scope.resolve_type(&TypeName::BOOL, self.pool).with_span(*span)?;
-                let checked_cond = self.check_and_convert(cond, &cond_type, scope)?;
-                let checked_if = self.check_seq(if_, &mut scope.clone())?;
+                let mut if_scope = scope.clone();
+                let checked_cond = if let Expr::Declare(name, None, Some(init), span) = cond.as_ref() {
+                    self.check_variant_guard(name.clone(), init, &mut if_scope, *span)?
+                } else {
+                    let cond_type = scope.resolve_type(&TypeName::BOOL, self.pool).with_span(*span)?;
+                    self.check_and_convert(cond, &cond_type, scope)?
+                };
+                let checked_if = self.check_seq(if_, &mut if_scope)?;
                 let checked_else = else_
                     .as_ref()
                     .map_or_else(|| Ok(None), |body| self.check_seq(body, &mut scope.clone()).map(Some))?;
@@ -355,12 +420,277 @@ impl<'a> TypeChecker<'a> {
 
     pub fn check_seq(&mut self, seq: &Seq<SourceAst>, scope: &mut Scope) -> Result<Seq<TypedAst>, Error> {
         let mut exprs = Vec::with_capacity(seq.exprs.len());
+        let mut deferred = vec![];
         for expr in &seq.exprs {
-            exprs.push(self.check(expr, None, scope)?);
+            let checked = match expr {
+                Expr::Match(matched, arms, default, span) => {
+                    self.check_match(matched, arms, default.as_deref(), None, true, *span, scope)?
+                }
+                Expr::Declare(name, None, None, span) => {
+                    let local = self.add_local_def(name.clone(), PoolIndex::UNDEFINED, scope);
+                    self.deferred_locals.insert(local, None);
+                    deferred.push((exprs.len(), local, *span));
+                    Expr::Declare(local, None, None, *span)
+                }
+                expr => self.check(expr, None, scope)?,
+            };
+            exprs.push(checked);
+
+            // the type of a deferred local is fixed by the first statement that assigns it,
+            // all assignments within that statement (e.g. in different branches) are unified
+            // and the local cannot be read until the statement ends
+            let mut i = 0;
+            while i < deferred.len() {
+                let (pos, local, span) = deferred[i];
+                match self.deferred_locals.get(&local) {
+                    Some(Some(TypeId::Null)) => return Err(Cause::TypeAnnotationRequired.with_span(span)),
+                    Some(Some(_)) => {
+                        let type_ = self.deferred_locals.remove(&local).flatten().unwrap();
+                        exprs[pos] = Expr::Declare(local, Some(type_.into()), None, span);
+                        deferred.remove(i);
+                    }
+                    _ => i += 1,
+                }
+            }
+        }
+        if let Some((_, _, span)) = deferred.first() {
+            return Err(Cause::TypeAnnotationRequired.with_span(*span));
         }
         Ok(Seq { exprs })
     }
 
+    fn check_operator(
+        &mut self,
+        lhs: &Expr<SourceAst>,
+        rhs: &Expr<SourceAst>,
+        op: BinOp,
+        expected: Option<&TypeId>,
+        span: Span,
+        scope: &mut Scope,
+    ) -> Result<Expr<TypedAst>, Error> {
+        let name = Ident::from_static(op.into());
+        let args = IntoIterator::into_iter([lhs, rhs]);
+        let result = match scope.resolve_function(name.clone()) {
+            Ok(candidates) => self.resolve_overload(name, candidates, args, expected, scope, span),
+            Err(cause) => Err(cause.with_span(span)),
+        };
+        match result {
+            Ok(match_) => Ok(Expr::Call(
+                Callable::Function(match_.index),
+                [].into(),
+                match_.args.into_boxed_slice(),
+                span,
+            )),
+            Err(_) if op.is_shift() => self.check_shift(lhs, rhs, op, expected, span, scope),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Lowers a shift by a constant amount to a multiplication or a division by a power of two,
+    /// for when the shift operators are not defined. The division rounds negative values towards zero.
+    fn check_shift(
+        &mut self,
+        lhs: &Expr<SourceAst>,
+        rhs: &Expr<SourceAst>,
+        op: BinOp,
+        expected: Option<&TypeId>,
+        span: Span,
+        scope: &mut Scope,
+    ) -> Result<Expr<TypedAst>, Error> {
+        let factor = match rhs {
+            Expr::Constant(Constant::I32(n), _) if (0..31).contains(n) => Constant::I32(1 << n),
+            Expr::Constant(Constant::I64(n), _) if (0..63).contains(n) => Constant::I64(1 << n),
+            Expr::Constant(Constant::U32(n), _) if *n < 32 => Constant::U32(1 << n),
+            Expr::Constant(Constant::U64(n), _) if *n < 64 => Constant::U64(1 << n),
+            _ => return Err(Cause::InvalidShiftAmount.with_span(rhs.span())),
+        };
+        let factor = Expr::Constant(factor, rhs.span());
+        let op = if matches!(op, BinOp::ShiftLeft) {
+            BinOp::Multiply
+        } else {
+            BinOp::Divide
+        };
+        self.check_operator(lhs, &factor, op, expected, span, scope)
+    }
+
+    fn check_compound_assign(
+        &mut self,
+        lhs: &Expr<SourceAst>,
+        rhs: &Expr<SourceAst>,
+        op: BinOp,
+        span: Span,
+        scope: &mut Scope,
+    ) -> Result<Expr<TypedAst>, Error> {
+        let value = self.check_operator(lhs, rhs, op, None, span, scope)?;
+        let target = self.check(lhs, None, scope)?;
+        let type_ = type_of(&target, scope, self.pool)?;
+        let value = self.convert(value, &type_, scope)?;
+        Ok(Expr::Assign(Box::new(target), Box::new(value), span))
+    }
+
+    fn resolve_deferred_local(&self, expr: &Expr<SourceAst>, scope: &Scope) -> Option<PoolIndex<Local>> {
+        match expr {
+            Expr::Ident(name, _) => match scope.resolve_reference(name.clone()) {
+                Ok(Reference::Value(Value::Local(local))) if self.deferred_locals.contains_key(&local) => Some(local),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn infer_local_type(
+        &mut self,
+        local: PoolIndex<Local>,
+        type_: TypeId,
+        span: Span,
+        scope: &mut Scope,
+    ) -> Result<TypeId, Error> {
+        let type_ = match self.deferred_locals.get(&local) {
+            Some(Some(prev)) => {
+                let widened = lub(prev.clone(), type_, self.pool).with_span(span)?;
+                // the earlier assignments have already been checked against the previous type,
+                // so it can only be widened when their values don't need a conversion
+                if find_conversion(prev, &widened, self.pool)? != Some(Conversion::Identity) {
+                    return Err(Cause::TypeAnnotationRequired.with_span(span));
+                }
+                widened
+            }
+            _ => type_,
+        };
+        match type_ {
+            TypeId::Void => return Err(Cause::VoidCannotBeUsed.with_span(span)),
+            TypeId::Null => {}
+            ref type_ => {
+                self.pool.local_mut(local)?.type_ = scope.get_type_index(type_, self.pool).with_span(span)?;
+            }
+        }
+        self.deferred_locals.insert(local, Some(type_.clone()));
+        Ok(type_)
+    }
+
+    // the type of a deferred local can still be widened until the end of the statement that assigns it,
+    // so it cannot be read before that
+    fn is_local_type_known(&self, local: PoolIndex<Local>) -> bool {
+        !self.deferred_locals.contains_key(&local)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn check_match(
+        &mut self,
+        matched: &Expr<SourceAst>,
+        arms: &[MatchArm<SourceAst>],
+        default: Option<&Expr<SourceAst>>,
+        expected: Option<&TypeId>,
+        is_statement: bool,
+        span: Span,
+        scope: &mut Scope,
+    ) -> Result<Expr<TypedAst>, Error> {
+        let checked_matched = self.check(matched, None, scope)?;
+        let matched_type = type_of(&checked_matched, scope, self.pool)?;
+        let body_type = expected.filter(|_| !is_statement);
+
+        let mut covered = HashSet::new();
+        let mut checked_arms = Vec::with_capacity(arms.len());
+        for arm in arms {
+            let pattern = self.check_and_convert(&arm.pattern, &matched_type, scope)?;
+            match &pattern {
+                Expr::Constant(_, _) => {}
+                Expr::Member(_, Member::EnumMember(_, member), _) => {
+                    covered.insert(*member);
+                }
+                _ => return Err(Cause::InvalidConstant.with_span(arm.pattern.span())),
+            }
+            let body = match body_type {
+                Some(type_) => self.check_and_convert(&arm.body, type_, scope)?,
+                None => self.check(&arm.body, None, scope)?,
+            };
+            checked_arms.push(MatchArm { pattern, body });
+        }
+        let mut checked_default = match (default, body_type) {
+            (Some(expr), Some(type_)) => Some(self.check_and_convert(expr, type_, scope)?),
+            (Some(expr), None) => Some(self.check(expr, None, scope)?),
+            (None, _) => None,
+        };
+
+        if is_statement {
+            return Ok(Expr::Match(
+                Box::new(checked_matched),
+                checked_arms,
+                checked_default.map(Box::new),
+                span,
+            ));
+        }
+
+        let is_exhaustive = match (&checked_default, &matched_type) {
+            (Some(_), _) => true,
+            (None, TypeId::Enum(enum_)) => self.pool.enum_(*enum_)?.members.iter().all(|m| covered.contains(m)),
+            (None, _) => false,
+        };
+        if !is_exhaustive {
+            return Err(Cause::NonExhaustiveMatch(matched_type.pretty(self.pool)?).with_span(span));
+        }
+
+        if body_type.is_none() {
+            let result_type = match_type(&checked_arms, checked_default.as_ref(), scope, self.pool, span)?;
+            if result_type == TypeId::Void {
+                return Err(Cause::VoidCannotBeUsed.with_span(span));
+            }
+
+            for arm in &mut checked_arms {
+                let body = std::mem::replace(&mut arm.body, Expr::Null(span));
+                arm.body = self.convert(body, &result_type, scope)?;
+            }
+            checked_default = checked_default
+                .map(|expr| self.convert(expr, &result_type, scope))
+                .transpose()?;
+        }
+
+        Ok(Expr::Match(
+            Box::new(checked_matched),
+            checked_arms,
+            checked_default.map(Box::new),
+            span,
+        ))
+    }
+
+    fn check_variant_guard(
+        &mut self,
+        name: Ident,
+        init: &Expr<SourceAst>,
+        scope: &mut Scope,
+        span: Span,
+    ) -> Result<Expr<TypedAst>, Error> {
+        let (type_name, arg) = match init {
+            Expr::Call(callable, type_args, args, _)
+                if matches!(IntrinsicOp::from_str(callable.as_ref()), Ok(IntrinsicOp::FromVariant)) =>
+            {
+                match (&type_args[..], &args[..]) {
+                    ([type_name], [arg]) => (type_name, arg),
+                    (_, [_]) => return Err(Cause::TypeAnnotationRequired.with_span(init.span())),
+                    _ => return Err(Cause::InvalidArgCount(callable.clone(), 1).with_span(init.span())),
+                }
+            }
+            _ => return Err(Cause::UnsupportedFeature("'if let' without FromVariant").with_span(span)),
+        };
+        let type_ = scope.resolve_type(type_name, self.pool).with_span(span)?;
+        let checked_arg = self.check(arg, None, scope)?;
+        match type_of(&checked_arg, scope, self.pool)? {
+            TypeId::Variant => {}
+            other => {
+                let cause = Cause::InvalidIntrinsicUse(IntrinsicOp::FromVariant, other.pretty(self.pool)?);
+                return Err(cause.with_span(arg.span()));
+            }
+        }
+        let local = self.add_local(name, &type_, scope).with_span(span)?;
+        let unwrapped = Expr::Call(
+            Callable::Intrinsic(IntrinsicOp::FromVariant, type_.clone()),
+            [].into(),
+            [checked_arg].into(),
+            init.span(),
+        );
+        Ok(Expr::Declare(local, Some(type_.into()), Some(unwrapped.into()), span))
+    }
+
     #[allow(clippy::match_same_arms)]
     fn check_intrinsic(
         &mut self,
@@ -526,11 +856,16 @@ impl<'a> TypeChecker<'a> {
         scope: &mut Scope,
     ) -> Result<Expr<TypedAst>, Error> {
         let checked = self.check(expr, Some(to), scope)?;
+        self.convert(checked, to, scope)
+    }
+
+    fn convert(&mut self, checked: Expr<TypedAst>, to: &TypeId, scope: &Scope) -> Result<Expr<TypedAst>, Error> {
         let from = type_of(&checked, scope, self.pool)?;
         if let Some(conversion) = find_conversion(&from, to, self.pool)? {
             Ok(insert_conversion(checked, to, conversion))
         } else {
-            self.report(Cause::TypeError(from.pretty(self.pool)?, to.pretty(self.pool)?).with_span(expr.span()))?;
+            let span = checked.span();
+            self.report(Cause::TypeError(from.pretty(self.pool)?, to.pretty(self.pool)?).with_span(span))?;
             Ok(checked)
         }
     }
@@ -673,16 +1008,21 @@ impl<'a> TypeChecker<'a> {
     }
 
     fn add_local(&mut self, name: Ident, type_: &TypeId, scope: &mut Scope) -> Result<PoolIndex<Local>, Cause> {
+        let type_idx = scope.get_type_index(type_, self.pool)?;
+        Ok(self.add_local_def(name, type_idx, scope))
+    }
+
+    fn add_local_def(&mut self, name: Ident, type_idx: PoolIndex<Type>, scope: &mut Scope) -> PoolIndex<Local> {
         let idx = self.locals.len();
         let name_mangled = Ref::from(format!("{name}$local${idx}"));
         let name_idx = self.pool.names.add(name_mangled);
 
-        let local = Local::new(scope.get_type_index(type_, self.pool)?, LocalFlags::new());
+        let local = Local::new(type_idx, LocalFlags::new());
         let local_def = Definition::local(name_idx, scope.function.unwrap().cast(), local);
         let local_idx = self.pool.add_definition(local_def);
         scope.add_local(name, local_idx);
         self.locals.push(local_idx);
-        Ok(local_idx)
+        local_idx
     }
 
     fn report(&mut self, err: Error) -> Result<(), Error> {
@@ -759,11 +1099,12 @@ pub fn type_of(expr: &Expr<TypedAst>, scope: &Scope, pool: &ConstantPool) -> Res
             TypeId::Class(s) => TypeId::Ref(Box::new(TypeId::Class(*s))),
             type_ => return Err(Cause::UnsupportedOperation("constructing", type_.pretty(pool)?).with_span(*span)),
         },
-        Expr::Conditional(_, lhs, rhs, span) => {
+        Expr::Conditional(_, lhs, rhs, span) | Expr::BinOp(lhs, rhs, BinOp::Coalesce, span) => {
             let lt = type_of(lhs, scope, pool)?;
             let rt = type_of(rhs, scope, pool)?;
             return lub(lt, rt, pool).with_span(*span);
         }
+        Expr::Match(_, arms, default, span) => return match_type(arms, default.as_deref(), scope, pool, *span),
         Expr::This(span) => match scope.this {
             Some(class_idx) => TypeId::Ref(Box::new(TypeId::Class(class_idx))),
             None => return Err(Cause::UnexpectedThis.with_span(*span)),
@@ -776,6 +1117,7 @@ pub fn type_of(expr: &Expr<TypedAst>, scope: &Scope, pool: &ConstantPool) -> Res
             None => return Err(Cause::UnexpectedThis.with_span(*span)),
         },
         Expr::Null(_) => TypeId::Null,
+        Expr::NullSafe(expr, _) => type_of(expr, scope, pool)?,
         Expr::BinOp(_, _, _, span) => return Err(Cause::UnsupportedFeature("BinOp").with_span(*span)),
         Expr::UnOp(_, _, span) => return Err(Cause::UnsupportedFeature("UnOp").with_span(*span)),
         Expr::Declare(_, _, _, _)
@@ -792,6 +1134,32 @@ pub fn type_of(expr: &Expr<TypedAst>, scope: &Scope, pool: &ConstantPool) -> Res
     Ok(res)
 }
 
+pub fn match_type(
+    arms: &[MatchArm<TypedAst>],
+    default: Option<&Expr<TypedAst>>,
+    scope: &Scope,
+    pool: &ConstantPool,
+    span: Span,
+) -> Result<TypeId, Error> {
+    let mut bodies = arms.iter().map(|arm| &arm.body).chain(default);
+    match bodies.next() {
+        Some(first) => bodies.try_fold(type_of(first, scope, pool)?, |acc, body| {
+            lub(acc, type_of(body, scope, pool)?, pool).with_span(span)
+        }),
+        None => Ok(TypeId::Void),
+    }
+}
+
+// whether an expression can be evaluated more than once without changing the result
+fn is_side_effect_free(expr: &Expr<SourceAst>) -> bool {
+    match expr {
+        Expr::Ident(_, _) | Expr::Constant(_, _) | Expr::This(_) => true,
+        Expr::Member(context, _, _) => is_side_effect_free(context),
+        Expr::ArrayElem(array, index, _) => is_side_effect_free(array) && is_side_effect_free(index),
+        _ => false,
+    }
+}
+
 pub fn collect_supertypes(
     class_idx: PoolIndex<Class>,
     pool: &ConstantPool,
@@ -1,20 +1,26 @@
 use std::collections::BTreeSet;
+use std::convert::Infallible;
 use std::path::PathBuf;
+use std::str::FromStr;
 
 use hashbrown::{HashMap, HashSet};
+use redscript::asm::qualified_name;
 use redscript::ast::{Constant, Expr, Ident, Literal, Pos, Seq, SourceAst, Span, TypeName};
-use redscript::bundle::{ConstantPool, PoolIndex};
-use redscript::bytecode::{Code, Instr};
+use redscript::bundle::{ConstantPool, PoolError, PoolIndex};
+use redscript::bytecode::{Code, Instr, Location};
 use redscript::definition::*;
 use redscript::mapper::{Mapper, MultiMapper, PoolMapper};
+use redscript::verify::Verifier;
 use redscript::Ref;
 
 use crate::assembler::Assembler;
 use crate::cte;
 use crate::diagnostics::return_val::ReturnValueCheck;
 use crate::diagnostics::unused::UnusedCheck;
+use crate::diagnostics::variant::VariantGuardCheck;
 use crate::diagnostics::{Diagnostic, DiagnosticPass, FunctionMetadata};
 use crate::error::{Cause, Error, ResultSpan};
+use crate::line_map::LineMap;
 use crate::parser::*;
 use crate::scope::{Reference, Scope, TypeId, Value};
 use crate::source_map::{Files, SourceLoc};
@@ -37,11 +43,22 @@ pub struct CompilationUnit<'a> {
     diagnostics: Vec<Diagnostic>,
     file_map: HashMap<PathBuf, PoolIndex<SourceFile>>,
     diagnostic_passes: Vec<Box<dyn DiagnosticPass + Send>>,
+    verify_bytecode: bool,
+    emit_breakpoints: bool,
+    profiling: Profiling,
+    profiled_functions: HashSet<PoolIndex<Function>>,
+    include_test_code: bool,
+    test_functions: Vec<PoolIndex<Function>>,
+    checking_targets: bool,
 }
 
 impl<'a> CompilationUnit<'a> {
     pub fn new_with_defaults(pool: &'a mut ConstantPool) -> Result<Self, Error> {
-        let passes: Vec<Box<dyn DiagnosticPass + Send>> = vec![Box::new(UnusedCheck), Box::new(ReturnValueCheck)];
+        let passes: Vec<Box<dyn DiagnosticPass + Send>> = vec![
+            Box::new(UnusedCheck),
+            Box::new(ReturnValueCheck),
+            Box::new(VariantGuardCheck),
+        ];
         Self::new(pool, passes)
     }
 
@@ -67,9 +84,41 @@ impl<'a> CompilationUnit<'a> {
             diagnostics: vec![],
             file_map: HashMap::new(),
             diagnostic_passes: passes,
+            verify_bytecode: false,
+            emit_breakpoints: false,
+            profiling: Profiling::Disabled,
+            profiled_functions: HashSet::new(),
+            include_test_code: false,
+            test_functions: vec![],
+            checking_targets: false,
         })
     }
 
+    /// Enables verification of the generated bytecode once compilation finishes.
+    pub fn with_bytecode_verification(mut self, enabled: bool) -> Self {
+        self.verify_bytecode = enabled;
+        self
+    }
+
+    /// Emits breakpoint instructions with source locations before every statement.
+    pub fn with_breakpoints(mut self, enabled: bool) -> Self {
+        self.emit_breakpoints = enabled;
+        self
+    }
+
+    /// Selects the functions that start with a profiling instruction named after the function.
+    pub fn with_profiling(mut self, profiling: Profiling) -> Self {
+        self.profiling = profiling;
+        self
+    }
+
+    /// Includes the `@test` functions and the `testonly` definitions in the output.
+    /// They are stripped by default.
+    pub fn with_test_code(mut self, enabled: bool) -> Self {
+        self.include_test_code = enabled;
+        self
+    }
+
     pub fn compile(mut self, modules: Vec<SourceModule>, files: &Files) -> Result<CompilationOutput, Error> {
         let funcs = self.compile_modules(modules, files, true, false)?;
         self.finish(funcs, files)
@@ -99,16 +148,58 @@ impl<'a> CompilationUnit<'a> {
         self.typecheck(Self::parse(files)?, files, desugar, permissive)
     }
 
+    /// Resolves the declarations and the annotation targets of the sources against the pool without
+    /// typechecking any function bodies. This is useful for checking whether existing sources are still
+    /// compatible with a new version of the pool.
+    /// Every failure is reported as a diagnostic instead of ending the check.
+    pub fn check_targets(mut self, modules: Vec<SourceModule>, files: &Files) -> Result<Vec<Diagnostic>, Error> {
+        self.checking_targets = true;
+        for module in &modules {
+            self.check_native_signatures(module)?;
+        }
+        self.define_modules(modules, files, false)?;
+        Ok(self.diagnostics)
+    }
+
+    pub fn check_targets_files(self, files: &Files) -> Result<Vec<Diagnostic>, Error> {
+        self.check_targets(Self::parse(files)?, files)
+    }
+
+    pub fn check_targets_and_report(self, files: &Files) -> Result<Vec<Diagnostic>, Error> {
+        Self::report_result(self.check_targets_files(files), Vec::as_slice, files, |_| true)
+    }
+
     pub fn compile_and_report(self, files: &Files) -> Result<CompilationOutput, Error> {
-        match self.compile_files(files) {
+        self.compile_and_report_filtered(files, |_| true)
+    }
+
+    /// Works like [`Self::compile_and_report`], but only logs the diagnostics accepted by the filter.
+    /// The result is not affected by the filter, it fails if any of the diagnostics is fatal.
+    pub fn compile_and_report_filtered(
+        self,
+        files: &Files,
+        filter: impl FnMut(&Diagnostic) -> bool,
+    ) -> Result<CompilationOutput, Error> {
+        Self::report_result(self.compile_files(files), CompilationOutput::diagnostics, files, filter)
+    }
+
+    fn report_result<A>(
+        result: Result<A, Error>,
+        get_diagnostics: impl Fn(&A) -> &[Diagnostic],
+        files: &Files,
+        mut filter: impl FnMut(&Diagnostic) -> bool,
+    ) -> Result<A, Error> {
+        match result {
             Ok(output) => {
-                for diagnostic in &output.diagnostics {
-                    diagnostic.log(files);
+                let diagnostics = get_diagnostics(&output);
+                for diagnostic in diagnostics {
+                    if filter(diagnostic) {
+                        diagnostic.log(files);
+                    }
                 }
 
-                if output.diagnostics.iter().any(Diagnostic::is_fatal) {
-                    let spans = output
-                        .diagnostics
+                if diagnostics.iter().any(Diagnostic::is_fatal) {
+                    let spans = diagnostics
                         .iter()
                         .filter(|d| d.is_fatal())
                         .map(|d| (d.code(), d.span()))
@@ -120,7 +211,9 @@ impl<'a> CompilationUnit<'a> {
             }
             Err(err) => match Diagnostic::from_error(err) {
                 Ok(diagnostic) => {
-                    diagnostic.log(files);
+                    if filter(&diagnostic) {
+                        diagnostic.log(files);
+                    }
                     Err(Error::MultipleErrors(vec![(diagnostic.code(), diagnostic.span())]))
                 }
                 Err(other) => {
@@ -131,7 +224,7 @@ impl<'a> CompilationUnit<'a> {
         }
     }
 
-    fn parse(files: &Files) -> Result<Vec<SourceModule>, Error> {
+    pub(crate) fn parse(files: &Files) -> Result<Vec<SourceModule>, Error> {
         let mut modules = vec![];
         for file in files.files() {
             let parsed = parse_file(file).map_err(|err| {
@@ -150,9 +243,13 @@ impl<'a> CompilationUnit<'a> {
         desugar: bool,
         permissive: bool,
     ) -> Result<Vec<CompiledFunction>, Error> {
+        self.define_modules(modules, files, permissive)?;
+        self.compile_bodies(files, desugar, permissive)
+    }
+
+    fn define_modules(&mut self, modules: Vec<SourceModule>, files: &Files, permissive: bool) -> Result<(), Error> {
         let mut seen_funcs = HashSet::new();
         let mut queue = Vec::with_capacity(modules.len());
-        let mut compiled_funcs = Vec::new();
 
         let cte = cte::Context::new(modules.iter().filter_map(|m| m.path.clone()).collect());
 
@@ -161,6 +258,9 @@ impl<'a> CompilationUnit<'a> {
             let mut slots = Vec::with_capacity(module.entries.len());
 
             for entry in module.entries {
+                if !self.include_test_code && entry.is_test_only() {
+                    continue;
+                }
                 if eval_conditions(&cte, entry.annotations())? {
                     match self.define_symbol(entry, &path, permissive) {
                         Ok(slot) => slots.push(slot),
@@ -217,6 +317,16 @@ impl<'a> CompilationUnit<'a> {
                         } else {
                             None
                         };
+                        let is_test = source.declaration.has_annotation(AnnotationKind::Test);
+                        if is_test && !parent.is_undefined() {
+                            let err = Cause::UnsupportedFeature("test methods").with_span(pos);
+                            self.report(err)?;
+                        } else if is_test && !source.parameters.is_empty() {
+                            let err = Cause::UnsupportedFeature("test functions with parameters").with_span(pos);
+                            self.report(err)?;
+                        } else if is_test {
+                            self.test_functions.push(index);
+                        }
                         let opt_loc = files.lookup(source.declaration.span);
                         let source_ref = opt_loc.map(|loc| self.define_source_ref(loc)).unwrap_or_default();
                         let spec = FunctionSpec {
@@ -254,6 +364,16 @@ impl<'a> CompilationUnit<'a> {
                 }
             }
         }
+        Ok(())
+    }
+
+    fn compile_bodies(
+        &mut self,
+        files: &Files,
+        desugar: bool,
+        permissive: bool,
+    ) -> Result<Vec<CompiledFunction>, Error> {
+        let mut compiled_funcs = Vec::new();
 
         for default in self.field_defaults.drain(..) {
             let diagnostics = Self::compile_default(default, self.pool)?;
@@ -289,14 +409,28 @@ impl<'a> CompilationUnit<'a> {
     }
 
     fn finish(self, functions: Vec<CompiledFunction>, files: &Files) -> Result<CompilationOutput, Error> {
+        let mut compiled = Vec::with_capacity(functions.len());
+        let mut statements = HashMap::with_capacity(functions.len());
         for mut func in functions {
-            let code = Assembler::from_body(func.code, files, &mut func.scope, self.pool)?;
+            compiled.push(func.index);
+            let is_annotated = self.profiled_functions.contains(&func.index);
+            let profiling = self.profiling.function_name(func.index, is_annotated, self.pool)?;
+            let (code, spans) = Assembler::from_body(
+                func.code,
+                files,
+                &mut func.scope,
+                self.pool,
+                self.emit_breakpoints,
+                profiling,
+            )?;
             let function = self.pool.function_mut(func.index)?;
             function.code = code;
             function.locals = func.locals;
+            statements.insert(func.index, spans);
         }
 
         // swap proxies with the functions they wrap
+        let mut wrapped_functions = Vec::with_capacity(self.proxies.len());
         for (wrapped, proxy) in self.proxies {
             let wrapped_name = self.pool.definition(wrapped)?.name;
             let proxy_name = self.pool.definition(proxy)?.name;
@@ -309,6 +443,18 @@ impl<'a> CompilationUnit<'a> {
             self.pool.rename(wrapped, proxy_name);
             self.pool.rename(proxy, wrapped_name);
             self.pool.swap_definition(wrapped, proxy);
+            if let Some(spans) = statements.remove(&wrapped) {
+                statements.insert(proxy, spans);
+            }
+            compiled.extend([wrapped, proxy]);
+            wrapped_functions.push((wrapped, proxy));
+        }
+
+        if self.verify_bytecode {
+            let verifier = Verifier::new(self.pool);
+            for index in compiled {
+                verifier.verify_function(index)?;
+            }
         }
 
         let mut diagnostics = self.diagnostics;
@@ -320,6 +466,9 @@ impl<'a> CompilationUnit<'a> {
         Ok(CompilationOutput {
             diagnostics,
             source_refs,
+            wrapped_functions,
+            test_functions: self.test_functions,
+            statements,
         })
     }
 
@@ -421,6 +570,51 @@ impl<'a> CompilationUnit<'a> {
         }
     }
 
+    fn check_native_signatures(&mut self, module: &SourceModule) -> Result<(), Error> {
+        let path = module.path.clone().unwrap_or(ModulePath::EMPTY);
+        for entry in &module.entries {
+            let SourceEntry::Function(source) = entry else {
+                continue;
+            };
+            let decl = &source.declaration;
+            if !decl.qualifiers.contain(Qualifier::Native) || !decl.annotations.is_empty() {
+                continue;
+            }
+            // natives that don't exist in the pool yet could be provided by a plugin
+            let Ok(Symbol::Functions(candidates)) = self.symbols.get_symbol(&path.with_child(decl.name.clone())) else {
+                continue;
+            };
+            let name = path.with_function(FunctionSignature::from_source(source)).render();
+            let matches = candidates
+                .iter()
+                .find(|(idx, _)| {
+                    self.pool
+                        .def_name(*idx)
+                        .map_or(false, |str| str.as_ref() == name.as_ref())
+                })
+                .map_or(Ok(false), |(idx, _)| self.native_signature_matches(*idx, source))
+                .with_span(decl.span);
+            match matches {
+                Ok(true) => {}
+                Ok(false) => self.report(Cause::SignatureMismatch(decl.name.clone()).with_span(decl.span))?,
+                Err(err) => self.report(err)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn native_signature_matches(&self, index: PoolIndex<Function>, source: &FunctionSource) -> Result<bool, Cause> {
+        let fun = self.pool.function(index)?;
+        let return_type = fun.return_type.map(|typ| self.pool.def_name(typ)).transpose()?;
+        let expected_return = source.type_.as_ref().filter(|typ| **typ != TypeName::VOID);
+        let mut params_match = fun.parameters.len() == source.parameters.len();
+        for (param, param_source) in fun.parameters.iter().zip(&source.parameters) {
+            let type_name = self.pool.def_name(self.pool.parameter(*param)?.type_)?;
+            params_match &= type_name.as_ref() == param_source.type_.repr().as_ref();
+        }
+        Ok(params_match && return_type.as_deref() == expected_return.map(TypeName::repr).as_deref())
+    }
+
     fn define_class(
         &mut self,
         class_idx: PoolIndex<Class>,
@@ -434,17 +628,22 @@ impl<'a> CompilationUnit<'a> {
         let is_class_native = is_import_only || source.qualifiers.contain(Qualifier::Native);
         let is_class_abstract = !is_struct && source.qualifiers.contain(Qualifier::Abstract);
         let is_class_final = source.qualifiers.contain(Qualifier::Final);
+        let is_test_only = source.qualifiers.contain(Qualifier::TestOnly);
 
         let flags = ClassFlags::new()
             .with_is_abstract(is_class_abstract)
             .with_is_final(is_class_final)
             .with_is_native(is_class_native)
             .with_is_import_only(is_import_only)
-            .with_is_struct(is_struct);
+            .with_is_struct(is_struct)
+            .with_is_test_only(is_test_only);
         let mut functions = vec![];
         let mut fields = vec![];
 
         for member in source.members {
+            if !self.include_test_code && member.is_test_only() {
+                continue;
+            }
             match member {
                 MemberSource::Function(fun) => {
                     if is_struct && !fun.declaration.qualifiers.contain(Qualifier::Static) {
@@ -471,14 +670,16 @@ impl<'a> CompilationUnit<'a> {
                         source: fun,
                     };
 
-                    self.define_function(spec, scope)?;
+                    let res = self.define_function(spec, scope);
+                    self.report_member_error(res)?;
                     functions.push(fun_idx);
                 }
                 MemberSource::Field(let_) => {
                     let name_idx = self.pool.names.add(let_.declaration.name.to_heap());
                     let field_idx = self.pool.stub_definition(name_idx);
 
-                    self.define_field(field_idx, class_idx, flags, visibility, let_, scope)?;
+                    let res = self.define_field(field_idx, class_idx, flags, visibility, let_, scope);
+                    self.report_member_error(res)?;
                     fields.push(field_idx);
                 }
             }
@@ -524,6 +725,10 @@ impl<'a> CompilationUnit<'a> {
         let is_static = decl.qualifiers.contain(Qualifier::Static) || spec.class_idx.is_undefined();
         let is_callback = decl.qualifiers.contain(Qualifier::Callback);
 
+        if decl.has_annotation(AnnotationKind::Profile) {
+            self.profiled_functions.insert(spec.fun_idx);
+        }
+
         if is_native && spec.class_flags.map_or(false, |f| !f.is_native()) {
             self.report(Cause::UnexpectedNative.with_span(spec.source.declaration.span))?;
         }
@@ -643,7 +848,8 @@ impl<'a> CompilationUnit<'a> {
         let flags = FieldFlags::new()
             .with_is_browsable(true)
             .with_is_native(is_native)
-            .with_is_persistent(is_persistent);
+            .with_is_persistent(is_persistent)
+            .with_is_test_only(decl.is_test_only());
 
         let attributes = decl
             .annotations
@@ -780,7 +986,10 @@ impl<'a> CompilationUnit<'a> {
                         .resolve_method(name.clone(), target_class_idx, self.pool)
                         .with_span(ann.span)?
                         .by_id(&sig, self.pool)
-                        .ok_or_else(|| Cause::MethodNotFound(name, class_name.clone()).with_span(ann.span))?;
+                        .ok_or_else(|| {
+                            let cause = Cause::MethodNotFound(name.clone(), class_name.clone());
+                            self.unmatched_target(cause, name, ann.span)
+                        })?;
 
                     let wrapped_idx = if let Some(wrapped) = self.wrappers.get(&fun_idx) {
                         *wrapped
@@ -823,7 +1032,10 @@ impl<'a> CompilationUnit<'a> {
                         .resolve_method(name.clone(), target_class_idx, self.pool)
                         .with_span(ann.span)?
                         .by_id(&sig, self.pool)
-                        .ok_or_else(|| Cause::MethodNotFound(name, class_name.clone()).with_span(ann.span))?;
+                        .ok_or_else(|| {
+                            let cause = Cause::MethodNotFound(name.clone(), class_name.clone());
+                            self.unmatched_target(cause, name, ann.span)
+                        })?;
                     let base = self.pool.function(fun_idx).ok().and_then(|fun| fun.base_method);
                     let slot = Slot::Function {
                         index: fun_idx,
@@ -842,7 +1054,7 @@ impl<'a> CompilationUnit<'a> {
                         .resolve_function(name.clone())
                         .with_span(ann.span)?
                         .by_id(&sig, self.pool)
-                        .ok_or_else(|| Cause::FunctionNotFound(name).with_span(ann.span))?;
+                        .ok_or_else(|| self.unmatched_target(Cause::FunctionNotFound(name.clone()), name, ann.span))?;
 
                     let slot = Slot::Function {
                         index: fun_idx,
@@ -866,6 +1078,14 @@ impl<'a> CompilationUnit<'a> {
                         _ => return Err(Cause::ClassNotFound(class_name.clone()).with_span(ann.span)),
                     };
                     let class = self.pool.class(target_class_idx)?;
+                    let is_defined = class.functions.iter().any(|fun| {
+                        self.pool
+                            .def_name(*fun)
+                            .map_or(false, |name| name.as_ref() == sig.as_ref())
+                    });
+                    if is_defined {
+                        self.diagnostics.push(Diagnostic::AddedMethodConflict(ann.span));
+                    }
                     let base_method = if class.base != PoolIndex::UNDEFINED {
                         let base = self.pool.class(class.base)?;
                         base.functions
@@ -890,7 +1110,11 @@ impl<'a> CompilationUnit<'a> {
                     };
                     return Ok(slot);
                 }
-                AnnotationKind::AddField | AnnotationKind::If | AnnotationKind::RuntimeProperty => {}
+                AnnotationKind::AddField
+                | AnnotationKind::If
+                | AnnotationKind::RuntimeProperty
+                | AnnotationKind::Test
+                | AnnotationKind::Profile => {}
             }
         }
 
@@ -1036,7 +1260,7 @@ impl<'a> CompilationUnit<'a> {
         } else {
             call
         };
-        let code = Assembler::from_body(Seq::new(vec![expr]), files, scope, pool)?;
+        let (code, _) = Assembler::from_body(Seq::new(vec![expr]), files, scope, pool, false, None)?;
 
         let compiled = Function {
             code,
@@ -1174,6 +1398,24 @@ impl<'a> CompilationUnit<'a> {
         self.diagnostics.push(Diagnostic::from_error(err)?);
         Ok(())
     }
+
+    // an annotation target that exists under a different signature is reported as a signature mismatch
+    // when checking targets, compilation keeps the original causes since user hints are keyed by them
+    fn unmatched_target(&self, cause: Cause, name: Ident, span: Span) -> Error {
+        if self.checking_targets {
+            Cause::SignatureMismatch(name).with_span(span)
+        } else {
+            cause.with_span(span)
+        }
+    }
+
+    // when checking targets the remaining members are still checked after one of them fails
+    fn report_member_error(&mut self, result: Result<(), Error>) -> Result<(), Error> {
+        match result {
+            Err(err) if self.checking_targets => self.report(err),
+            result => result,
+        }
+    }
 }
 
 struct FunctionBody {
@@ -1193,6 +1435,54 @@ struct FieldDefault {
     scope: Scope,
 }
 
+/// Selects the functions instrumented with profiling instructions.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Profiling {
+    #[default]
+    Disabled,
+    /// Functions annotated with `@profile`.
+    Annotated,
+    /// Functions annotated with `@profile` and the ones with a qualified name containing the pattern.
+    Matching(String),
+    All,
+}
+
+impl Profiling {
+    /// Returns the profiling name of the function if it should be instrumented.
+    fn function_name(
+        &self,
+        index: PoolIndex<Function>,
+        is_annotated: bool,
+        pool: &ConstantPool,
+    ) -> Result<Option<String>, PoolError> {
+        match self {
+            Self::Disabled => return Ok(None),
+            Self::Annotated if !is_annotated => return Ok(None),
+            _ => {}
+        }
+        let name = qualified_name(index, pool)?;
+        let included = match self {
+            Self::Matching(pattern) => is_annotated || name.contains(pattern.as_str()),
+            _ => true,
+        };
+        Ok(included.then_some(name))
+    }
+}
+
+impl FromStr for Profiling {
+    type Err = Infallible;
+
+    fn from_str(str: &str) -> Result<Self, Self::Err> {
+        let res = match str {
+            "off" => Self::Disabled,
+            "annotated" => Self::Annotated,
+            "all" => Self::All,
+            pattern => Self::Matching(pattern.to_owned()),
+        };
+        Ok(res)
+    }
+}
+
 pub struct CompiledFunction {
     pub index: PoolIndex<Function>,
     pub code: Seq<TypedAst>,
@@ -1262,6 +1552,9 @@ fn eval_conditions(cte: &cte::Context, anns: &[Annotation]) -> Result<bool, Erro
 pub struct CompilationOutput {
     diagnostics: Vec<Diagnostic>,
     source_refs: Vec<SourceRef>,
+    wrapped_functions: Vec<(PoolIndex<Function>, PoolIndex<Function>)>,
+    test_functions: Vec<PoolIndex<Function>>,
+    statements: HashMap<PoolIndex<Function>, Vec<(Location, Span)>>,
 }
 
 impl CompilationOutput {
@@ -1273,6 +1566,21 @@ impl CompilationOutput {
         &self.source_refs
     }
 
+    /// Returns the pairs of wrapped functions and the functions that hold their original bodies.
+    pub fn wrapped_functions(&self) -> &[(PoolIndex<Function>, PoolIndex<Function>)] {
+        &self.wrapped_functions
+    }
+
+    /// Returns the `@test` functions, they're only included when compiling with test code enabled.
+    pub fn test_functions(&self) -> &[PoolIndex<Function>] {
+        &self.test_functions
+    }
+
+    /// Creates a map from the bytecode locations of the compiled functions to their source locations.
+    pub fn line_map(&self, pool: &ConstantPool, files: &Files) -> Result<LineMap, PoolError> {
+        LineMap::new(&self.statements, pool, files)
+    }
+
     pub fn into_diagnostics(self) -> Vec<Diagnostic> {
         self.diagnostics
     }
@@ -0,0 +1,117 @@
+use itertools::Itertools;
+use redscript::asm::{assemble, disassemble, patch_function, qualified_name};
+use redscript::bytecode::Instr;
+use redscript::verify::verify_pool;
+
+#[allow(unused)]
+mod utils;
+
+use utils::compiled;
+
+#[test]
+fn round_trip_assembly() {
+    let sources = "
+        native func OperatorLess(a: Int32, b: Int32) -> Bool
+        native func OperatorAssignAdd(out l: Int32, r: Int32) -> Int32
+
+        enum Direction {
+            Left = 0,
+            Right = 1,
+        }
+
+        class Counter {
+            let count: Int32;
+            let label: String;
+
+            func Describe(dir: Direction) -> String {
+                if this.count < 10 {
+                    let i = 1.5;
+                    return \"quoted \\\"text\\\"\";
+                } else {
+                    let i = n\"Name\";
+                    return this.label;
+                }
+            }
+
+            func Check(arr: array<Int32>) -> Bool {
+                let total = 0;
+                for item in arr {
+                    total += item;
+                }
+                switch total {
+                    case 1:
+                        return Equals(Direction.Left, Direction.Right);
+                    default:
+                        return arr[0] < total ? true : false;
+                }
+            }
+        }";
+
+    let (mut pool, _) = compiled(vec![sources]).unwrap();
+
+    let functions = pool
+        .definitions()
+        .filter(|(_, def)| def.value.as_function().map_or(false, |fun| !fun.code.is_empty()))
+        .map(|(idx, _)| idx.cast())
+        .collect_vec();
+    assert!(!functions.is_empty());
+
+    for index in functions {
+        let fun = pool.function(index).unwrap().clone();
+        let listing = disassemble(&fun, &pool).unwrap();
+        let (code, locals) = assemble(&listing, index, &mut pool).unwrap();
+        assert_eq!(code, fun.code, "{}", listing);
+        assert_eq!(locals, fun.locals, "{}", listing);
+    }
+}
+
+#[test]
+fn patch_function_with_assembly() {
+    let sources = "
+        native func OperatorAdd(a: Int32, b: Int32) -> Int32
+
+        func Testing(a: Int32) -> Int32 {
+            return a;
+        }";
+
+    let (mut pool, _) = compiled(vec![sources]).unwrap();
+    let index = pool
+        .definitions()
+        .find(|(idx, _)| qualified_name(*idx, &pool).map_or(false, |name| name == "Testing;Int32"))
+        .map(|(idx, _)| idx.cast())
+        .unwrap();
+
+    let listing = "
+        .param a: Int32
+        .local tmp: Int32   # a new local
+          assign
+          local tmp
+          invokestatic +26 0 OperatorAdd;Int32Int32;Int32 0
+          param a
+          i32one
+          paramend
+          return
+          local tmp
+        ";
+    patch_function(listing, index, &mut pool).unwrap();
+    assert!(verify_pool(&pool).is_empty());
+
+    let fun = pool.function(index).unwrap();
+    assert_eq!(fun.locals.len(), 1);
+    assert!(matches!(
+        &fun.code.0[..],
+        &[
+            Instr::Assign,
+            Instr::Local(_),
+            Instr::InvokeStatic(_, 0, _, 0),
+            Instr::Param(_),
+            Instr::I32One,
+            Instr::ParamEnd,
+            Instr::Return,
+            Instr::Local(_)
+        ]
+    ));
+
+    let err = assemble("  jump L0", index, &mut pool).unwrap_err();
+    assert_eq!(err.to_string(), "label L0 is never declared at line 1");
+}
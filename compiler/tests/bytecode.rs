@@ -269,6 +269,39 @@ fn compile_variant_conversions() {
     TestContext::compiled(vec![sources]).unwrap().run("Testing", check);
 }
 
+#[test]
+fn compile_variant_guard() {
+    let sources = "
+        func Testing(v: Variant) {
+            if let a = FromVariant<ref<A>>(v) {
+                a.Testing();
+            }
+        }
+
+        class A {
+            final func Testing() {}
+        }
+        ";
+
+    let check = check_code![
+        pat!(JumpIfFalse(_)),
+        pat!(Equals(_)),
+        pat!(VariantTypeName),
+        mem!(Param(v)),
+        pat!(NameConst(_)),
+        pat!(Assign),
+        mem!(Local(a)),
+        mem!(FromVariant(typ)),
+        mem!(Param(v)),
+        pat!(Context(_)),
+        mem!(Local(a)),
+        pat!(InvokeStatic(_, _, _, 0)),
+        pat!(ParamEnd),
+        pat!(Nop)
+    ];
+    TestContext::compiled(vec![sources]).unwrap().run("Testing", check);
+}
+
 #[test]
 fn compile_variant_intrinsics() {
     let sources = "
@@ -357,6 +390,37 @@ fn compile_implicit_conversions() {
     TestContext::compiled(vec![sources]).unwrap().run("Testing", check);
 }
 
+#[test]
+fn compile_deferred_local_conversions() {
+    let sources = "
+        func Testing(cond: Bool, w: wref<A>) {
+            let a;
+            if cond {
+                a = w;
+            } else {
+                a = null;
+            }
+        }
+
+        class A {}
+        ";
+
+    let check = check_code![
+        pat!(JumpIfFalse(_)),
+        mem!(Param(cond)),
+        pat!(Assign),
+        mem!(Local(a)),
+        mem!(Param(w)),
+        pat!(Jump(_)),
+        pat!(Assign),
+        mem!(Local(a)),
+        pat!(RefToWeakRef),
+        pat!(Null),
+        pat!(Nop)
+    ];
+    TestContext::compiled(vec![sources]).unwrap().run("Testing", check);
+}
+
 #[test]
 fn compile_switch_case() {
     let sources = "
@@ -398,6 +462,121 @@ fn compile_switch_case() {
     TestContext::compiled(vec![sources]).unwrap().run("Testing", check);
 }
 
+#[test]
+fn compile_string_match() {
+    let sources = r#"
+        func Testing(str: String) -> Int32 {
+            return match str { "a" => 1, "b" => 2, default => 0 };
+        }
+        "#;
+
+    let check = check_code![
+        pat!(JumpIfFalse(Offset { value: 44 })),
+        pat!(Equals(_)),
+        mem!(Param(str)),
+        pat!(StringConst(_)),
+        pat!(Assign),
+        mem!(Local(res)),
+        pat!(I32Const(1)),
+        pat!(Jump(Offset { value: 62 })),
+        pat!(JumpIfFalse(Offset { value: 44 })),
+        pat!(Equals(_)),
+        mem!(Param(str)),
+        pat!(StringConst(_)),
+        pat!(Assign),
+        mem!(Local(res)),
+        pat!(I32Const(2)),
+        pat!(Jump(Offset { value: 18 })),
+        pat!(Assign),
+        mem!(Local(res)),
+        pat!(I32Const(0)),
+        pat!(Return),
+        mem!(Local(res)),
+        pat!(Nop)
+    ];
+    TestContext::compiled(vec![sources]).unwrap().run("Testing", check);
+}
+
+#[test]
+fn compile_null_coalescing() {
+    let sources = "
+        func Testing(a: ref<A>) -> ref<A> = a ?? new A()
+
+        class A {}
+        ";
+
+    let check = check_code![
+        pat!(Assign),
+        mem!(Local(res)),
+        mem!(Param(a)),
+        pat!(JumpIfFalse(_)),
+        pat!(RefToBool),
+        mem!(Local(res)),
+        pat!(Jump(_)),
+        pat!(Assign),
+        mem!(Local(res)),
+        pat!(New(_)),
+        pat!(Return),
+        mem!(Local(res)),
+        pat!(Nop)
+    ];
+    TestContext::compiled(vec![sources]).unwrap().run("Testing", check);
+}
+
+#[test]
+fn compile_expanded_compound_assignment() {
+    let sources = "
+        func Testing(val: Int32) -> Int32 {
+            val %= 4;
+            return val;
+        }
+
+        native func OperatorModulo(l: Int32, r: Int32) -> Int32
+        ";
+
+    let check = check_code![
+        pat!(Assign),
+        mem!(Param(val)),
+        pat!(InvokeStatic(_, 0, _, 0)),
+        mem!(Param(val)),
+        pat!(I32Const(4)),
+        pat!(ParamEnd),
+        pat!(Return),
+        mem!(Param(val)),
+        pat!(Nop)
+    ];
+    TestContext::compiled(vec![sources]).unwrap().run("Testing", check);
+}
+
+#[test]
+fn compile_shift_without_operators() {
+    let sources = "
+        func Testing(val: Int32) -> Int32 {
+            val >>= 1;
+            return val << 3;
+        }
+
+        native func OperatorMultiply(l: Int32, r: Int32) -> Int32
+        native func OperatorDivide(l: Int32, r: Int32) -> Int32
+        ";
+
+    let check = check_code![
+        pat!(Assign),
+        mem!(Param(val)),
+        pat!(InvokeStatic(_, 0, _, 0)),
+        mem!(Param(val)),
+        pat!(I32Const(2)),
+        pat!(ParamEnd),
+        pat!(Return),
+        pat!(InvokeStatic(_, 0, _, 0)),
+        mem!(Param(val)),
+        pat!(I32Const(8)),
+        pat!(ParamEnd),
+        pat!(Nop)
+    ];
+    TestContext::compiled(vec![sources]).unwrap().run("Testing", check);
+}
+
 #[test]
 fn compile_ternary_op() {
     let sources = "
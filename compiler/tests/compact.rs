@@ -0,0 +1,98 @@
+use itertools::Itertools;
+use redscript::asm::{disassemble, qualified_name};
+use redscript::bundle::{ConstantPool, PoolIndex};
+use redscript::bytecode::Instr;
+use redscript::compact::{compact_pool, CompactionStats};
+use redscript::verify::verify_pool;
+
+#[allow(unused)]
+mod utils;
+
+use utils::{compiled, recompiled};
+
+#[test]
+fn compact_pool_after_replacement() {
+    let base = "
+        native func OperatorAdd(a: Int32, b: Int32) -> Int32
+
+        class Player {
+            let health: Int32;
+
+            func Heal(amount: Int32) -> Int32 {
+                let previous = this.health;
+                let message = \"healed\";
+                this.health = previous + amount;
+                return previous;
+            }
+        }";
+    let sources = "
+        @replaceMethod(Player)
+        func Heal(amount: Int32) -> Int32 {
+            return amount;
+        }
+
+        @wrapMethod(Player)
+        func Heal(amount: Int32) -> Int32 {
+            return wrappedMethod(amount);
+        }";
+
+    let (mut pool, _) = recompiled(vec![base], vec![sources]).unwrap();
+
+    let listings = |pool: &ConstantPool| {
+        pool.definitions()
+            .filter(|(_, def)| def.value.as_function().map_or(false, |fun| !fun.code.is_empty()))
+            .map(|(idx, def)| {
+                let name = qualified_name(idx, pool).unwrap();
+                (name, disassemble(def.value.as_function().unwrap(), pool).unwrap())
+            })
+            .sorted()
+            .collect_vec()
+    };
+    let before = listings(&pool);
+    let count = pool.definitions().count();
+
+    let stats = compact_pool(&mut pool).unwrap();
+    assert!(stats.definitions > 0);
+    assert_eq!(pool.definitions().count(), count - stats.definitions);
+    assert_eq!(pool.strings.get_index("healed"), None);
+    assert!(verify_pool(&pool).is_empty());
+    assert_eq!(listings(&pool), before);
+
+    assert_eq!(compact_pool(&mut pool).unwrap(), CompactionStats::default());
+}
+
+#[test]
+fn compact_pool_with_undefined_constants() {
+    let sources = "
+        func Apple() -> TweakDBID {
+            return t\"Items.Apple\";
+        }";
+
+    let (mut pool, _) = compiled(vec![sources]).unwrap();
+
+    let index = pool
+        .definitions()
+        .find(|(_, def)| {
+            def.value.as_function().map_or(false, |fun| {
+                fun.code.0.iter().any(|instr| matches!(instr, Instr::TweakDbIdConst(_)))
+            })
+        })
+        .map(|(idx, _)| idx.cast())
+        .unwrap();
+    for instr in &mut pool.function_mut(index).unwrap().code.0 {
+        if let Instr::TweakDbIdConst(idx) = instr {
+            *idx = PoolIndex::UNDEFINED;
+        }
+    }
+
+    compact_pool(&mut pool).unwrap();
+    assert!(verify_pool(&pool).is_empty());
+    assert!(pool.definitions().any(|(_, def)| {
+        def.value.as_function().map_or(false, |fun| {
+            fun.code
+                .0
+                .iter()
+                .any(|instr| matches!(instr, Instr::TweakDbIdConst(idx) if idx.is_undefined()))
+        })
+    }));
+}
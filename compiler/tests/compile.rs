@@ -1,12 +1,20 @@
+use std::io::Cursor;
+
 use itertools::Itertools;
+use redscript::bundle::{ConstantPool, ScriptBundle};
+use redscript::bytecode::{Instr, StartProfiling};
 use redscript::definition::{ClassFlags, Property};
 
 #[allow(unused)]
 mod utils;
 
 use redscript_compiler::diagnostics::Diagnostic;
+use redscript_compiler::doc::Documentation;
 use redscript_compiler::error::Cause;
-use utils::{check_class_flags, compiled};
+use redscript_compiler::line_map::LineMap;
+use redscript_compiler::source_map::Files;
+use redscript_compiler::unit::{CompilationUnit, Profiling};
+use utils::{check_class_flags, checked_targets, compiled, compiled_with_profiling, compiled_with_tests, recompiled};
 
 #[test]
 fn compile_simple_class() {
@@ -146,6 +154,182 @@ fn compile_lub_types() {
     assert!(matches!(&errs[..], &[]));
 }
 
+#[test]
+fn compile_deferred_local_types() {
+    let sources = "
+        func Testing(cond: Bool, w: wref<A>) {
+            let a;
+            if cond {
+                a = new B();
+            } else {
+                a = new C();
+            }
+            let b: ref<A> = a;
+
+            let c;
+            c = 1;
+            c = 2;
+
+            let d;
+            if cond {
+                d = w;
+            } else {
+                d = null;
+            }
+        }
+
+        class A {}
+        class B extends A {}
+        class C extends A {}
+    ";
+
+    let (_, errs) = compiled(vec![sources]).unwrap();
+    let errs = errs.into_iter().filter(Diagnostic::is_fatal).collect_vec();
+    assert!(matches!(&errs[..], &[]));
+}
+
+#[test]
+fn fail_on_uninferrable_local_types() {
+    let sources = "
+        func Unassigned() {
+            let a;
+        }
+
+        func UsedBeforeAssignment() -> Int32 {
+            let a;
+            let b = a;
+            a = 1;
+            return b;
+        }
+    ";
+
+    let (_, errs) = compiled(vec![sources]).unwrap();
+    assert!(matches!(
+        &errs[..],
+        &[
+            Diagnostic::CompileError(Cause::TypeAnnotationRequired, _),
+            Diagnostic::CompileError(Cause::TypeAnnotationRequired, _)
+        ]
+    ));
+}
+
+#[test]
+fn fail_on_deferred_local_widening_with_conversion() {
+    let sources = "
+        func Testing(cond: Bool, w: wref<A>) {
+            let a;
+            if cond {
+                a = null;
+            } else {
+                a = w;
+            }
+        }
+
+        class A {}
+    ";
+
+    let (_, errs) = compiled(vec![sources]).unwrap();
+    assert!(matches!(
+        &errs[..],
+        &[Diagnostic::CompileError(Cause::TypeAnnotationRequired, _)]
+    ));
+}
+
+#[test]
+fn compile_null_safe_ops() {
+    let sources = "
+        func Testing(a: ref<A>, w: wref<A>) {
+            let b: ref<A> = a ?? new A();
+            let c = w?.next ?? a;
+            let d: wref<A> = a?.weak;
+            a?.Run();
+            w?.Get()?.Run();
+        }
+
+        class A {
+            let next: ref<A>;
+            let weak: wref<A>;
+
+            func Run() {}
+            func Get() -> ref<A> = this.next
+        }
+    ";
+
+    let (_, errs) = compiled(vec![sources]).unwrap();
+    let errs = errs.into_iter().filter(Diagnostic::is_fatal).collect_vec();
+    assert!(matches!(&errs[..], &[]));
+}
+
+#[test]
+fn fail_on_shift_by_invalid_amount() {
+    let sources = "
+        func Variable(a: Int32, b: Int32) -> Int32 = a << b
+
+        func OutOfRange(a: Int32) -> Int32 = a >> 31
+
+        native func OperatorMultiply(l: Int32, r: Int32) -> Int32
+        native func OperatorDivide(l: Int32, r: Int32) -> Int32
+    ";
+
+    let (_, errs) = compiled(vec![sources]).unwrap();
+    assert!(matches!(
+        &errs[..],
+        &[
+            Diagnostic::CompileError(Cause::InvalidShiftAmount, _),
+            Diagnostic::CompileError(Cause::InvalidShiftAmount, _)
+        ]
+    ));
+}
+
+#[test]
+fn fail_on_null_safe_value_types() {
+    let sources = "
+        func Coalesce(a: Int32) -> Int32 = a ?? 1
+
+        func Access(a: ref<A>) -> Int32 = a?.x
+
+        class A {
+            let x: Int32;
+        }
+    ";
+
+    let (_, errs) = compiled(vec![sources]).unwrap();
+    assert!(matches!(
+        &errs[..],
+        &[
+            Diagnostic::CompileError(Cause::UnsupportedOperation(_, _), _),
+            Diagnostic::CompileError(Cause::UnsupportedOperation(_, _), _)
+        ]
+    ));
+}
+
+#[test]
+fn fail_on_read_of_local_before_its_type_is_inferred() {
+    let sources = "
+        func Testing(cond: Bool) {
+            let a;
+            if cond {
+                a = new B();
+                a.BOnly();
+            } else {
+                a = new C();
+            }
+        }
+
+        class A {}
+        class B extends A {
+            func BOnly() {}
+        }
+        class C extends A {}
+    ";
+
+    let (_, errs) = compiled(vec![sources]).unwrap();
+    assert!(matches!(
+        &errs[..],
+        &[Diagnostic::CompileError(Cause::TypeAnnotationRequired, _)]
+    ));
+}
+
 #[test]
 fn compile_casts() {
     let sources = "
@@ -218,6 +402,58 @@ fn fail_on_invalid_structs() {
     ));
 }
 
+#[test]
+fn compile_match() {
+    let sources = r#"
+        func Testing(str: String, name: CName, dir: Direction) {
+            let a = match str { "left" => 1, "right" => 2, default => 0 };
+            let b: ref<A> = match name { n"b" => new B(), n"c" => new C(), default => null };
+            let c = match dir { Direction.Left => "left", Direction.Right => "right" };
+            match str {
+                "log" => Log(c),
+            }
+        }
+
+        enum Direction {
+            Left = 0,
+            Right = 1,
+        }
+
+        class A {}
+        class B extends A {}
+        class C extends A {}
+
+        native func Log(str: String)
+    "#;
+
+    let (_, errs) = compiled(vec![sources]).unwrap();
+    let errs = errs.into_iter().filter(Diagnostic::is_fatal).collect_vec();
+    assert!(matches!(&errs[..], &[]));
+}
+
+#[test]
+fn fail_on_non_exhaustive_match() {
+    let sources = r#"
+        func Testing1(str: String) -> Int32 = match str { "left" => 1, "right" => 2 }
+
+        func Testing2(dir: Direction) -> Int32 = match dir { Direction.Left => 1 }
+
+        enum Direction {
+            Left = 0,
+            Right = 1,
+        }
+    "#;
+
+    let (_, errs) = compiled(vec![sources]).unwrap();
+    assert!(matches!(
+        &errs[..],
+        &[
+            Diagnostic::CompileError(Cause::NonExhaustiveMatch(_), _),
+            Diagnostic::CompileError(Cause::NonExhaustiveMatch(_), _)
+        ]
+    ));
+}
+
 #[test]
 fn report_unused_variables() {
     let sources = "
@@ -248,6 +484,23 @@ fn report_missing_return() {
     assert!(matches!(&errs[..], &[Diagnostic::MissingReturn(_)]));
 }
 
+#[test]
+fn report_unchecked_variant() {
+    let sources = "
+        func Unchecked(v: Variant) -> Int32 = FromVariant<Int32>(v)
+
+        func Checked(v: Variant) -> Int32 {
+            if let x = FromVariant<Int32>(v) {
+                return x;
+            }
+            return 0;
+        }
+    ";
+
+    let (_, errs) = compiled(vec![sources]).unwrap();
+    assert!(matches!(&errs[..], &[Diagnostic::UncheckedVariant(_)]));
+}
+
 #[test]
 fn compile_defaults() {
     let sources = r#"
@@ -298,3 +551,264 @@ fn compile_defaults() {
         ]
     );
 }
+
+#[test]
+fn check_annotation_targets() {
+    let base = "
+        public class Player {
+            let health: Float;
+
+            public func GetHealth() -> Float {
+                return this.health;
+            }
+
+            public func Heal(amount: Float) {
+                this.health = amount;
+            }
+        }
+
+        public native func GetGameTime() -> Float;
+
+        public native func SetTimeDilation(dilation: Float);
+        ";
+    let base_module = "
+        module Game
+
+        public native func GetVersion() -> Int32;
+        ";
+    let sources = "
+        @replaceMethod(Player)
+        public func GetHealth() -> Float {
+            return \"bodies are not typechecked\";
+        }
+
+        @wrapMethod(Player)
+        public func Heal(amount: Int32) {
+            wrappedMethod(amount);
+        }
+
+        @addMethod(Player)
+        public func GetHealth() -> Float {
+            return 0.0;
+        }
+
+        @addMethod(Enemy)
+        public func GetHealth() -> Float {
+            return 0.0;
+        }
+
+        @addField(Player)
+        let health: Float;
+
+        public native func GetGameTime() -> Int32;
+
+        public native func SetTimeDilation(dilation: Float);
+
+        public native func GetPluginVersion() -> Int32;
+        ";
+    let sources_module = "
+        module Game
+
+        public native func GetVersion() -> Float;
+        ";
+
+    let errs = checked_targets(vec![base, base_module], vec![sources, sources_module]).unwrap();
+    assert!(matches!(
+        &errs[..],
+        &[
+            Diagnostic::CompileError(Cause::SignatureMismatch(_), _),
+            Diagnostic::CompileError(Cause::SignatureMismatch(_), _),
+            Diagnostic::CompileError(Cause::SignatureMismatch(_), _),
+            Diagnostic::AddedMethodConflict(_),
+            Diagnostic::CompileError(Cause::UnresolvedReference(_), _),
+            Diagnostic::FieldConflict(_),
+        ]
+    ));
+}
+
+#[test]
+fn fail_on_unmatched_annotation_targets() {
+    let base = "
+        public class Player {
+            public func Heal(amount: Float) {}
+        }
+
+        public func SetTimeDilation(dilation: Float) {}
+        ";
+    let sources = "
+        @wrapMethod(Player)
+        public func Heal(amount: Int32) {
+            wrappedMethod(amount);
+        }
+
+        @replaceGlobal
+        public func SetTimeDilation(dilation: Int32) {}
+        ";
+
+    let (_, errs) = recompiled(vec![base], vec![sources]).unwrap();
+    assert!(matches!(
+        &errs[..],
+        &[
+            Diagnostic::CompileError(Cause::MethodNotFound(_, _), _),
+            Diagnostic::CompileError(Cause::FunctionNotFound(_), _),
+        ]
+    ));
+}
+
+#[test]
+fn compile_test_code() {
+    let sources = "
+        testonly class Fixture {
+            let value: Int32;
+        }
+
+        class Counter {
+            let count: Int32;
+            testonly let history: array<Int32>;
+
+            testonly func Reset() {}
+        }
+
+        @test
+        func TestCounter() {}
+
+        func Regular() {}
+        ";
+
+    let (pool, diagnostics) = compiled(vec![sources]).unwrap();
+    assert!(!diagnostics.iter().any(Diagnostic::is_fatal));
+    let names = pool
+        .definitions()
+        .filter_map(|(_, def)| pool.names.get(def.name).ok())
+        .collect_vec();
+    for stripped in ["Fixture", "history", "Reset;", "TestCounter;"] {
+        assert!(
+            !names.iter().any(|name| name.as_ref() == stripped),
+            "{stripped} was not stripped"
+        );
+    }
+    assert!(names.iter().any(|name| name.as_ref() == "Regular;"));
+
+    let (pool, output) = compiled_with_tests(vec![sources]).unwrap();
+    check_class_flags(&pool, "Fixture", ClassFlags::new().with_is_test_only(true)).unwrap();
+    let names = output
+        .test_functions()
+        .iter()
+        .map(|idx| pool.def_name(*idx).unwrap())
+        .collect_vec();
+    assert_eq!(names, vec!["TestCounter;".into()]);
+}
+
+#[test]
+fn fail_on_test_with_parameters() {
+    let sources = "
+        @test
+        func TestWithParam(a: Int32) {}
+        ";
+
+    let (_, output) = compiled_with_tests(vec![sources]).unwrap();
+    assert!(output.test_functions().is_empty());
+    assert!(matches!(
+        output.diagnostics(),
+        &[Diagnostic::CompileError(
+            Cause::UnsupportedFeature("test functions with parameters"),
+            _
+        )]
+    ));
+}
+
+#[test]
+fn compile_profiled_functions() {
+    let sources = "
+        @profile
+        func Profiled() {}
+
+        func Regular() {}
+        ";
+
+    let profiled_functions = |pool: &ConstantPool| {
+        pool.definitions()
+            .filter_map(|(_, def)| match def.value.as_function()?.code.0.first()? {
+                Instr::StartProfiling(profiling) => Some(profiling.as_ref().clone()),
+                _ => None,
+            })
+            .collect_vec()
+    };
+
+    let pool = compiled_with_profiling(vec![sources], Profiling::Annotated).unwrap();
+    assert_eq!(
+        profiled_functions(&pool),
+        vec![StartProfiling("Profiled;".to_owned(), 1)]
+    );
+
+    let pool = compiled_with_profiling(vec![sources], Profiling::Matching("Regular".to_owned())).unwrap();
+    assert_eq!(
+        profiled_functions(&pool),
+        vec![
+            StartProfiling("Profiled;".to_owned(), 1),
+            StartProfiling("Regular;".to_owned(), 1)
+        ]
+    );
+}
+
+#[test]
+fn compile_line_map() {
+    let sources = "func Sum(a: Int32, b: Int32) -> Int32 {
+    let c = a + b;
+    return c;
+}
+";
+    let mut files = Files::new();
+    files.add("sum.reds".into(), sources.to_owned());
+    let mut bundle = ScriptBundle::load(&mut Cursor::new(utils::PREDEF)).unwrap();
+    let output = CompilationUnit::new_with_defaults(&mut bundle.pool)
+        .unwrap()
+        .compile_files(&files)
+        .unwrap();
+
+    let mut buf = vec![];
+    output.line_map(&bundle.pool, &files).unwrap().save(&mut buf).unwrap();
+    let line_map = LineMap::load(&mut Cursor::new(buf)).unwrap();
+
+    let locations = [0, 1000].map(|offset| line_map.lookup("Sum", offset).map(|loc| loc.to_string()).collect_vec());
+    assert_eq!(locations, [vec!["sum.reds:2:5"], vec!["sum.reds:3:5"]]);
+    assert_eq!(line_map.lookup("Sum;Int32Int32", 0).count(), 1);
+}
+
+#[test]
+fn generate_documentation() {
+    let sources = "module Utils.Math
+
+/// Adds two numbers.
+public func Add(a: Int32, b: Int32) -> Int32 = a + b;
+
+public class Counter {
+    /** The current count. */
+    public let count: Int32;
+
+    private func Reset() {}
+}
+";
+    let mut files = Files::new();
+    files.add("math.reds".into(), sources.to_owned());
+    let doc = Documentation::from_files(&files).unwrap();
+    assert_eq!(doc.modules().collect_vec(), ["Utils.Math"]);
+
+    let mut buf = vec![];
+    doc.write_markdown("Utils.Math", &mut buf).unwrap();
+    assert_eq!(
+        String::from_utf8(buf).unwrap(),
+        "# Utils.Math
+
+## `public func Add(a: Int32, b: Int32) -> Int32`
+
+Adds two numbers.
+
+## `public class Counter`
+
+### `public let count: Int32`
+
+The current count.
+"
+    );
+}
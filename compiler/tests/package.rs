@@ -0,0 +1,118 @@
+use std::io::Cursor;
+
+use itertools::Itertools;
+use redscript::asm::{disassemble, qualified_name};
+use redscript::bundle::ConstantPool;
+use redscript::package::{Linker, Package, PackageError};
+use redscript::verify::verify_pool;
+
+#[allow(unused)]
+mod utils;
+
+use utils::{compiled, packaged};
+
+#[test]
+fn link_package_into_base() {
+    let base = "
+        native func OperatorAdd(a: Int32, b: Int32) -> Int32
+
+        class Player {
+            let health: Int32;
+
+            func Heal(amount: Int32) -> Int32 {
+                let previous = this.health;
+                this.health = previous + amount;
+                return previous;
+            }
+        }";
+    let sources = "
+        @addField(Player)
+        let armor: Int32;
+
+        @addMethod(Player)
+        func Armor() -> Int32 {
+            return this.armor;
+        }
+
+        @wrapMethod(Player)
+        func Heal(amount: Int32) -> Int32 {
+            let bonus = this.Armor();
+            return wrappedMethod(amount + bonus);
+        }
+
+        class Shield {
+            func Block(player: ref<Player>) -> Int32 {
+                return player.Heal(1);
+            }
+        }";
+
+    let (base, compiled, package) = packaged(vec![base], vec![sources]).unwrap();
+    assert!(package.imports().any(|import| import == "Player::Heal;Int32"));
+
+    let mut encoded = Cursor::new(vec![]);
+    package.save(&mut encoded).unwrap();
+    encoded.set_position(0);
+    let package = Package::load(&mut encoded).unwrap();
+
+    let mut linked = base.clone();
+    Linker::new(&mut linked).unwrap().link(&package).unwrap();
+    assert!(verify_pool(&linked).is_empty());
+
+    // generated functions are named after their index, which differs between the pools
+    let listings = |pool: &ConstantPool| {
+        let generated = |str: String| {
+            str.split('$')
+                .enumerate()
+                .map(|(i, part)| match i {
+                    0 => part.to_owned(),
+                    _ => part.trim_start_matches(|c: char| c.is_ascii_digit()).to_owned(),
+                })
+                .join("$")
+        };
+        pool.definitions()
+            .filter(|(_, def)| def.value.as_function().map_or(false, |fun| !fun.code.is_empty()))
+            .map(|(idx, def)| {
+                let name = qualified_name(idx, pool).unwrap();
+                let code = disassemble(def.value.as_function().unwrap(), pool).unwrap();
+                (generated(name), generated(code))
+            })
+            .sorted()
+            .collect_vec()
+    };
+    assert_eq!(listings(&linked), listings(&compiled));
+
+    // linking the package again wraps the method a second time
+    let mut twice = linked.clone();
+    Linker::new(&mut twice).unwrap().link(&package).unwrap();
+    assert!(verify_pool(&twice).is_empty());
+    let wrappers = twice
+        .definitions()
+        .filter_map(|(_, def)| twice.names.get(def.name).ok())
+        .filter(|name| name.starts_with("wrapper$"))
+        .unique()
+        .count();
+    assert_eq!(wrappers, 2);
+}
+
+#[test]
+fn fail_to_link_package_with_unresolved_imports() {
+    let base = "
+        class Player {
+            func Heal(amount: Int32) -> Int32 {
+                return amount;
+            }
+        }";
+    let sources = "
+        @wrapMethod(Player)
+        func Heal(amount: Int32) -> Int32 {
+            return wrappedMethod(amount);
+        }";
+
+    let (_, _, package) = packaged(vec![base], vec![sources]).unwrap();
+    let (mut pool, _) = compiled(vec![]).unwrap();
+    let count = pool.definitions().count();
+
+    let res = Linker::new(&mut pool).unwrap().link(&package);
+    assert!(matches!(res, Err(PackageError::UnresolvedImport(_))));
+    assert_eq!(pool.definitions().count(), count);
+}
@@ -4,12 +4,13 @@ use hashbrown::HashMap;
 use redscript::bundle::{ConstantPool, PoolIndex, ScriptBundle};
 use redscript::bytecode::{Code, Offset};
 use redscript::definition::{AnyDefinition, ClassFlags, Definition};
+use redscript::package::Package;
 use redscript_compiler::diagnostics::Diagnostic;
 use redscript_compiler::error::Error;
-use redscript_compiler::parser;
+use redscript_compiler::parser::{self, SourceModule};
 use redscript_compiler::source_map::Files;
 use redscript_compiler::symbol::FunctionSignature;
-use redscript_compiler::unit::CompilationUnit;
+use redscript_compiler::unit::{CompilationOutput, CompilationUnit, Profiling};
 
 pub const PREDEF: &[u8] = include_bytes!("../../resources/predef.redscripts");
 
@@ -114,14 +115,62 @@ macro_rules! check_code {
 }
 
 pub fn compiled(sources: Vec<&str>) -> Result<(ConstantPool, Vec<Diagnostic>), Error> {
+    let mut scripts = ScriptBundle::load(&mut Cursor::new(PREDEF)).unwrap();
+    let res = compiled_into(&mut scripts.pool, sources, |unit, modules, files| {
+        unit.compile(modules, files)
+    })?;
+    Ok((scripts.pool, res.into_diagnostics()))
+}
+
+pub fn compiled_with_tests(sources: Vec<&str>) -> Result<(ConstantPool, CompilationOutput), Error> {
+    let mut scripts = ScriptBundle::load(&mut Cursor::new(PREDEF)).unwrap();
+    let res = compiled_into(&mut scripts.pool, sources, |unit, modules, files| {
+        unit.with_test_code(true).compile(modules, files)
+    })?;
+    Ok((scripts.pool, res))
+}
+
+pub fn compiled_with_profiling(sources: Vec<&str>, profiling: Profiling) -> Result<ConstantPool, Error> {
+    let mut scripts = ScriptBundle::load(&mut Cursor::new(PREDEF)).unwrap();
+    compiled_into(&mut scripts.pool, sources, |unit, modules, files| {
+        unit.with_profiling(profiling).compile(modules, files)
+    })?;
+    Ok(scripts.pool)
+}
+
+pub fn recompiled(base: Vec<&str>, sources: Vec<&str>) -> Result<(ConstantPool, Vec<Diagnostic>), Error> {
+    let (mut pool, _) = compiled(base)?;
+    let res = compiled_into(&mut pool, sources, |unit, modules, files| unit.compile(modules, files))?;
+    Ok((pool, res.into_diagnostics()))
+}
+
+pub fn packaged(base: Vec<&str>, sources: Vec<&str>) -> Result<(ConstantPool, ConstantPool, Package), Error> {
+    let (base, _) = compiled(base)?;
+    let mut pool = base.clone();
+    let res = compiled_into(&mut pool, sources, |unit, modules, files| unit.compile(modules, files))?;
+    let package = Package::new(&base, &pool, res.wrapped_functions()).unwrap();
+    Ok((base, pool, package))
+}
+
+pub fn checked_targets(base: Vec<&str>, sources: Vec<&str>) -> Result<Vec<Diagnostic>, Error> {
+    let (mut pool, _) = compiled(base)?;
+    compiled_into(&mut pool, sources, |unit, modules, files| {
+        unit.check_targets(modules, files)
+    })
+}
+
+/// Parses the sources and passes them to the callback along with a compilation unit over the pool,
+/// the callback is expected to configure the unit and run it.
+fn compiled_into<A>(
+    pool: &mut ConstantPool,
+    sources: Vec<&str>,
+    run: impl FnOnce(CompilationUnit<'_>, Vec<SourceModule>, &Files) -> Result<A, Error>,
+) -> Result<A, Error> {
     let modules = sources
         .iter()
         .map(|source| parser::parse_str(source).unwrap())
         .collect();
-    let mut scripts = ScriptBundle::load(&mut Cursor::new(PREDEF))?;
-    let res = CompilationUnit::new_with_defaults(&mut scripts.pool)?.compile(modules, &Files::default())?;
-
-    Ok((scripts.pool, res.into_diagnostics()))
+    run(CompilationUnit::new_with_defaults(pool)?, modules, &Files::default())
 }
 
 pub fn check_class_flags(pool: &ConstantPool, name: &str, flags: ClassFlags) -> Result<(), Error> {
@@ -0,0 +1,80 @@
+use redscript::bytecode::Instr;
+use redscript::verify::{verify_pool, VerifyCause, VerifyError};
+
+#[allow(unused)]
+mod utils;
+
+use utils::compiled;
+
+#[test]
+fn verify_compiled_bytecode() {
+    let sources = "
+        native func OperatorLess(a: Int32, b: Int32) -> Bool
+        native func OperatorAdd(a: Int32, b: Int32) -> Int32
+        native func OperatorAssignAdd(out l: Int32, r: Int32) -> Int32
+
+        struct Point {
+            let x: Int32;
+            let y: Int32;
+        }
+
+        class Counter {
+            let count: Int32;
+
+            func Increment(by: Int32, opt step: Int32) {
+                this.count = this.count + by;
+            }
+        }
+
+        func Testing(counter: ref<Counter>, arr: array<Int32>) -> Int32 {
+            let point = new Point(1, 2);
+            for item in arr {
+                counter.Increment(item);
+            }
+            switch point.x {
+                case 1:
+                case 2:
+                    return point.y;
+                default:
+                    break;
+            }
+            if counter.count < 10 {
+                return ArraySize(arr) < 1 ? 0 : arr[0];
+            } else {
+                return 0;
+            }
+        }";
+
+    let (mut pool, _) = compiled(vec![sources]).unwrap();
+    assert!(verify_pool(&pool).is_empty());
+
+    let testing = pool
+        .definitions()
+        .find(|(_, def)| {
+            def.value.as_function().is_some()
+                && pool
+                    .names
+                    .get(def.name)
+                    .map_or(false, |name| name.starts_with("Testing;"))
+        })
+        .map(|(idx, _)| idx.cast())
+        .unwrap();
+    let code = &mut pool.function_mut(testing).unwrap().code.0;
+    let offset = code
+        .iter_mut()
+        .find_map(|instr| match instr {
+            Instr::Jump(offset) => Some(offset),
+            _ => None,
+        })
+        .expect("Testing should contain a jump");
+    offset.value += 1;
+
+    let errs = verify_pool(&pool);
+    assert!(matches!(
+        &errs[..],
+        &[VerifyError {
+            cause: VerifyCause::InvalidJumpTarget(_),
+            ..
+        }]
+    ));
+}
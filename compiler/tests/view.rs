@@ -0,0 +1,59 @@
+use std::io::Cursor;
+
+use redscript::bundle::ScriptBundle;
+use redscript::definition::AnyDefinition;
+use redscript::view::BundleView;
+
+#[allow(unused)]
+mod utils;
+
+use utils::{compiled, PREDEF};
+
+#[test]
+fn subset_matches_loaded_pool() {
+    let sources = "
+        class Player {
+            let health: Int32;
+
+            func Heal(amount: Int32) -> Int32 {
+                let previous = this.health;
+                this.health = amount;
+                return previous;
+            }
+        }
+
+        class Medic {
+            func Treat(player: ref<Player>) -> Int32 {
+                return player.Heal(10);
+            }
+        }";
+
+    let mut bundle = ScriptBundle::load(&mut Cursor::new(PREDEF)).unwrap();
+    bundle.pool = compiled(vec![sources]).unwrap().0;
+    let mut bytes = vec![];
+    bundle.save(&mut Cursor::new(&mut bytes)).unwrap();
+    let pool = ScriptBundle::load(&mut Cursor::new(&bytes)).unwrap().pool;
+    let view = BundleView::new(&bytes).unwrap();
+
+    let index = view.find("Medic")[0];
+    let subset = view.subset(&[index]).unwrap();
+
+    // the class and everything that belongs to it is decoded as is
+    for (idx, def) in pool.definitions() {
+        let owner = pool.definition(def.parent).map_or(def.parent, |parent| parent.parent);
+        if idx == index || def.parent == index || owner == index {
+            assert_eq!(format!("{:?}", subset.definition(idx).unwrap()), format!("{def:?}"));
+        }
+    }
+
+    // the functions it calls are decoded without their bodies
+    let heal = view.find("Heal;Int32")[0];
+    let fun = subset.function(heal.cast()).unwrap();
+    assert!(fun.code.is_empty());
+    assert_eq!(fun.parameters, pool.function(heal.cast()).unwrap().parameters);
+    assert_eq!(subset.def_name(heal).unwrap(), pool.def_name(heal).unwrap());
+    assert!(matches!(
+        subset.definition(pool.definition(heal).unwrap().parent).unwrap().value,
+        AnyDefinition::Class(_)
+    ));
+}
@@ -0,0 +1,65 @@
+use itertools::Itertools;
+use redscript::asm::qualified_name;
+use redscript::xref::{CrossReferences, ReferenceKind};
+
+#[allow(unused)]
+mod utils;
+
+use utils::compiled;
+
+#[test]
+fn compile_cross_references() {
+    let sources = "
+        class Counter {
+            let count: Int32;
+
+            func Increment() {
+                this.count = this.Get() + 1;
+            }
+
+            final func Get() -> Int32 {
+                return this.count;
+            }
+        }
+
+        func Run() {
+            let counter = new Counter();
+            counter.Increment();
+        }
+        ";
+    let (pool, _) = compiled(vec![sources]).unwrap();
+    let xrefs = CrossReferences::new(&pool);
+
+    let index = |name: &str| {
+        pool.definitions()
+            .find(|(idx, _)| qualified_name(*idx, &pool).map_or(false, |n| n == name))
+            .unwrap()
+            .0
+    };
+    let references_to = |name: &str| {
+        xrefs
+            .references_to(index(name))
+            .map(|r| (qualified_name(r.source, &pool).unwrap(), r.kind))
+            .collect_vec()
+    };
+
+    assert_eq!(
+        references_to("Counter.count"),
+        vec![
+            ("Counter::Increment;".to_owned(), ReferenceKind::Write),
+            ("Counter::Get;".to_owned(), ReferenceKind::Read)
+        ]
+    );
+    assert_eq!(
+        references_to("Counter::Get;"),
+        vec![("Counter::Increment;".to_owned(), ReferenceKind::Call)]
+    );
+    assert_eq!(
+        references_to("Counter::Increment;"),
+        vec![("Run;".to_owned(), ReferenceKind::VirtualCall)]
+    );
+    assert_eq!(
+        references_to("Counter"),
+        vec![("Run;".to_owned(), ReferenceKind::Construct)]
+    );
+}
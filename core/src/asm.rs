@@ -0,0 +1,868 @@
+//! A textual assembly format for function bytecode.
+//!
+//! A listing declares the parameters and locals of a function followed by its instructions, one per line:
+//!
+//! ```text
+//! .param count: Int32
+//! .local i: Int32
+//!   assign
+//!   local i
+//!   i32zero
+//! L0:
+//!   jumpiffalse L1
+//!   invokestatic L2 4 OperatorLess;Int32Int32;Bool 0
+//!   local i
+//!   param count
+//!   paramend
+//! L2:
+//!   ...
+//!   jump L0
+//! L1:
+//!   nop
+//! ```
+//!
+//! - parameters and locals are declared with the `.param` and `.local` directives, parameters have to match
+//!   the signature of the function, locals are created when the function has no matching local yet
+//! - instructions are written as the lowercase name of the opcode followed by its operands
+//! - labels are declared as `name:` on a line of their own, jump operands refer to them by name or use
+//!   a raw offset relative to the start of the instruction (e.g. `+12` or `-3`)
+//! - definitions are referenced by their qualified names: `Class` for classes and enums, `Class::Method;Int32`
+//!   for methods, `Function;Int32` for global functions, `Class.field` for fields and the pool representation
+//!   for types (e.g. `ref:Class` or `array:Int32`), `none` stands for an undefined reference
+//! - enum members are referenced by their name within the enum
+//! - locals and parameters are referenced by the names they're declared with
+//! - names, strings, TweakDB IDs and resources are written as quoted strings
+//! - everything after a `#` is a comment
+use std::str::FromStr;
+use std::vec;
+
+use hashbrown::{HashMap, HashSet};
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::bundle::{ConstantPool, PoolError, PoolIndex};
+use crate::bytecode::{Breakpoint, Code, Instr, Label, Location, Offset, StartProfiling};
+use crate::definition::{AnyDefinition, Definition, Function, Local, LocalFlags, Parameter};
+use crate::verify::jump_offsets;
+use crate::Ref;
+
+/// Prints the parameters, locals and the bytecode of a function in the assembly format.
+pub fn disassemble(function: &Function, pool: &ConstantPool) -> Result<String, AsmError> {
+    let mut lines = vec![];
+    for param in &function.parameters {
+        let type_ = pool.def_name(pool.parameter(*param)?.type_)?;
+        lines.push(format!(".param {}: {}", pool.def_name(*param)?, type_));
+    }
+
+    let locals = local_aliases(&function.locals, pool)?;
+    for (alias, index) in &locals {
+        let local = pool.local(*index)?;
+        let qualifier = if local.flags.is_const() { "const " } else { "" };
+        lines.push(format!(".local {qualifier}{alias}: {}", pool.def_name(local.type_)?));
+    }
+
+    let mut boundaries = HashSet::new();
+    let mut end = 0;
+    for (location, instr) in function.code.iter() {
+        boundaries.insert(location.value);
+        end = location.value + instr.size();
+    }
+    boundaries.insert(end);
+
+    let targets: HashSet<u16> = function
+        .code
+        .iter()
+        .flat_map(|(location, instr)| jump_offsets(&instr).into_iter().map(move |off| off.absolute(location)))
+        .map(|location| location.value)
+        .filter(|location| boundaries.contains(location))
+        .collect();
+    let labels: HashMap<u16, usize> = targets.into_iter().sorted().enumerate().map(|(i, l)| (l, i)).collect();
+
+    let printer = Printer {
+        pool,
+        labels: &labels,
+        locals: &locals,
+    };
+    for (location, instr) in function.code.iter() {
+        if let Some(label) = labels.get(&location.value) {
+            lines.push(format!("L{label}:"));
+        }
+        let mnemonic: &'static str = (&instr).into();
+        let operands = printer.operands(&instr, location)?;
+        lines.push(format!(
+            "  {}",
+            [mnemonic.to_owned()].into_iter().chain(operands).join(" ")
+        ));
+    }
+    if let Some(label) = labels.get(&end) {
+        lines.push(format!("L{label}:"));
+    }
+    Ok(lines.into_iter().map(|line| line + "\n").collect())
+}
+
+/// Parses an assembly listing of the body of a function.
+/// Locals that do not exist in the function yet are added to the pool.
+pub fn assemble(
+    text: &str,
+    function: PoolIndex<Function>,
+    pool: &mut ConstantPool,
+) -> Result<(Code<Offset>, Vec<PoolIndex<Local>>), AsmError> {
+    let fun = pool.function(function)?;
+    let params = fun
+        .parameters
+        .iter()
+        .map(|idx| Ok((pool.def_name(*idx)?, *idx)))
+        .collect::<Result<_, PoolError>>()?;
+    let existing_locals = local_aliases(&fun.locals, pool)?;
+
+    let mut parser = Parser {
+        index: NameIndex::new(pool)?,
+        pool,
+        function,
+        params,
+        existing_locals,
+        locals: vec![],
+        labels: HashMap::new(),
+        targets: vec![],
+        location: 0,
+        code: vec![],
+    };
+    for (i, line) in text.lines().enumerate() {
+        parser
+            .parse_line(line, i + 1)
+            .map_err(|cause| AsmError::Syntax { cause, line: i + 1 })?;
+    }
+    parser.finish()
+}
+
+/// Replaces the code and the locals of a function with the contents of an assembly listing.
+pub fn patch_function(text: &str, function: PoolIndex<Function>, pool: &mut ConstantPool) -> Result<(), AsmError> {
+    let (code, locals) = assemble(text, function, pool)?;
+    let fun = pool.function_mut(function)?;
+    fun.code = code;
+    fun.locals = locals;
+    Ok(())
+}
+
+/// Returns the name of a definition qualified with the name of its parent class for methods and fields.
+pub fn qualified_name<A>(index: PoolIndex<A>, pool: &ConstantPool) -> Result<String, PoolError> {
+    let def = pool.definition(index)?;
+    let name = pool.names.get(def.name)?;
+    match &def.value {
+        AnyDefinition::Function(_) if !def.parent.is_undefined() => {
+            Ok(format!("{}::{}", pool.def_name(def.parent)?, name))
+        }
+        AnyDefinition::Field(_) if !def.parent.is_undefined() => Ok(format!("{}.{}", pool.def_name(def.parent)?, name)),
+        _ => Ok(name.to_string()),
+    }
+}
+
+/// Returns a name that identifies a definition across pools, it extends [`qualified_name`] with the
+/// function of parameters and locals (`Class::Method;Int32/arg`), the enum of its members (`Enum.Member`)
+/// and the paths of source files.
+pub fn symbol_name<A>(index: PoolIndex<A>, pool: &ConstantPool) -> Result<String, PoolError> {
+    let def = pool.definition(index)?;
+    match &def.value {
+        AnyDefinition::Parameter(_) | AnyDefinition::Local(_) if !def.parent.is_undefined() => Ok(format!(
+            "{}/{}",
+            qualified_name(def.parent, pool)?,
+            pool.names.get(def.name)?
+        )),
+        AnyDefinition::EnumValue(_) if !def.parent.is_undefined() => {
+            Ok(format!("{}.{}", pool.def_name(def.parent)?, pool.names.get(def.name)?))
+        }
+        AnyDefinition::SourceFile(file) => Ok(file.path.display().to_string()),
+        _ => qualified_name(index, pool),
+    }
+}
+
+// locals can share a name, so the duplicates get a unique suffix
+fn local_aliases(
+    locals: &[PoolIndex<Local>],
+    pool: &ConstantPool,
+) -> Result<Vec<(String, PoolIndex<Local>)>, PoolError> {
+    let mut seen = HashSet::new();
+    let mut aliases = Vec::with_capacity(locals.len());
+    for local in locals {
+        let name = pool.def_name(*local)?;
+        let mut alias = name.to_string();
+        let mut suffix = 0;
+        while !seen.insert(alias.clone()) {
+            suffix += 1;
+            alias = format!("{name}${suffix}");
+        }
+        aliases.push((alias, *local));
+    }
+    Ok(aliases)
+}
+
+struct Printer<'a> {
+    pool: &'a ConstantPool,
+    labels: &'a HashMap<u16, usize>,
+    locals: &'a [(String, PoolIndex<Local>)],
+}
+
+impl<'a> Printer<'a> {
+    fn operands(&self, instr: &Instr<Offset>, location: Location) -> Result<Vec<String>, AsmError> {
+        let pool = self.pool;
+        let operands = match instr {
+            Instr::I8Const(val) => vec![val.to_string()],
+            Instr::I16Const(val) => vec![val.to_string()],
+            Instr::I32Const(val) => vec![val.to_string()],
+            Instr::I64Const(val) => vec![val.to_string()],
+            Instr::U8Const(val) => vec![val.to_string()],
+            Instr::U16Const(val) => vec![val.to_string()],
+            Instr::U32Const(val) => vec![val.to_string()],
+            Instr::U64Const(val) => vec![val.to_string()],
+            Instr::F32Const(val) => vec![format!("{val:?}")],
+            Instr::F64Const(val) => vec![format!("{val:?}")],
+            Instr::NameConst(idx) => vec![quote(&pool.names.get(*idx)?)],
+            Instr::EnumConst(enum_, member) => vec![self.definition(*enum_)?, word(&pool.def_name(*member)?)],
+            Instr::StringConst(idx) => vec![quote(&pool.strings.get(*idx)?)],
+            Instr::TweakDbIdConst(idx) if idx.is_undefined() => vec![NONE.to_owned()],
+            Instr::TweakDbIdConst(idx) => vec![quote(&pool.tweakdb_ids.get(*idx)?)],
+            Instr::ResourceConst(idx) if idx.is_undefined() => vec![NONE.to_owned()],
+            Instr::ResourceConst(idx) => vec![quote(&pool.resources.get(*idx)?)],
+            Instr::Breakpoint(breakpoint) => vec![
+                breakpoint.line.to_string(),
+                breakpoint.line_start.to_string(),
+                breakpoint.col.to_string(),
+                breakpoint.length.to_string(),
+                breakpoint.enabled.to_string(),
+                breakpoint.padding.to_string(),
+            ],
+            Instr::Local(idx) => match self.locals.iter().find(|(_, local)| local == idx) {
+                Some((alias, _)) => vec![word(alias)],
+                None => vec![word(&pool.def_name(*idx)?)],
+            },
+            Instr::Param(idx) => vec![word(&pool.def_name(*idx)?)],
+            Instr::ObjectField(idx) | Instr::StructField(idx) => vec![self.definition(*idx)?],
+            Instr::Switch(idx, offset) => vec![self.definition(*idx)?, self.label(*offset, location)],
+            Instr::SwitchLabel(first, second) | Instr::Conditional(first, second) => {
+                vec![self.label(*first, location), self.label(*second, location)]
+            }
+            Instr::Target(offset)
+            | Instr::Jump(offset)
+            | Instr::JumpIfFalse(offset)
+            | Instr::Skip(offset)
+            | Instr::Context(offset) => vec![self.label(*offset, location)],
+            Instr::Construct(args, idx) => vec![args.to_string(), self.definition(*idx)?],
+            Instr::InvokeStatic(exit, line, idx, flags) => vec![
+                self.label(*exit, location),
+                line.to_string(),
+                self.definition(*idx)?,
+                flags.to_string(),
+            ],
+            Instr::InvokeVirtual(exit, line, idx, flags) => vec![
+                self.label(*exit, location),
+                line.to_string(),
+                quote(&pool.names.get(*idx)?),
+                flags.to_string(),
+            ],
+            Instr::New(idx) => vec![self.definition(*idx)?],
+            Instr::StartProfiling(profiling) => vec![quote(&profiling.0), profiling.1.to_string()],
+            Instr::EnumToI32(idx, size) | Instr::I32ToEnum(idx, size) => vec![self.definition(*idx)?, size.to_string()],
+            Instr::DynamicCast(idx, size) => vec![self.definition(*idx)?, size.to_string()],
+            Instr::Equals(idx)
+            | Instr::RefStringEqualsString(idx)
+            | Instr::StringEqualsRefString(idx)
+            | Instr::NotEquals(idx)
+            | Instr::RefStringNotEqualsString(idx)
+            | Instr::StringNotEqualsRefString(idx)
+            | Instr::ArrayClear(idx)
+            | Instr::ArraySize(idx)
+            | Instr::ArrayResize(idx)
+            | Instr::ArrayFindFirst(idx)
+            | Instr::ArrayFindFirstFast(idx)
+            | Instr::ArrayFindLast(idx)
+            | Instr::ArrayFindLastFast(idx)
+            | Instr::ArrayContains(idx)
+            | Instr::ArrayContainsFast(idx)
+            | Instr::ArrayCount(idx)
+            | Instr::ArrayCountFast(idx)
+            | Instr::ArrayPush(idx)
+            | Instr::ArrayPop(idx)
+            | Instr::ArrayInsert(idx)
+            | Instr::ArrayRemove(idx)
+            | Instr::ArrayRemoveFast(idx)
+            | Instr::ArrayGrow(idx)
+            | Instr::ArrayErase(idx)
+            | Instr::ArrayEraseFast(idx)
+            | Instr::ArrayLast(idx)
+            | Instr::ArrayElement(idx)
+            | Instr::ArraySort(idx)
+            | Instr::ArraySortByPredicate(idx)
+            | Instr::StaticArraySize(idx)
+            | Instr::StaticArrayFindFirst(idx)
+            | Instr::StaticArrayFindFirstFast(idx)
+            | Instr::StaticArrayFindLast(idx)
+            | Instr::StaticArrayFindLastFast(idx)
+            | Instr::StaticArrayContains(idx)
+            | Instr::StaticArrayContainsFast(idx)
+            | Instr::StaticArrayCount(idx)
+            | Instr::StaticArrayCountFast(idx)
+            | Instr::StaticArrayLast(idx)
+            | Instr::StaticArrayElement(idx)
+            | Instr::ToString(idx)
+            | Instr::ToVariant(idx)
+            | Instr::FromVariant(idx)
+            | Instr::AsRef(idx)
+            | Instr::Deref(idx) => vec![self.definition(*idx)?],
+            Instr::Nop
+            | Instr::Null
+            | Instr::I32One
+            | Instr::I32Zero
+            | Instr::TrueConst
+            | Instr::FalseConst
+            | Instr::Assign
+            | Instr::ExternalVar
+            | Instr::SwitchDefault
+            | Instr::ParamEnd
+            | Instr::Return
+            | Instr::Delete
+            | Instr::This
+            | Instr::RefToBool
+            | Instr::WeakRefToBool
+            | Instr::VariantIsDefined
+            | Instr::VariantIsRef
+            | Instr::VariantIsArray
+            | Instr::VariantTypeName
+            | Instr::VariantToString
+            | Instr::WeakRefToRef
+            | Instr::RefToWeakRef
+            | Instr::WeakRefNull => vec![],
+        };
+        Ok(operands)
+    }
+
+    fn definition<A>(&self, index: PoolIndex<A>) -> Result<String, PoolError> {
+        if index.is_undefined() {
+            Ok(NONE.to_owned())
+        } else {
+            Ok(word(&qualified_name(index, self.pool)?))
+        }
+    }
+
+    fn label(&self, offset: Offset, location: Location) -> String {
+        match self.labels.get(&offset.absolute(location).value) {
+            Some(label) => format!("L{label}"),
+            None => format!("{:+}", offset.value),
+        }
+    }
+}
+
+const NONE: &str = "none";
+
+fn quote(str: &str) -> String {
+    let mut res = String::with_capacity(str.len() + 2);
+    res.push('"');
+    for char in str.chars() {
+        match char {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            other => res.push(other),
+        }
+    }
+    res.push('"');
+    res
+}
+
+fn word(str: &str) -> String {
+    let needs_quotes = str.is_empty() || str == NONE || str.chars().any(|c| c.is_whitespace() || c == '"' || c == '#');
+    if needs_quotes {
+        quote(str)
+    } else {
+        str.to_owned()
+    }
+}
+
+#[derive(Debug)]
+enum Token {
+    Word(String),
+    Str(String),
+}
+
+impl Token {
+    fn into_string(self) -> String {
+        match self {
+            Token::Word(str) | Token::Str(str) => str,
+        }
+    }
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, AsmCause> {
+    let mut tokens = vec![];
+    let mut chars = line.chars().peekable();
+    while let Some(char) = chars.next() {
+        match char {
+            '#' => break,
+            '"' => {
+                let mut str = String::new();
+                loop {
+                    match chars.next().ok_or(AsmCause::UnterminatedString)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or(AsmCause::UnterminatedString)? {
+                            'n' => str.push('\n'),
+                            'r' => str.push('\r'),
+                            't' => str.push('\t'),
+                            other => str.push(other),
+                        },
+                        other => str.push(other),
+                    }
+                }
+                tokens.push(Token::Str(str));
+            }
+            char if char.is_whitespace() => {}
+            char => {
+                let mut str = String::from(char);
+                while let Some(&next) = chars.peek() {
+                    if next.is_whitespace() || next == '"' || next == '#' {
+                        break;
+                    }
+                    str.push(next);
+                    chars.next();
+                }
+                tokens.push(Token::Word(str));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct NameIndex {
+    functions: HashMap<String, PoolIndex<Definition>>,
+    fields: HashMap<String, PoolIndex<Definition>>,
+    classes: HashMap<String, PoolIndex<Definition>>,
+    enums: HashMap<String, PoolIndex<Definition>>,
+    types: HashMap<String, PoolIndex<Definition>>,
+}
+
+impl NameIndex {
+    fn new(pool: &ConstantPool) -> Result<Self, PoolError> {
+        let mut index = Self {
+            functions: HashMap::new(),
+            fields: HashMap::new(),
+            classes: HashMap::new(),
+            enums: HashMap::new(),
+            types: HashMap::new(),
+        };
+        for (idx, def) in pool.definitions() {
+            let map = match &def.value {
+                AnyDefinition::Function(_) => &mut index.functions,
+                AnyDefinition::Field(_) => &mut index.fields,
+                AnyDefinition::Class(_) => &mut index.classes,
+                AnyDefinition::Enum(_) => &mut index.enums,
+                AnyDefinition::Type(_) => &mut index.types,
+                _ => continue,
+            };
+            map.entry(qualified_name(idx, pool)?).or_insert(idx);
+        }
+        Ok(index)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum DefKind {
+    Function,
+    Field,
+    Class,
+    Enum,
+    Type,
+}
+
+impl DefKind {
+    fn name(self) -> &'static str {
+        match self {
+            DefKind::Function => "function",
+            DefKind::Field => "field",
+            DefKind::Class => "class",
+            DefKind::Enum => "enum",
+            DefKind::Type => "type",
+        }
+    }
+}
+
+type Operands = vec::IntoIter<Token>;
+
+struct Parser<'a> {
+    pool: &'a mut ConstantPool,
+    index: NameIndex,
+    function: PoolIndex<Function>,
+    params: Vec<(Ref<str>, PoolIndex<Parameter>)>,
+    existing_locals: Vec<(String, PoolIndex<Local>)>,
+    locals: Vec<(String, PoolIndex<Local>)>,
+    labels: HashMap<String, usize>,
+    // the location of each label along with the name and the line of its first use
+    targets: Vec<(Option<Location>, String, usize)>,
+    location: u32,
+    code: Vec<Instr<Label>>,
+}
+
+impl<'a> Parser<'a> {
+    fn parse_line(&mut self, line: &str, line_number: usize) -> Result<(), AsmCause> {
+        let mut tokens = tokenize(line)?.into_iter();
+        let mnemonic = match tokens.next() {
+            Some(Token::Word(word)) => word,
+            Some(Token::Str(str)) => return Err(AsmCause::UnknownInstruction(str)),
+            None => return Ok(()),
+        };
+        match mnemonic.as_str() {
+            label if label.ends_with(':') && tokens.len() == 0 => {
+                self.declare_label(label.trim_end_matches(':'), line_number)?;
+            }
+            ".param" => self.declare_param(&mut tokens)?,
+            ".local" => self.declare_local(&mut tokens)?,
+            mnemonic => {
+                let instr = self.parse_instr(mnemonic, &mut tokens, line_number)?;
+                self.location += u32::from(instr.size());
+                if self.location > u32::from(u16::MAX) {
+                    return Err(AsmCause::CodeTooLarge);
+                }
+                self.code.push(instr);
+            }
+        }
+        match tokens.next() {
+            Some(token) => Err(AsmCause::UnexpectedOperand(token.into_string())),
+            None => Ok(()),
+        }
+    }
+
+    fn finish(self) -> Result<(Code<Offset>, Vec<PoolIndex<Local>>), AsmError> {
+        let mut targets = Vec::with_capacity(self.targets.len());
+        for (location, name, line) in self.targets {
+            let location = location.ok_or(AsmError::Syntax {
+                cause: AsmCause::UndeclaredLabel(name),
+                line,
+            })?;
+            targets.push(location);
+        }
+
+        let mut location = Location::ZERO;
+        let mut code = Vec::with_capacity(self.code.len());
+        for instr in self.code {
+            let size = instr.size();
+            code.push(instr.resolve_labels(location, &targets));
+            location = Location::new(location.value + size);
+        }
+        let locals = self.locals.into_iter().map(|(_, idx)| idx).collect();
+        Ok((Code(code), locals))
+    }
+
+    fn declare_label(&mut self, name: &str, line: usize) -> Result<(), AsmCause> {
+        let location = Location::new(self.location as u16);
+        let index = self.label_index(name, line);
+        match &mut self.targets[index].0 {
+            Some(_) => Err(AsmCause::DuplicateDeclaration(name.to_owned())),
+            target => {
+                *target = Some(location);
+                Ok(())
+            }
+        }
+    }
+
+    fn declare_param(&mut self, tokens: &mut Operands) -> Result<(), AsmCause> {
+        let name = declared_name(tokens)?;
+        let type_ = next(tokens, "type")?.into_string();
+        let (_, index) = self
+            .params
+            .iter()
+            .find(|(param, _)| param.as_ref() == name)
+            .ok_or_else(|| AsmCause::UnresolvedName("parameter", name.clone()))?;
+        if self.pool.def_name(self.pool.parameter(*index)?.type_)?.as_ref() != type_ {
+            return Err(AsmCause::InvalidOperand("parameter type", type_));
+        }
+        Ok(())
+    }
+
+    fn declare_local(&mut self, tokens: &mut Operands) -> Result<(), AsmCause> {
+        let is_const =
+            tokens.len() > 2 && matches!(tokens.as_slice().first(), Some(Token::Word(word)) if word == "const");
+        if is_const {
+            tokens.next();
+        }
+        let name = declared_name(tokens)?;
+        let type_ = self.definition(tokens, DefKind::Type)?;
+        if self.locals.iter().any(|(local, _)| *local == name) {
+            return Err(AsmCause::DuplicateDeclaration(name));
+        }
+
+        let mut existing = None;
+        for (alias, idx) in &self.existing_locals {
+            let local = self.pool.local(*idx)?;
+            if *alias == name && local.type_ == type_ && local.flags.is_const() == is_const {
+                existing = Some(*idx);
+            }
+        }
+        let index = if let Some(index) = existing {
+            index
+        } else {
+            let name_idx = self.pool.names.add(name.as_str().into());
+            let local = Local::new(type_, LocalFlags::new().with_is_const(is_const));
+            self.pool
+                .add_definition(Definition::local(name_idx, self.function, local))
+        };
+        self.locals.push((name, index));
+        Ok(())
+    }
+
+    fn parse_instr(&mut self, mnemonic: &str, ops: &mut Operands, line: usize) -> Result<Instr<Label>, AsmCause> {
+        let instr = match mnemonic {
+            "nop" => Instr::Nop,
+            "null" => Instr::Null,
+            "i32one" => Instr::I32One,
+            "i32zero" => Instr::I32Zero,
+            "i8const" => Instr::I8Const(number(ops, "integer")?),
+            "i16const" => Instr::I16Const(number(ops, "integer")?),
+            "i32const" => Instr::I32Const(number(ops, "integer")?),
+            "i64const" => Instr::I64Const(number(ops, "integer")?),
+            "u8const" => Instr::U8Const(number(ops, "integer")?),
+            "u16const" => Instr::U16Const(number(ops, "integer")?),
+            "u32const" => Instr::U32Const(number(ops, "integer")?),
+            "u64const" => Instr::U64Const(number(ops, "integer")?),
+            "f32const" => Instr::F32Const(number(ops, "float")?),
+            "f64const" => Instr::F64Const(number(ops, "float")?),
+            "nameconst" => Instr::NameConst(self.pool.names.add(next(ops, "name")?.into_string().into())),
+            "enumconst" => {
+                let enum_ = self.definition(ops, DefKind::Enum)?;
+                let name = next(ops, "enum member")?.into_string();
+                let mut member = None;
+                for idx in &self.pool.enum_(enum_)?.members {
+                    if self.pool.def_name(*idx)?.as_ref() == name {
+                        member = Some(*idx);
+                    }
+                }
+                Instr::EnumConst(enum_, member.ok_or(AsmCause::UnresolvedName("enum member", name))?)
+            }
+            "stringconst" => Instr::StringConst(self.pool.strings.add(next(ops, "string")?.into_string().into())),
+            "tweakdbidconst" => Instr::TweakDbIdConst(match next(ops, "TweakDB ID")? {
+                Token::Word(word) if word == NONE => PoolIndex::UNDEFINED,
+                token => self.pool.tweakdb_ids.add(token.into_string().into()),
+            }),
+            "resourceconst" => Instr::ResourceConst(match next(ops, "resource")? {
+                Token::Word(word) if word == NONE => PoolIndex::UNDEFINED,
+                token => self.pool.resources.add(token.into_string().into()),
+            }),
+            "trueconst" => Instr::TrueConst,
+            "falseconst" => Instr::FalseConst,
+            "breakpoint" => Instr::Breakpoint(Box::new(Breakpoint {
+                line: number(ops, "line")?,
+                line_start: number(ops, "line start")?,
+                col: number(ops, "column")?,
+                length: number(ops, "length")?,
+                enabled: number(ops, "enabled")?,
+                padding: number(ops, "padding")?,
+            })),
+            "assign" => Instr::Assign,
+            "target" => Instr::Target(self.label(ops, line)?),
+            "local" => {
+                let name = next(ops, "local")?.into_string();
+                let (_, idx) = self
+                    .locals
+                    .iter()
+                    .find(|(local, _)| *local == name)
+                    .ok_or(AsmCause::UnresolvedName("local", name.clone()))?;
+                Instr::Local(*idx)
+            }
+            "param" => {
+                let name = next(ops, "parameter")?.into_string();
+                let (_, idx) = self
+                    .params
+                    .iter()
+                    .find(|(param, _)| param.as_ref() == name)
+                    .ok_or(AsmCause::UnresolvedName("parameter", name.clone()))?;
+                Instr::Param(*idx)
+            }
+            "objectfield" => Instr::ObjectField(self.definition(ops, DefKind::Field)?),
+            "externalvar" => Instr::ExternalVar,
+            "switch" => Instr::Switch(self.definition(ops, DefKind::Type)?, self.label(ops, line)?),
+            "switchlabel" => Instr::SwitchLabel(self.label(ops, line)?, self.label(ops, line)?),
+            "switchdefault" => Instr::SwitchDefault,
+            "jump" => Instr::Jump(self.label(ops, line)?),
+            "jumpiffalse" => Instr::JumpIfFalse(self.label(ops, line)?),
+            "skip" => Instr::Skip(self.label(ops, line)?),
+            "conditional" => Instr::Conditional(self.label(ops, line)?, self.label(ops, line)?),
+            "construct" => Instr::Construct(number(ops, "argument count")?, self.definition(ops, DefKind::Class)?),
+            "invokestatic" => Instr::InvokeStatic(
+                self.label(ops, line)?,
+                number(ops, "line")?,
+                self.definition(ops, DefKind::Function)?,
+                number(ops, "flags")?,
+            ),
+            "invokevirtual" => Instr::InvokeVirtual(
+                self.label(ops, line)?,
+                number(ops, "line")?,
+                self.pool.names.add(next(ops, "name")?.into_string().into()),
+                number(ops, "flags")?,
+            ),
+            "paramend" => Instr::ParamEnd,
+            "return" => Instr::Return,
+            "structfield" => Instr::StructField(self.definition(ops, DefKind::Field)?),
+            "context" => Instr::Context(self.label(ops, line)?),
+            "equals" => Instr::Equals(self.definition(ops, DefKind::Type)?),
+            "refstringequalsstring" => Instr::RefStringEqualsString(self.definition(ops, DefKind::Type)?),
+            "stringequalsrefstring" => Instr::StringEqualsRefString(self.definition(ops, DefKind::Type)?),
+            "notequals" => Instr::NotEquals(self.definition(ops, DefKind::Type)?),
+            "refstringnotequalsstring" => Instr::RefStringNotEqualsString(self.definition(ops, DefKind::Type)?),
+            "stringnotequalsrefstring" => Instr::StringNotEqualsRefString(self.definition(ops, DefKind::Type)?),
+            "new" => Instr::New(self.definition(ops, DefKind::Class)?),
+            "delete" => Instr::Delete,
+            "this" => Instr::This,
+            "startprofiling" => Instr::StartProfiling(Box::new(StartProfiling(
+                next(ops, "function")?.into_string(),
+                number(ops, "enabled")?,
+            ))),
+            "arrayclear" => Instr::ArrayClear(self.definition(ops, DefKind::Type)?),
+            "arraysize" => Instr::ArraySize(self.definition(ops, DefKind::Type)?),
+            "arrayresize" => Instr::ArrayResize(self.definition(ops, DefKind::Type)?),
+            "arrayfindfirst" => Instr::ArrayFindFirst(self.definition(ops, DefKind::Type)?),
+            "arrayfindfirstfast" => Instr::ArrayFindFirstFast(self.definition(ops, DefKind::Type)?),
+            "arrayfindlast" => Instr::ArrayFindLast(self.definition(ops, DefKind::Type)?),
+            "arrayfindlastfast" => Instr::ArrayFindLastFast(self.definition(ops, DefKind::Type)?),
+            "arraycontains" => Instr::ArrayContains(self.definition(ops, DefKind::Type)?),
+            "arraycontainsfast" => Instr::ArrayContainsFast(self.definition(ops, DefKind::Type)?),
+            "arraycount" => Instr::ArrayCount(self.definition(ops, DefKind::Type)?),
+            "arraycountfast" => Instr::ArrayCountFast(self.definition(ops, DefKind::Type)?),
+            "arraypush" => Instr::ArrayPush(self.definition(ops, DefKind::Type)?),
+            "arraypop" => Instr::ArrayPop(self.definition(ops, DefKind::Type)?),
+            "arrayinsert" => Instr::ArrayInsert(self.definition(ops, DefKind::Type)?),
+            "arrayremove" => Instr::ArrayRemove(self.definition(ops, DefKind::Type)?),
+            "arrayremovefast" => Instr::ArrayRemoveFast(self.definition(ops, DefKind::Type)?),
+            "arraygrow" => Instr::ArrayGrow(self.definition(ops, DefKind::Type)?),
+            "arrayerase" => Instr::ArrayErase(self.definition(ops, DefKind::Type)?),
+            "arrayerasefast" => Instr::ArrayEraseFast(self.definition(ops, DefKind::Type)?),
+            "arraylast" => Instr::ArrayLast(self.definition(ops, DefKind::Type)?),
+            "arrayelement" => Instr::ArrayElement(self.definition(ops, DefKind::Type)?),
+            "arraysort" => Instr::ArraySort(self.definition(ops, DefKind::Type)?),
+            "arraysortbypredicate" => Instr::ArraySortByPredicate(self.definition(ops, DefKind::Type)?),
+            "staticarraysize" => Instr::StaticArraySize(self.definition(ops, DefKind::Type)?),
+            "staticarrayfindfirst" => Instr::StaticArrayFindFirst(self.definition(ops, DefKind::Type)?),
+            "staticarrayfindfirstfast" => Instr::StaticArrayFindFirstFast(self.definition(ops, DefKind::Type)?),
+            "staticarrayfindlast" => Instr::StaticArrayFindLast(self.definition(ops, DefKind::Type)?),
+            "staticarrayfindlastfast" => Instr::StaticArrayFindLastFast(self.definition(ops, DefKind::Type)?),
+            "staticarraycontains" => Instr::StaticArrayContains(self.definition(ops, DefKind::Type)?),
+            "staticarraycontainsfast" => Instr::StaticArrayContainsFast(self.definition(ops, DefKind::Type)?),
+            "staticarraycount" => Instr::StaticArrayCount(self.definition(ops, DefKind::Type)?),
+            "staticarraycountfast" => Instr::StaticArrayCountFast(self.definition(ops, DefKind::Type)?),
+            "staticarraylast" => Instr::StaticArrayLast(self.definition(ops, DefKind::Type)?),
+            "staticarrayelement" => Instr::StaticArrayElement(self.definition(ops, DefKind::Type)?),
+            "reftobool" => Instr::RefToBool,
+            "weakreftobool" => Instr::WeakRefToBool,
+            "enumtoi32" => Instr::EnumToI32(self.definition(ops, DefKind::Type)?, number(ops, "size")?),
+            "i32toenum" => Instr::I32ToEnum(self.definition(ops, DefKind::Type)?, number(ops, "size")?),
+            "dynamiccast" => Instr::DynamicCast(self.definition(ops, DefKind::Class)?, number(ops, "size")?),
+            "tostring" => Instr::ToString(self.definition(ops, DefKind::Type)?),
+            "tovariant" => Instr::ToVariant(self.definition(ops, DefKind::Type)?),
+            "fromvariant" => Instr::FromVariant(self.definition(ops, DefKind::Type)?),
+            "variantisdefined" => Instr::VariantIsDefined,
+            "variantisref" => Instr::VariantIsRef,
+            "variantisarray" => Instr::VariantIsArray,
+            "varianttypename" => Instr::VariantTypeName,
+            "varianttostring" => Instr::VariantToString,
+            "weakreftoref" => Instr::WeakRefToRef,
+            "reftoweakref" => Instr::RefToWeakRef,
+            "weakrefnull" => Instr::WeakRefNull,
+            "asref" => Instr::AsRef(self.definition(ops, DefKind::Type)?),
+            "deref" => Instr::Deref(self.definition(ops, DefKind::Type)?),
+            other => return Err(AsmCause::UnknownInstruction(other.to_owned())),
+        };
+        Ok(instr)
+    }
+
+    fn definition<A>(&self, ops: &mut Operands, kind: DefKind) -> Result<PoolIndex<A>, AsmCause> {
+        let name = match next(ops, kind.name())? {
+            Token::Word(word) if word == NONE => return Ok(PoolIndex::UNDEFINED),
+            token => token.into_string(),
+        };
+        let map = match kind {
+            DefKind::Function => &self.index.functions,
+            DefKind::Field => &self.index.fields,
+            DefKind::Class => &self.index.classes,
+            DefKind::Enum => &self.index.enums,
+            DefKind::Type => &self.index.types,
+        };
+        map.get(&name)
+            .map(PoolIndex::cast)
+            .ok_or_else(|| AsmCause::UnresolvedName(kind.name(), name))
+    }
+
+    fn label(&mut self, ops: &mut Operands, line: usize) -> Result<Label, AsmCause> {
+        let name = next(ops, "label")?.into_string();
+        if name.starts_with(['+', '-']) {
+            let offset: i16 = name
+                .parse()
+                .map_err(|_| AsmCause::InvalidOperand("offset", name.clone()))?;
+            let location = Offset::new(offset).absolute(Location::new(self.location as u16));
+            self.targets.push((Some(location), name, line));
+            Ok(Label {
+                index: self.targets.len() - 1,
+            })
+        } else {
+            Ok(Label {
+                index: self.label_index(&name, line),
+            })
+        }
+    }
+
+    fn label_index(&mut self, name: &str, line: usize) -> usize {
+        *self.labels.entry_ref(name).or_insert_with(|| {
+            self.targets.push((None, name.to_owned(), line));
+            self.targets.len() - 1
+        })
+    }
+}
+
+fn next(ops: &mut Operands, what: &'static str) -> Result<Token, AsmCause> {
+    ops.next().ok_or(AsmCause::MissingOperand(what))
+}
+
+fn number<N: FromStr>(ops: &mut Operands, what: &'static str) -> Result<N, AsmCause> {
+    match next(ops, what)? {
+        Token::Word(word) => word.parse().map_err(|_| AsmCause::InvalidOperand(what, word)),
+        Token::Str(str) => Err(AsmCause::InvalidOperand(what, str)),
+    }
+}
+
+fn declared_name(ops: &mut Operands) -> Result<String, AsmCause> {
+    match next(ops, "name")? {
+        Token::Word(word) => match word.strip_suffix(':') {
+            Some(name) => Ok(name.to_owned()),
+            None => Err(AsmCause::InvalidOperand("declaration", word)),
+        },
+        Token::Str(str) => Err(AsmCause::InvalidOperand("declaration", str)),
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum AsmError {
+    #[error("pool error: {0}")]
+    PoolError(#[from] PoolError),
+    #[error("{cause} at line {line}")]
+    Syntax { cause: AsmCause, line: usize },
+}
+
+#[derive(Debug, Error)]
+pub enum AsmCause {
+    #[error("unknown instruction {0}")]
+    UnknownInstruction(String),
+    #[error("missing {0} operand")]
+    MissingOperand(&'static str),
+    #[error("invalid {0} operand: {1}")]
+    InvalidOperand(&'static str, String),
+    #[error("unexpected operand: {0}")]
+    UnexpectedOperand(String),
+    #[error("unresolved {0}: {1}")]
+    UnresolvedName(&'static str, String),
+    #[error("{0} is declared more than once")]
+    DuplicateDeclaration(String),
+    #[error("label {0} is never declared")]
+    UndeclaredLabel(String),
+    #[error("unterminated string")]
+    UnterminatedString,
+    #[error("the code exceeds the maximum size")]
+    CodeTooLarge,
+    #[error("pool error: {0}")]
+    PoolError(#[from] PoolError),
+}
@@ -32,11 +32,13 @@ where
     Call(Name::Callable, Box<[Name::Type]>, Box<[Self]>, Span),
     MethodCall(Box<Self>, Name::Function, Vec<Self>, Span),
     Member(Box<Self>, Name::Member, Span),
+    NullSafe(Box<Self>, Span),
     ArrayElem(Box<Self>, Box<Self>, Span),
     New(Name::Type, Box<[Self]>, Span),
     Return(Option<Box<Self>>, Span),
     Seq(Seq<Name>),
     Switch(Box<Self>, Vec<SwitchCase<Name>>, Option<Seq<Name>>, Span),
+    Match(Box<Self>, Vec<MatchArm<Name>>, Option<Box<Self>>, Span),
     Goto(Target, Span),
     If(Box<Self>, Seq<Name>, Option<Seq<Name>>, Span),
     Conditional(Box<Self>, Box<Self>, Box<Self>, Span),
@@ -102,10 +104,12 @@ where
             | Expr::Call(_, _, _, span)
             | Expr::MethodCall(_, _, _, span)
             | Expr::Member(_, _, span)
+            | Expr::NullSafe(_, span)
             | Expr::ArrayElem(_, _, span)
             | Expr::New(_, _, span)
             | Expr::Return(_, span)
             | Expr::Switch(_, _, _, span)
+            | Expr::Match(_, _, _, span)
             | Expr::Goto(_, span)
             | Expr::If(_, _, _, span)
             | Expr::Conditional(_, _, _, span)
@@ -154,6 +158,16 @@ pub enum BinOp {
     AssignOr,
     #[strum(serialize = "OperatorAssignAnd")]
     AssignAnd,
+    #[strum(serialize = "OperatorAssignXor")]
+    AssignXor,
+    #[strum(serialize = "OperatorAssignModulo")]
+    AssignModulo,
+    #[strum(serialize = "OperatorAssignShiftLeft")]
+    AssignShiftLeft,
+    #[strum(serialize = "OperatorAssignShiftRight")]
+    AssignShiftRight,
+    #[strum(serialize = "OperatorCoalesce")]
+    Coalesce,
     #[strum(serialize = "OperatorLogicOr")]
     LogicOr,
     #[strum(serialize = "OperatorLogicAnd")]
@@ -176,6 +190,10 @@ pub enum BinOp {
     Greater,
     #[strum(serialize = "OperatorGreaterEqual")]
     GreaterEqual,
+    #[strum(serialize = "OperatorShiftLeft")]
+    ShiftLeft,
+    #[strum(serialize = "OperatorShiftRight")]
+    ShiftRight,
     #[strum(serialize = "OperatorAdd")]
     Add,
     #[strum(serialize = "OperatorSubtract")]
@@ -196,14 +214,20 @@ impl BinOp {
             | BinOp::AssignMultiply
             | BinOp::AssignDivide
             | BinOp::AssignOr
-            | BinOp::AssignAnd => 10,
-            BinOp::LogicOr => 9,
-            BinOp::LogicAnd => 8,
-            BinOp::Or => 7,
-            BinOp::Xor => 6,
-            BinOp::And => 5,
-            BinOp::Equal | BinOp::NotEqual => 4,
-            BinOp::Less | BinOp::LessEqual | BinOp::Greater | BinOp::GreaterEqual => 3,
+            | BinOp::AssignAnd
+            | BinOp::AssignXor
+            | BinOp::AssignModulo
+            | BinOp::AssignShiftLeft
+            | BinOp::AssignShiftRight => 12,
+            BinOp::Coalesce => 11,
+            BinOp::LogicOr => 10,
+            BinOp::LogicAnd => 9,
+            BinOp::Or => 8,
+            BinOp::Xor => 7,
+            BinOp::And => 6,
+            BinOp::Equal | BinOp::NotEqual => 5,
+            BinOp::Less | BinOp::LessEqual | BinOp::Greater | BinOp::GreaterEqual => 4,
+            BinOp::ShiftLeft | BinOp::ShiftRight => 3,
             BinOp::Add | BinOp::Subtract => 2,
             BinOp::Multiply | BinOp::Divide | BinOp::Modulo => 1,
         }
@@ -217,6 +241,12 @@ impl BinOp {
             | BinOp::AssignDivide
             | BinOp::AssignOr
             | BinOp::AssignAnd
+            | BinOp::AssignXor
+            | BinOp::AssignModulo
+            | BinOp::AssignShiftLeft
+            | BinOp::AssignShiftRight
+            | BinOp::ShiftLeft
+            | BinOp::ShiftRight
             | BinOp::Divide
             | BinOp::Modulo
             | BinOp::Equal
@@ -225,7 +255,8 @@ impl BinOp {
             | BinOp::LessEqual
             | BinOp::Greater
             | BinOp::GreaterEqual => false,
-            BinOp::LogicOr
+            BinOp::Coalesce
+            | BinOp::LogicOr
             | BinOp::LogicAnd
             | BinOp::Or
             | BinOp::Xor
@@ -236,6 +267,27 @@ impl BinOp {
         }
     }
 
+    pub fn is_shift(self) -> bool {
+        matches!(self, BinOp::ShiftLeft | BinOp::ShiftRight)
+    }
+
+    /// Returns the operator applied by a compound assignment, e.g. `Add` for `AssignAdd`.
+    pub fn compound_operand(self) -> Option<BinOp> {
+        match self {
+            BinOp::AssignAdd => Some(BinOp::Add),
+            BinOp::AssignSubtract => Some(BinOp::Subtract),
+            BinOp::AssignMultiply => Some(BinOp::Multiply),
+            BinOp::AssignDivide => Some(BinOp::Divide),
+            BinOp::AssignOr => Some(BinOp::Or),
+            BinOp::AssignAnd => Some(BinOp::And),
+            BinOp::AssignXor => Some(BinOp::Xor),
+            BinOp::AssignModulo => Some(BinOp::Modulo),
+            BinOp::AssignShiftLeft => Some(BinOp::ShiftLeft),
+            BinOp::AssignShiftRight => Some(BinOp::ShiftRight),
+            _ => None,
+        }
+    }
+
     pub fn does_associate(self, parent: BinOp) -> bool {
         parent.precedence() > self.precedence() || (parent.precedence() == self.precedence() && parent.associative())
     }
@@ -266,6 +318,21 @@ where
     pub body: Seq<N>,
 }
 
+#[derive(Debug)]
+pub struct MatchArm<N>
+where
+    N: NameKind,
+    N::Reference: Debug,
+    N::Callable: Debug,
+    N::Local: Debug,
+    N::Function: Debug,
+    N::Member: Debug,
+    N::Type: Debug,
+{
+    pub pattern: Expr<N>,
+    pub body: Expr<N>,
+}
+
 #[derive(Debug)]
 pub struct Seq<N>
 where
@@ -8,7 +8,7 @@ use itertools::chain;
 use modular_bitfield::prelude::*;
 use thiserror::Error;
 
-use crate::decode::{Decode, DecodeExt};
+use crate::decode::{Decode, DecodeError, DecodeExt, Table};
 use crate::definition::{AnyDefinition, Class, Definition, Enum, Field, Function, Local, Parameter, Type};
 use crate::encode::{Encode, EncodeExt};
 use crate::io::StreamOffset;
@@ -16,13 +16,18 @@ use crate::Ref;
 
 #[derive(Debug)]
 pub struct ScriptBundle {
-    header: Header,
+    pub(crate) header: Header,
     pub pool: ConstantPool,
 }
 
 impl ScriptBundle {
-    pub fn load<I: io::Read + io::Seek>(input: &mut I) -> io::Result<Self> {
-        let header: Header = input.decode()?;
+    /// Returns the version of the format the bundle was loaded with, which is also used to save it.
+    pub fn version(&self) -> Version {
+        self.header.version
+    }
+
+    pub fn load<I: io::Read + io::Seek>(input: &mut I) -> Result<Self, DecodeError> {
+        let header: Header = input.decode().map_err(|err| err.at(0, Table::Header, None))?;
         let pool = ConstantPool::decode(input, &header)?;
         let cache = ScriptBundle { header, pool };
         Ok(cache)
@@ -40,37 +45,54 @@ impl ScriptBundle {
 
 #[derive(Debug, Clone)]
 pub struct Header {
-    version: u32,
-    flags: u32,
-    timestamp: Timestamp,
-    unk3: u32,
+    pub(crate) version: Version,
+    pub(crate) flags: u32,
+    pub(crate) timestamp: Timestamp,
+    pub(crate) unk3: u32,
     hash: u32,
-    chunks: u32,
-    data: TableHeader,
-    names: TableHeader,
-    tweakdb_indexes: TableHeader,
-    resources: TableHeader,
-    strings: TableHeader,
-    definitions: TableHeader,
+    pub(crate) chunks: u32,
+    pub(crate) data: TableHeader,
+    pub(crate) names: TableHeader,
+    pub(crate) tweakdb_indexes: TableHeader,
+    pub(crate) resources: TableHeader,
+    pub(crate) strings: TableHeader,
+    pub(crate) definitions: TableHeader,
 }
 
 impl Header {
     const MAGIC: u32 = 0x5344_4552;
     const SIZE: usize = 104;
-    const SUPPORTED_VERSION: u32 = 14;
+
+    #[cfg(feature = "serde")]
+    pub(crate) fn new(version: Version, flags: u32, timestamp: Timestamp, unk3: u32, chunks: u32) -> Self {
+        Header {
+            version,
+            flags,
+            timestamp,
+            unk3,
+            hash: 0,
+            chunks,
+            data: TableHeader::default(),
+            names: TableHeader::default(),
+            tweakdb_indexes: TableHeader::default(),
+            resources: TableHeader::default(),
+            strings: TableHeader::default(),
+            definitions: TableHeader::default(),
+        }
+    }
 }
 
 impl Decode for Header {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let magic: u32 = input.decode()?;
         if magic != Header::MAGIC {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid file header"));
+            return Err(DecodeError::InvalidHeader);
         }
 
-        let version: u32 = input.decode()?;
+        let version: Version = input.decode()?;
         let flags: u32 = input.decode()?;
         let timestamp: Timestamp = input.decode()?;
-        if version != Self::SUPPORTED_VERSION {
+        if !version.is_known() {
             log::warn!(
                 "Loading an unsupported version of the script cache (v{version}) built at {timestamp}. \
                  You might be running the wrong version of redscript."
@@ -122,6 +144,61 @@ impl Encode for Header {
     }
 }
 
+/// The version of the script cache format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version(u32);
+
+impl Version {
+    /// The versions with a known layout. Differences between them are handled by the
+    /// [`DecodeVersioned`](crate::decode::DecodeVersioned) and
+    /// [`EncodeVersioned`](crate::encode::EncodeVersioned) implementations,
+    /// other versions are assumed to share the layout of the latest one.
+    const KNOWN: &'static [Version] = &[Version::V12, Version::V13, Version::LATEST];
+    /// The version used by the current release of the game.
+    pub const LATEST: Version = Version(14);
+    /// The version used by older releases of the game, including the bundled predefs.
+    pub const V13: Version = Version(13);
+    /// The version used by releases of the game before 1.5.
+    pub const V12: Version = Version(12);
+
+    pub const fn new(version: u32) -> Self {
+        Version(version)
+    }
+
+    pub fn is_known(self) -> bool {
+        Self::KNOWN.contains(&self)
+    }
+
+    /// Whether invocations carry the flags introduced in 1.5, older versions leave them out.
+    pub fn has_invoke_flags(self) -> bool {
+        self != Version::V12
+    }
+}
+
+impl From<Version> for u32 {
+    fn from(version: Version) -> Self {
+        version.0
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Decode for Version {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(Version(input.decode()?))
+    }
+}
+
+impl Encode for Version {
+    fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
+        output.encode(&self.0)
+    }
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct ConstantPool {
     pub names: Strings<CName>,
@@ -132,22 +209,42 @@ pub struct ConstantPool {
 }
 
 impl ConstantPool {
-    pub fn decode<I: io::Read + io::Seek>(input: &mut I, header: &Header) -> io::Result<Self> {
-        let buffer = input.decode_bytes(header.data.count)?;
+    pub fn decode<I: io::Read + io::Seek>(input: &mut I, header: &Header) -> Result<Self, DecodeError> {
+        let data_offset = input.stream_position()?;
+        let buffer = input
+            .decode_bytes(header.data.count)
+            .map_err(|err| err.at(data_offset, Table::Data, None))?;
 
         let mut cursor = io::Cursor::new(buffer);
 
-        let names = Strings::decode_from(&mut cursor, &input.decode_vec(header.names.count)?)?;
-        let tweakdb_ids = Strings::decode_from(&mut cursor, &input.decode_vec(header.tweakdb_indexes.count)?)?;
-        let resources = Strings::decode_from(&mut cursor, &input.decode_vec(header.resources.count)?)?;
-        let headers: Vec<DefinitionHeader> = input.decode_vec(header.definitions.count)?;
-        let strings = Strings::decode_from(&mut cursor, &input.decode_vec(header.strings.count)?)?;
-
+        let names = Strings::decode_from(input, &mut cursor, header.names.count, data_offset, Table::Names)?;
+        let tweakdb_ids = Strings::decode_from(
+            input,
+            &mut cursor,
+            header.tweakdb_indexes.count,
+            data_offset,
+            Table::TweakDbIds,
+        )?;
+        let resources = Strings::decode_from(
+            input,
+            &mut cursor,
+            header.resources.count,
+            data_offset,
+            Table::Resources,
+        )?;
+        let headers_offset = input.stream_position()?;
+        let headers: Vec<DefinitionHeader> = input
+            .decode_vec(header.definitions.count)
+            .map_err(|err| err.at(headers_offset, Table::DefinitionHeaders, None))?;
+        let strings = Strings::decode_from(input, &mut cursor, header.strings.count, data_offset, Table::Strings)?;
+
+        let version = header.version;
         let mut definitions = Vec::with_capacity(headers.len());
         definitions.push(Definition::DEFAULT);
 
-        for header in headers.iter().skip(1) {
-            let definition = Definition::decode(input, header)?;
+        for (index, header) in headers.iter().enumerate().skip(1) {
+            let definition = Definition::decode(input, header, version)
+                .map_err(|err| err.at(header.offset.into(), Table::Definitions, Some(index as u32)))?;
             definitions.push(definition);
         }
 
@@ -212,8 +309,8 @@ impl ConstantPool {
 
         let mut offset_output = StreamOffset::new_seekable(output)?;
         for definition in self.definitions.iter().skip(1) {
-            let header = DefinitionHeader::encode_definition(&mut offset_output, definition)?;
-            buffer.encode(&header)?;
+            let def_header = DefinitionHeader::encode_definition(&mut offset_output, definition, header.version)?;
+            buffer.encode(&def_header)?;
         }
         let output = offset_output.into_inner();
         output.seek(io::SeekFrom::Start(def_header_pos))?;
@@ -288,6 +385,13 @@ impl ConstantPool {
         self.definition_by(index, AnyDefinition::as_local)
     }
 
+    pub fn local_mut(&mut self, index: PoolIndex<Local>) -> Result<&mut Local, PoolError> {
+        self.definitions
+            .get_mut(index.value as usize)
+            .and_then(|def| def.value.as_local_mut())
+            .ok_or_else(|| PoolError::DefinitionNotFound(index.cast()))
+    }
+
     pub fn type_(&self, index: PoolIndex<Type>) -> Result<&Type, PoolError> {
         self.definition_by(index, AnyDefinition::as_type)
     }
@@ -362,21 +466,41 @@ pub struct Strings<K> {
 }
 
 impl<K: DefaultString> Strings<K> {
-    fn decode_from<I: io::Read + io::Seek>(input: &mut I, offsets: &[u32]) -> io::Result<Strings<K>> {
+    fn decode_from<I: io::Read + io::Seek>(
+        input: &mut I,
+        data: &mut io::Cursor<Vec<u8>>,
+        count: u32,
+        data_offset: u64,
+        table: Table,
+    ) -> Result<Strings<K>, DecodeError> {
+        let offsets_offset = input.stream_position()?;
+        let offsets: Vec<u32> = input
+            .decode_vec(count)
+            .map_err(|err| err.at(offsets_offset, table, None))?;
+
         let mut strings = Vec::with_capacity(offsets.len());
-        let mut mappings = HashMap::new();
         for (idx, offset) in offsets.iter().enumerate() {
-            input.seek(io::SeekFrom::Start((*offset).into()))?;
-            let str: Ref<str> = Ref::from(input.decode::<String>()?);
-            strings.push(str.clone());
-            mappings.insert(str, PoolIndex::new(idx as u32));
+            data.set_position((*offset).into());
+            let str: Ref<str> = data
+                .decode::<String>()
+                .map(Ref::from)
+                .map_err(|err| err.at(data_offset + u64::from(*offset), table, Some(idx as u32)))?;
+            strings.push(str);
         }
-        let result = Strings {
+        Ok(Strings::from_vec(strings))
+    }
+
+    pub(crate) fn from_vec(strings: Vec<Ref<str>>) -> Strings<K> {
+        let mappings = strings
+            .iter()
+            .enumerate()
+            .map(|(idx, str)| (str.clone(), PoolIndex::new(idx as u32)))
+            .collect();
+        Strings {
             strings,
             mappings,
             phantom: PhantomData,
-        };
-        Ok(result)
+        }
     }
 
     fn encoded_offsets(&self, str_map: &HashMap<Ref<str>, u32>) -> io::Result<Vec<u8>> {
@@ -398,6 +522,10 @@ impl<K: DefaultString> Strings<K> {
         }
     }
 
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &Ref<str>> {
+        self.strings.iter()
+    }
+
     pub fn get_index(&self, name: &str) -> Option<PoolIndex<K>> {
         self.mappings.get(name).copied()
     }
@@ -428,10 +556,10 @@ impl<K> Default for Strings<K> {
     }
 }
 
-#[derive(Debug, Clone)]
-struct TableHeader {
-    offset: u32,
-    count: u32,
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TableHeader {
+    pub(crate) offset: u32,
+    pub(crate) count: u32,
     hash: u32,
 }
 
@@ -446,7 +574,7 @@ impl TableHeader {
 }
 
 impl Decode for TableHeader {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let offset = input.decode()?;
         let count = input.decode()?;
         let hash = input.decode()?;
@@ -491,9 +619,10 @@ impl DefinitionHeader {
     fn encode_definition<O: io::Write + io::Seek>(
         output: &mut StreamOffset<O>,
         definition: &Definition,
+        version: Version,
     ) -> io::Result<DefinitionHeader> {
         let offset = output.offset();
-        output.encode(&definition.value)?;
+        output.encode_versioned(&definition.value, version)?;
         let size = output.offset() - offset;
         let header = DefinitionHeader {
             name: definition.name,
@@ -510,7 +639,7 @@ impl DefinitionHeader {
 }
 
 impl Decode for DefinitionHeader {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let name = input.decode()?;
         let parent = input.decode()?;
         let offset = input.decode()?;
@@ -548,7 +677,7 @@ impl Encode for DefinitionHeader {
 
 #[derive(BitfieldSpecifier)]
 #[bits = 8]
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum DefinitionType {
     Type = 0,
     Class = 1,
@@ -563,8 +692,9 @@ pub enum DefinitionType {
 }
 
 impl Decode for DefinitionType {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
-        Ok(DefinitionType::from_bytes(input.decode()?).expect("Invalid DefinitionType enum value"))
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        let tag = input.decode()?;
+        DefinitionType::from_bytes(tag).map_err(|_| DecodeError::InvalidTag("definition type", tag))
     }
 }
 
@@ -610,7 +740,7 @@ impl Encode for Timestamp {
 }
 
 impl Decode for Timestamp {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         Ok(Timestamp::from_bytes(input.decode()?))
     }
 }
@@ -647,7 +777,7 @@ impl<A> PoolIndex<A> {
 
 impl<A> Decode for PoolIndex<A> {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let index = input.decode::<u32>()?;
         Ok(PoolIndex {
             value: index,
@@ -756,14 +886,15 @@ pub enum PoolError {
 
 #[cfg(test)]
 mod tests {
-    use std::io::{self, Cursor};
+    use std::io::Cursor;
 
-    use super::ScriptBundle;
+    use super::{ScriptBundle, Version};
+    use crate::decode::{DecodeError, Table};
 
     const PREDEF: &[u8] = include_bytes!("../../resources/predef.redscripts");
 
     #[test]
-    fn reload_scripts() -> io::Result<()> {
+    fn reload_scripts() -> Result<(), DecodeError> {
         let scripts = ScriptBundle::load(&mut Cursor::new(PREDEF))?;
         let mut tmp = Cursor::new(Vec::new());
         scripts.save(&mut tmp)?;
@@ -772,4 +903,81 @@ mod tests {
         assert_eq!(scripts.pool.definitions.len(), scripts2.pool.definitions.len());
         Ok(())
     }
+
+    #[test]
+    fn preserve_version_on_save() -> Result<(), DecodeError> {
+        let mut scripts = ScriptBundle::load(&mut Cursor::new(PREDEF))?;
+        assert_eq!(scripts.version(), Version::V13);
+
+        for version in [Version::V12, Version::V13, Version::LATEST] {
+            scripts.header.version = version;
+            let mut tmp = Cursor::new(Vec::new());
+            scripts.save(&mut tmp)?;
+            tmp.set_position(0);
+            assert_eq!(ScriptBundle::load(&mut tmp)?.version(), version);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn load_unknown_version() -> Result<(), DecodeError> {
+        let mut scripts = ScriptBundle::load(&mut Cursor::new(PREDEF))?;
+        scripts.header.version = Version::new(15);
+        let mut tmp = Cursor::new(Vec::new());
+        scripts.save(&mut tmp)?;
+        tmp.set_position(0);
+
+        let loaded = ScriptBundle::load(&mut tmp)?;
+        assert_eq!(loaded.version(), Version::new(15));
+        assert_eq!(loaded.pool.definitions().count(), scripts.pool.definitions().count());
+        Ok(())
+    }
+
+    #[test]
+    fn load_corrupted_scripts() {
+        // truncated at every possible length
+        for len in 0..PREDEF.len() {
+            assert!(ScriptBundle::load(&mut Cursor::new(&PREDEF[..len])).is_err());
+        }
+        // every byte replaced with a few interesting values
+        for pos in 0..PREDEF.len() {
+            for byte in [0x00, 0x01, 0x7F, 0x80, 0xFF, !PREDEF[pos]] {
+                let mut bytes = PREDEF.to_vec();
+                bytes[pos] = byte;
+                drop(ScriptBundle::load(&mut Cursor::new(bytes)));
+            }
+        }
+        // random multi-byte corruption, seeded for reproducibility
+        let mut state = 0x2545_F491_4F6C_DD1D_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..10000 {
+            let mut bytes = PREDEF.to_vec();
+            for _ in 0..(next() % 16 + 1) {
+                let pos = next() as usize % bytes.len();
+                bytes[pos] = next() as u8;
+            }
+            drop(ScriptBundle::load(&mut Cursor::new(bytes)));
+        }
+    }
+
+    #[test]
+    fn report_decode_error_location() {
+        let mut bytes = PREDEF.to_vec();
+        bytes[0] = 0;
+        let err = ScriptBundle::load(&mut Cursor::new(bytes)).unwrap_err();
+        assert!(matches!(
+            err,
+            DecodeError::At {
+                table: Table::Header,
+                offset: 0,
+                ..
+            }
+        ));
+        assert!(matches!(err.cause(), DecodeError::InvalidHeader));
+    }
 }
@@ -4,12 +4,13 @@ use std::{io, usize};
 use strum::{Display, EnumString, IntoStaticStr};
 use thiserror::Error;
 
-use crate::bundle::{CName, PoolIndex, Resource, TweakDbId};
-use crate::decode::{Decode, DecodeExt};
+use crate::bundle::{CName, PoolIndex, Resource, TweakDbId, Version};
+use crate::decode::{Decode, DecodeError, DecodeExt, DecodeVersioned};
 use crate::definition::{Class, Enum, Field, Function, Local, Parameter, Type};
-use crate::encode::{Encode, EncodeExt};
+use crate::encode::{Encode, EncodeExt, EncodeVersioned};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, IntoStaticStr)]
+#[strum(serialize_all = "lowercase")]
 pub enum Instr<Loc> {
     Nop,
     Null,
@@ -118,6 +119,16 @@ pub enum Instr<Loc> {
 }
 
 impl<L> Instr<L> {
+    /// Returns the size of the instruction in the layout of the given version.
+    pub fn size_in(&self, version: Version) -> u16 {
+        match self {
+            Instr::InvokeStatic(_, _, _, _) | Instr::InvokeVirtual(_, _, _, _) if !version.has_invoke_flags() => {
+                self.size() - 2
+            }
+            _ => self.size(),
+        }
+    }
+
     pub fn size(&self) -> u16 {
         let op_size = match self {
             Instr::Breakpoint(_) => 19,
@@ -348,9 +359,26 @@ impl Instr<Label> {
     }
 }
 
-impl Decode for Instr<Offset> {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
-        let code: u8 = input.decode()?;
+impl Instr<Offset> {
+    /// Applies the function to each of the offsets of the instruction.
+    fn map_offsets<E>(self, mut f: impl FnMut(Offset) -> Result<Offset, E>) -> Result<Self, E> {
+        let res = match self {
+            Instr::Target(offset) => Instr::Target(f(offset)?),
+            Instr::Switch(idx, offset) => Instr::Switch(idx, f(offset)?),
+            Instr::SwitchLabel(first_case, exit) => Instr::SwitchLabel(f(first_case)?, f(exit)?),
+            Instr::Jump(offset) => Instr::Jump(f(offset)?),
+            Instr::JumpIfFalse(offset) => Instr::JumpIfFalse(f(offset)?),
+            Instr::Skip(offset) => Instr::Skip(f(offset)?),
+            Instr::Conditional(false_, exit) => Instr::Conditional(f(false_)?, f(exit)?),
+            Instr::InvokeStatic(offset, line, idx, flags) => Instr::InvokeStatic(f(offset)?, line, idx, flags),
+            Instr::InvokeVirtual(offset, line, idx, flags) => Instr::InvokeVirtual(f(offset)?, line, idx, flags),
+            Instr::Context(offset) => Instr::Context(f(offset)?),
+            other => other,
+        };
+        Ok(res)
+    }
+
+    fn decode_operands<I: io::Read>(code: u8, input: &mut I) -> Result<Self, DecodeError> {
         match code {
             0 => Ok(Instr::Nop),
             1 => Ok(Instr::Null),
@@ -380,28 +408,31 @@ impl Decode for Instr<Offset> {
             25 => Ok(Instr::Param(input.decode()?)),
             26 => Ok(Instr::ObjectField(input.decode()?)),
             27 => Ok(Instr::ExternalVar),
-            28 => Ok(Instr::Switch(input.decode()?, Offset::new(input.decode::<i16>()? + 11))),
+            28 => Ok(Instr::Switch(
+                input.decode()?,
+                Offset::new(input.decode::<i16>()?.wrapping_add(11)),
+            )),
             29 => Ok(Instr::SwitchLabel(
-                Offset::new(input.decode::<i16>()? + 3),
-                Offset::new(input.decode::<i16>()? + 5),
+                Offset::new(input.decode::<i16>()?.wrapping_add(3)),
+                Offset::new(input.decode::<i16>()?.wrapping_add(5)),
             )),
             30 => Ok(Instr::SwitchDefault),
-            31 => Ok(Instr::Jump(Offset::new(input.decode::<i16>()? + 3))),
-            32 => Ok(Instr::JumpIfFalse(Offset::new(input.decode::<i16>()? + 3))),
-            33 => Ok(Instr::Skip(Offset::new(input.decode::<i16>()? + 3))),
+            31 => Ok(Instr::Jump(Offset::new(input.decode::<i16>()?.wrapping_add(3)))),
+            32 => Ok(Instr::JumpIfFalse(Offset::new(input.decode::<i16>()?.wrapping_add(3)))),
+            33 => Ok(Instr::Skip(Offset::new(input.decode::<i16>()?.wrapping_add(3)))),
             34 => Ok(Instr::Conditional(
-                Offset::new(input.decode::<i16>()? + 3),
-                Offset::new(input.decode::<i16>()? + 5),
+                Offset::new(input.decode::<i16>()?.wrapping_add(3)),
+                Offset::new(input.decode::<i16>()?.wrapping_add(5)),
             )),
             35 => Ok(Instr::Construct(input.decode()?, input.decode()?)),
             36 => Ok(Instr::InvokeStatic(
-                Offset::new(input.decode::<i16>()? + 3),
+                Offset::new(input.decode::<i16>()?.wrapping_add(3)),
                 input.decode()?,
                 input.decode()?,
                 input.decode()?,
             )),
             37 => Ok(Instr::InvokeVirtual(
-                Offset::new(input.decode::<i16>()? + 3),
+                Offset::new(input.decode::<i16>()?.wrapping_add(3)),
                 input.decode()?,
                 input.decode()?,
                 input.decode()?,
@@ -409,7 +440,7 @@ impl Decode for Instr<Offset> {
             38 => Ok(Instr::ParamEnd),
             39 => Ok(Instr::Return),
             40 => Ok(Instr::StructField(input.decode()?)),
-            41 => Ok(Instr::Context(Offset::new(input.decode::<i16>()? + 3))),
+            41 => Ok(Instr::Context(Offset::new(input.decode::<i16>()?.wrapping_add(3)))),
             42 => Ok(Instr::Equals(input.decode()?)),
 
             43 => Ok(Instr::RefStringEqualsString(input.decode()?)),
@@ -478,14 +509,18 @@ impl Decode for Instr<Offset> {
             101 => Ok(Instr::WeakRefNull),
             102 => Ok(Instr::AsRef(input.decode()?)),
             103 => Ok(Instr::Deref(input.decode()?)),
-            other => {
-                let msg = format!("Invalid instruction code: {other}");
-                Err(io::Error::new(io::ErrorKind::InvalidData, msg))
-            }
+            other => Err(DecodeError::InvalidTag("instruction", other)),
         }
     }
 }
 
+impl Decode for Instr<Offset> {
+    #[inline]
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Self::decode_operands(input.decode()?, input)
+    }
+}
+
 impl Encode for Instr<Offset> {
     fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
         match self {
@@ -902,16 +937,16 @@ impl Encode for Instr<Offset> {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Breakpoint {
-    line: u16,
-    line_start: u32,
-    col: u16,
-    length: u16,
-    enabled: bool,
-    padding: u64,
+    pub line: u16,
+    pub line_start: u32,
+    pub col: u16,
+    pub length: u16,
+    pub enabled: bool,
+    pub padding: u64,
 }
 
 impl Decode for Breakpoint {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let line = input.decode()?;
         let line_start = input.decode()?;
         let col = input.decode()?;
@@ -943,10 +978,10 @@ impl Encode for Breakpoint {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-pub struct StartProfiling(String, u8);
+pub struct StartProfiling(pub String, pub u8);
 
 impl Decode for StartProfiling {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let function = input.decode_str_prefixed::<u32>()?;
         let enabled = input.decode()?;
         Ok(StartProfiling(function, enabled))
@@ -1003,7 +1038,7 @@ impl Offset {
 
 impl Decode for Offset {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         Ok(Offset::new(input.decode()?))
     }
 }
@@ -1039,31 +1074,132 @@ impl<Loc: Clone> Code<Loc> {
     }
 }
 
+impl DecodeVersioned for Instr<Offset> {
+    fn decode_versioned<I: io::Read>(input: &mut I, version: Version) -> Result<Self, DecodeError> {
+        let code: u8 = input.decode()?;
+        match code {
+            36 if !version.has_invoke_flags() => Ok(Instr::InvokeStatic(
+                Offset::new(input.decode::<i16>()?.wrapping_add(3)),
+                input.decode()?,
+                input.decode()?,
+                0,
+            )),
+            37 if !version.has_invoke_flags() => Ok(Instr::InvokeVirtual(
+                Offset::new(input.decode::<i16>()?.wrapping_add(3)),
+                input.decode()?,
+                input.decode()?,
+                0,
+            )),
+            _ => Self::decode_operands(code, input),
+        }
+    }
+}
+
+impl EncodeVersioned for Instr<Offset> {
+    fn encode_versioned<O: io::Write>(&self, output: &mut O, version: Version) -> io::Result<()> {
+        match self {
+            Instr::InvokeStatic(offset, line, idx, _) if !version.has_invoke_flags() => {
+                output.encode(&36u8)?;
+                output.encode(&Offset::new(offset.value - 3))?;
+                output.encode(line)?;
+                output.encode(idx)
+            }
+            Instr::InvokeVirtual(offset, line, idx, _) if !version.has_invoke_flags() => {
+                output.encode(&37u8)?;
+                output.encode(&Offset::new(offset.value - 3))?;
+                output.encode(line)?;
+                output.encode(idx)
+            }
+            _ => output.encode(self),
+        }
+    }
+}
+
 impl Decode for Code<Offset> {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    #[inline]
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Self::decode_versioned(input, Version::LATEST)
+    }
+}
+
+impl DecodeVersioned for Code<Offset> {
+    fn decode_versioned<I: io::Read>(input: &mut I, version: Version) -> Result<Self, DecodeError> {
         let max_offset: u32 = input.decode()?;
         let mut offset = 0;
         let mut code = Vec::new();
         while offset < max_offset {
-            let instr: Instr<Offset> = input.decode()?;
-            offset += u32::from(instr.size());
+            let instr: Instr<Offset> = input.decode_versioned(version)?;
+            offset += u32::from(instr.size_in(version));
             code.push(instr);
         }
-        Ok(Code(code))
+        if version.has_invoke_flags() {
+            return Ok(Code(code));
+        }
+        relocate(code, |instr| instr.size_in(version), Instr::size).map_err(DecodeError::InvalidOffset)
     }
 }
 
 impl Encode for Code<Offset> {
+    #[inline]
     fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
-        let size: u32 = self.0.iter().map(|i| u32::from(i.size())).sum();
+        self.encode_versioned(output, Version::LATEST)
+    }
+}
+
+impl EncodeVersioned for Code<Offset> {
+    fn encode_versioned<O: io::Write>(&self, output: &mut O, version: Version) -> io::Result<()> {
+        let relocated;
+        let code = if version.has_invoke_flags() {
+            self
+        } else {
+            relocated = relocate(self.0.clone(), Instr::size, |instr| instr.size_in(version))
+                .map_err(|offset| io::Error::new(io::ErrorKind::InvalidData, format!("invalid offset: {offset}")))?;
+            &relocated
+        };
+        let size: u32 = code.0.iter().map(|i| u32::from(i.size_in(version))).sum();
         output.encode(&size)?;
-        for instr in &self.0 {
-            output.encode(instr)?;
+        for instr in &code.0 {
+            output.encode_versioned(instr, version)?;
         }
         Ok(())
     }
 }
 
+/// Translates the offsets of the instructions between two layouts that differ in the sizes of
+/// some instructions. Fails with the offending offset when it does not point at an instruction.
+fn relocate(
+    code: Vec<Instr<Offset>>,
+    from: impl Fn(&Instr<Offset>) -> u16,
+    to: impl Fn(&Instr<Offset>) -> u16,
+) -> Result<Code<Offset>, i16> {
+    let positions = |size: &dyn Fn(&Instr<Offset>) -> u16| {
+        let mut pos = 0i32;
+        let mut res = Vec::with_capacity(code.len() + 1);
+        for instr in &code {
+            res.push(pos);
+            pos += i32::from(size(instr));
+        }
+        res.push(pos);
+        res
+    };
+    let old = positions(&from);
+    let new = positions(&to);
+
+    let code = code
+        .into_iter()
+        .enumerate()
+        .map(|(i, instr)| {
+            instr.map_offsets(|offset| {
+                let target = old
+                    .binary_search(&(old[i] + i32::from(offset.value)))
+                    .map_err(|_| offset.value)?;
+                Ok(Offset::new((new[target] - new[i]) as i16))
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(Code(code))
+}
+
 #[derive(Debug, Clone, Copy, EnumString, Display, IntoStaticStr)]
 pub enum IntrinsicOp {
     Equals,
@@ -1258,4 +1394,44 @@ mod tests {
     fn instr_size() {
         assert_eq!(std::mem::size_of::<Instr<Offset>>(), 16);
     }
+
+    #[test]
+    fn decode_random_code() {
+        let mut state = 0x9E37_79B9_7F4A_7C15_u64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..10000 {
+            let len = next() as usize % 64;
+            let mut bytes: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            if let Some(size) = bytes.get_mut(..4) {
+                size.copy_from_slice(&(len as u32).to_le_bytes());
+            }
+            drop(io::Cursor::new(bytes).decode::<Code<Offset>>());
+        }
+    }
+
+    #[test]
+    fn relocate_code_without_invoke_flags() {
+        let code = Code(vec![
+            Instr::JumpIfFalse(Offset::new(19)),
+            Instr::InvokeStatic(Offset::new(16), 1, PoolIndex::UNDEFINED, 0),
+            Instr::ParamEnd,
+            Instr::Return,
+        ]);
+        let mut bytes = io::Cursor::new(vec![]);
+        bytes.encode_versioned(&code, Version::V12).unwrap();
+        let bytes = bytes.into_inner();
+
+        assert_eq!(bytes[..4], 18u32.to_le_bytes());
+        assert_eq!(bytes.len(), 4 + 18);
+        // the jump lands on the return, which moved back by the two bytes of the flags
+        assert_eq!(bytes[5..7], 14i16.to_le_bytes());
+
+        let decoded: Code<Offset> = io::Cursor::new(bytes).decode_versioned(Version::V12).unwrap();
+        assert_eq!(decoded, code);
+    }
 }
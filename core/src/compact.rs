@@ -0,0 +1,176 @@
+use hashbrown::HashMap;
+
+use crate::bundle::{CName, ConstantPool, DefaultString, PoolError, PoolIndex, Resource, Strings, TweakDbId};
+use crate::definition::{AnyDefinition, Definition};
+use crate::mapper::{visit_indexes, IndexVisitor, MultiMapper, ReferenceMapper};
+
+/// The number of entries removed from each table of the pool by [`compact_pool`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CompactionStats {
+    pub definitions: usize,
+    pub names: usize,
+    pub strings: usize,
+    pub tweakdb_ids: usize,
+    pub resources: usize,
+}
+
+/// Removes the definitions and strings that cannot be reached from any class, enum, global function
+/// or source file and remaps all of the remaining references.
+pub fn compact_pool(pool: &mut ConstantPool) -> Result<CompactionStats, PoolError> {
+    let marker = Marker::mark(pool)?;
+
+    let definitions = remapped(&marker.definitions);
+    let names = remapped(&marker.names);
+    let strings = remapped(&marker.strings);
+    let tweakdb_ids = remapped(&marker.tweakdb_ids);
+    let resources = remapped(&marker.resources);
+    ReferenceMapper::new(
+        MultiMapper::new(definitions),
+        MultiMapper::new(names),
+        MultiMapper::new(strings),
+        MultiMapper::new(tweakdb_ids),
+        MultiMapper::new(resources),
+    )
+    .map(pool);
+
+    let mut reachable = marker.definitions.iter();
+    pool.definitions.retain(|_| *reachable.next().unwrap());
+
+    let stats = CompactionStats {
+        definitions: count_removed(&marker.definitions),
+        names: count_removed(&marker.names),
+        strings: count_removed(&marker.strings),
+        tweakdb_ids: count_removed(&marker.tweakdb_ids),
+        resources: count_removed(&marker.resources),
+    };
+    pool.names = retained(&pool.names, &marker.names);
+    pool.strings = retained(&pool.strings, &marker.strings);
+    pool.tweakdb_ids = retained(&pool.tweakdb_ids, &marker.tweakdb_ids);
+    pool.resources = retained(&pool.resources, &marker.resources);
+    Ok(stats)
+}
+
+fn remapped<A>(marks: &[bool]) -> HashMap<PoolIndex<A>, PoolIndex<A>> {
+    marks
+        .iter()
+        .enumerate()
+        .filter(|(_, reachable)| **reachable)
+        .enumerate()
+        .filter(|(new, (old, _))| new != old)
+        .map(|(new, (old, _))| (PoolIndex::new(old as u32), PoolIndex::new(new as u32)))
+        .collect()
+}
+
+fn retained<K: DefaultString>(strings: &Strings<K>, marks: &[bool]) -> Strings<K> {
+    let strings = strings
+        .iter()
+        .zip(marks)
+        .filter(|(_, reachable)| **reachable)
+        .map(|(str, _)| str.clone())
+        .collect();
+    Strings::from_vec(strings)
+}
+
+fn count_removed(marks: &[bool]) -> usize {
+    marks.iter().filter(|reachable| !**reachable).count()
+}
+
+struct Marker {
+    definitions: Vec<bool>,
+    names: Vec<bool>,
+    strings: Vec<bool>,
+    tweakdb_ids: Vec<bool>,
+    resources: Vec<bool>,
+    queue: Vec<PoolIndex<Definition>>,
+    error: Option<PoolError>,
+}
+
+impl Marker {
+    fn mark(pool: &mut ConstantPool) -> Result<Self, PoolError> {
+        let mut marker = Marker {
+            definitions: vec![false; pool.definitions.len()],
+            names: vec![false; pool.names.iter().len()],
+            strings: vec![false; pool.strings.iter().len()],
+            tweakdb_ids: vec![false; pool.tweakdb_ids.iter().len()],
+            resources: vec![false; pool.resources.iter().len()],
+            queue: vec![],
+            error: None,
+        };
+        // the undefined entries have to stay in place
+        marker.definitions[0] = true;
+        for marks in [&mut marker.names, &mut marker.tweakdb_ids, &mut marker.resources] {
+            if let Some(none) = marks.first_mut() {
+                *none = true;
+            }
+        }
+
+        marker.queue = pool
+            .roots()
+            .filter(|(_, def)| {
+                matches!(
+                    def.value,
+                    AnyDefinition::Class(_)
+                        | AnyDefinition::Enum(_)
+                        | AnyDefinition::Function(_)
+                        | AnyDefinition::SourceFile(_)
+                )
+            })
+            .map(|(idx, _)| idx)
+            .collect();
+
+        while let Some(index) = marker.queue.pop() {
+            let position = u32::from(index) as usize;
+            if marker.definitions[position] {
+                continue;
+            }
+            marker.definitions[position] = true;
+            visit_indexes(&mut pool.definitions[position], &mut marker);
+            if let Some(err) = marker.error.take() {
+                return Err(err);
+            }
+        }
+        Ok(marker)
+    }
+
+    fn mark_string<K>(marks: &mut [bool], index: PoolIndex<K>, error: &mut Option<PoolError>) {
+        match marks.get_mut(u32::from(index) as usize) {
+            Some(mark) => *mark = true,
+            None => *error = Some(PoolError::StringNotFound(index.cast())),
+        }
+    }
+}
+
+impl IndexVisitor for Marker {
+    fn visit_definition(&mut self, index: &mut PoolIndex<Definition>) {
+        match self.definitions.get(u32::from(*index) as usize) {
+            Some(true) => {}
+            Some(false) => self.queue.push(*index),
+            None => self.error = Some(PoolError::DefinitionNotFound(*index)),
+        }
+    }
+
+    fn visit_name(&mut self, index: &mut PoolIndex<CName>) {
+        if index.is_undefined() {
+            return;
+        }
+        Self::mark_string(&mut self.names, *index, &mut self.error);
+    }
+
+    fn visit_string(&mut self, index: &mut PoolIndex<String>) {
+        Self::mark_string(&mut self.strings, *index, &mut self.error);
+    }
+
+    fn visit_tweakdb_id(&mut self, index: &mut PoolIndex<TweakDbId>) {
+        if index.is_undefined() {
+            return;
+        }
+        Self::mark_string(&mut self.tweakdb_ids, *index, &mut self.error);
+    }
+
+    fn visit_resource(&mut self, index: &mut PoolIndex<Resource>) {
+        if index.is_undefined() {
+            return;
+        }
+        Self::mark_string(&mut self.resources, *index, &mut self.error);
+    }
+}
@@ -1,90 +1,103 @@
-use std::io;
+use std::io::Read;
+use std::{fmt, io};
 
 use byteorder::{LittleEndian, ReadBytesExt};
+use thiserror::Error;
+
+use crate::bundle::Version;
+
+/// Upper bound on the number of elements preallocated for a length-prefixed sequence.
+/// Lengths are read from untrusted input, so anything past this grows on demand instead.
+const MAX_PREALLOC: usize = 0x1000;
 
 pub trait Decode: Sized {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self>;
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError>;
+}
+
+/// Decoding of entries whose layout depends on the version of the script cache format.
+pub trait DecodeVersioned: Sized {
+    fn decode_versioned<I: io::Read>(input: &mut I, version: Version) -> Result<Self, DecodeError>;
 }
 
 impl Decode for i64 {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
-        input.read_i64::<LittleEndian>()
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(input.read_i64::<LittleEndian>()?)
     }
 }
 
 impl Decode for i32 {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
-        input.read_i32::<LittleEndian>()
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(input.read_i32::<LittleEndian>()?)
     }
 }
 
 impl Decode for i16 {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
-        input.read_i16::<LittleEndian>()
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(input.read_i16::<LittleEndian>()?)
     }
 }
 
 impl Decode for i8 {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
-        input.read_i8()
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(input.read_i8()?)
     }
 }
 
 impl Decode for u64 {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
-        input.read_u64::<LittleEndian>()
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(input.read_u64::<LittleEndian>()?)
     }
 }
 
 impl Decode for u32 {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
-        input.read_u32::<LittleEndian>()
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(input.read_u32::<LittleEndian>()?)
     }
 }
 
 impl Decode for u16 {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
-        input.read_u16::<LittleEndian>()
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(input.read_u16::<LittleEndian>()?)
     }
 }
 
 impl Decode for u8 {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
-        input.read_u8()
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(input.read_u8()?)
     }
 }
 
 impl Decode for bool {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         Ok(input.read_u8()? != 0)
     }
 }
 
 impl Decode for f64 {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
-        input.read_f64::<LittleEndian>()
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(input.read_f64::<LittleEndian>()?)
     }
 }
 
 impl Decode for f32 {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
-        input.read_f32::<LittleEndian>()
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(input.read_f32::<LittleEndian>()?)
     }
 }
 
 impl Decode for String {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let mut buf = Vec::new();
         loop {
             let c = input.read_u8()?;
@@ -93,13 +106,13 @@ impl Decode for String {
             }
             buf.push(c);
         }
-        String::from_utf8(buf).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+        Ok(String::from_utf8(buf)?)
     }
 }
 
 impl<const N: usize> Decode for [u8; N] {
     #[inline]
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let mut buf: [u8; N] = [0; N];
         input.read_exact(&mut buf)?;
         Ok(buf)
@@ -108,39 +121,121 @@ impl<const N: usize> Decode for [u8; N] {
 
 pub trait DecodeExt: io::Read + Sized {
     #[inline]
-    fn decode<A: Decode>(&mut self) -> io::Result<A> {
+    fn decode<A: Decode>(&mut self) -> Result<A, DecodeError> {
         Decode::decode(self)
     }
 
-    fn decode_vec<S: Into<u32>, A: Decode>(&mut self, count: S) -> io::Result<Vec<A>> {
+    #[inline]
+    fn decode_versioned<A: DecodeVersioned>(&mut self, version: Version) -> Result<A, DecodeError> {
+        DecodeVersioned::decode_versioned(self, version)
+    }
+
+    fn decode_vec<S: Into<u32>, A: Decode>(&mut self, count: S) -> Result<Vec<A>, DecodeError> {
         let size = count.into() as usize;
-        let mut vec = Vec::with_capacity(size);
+        let mut vec = Vec::with_capacity(size.min(MAX_PREALLOC));
         for _ in 0..size {
             vec.push(self.decode()?);
         }
         Ok(vec)
     }
 
-    fn decode_vec_prefixed<S: Decode + Into<u32>, A: Decode>(&mut self) -> io::Result<Vec<A>> {
+    fn decode_vec_prefixed<S: Decode + Into<u32>, A: Decode>(&mut self) -> Result<Vec<A>, DecodeError> {
         let size: S = self.decode()?;
         self.decode_vec(size)
     }
 
-    fn decode_bytes<S: Into<u32>>(&mut self, count: S) -> io::Result<Vec<u8>> {
+    fn decode_bytes<S: Into<u32>>(&mut self, count: S) -> Result<Vec<u8>, DecodeError> {
         let size = count.into() as usize;
-        let mut vec = vec![0; size];
-        self.read_exact(&mut vec)?;
+        let mut vec = Vec::with_capacity(size.min(MAX_PREALLOC));
+        self.by_ref().take(size as u64).read_to_end(&mut vec)?;
+        if vec.len() != size {
+            return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+        }
         Ok(vec)
     }
 
-    fn decode_str<S: Into<u32>>(&mut self, count: S) -> io::Result<String> {
-        String::from_utf8(self.decode_bytes(count)?).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    fn decode_str<S: Into<u32>>(&mut self, count: S) -> Result<String, DecodeError> {
+        Ok(String::from_utf8(self.decode_bytes(count)?)?)
     }
 
-    fn decode_str_prefixed<S: Decode + Into<u32>>(&mut self) -> io::Result<String> {
+    fn decode_str_prefixed<S: Decode + Into<u32>>(&mut self) -> Result<String, DecodeError> {
         let size: S = self.decode()?;
         self.decode_str(size)
     }
 }
 
 impl<I: io::Read> DecodeExt for I {}
+
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("{0}")]
+    Io(#[from] io::Error),
+    #[error("invalid UTF-8 string: {0}")]
+    InvalidString(#[from] std::string::FromUtf8Error),
+    #[error("invalid file header")]
+    InvalidHeader,
+    #[error("invalid bytecode offset: {0}")]
+    InvalidOffset(i16),
+    #[error("invalid {0} tag: {1}")]
+    InvalidTag(&'static str, u8),
+    #[error("{0} definitions are not supported")]
+    UnsupportedDefinition(&'static str),
+    #[error("{cause} at offset {offset:#x} in the {table} table{}", index.map(|i| format!(" (entry {i})")).unwrap_or_default())]
+    At {
+        cause: Box<DecodeError>,
+        offset: u64,
+        table: Table,
+        index: Option<u32>,
+    },
+}
+
+impl DecodeError {
+    /// Attaches the location of the entry being decoded to the error.
+    pub fn at(self, offset: u64, table: Table, index: Option<u32>) -> Self {
+        match self {
+            Self::At { .. } => self,
+            cause => Self::At {
+                cause: Box::new(cause),
+                offset,
+                table,
+                index,
+            },
+        }
+    }
+
+    /// Returns the underlying error without the location.
+    pub fn cause(&self) -> &DecodeError {
+        match self {
+            Self::At { cause, .. } => cause,
+            other => other,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Table {
+    Header,
+    Data,
+    Names,
+    TweakDbIds,
+    Resources,
+    DefinitionHeaders,
+    Definitions,
+    Strings,
+}
+
+impl fmt::Display for Table {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Table::Header => "header",
+            Table::Data => "data",
+            Table::Names => "names",
+            Table::TweakDbIds => "TweakDBID",
+            Table::Resources => "resources",
+            Table::DefinitionHeaders => "definition header",
+            Table::Definitions => "definitions",
+            Table::Strings => "strings",
+        };
+        f.write_str(name)
+    }
+}
@@ -5,10 +5,10 @@ use std::path::PathBuf;
 use enum_as_inner::EnumAsInner;
 use modular_bitfield::prelude::*;
 
-use crate::bundle::{CName, ConstantPool, DefinitionHeader, DefinitionType, PoolIndex};
+use crate::bundle::{CName, ConstantPool, DefinitionHeader, DefinitionType, PoolIndex, Version};
 use crate::bytecode::{Code, Offset};
-use crate::decode::{Decode, DecodeExt};
-use crate::encode::{Encode, EncodeExt};
+use crate::decode::{Decode, DecodeError, DecodeExt, DecodeVersioned};
+use crate::encode::{Encode, EncodeExt, EncodeVersioned};
 
 #[derive(Debug, Clone)]
 pub struct Definition {
@@ -30,21 +30,14 @@ impl Definition {
         value: AnyDefinition::Type(Type::Prim),
     };
 
-    pub fn decode<I: io::Read + io::Seek>(input: &mut I, header: &DefinitionHeader) -> io::Result<Definition> {
+    pub fn decode<I: io::Read + io::Seek>(
+        input: &mut I,
+        header: &DefinitionHeader,
+        version: Version,
+    ) -> Result<Definition, DecodeError> {
         input.seek(io::SeekFrom::Start(header.offset.into()))?;
 
-        let value = match header.type_ {
-            DefinitionType::Type => AnyDefinition::Type(input.decode()?),
-            DefinitionType::Class => AnyDefinition::Class(input.decode()?),
-            DefinitionType::EnumValue => AnyDefinition::EnumValue(input.decode()?),
-            DefinitionType::Enum => AnyDefinition::Enum(input.decode()?),
-            DefinitionType::BitField => panic!("Bit field not supported"),
-            DefinitionType::Function => AnyDefinition::Function(input.decode()?),
-            DefinitionType::Parameter => AnyDefinition::Parameter(input.decode()?),
-            DefinitionType::Local => AnyDefinition::Local(input.decode()?),
-            DefinitionType::Field => AnyDefinition::Field(input.decode()?),
-            DefinitionType::SourceFile => AnyDefinition::SourceFile(input.decode()?),
-        };
+        let value = AnyDefinition::decode_as(input, header.type_, version)?;
         let definition = Definition {
             name: header.name,
             parent: header.parent,
@@ -159,16 +152,44 @@ impl AnyDefinition {
             AnyDefinition::SourceFile(_) => DefinitionType::SourceFile,
         }
     }
+
+    /// Decodes a definition value of the given type.
+    pub fn decode_as<I: io::Read>(
+        input: &mut I,
+        type_: DefinitionType,
+        version: Version,
+    ) -> Result<AnyDefinition, DecodeError> {
+        let value = match type_ {
+            DefinitionType::Type => AnyDefinition::Type(input.decode()?),
+            DefinitionType::Class => AnyDefinition::Class(input.decode()?),
+            DefinitionType::EnumValue => AnyDefinition::EnumValue(input.decode()?),
+            DefinitionType::Enum => AnyDefinition::Enum(input.decode()?),
+            DefinitionType::BitField => return Err(DecodeError::UnsupportedDefinition("bit field")),
+            DefinitionType::Function => AnyDefinition::Function(input.decode_versioned(version)?),
+            DefinitionType::Parameter => AnyDefinition::Parameter(input.decode()?),
+            DefinitionType::Local => AnyDefinition::Local(input.decode()?),
+            DefinitionType::Field => AnyDefinition::Field(input.decode()?),
+            DefinitionType::SourceFile => AnyDefinition::SourceFile(input.decode()?),
+        };
+        Ok(value)
+    }
 }
 
 impl Encode for AnyDefinition {
+    #[inline]
     fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
+        self.encode_versioned(output, Version::LATEST)
+    }
+}
+
+impl EncodeVersioned for AnyDefinition {
+    fn encode_versioned<O: io::Write>(&self, output: &mut O, version: Version) -> io::Result<()> {
         match &self {
             AnyDefinition::Type(type_) => output.encode(type_),
             AnyDefinition::Class(class) => output.encode(class),
             AnyDefinition::EnumValue(value) => output.encode(value),
             AnyDefinition::Enum(enum_) => output.encode(enum_),
-            AnyDefinition::Function(fun) => output.encode(fun),
+            AnyDefinition::Function(fun) => output.encode_versioned(fun, version),
             AnyDefinition::Parameter(param) => output.encode(param),
             AnyDefinition::Local(local) => output.encode(local),
             AnyDefinition::Field(field) => output.encode(field),
@@ -188,7 +209,7 @@ pub struct Class {
 }
 
 impl Decode for Class {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let visibility = input.decode()?;
         let flags: ClassFlags = input.decode()?;
         let base = input.decode()?;
@@ -254,7 +275,7 @@ pub struct Enum {
 }
 
 impl Decode for Enum {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let flags = input.decode()?;
         let size = input.decode()?;
         let members = input.decode_vec_prefixed::<u32, PoolIndex<i64>>()?;
@@ -296,7 +317,14 @@ pub struct Function {
 }
 
 impl Decode for Function {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    #[inline]
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Self::decode_versioned(input, Version::LATEST)
+    }
+}
+
+impl DecodeVersioned for Function {
+    fn decode_versioned<I: io::Read>(input: &mut I, version: Version) -> Result<Self, DecodeError> {
         let visibility = input.decode()?;
         let flags: FunctionFlags = input.decode()?;
         let source = if flags.is_native() { None } else { Some(input.decode()?) };
@@ -331,7 +359,11 @@ impl Decode for Function {
             None
         };
         let cast = if flags.is_cast() { input.decode()? } else { 0u8 };
-        let code = if flags.has_body() { input.decode()? } else { Code::EMPTY };
+        let code = if flags.has_body() {
+            input.decode_versioned(version)?
+        } else {
+            Code::EMPTY
+        };
 
         let unk2 = if flags.unk4() {
             input.decode_vec_prefixed::<u32, PoolIndex<Parameter>>()?
@@ -359,7 +391,14 @@ impl Decode for Function {
 }
 
 impl Encode for Function {
+    #[inline]
     fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
+        self.encode_versioned(output, Version::LATEST)
+    }
+}
+
+impl EncodeVersioned for Function {
+    fn encode_versioned<O: io::Write>(&self, output: &mut O, version: Version) -> io::Result<()> {
         let flags = self
             .flags
             .with_has_return_value(self.return_type.is_some())
@@ -396,7 +435,7 @@ impl Encode for Function {
             output.encode(&self.cast)?;
         }
         if flags.has_body() {
-            output.encode(&self.code)?;
+            output.encode_versioned(&self.code, version)?;
         }
         if flags.unk4() {
             output.encode_slice_prefixed::<u32, PoolIndex<Parameter>>(&self.unk2)?;
@@ -416,7 +455,7 @@ pub struct Field {
 }
 
 impl Decode for Field {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let visibility = input.decode()?;
         let type_ = input.decode()?;
         let flags: FieldFlags = input.decode()?;
@@ -467,7 +506,7 @@ pub enum Type {
 }
 
 impl Decode for Type {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let tag: u8 = input.decode()?;
         match tag {
             0 => Ok(Type::Prim),
@@ -477,7 +516,7 @@ impl Decode for Type {
             4 => Ok(Type::Array(input.decode()?)),
             5 => Ok(Type::StaticArray(input.decode()?, input.decode()?)),
             6 => Ok(Type::ScriptRef(input.decode()?)),
-            _ => panic!("Unknown Type enum value {}", tag),
+            _ => Err(DecodeError::InvalidTag("type", tag)),
         }
     }
 }
@@ -525,7 +564,7 @@ impl Local {
 }
 
 impl Decode for Local {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let type_ = input.decode()?;
         let flags = input.decode()?;
         Ok(Local { type_, flags })
@@ -546,7 +585,7 @@ pub struct Parameter {
 }
 
 impl Decode for Parameter {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let type_ = input.decode()?;
         let flags = input.decode()?;
         Ok(Parameter { type_, flags })
@@ -568,7 +607,7 @@ pub struct SourceFile {
 }
 
 impl Decode for SourceFile {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let id = input.decode()?;
         let path_hash = input.decode()?;
         let raw_path = input.decode_str_prefixed::<u16>()?;
@@ -605,7 +644,7 @@ pub struct FieldFlags {
 }
 
 impl Decode for FieldFlags {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         Ok(FieldFlags::from_bytes(input.decode()?))
     }
 }
@@ -625,7 +664,7 @@ pub struct LocalFlags {
 }
 
 impl Decode for LocalFlags {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         Ok(LocalFlags::from_bytes(input.decode()?))
     }
 }
@@ -648,7 +687,7 @@ pub struct ParameterFlags {
 }
 
 impl Decode for ParameterFlags {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         Ok(ParameterFlags::from_bytes(input.decode()?))
     }
 }
@@ -676,7 +715,7 @@ pub struct ClassFlags {
 }
 
 impl Decode for ClassFlags {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         Ok(ClassFlags::from_bytes(input.decode()?))
     }
 }
@@ -720,7 +759,7 @@ pub struct FunctionFlags {
 }
 
 impl Decode for FunctionFlags {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         Ok(FunctionFlags::from_bytes(input.decode()?))
     }
 }
@@ -734,6 +773,11 @@ impl Encode for FunctionFlags {
 #[derive(BitfieldSpecifier)]
 #[bits = 8]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "lowercase")
+)]
 pub enum Visibility {
     Public = 0,
     Protected = 1,
@@ -752,8 +796,9 @@ impl Display for Visibility {
 }
 
 impl Decode for Visibility {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
-        Ok(Visibility::from_bytes(input.decode()?).expect("Invalid Visibility enum value"))
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        let tag = input.decode()?;
+        Visibility::from_bytes(tag).map_err(|_| DecodeError::InvalidTag("visibility", tag))
     }
 }
 
@@ -779,7 +824,7 @@ impl Default for SourceReference {
 }
 
 impl Decode for SourceReference {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let file = input.decode()?;
         let line = input.decode()?;
         let result = SourceReference { file, line };
@@ -795,13 +840,14 @@ impl Encode for SourceReference {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Property {
     pub name: String,
     pub value: String,
 }
 
 impl Decode for Property {
-    fn decode<I: io::Read>(input: &mut I) -> io::Result<Self> {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
         let name = input.decode_str_prefixed::<u16>()?;
         let value = input.decode_str_prefixed::<u16>()?;
         Ok(Property { name, value })
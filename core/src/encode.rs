@@ -4,10 +4,17 @@ use std::io;
 
 use byteorder::{LittleEndian, WriteBytesExt};
 
+use crate::bundle::Version;
+
 pub trait Encode {
     fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()>;
 }
 
+/// Encoding of entries whose layout depends on the version of the script cache format.
+pub trait EncodeVersioned {
+    fn encode_versioned<O: io::Write>(&self, output: &mut O, version: Version) -> io::Result<()>;
+}
+
 impl Encode for i64 {
     #[inline]
     fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
@@ -106,6 +113,11 @@ pub trait EncodeExt: io::Write + Sized {
         value.encode(self)
     }
 
+    #[inline]
+    fn encode_versioned<A: EncodeVersioned>(&mut self, value: &A, version: Version) -> io::Result<()> {
+        value.encode_versioned(self, version)
+    }
+
     fn encode_slice<A: Encode>(&mut self, value: &[A]) -> io::Result<()> {
         for elem in value {
             self.encode(elem)?;
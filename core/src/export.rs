@@ -0,0 +1,564 @@
+//! A serializable document representation of a whole script bundle.
+//!
+//! Every definition gets a unique symbol that other definitions use to refer to it:
+//!
+//! - classes, enums, types and functions use their qualified names (e.g. `Class::Method;Int32`)
+//! - fields are written as `Class.field`, enum members as `Enum.Member`
+//! - parameters and locals are prefixed with the symbol of their function (e.g. `Class::Method;Int32/arg`)
+//! - source files use their path
+//!
+//! Symbols that would otherwise clash get a unique `$n` suffix. Function bodies are written in the
+//! assembly format of the [`asm`](crate::asm) module. Undefined references are represented as missing values.
+//!
+//! The definitions are listed in the order of the pool, so importing a document that has been exported
+//! produces a bundle identical to the original.
+use std::path::PathBuf;
+
+use hashbrown::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::asm::{assemble, disassemble, symbol_name, AsmError};
+use crate::bundle::{
+    ConstantPool, DefaultString, Header, PoolError, PoolIndex, ScriptBundle, Strings, Timestamp, Version,
+};
+use crate::bytecode::Code;
+use crate::definition::{
+    AnyDefinition, Class, ClassFlags, Definition, Enum, Field, FieldFlags, Function, FunctionFlags, Local, LocalFlags,
+    Parameter, ParameterFlags, Property, SourceFile, SourceReference, Type, Visibility,
+};
+use crate::Ref;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BundleDocument {
+    pub header: HeaderDocument,
+    pub names: Vec<String>,
+    pub tweakdb_ids: Vec<String>,
+    pub resources: Vec<String>,
+    pub strings: Vec<String>,
+    pub definitions: Vec<DefinitionDocument>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct HeaderDocument {
+    pub version: u32,
+    pub flags: u32,
+    pub timestamp: u64,
+    pub unk3: u32,
+    pub chunks: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DefinitionDocument {
+    pub symbol: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    pub unk1: u8,
+    pub unk2: u8,
+    pub unk3: u8,
+    #[serde(flatten)]
+    pub value: ValueDocument,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ValueDocument {
+    Type {
+        #[serde(rename = "type")]
+        type_: TypeDocument,
+    },
+    Class(ClassDocument),
+    EnumValue {
+        value: i64,
+    },
+    Enum(EnumDocument),
+    Function(FunctionDocument),
+    Parameter {
+        #[serde(rename = "type")]
+        type_: String,
+        flags: u8,
+    },
+    Local {
+        #[serde(rename = "type")]
+        type_: String,
+        flags: u8,
+    },
+    Field(FieldDocument),
+    SourceFile {
+        id: u32,
+        path_hash: u64,
+        path: PathBuf,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TypeDocument {
+    Prim,
+    Class,
+    Ref(String),
+    WeakRef(String),
+    Array(String),
+    StaticArray(String, u32),
+    ScriptRef(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClassDocument {
+    pub visibility: Visibility,
+    pub flags: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub functions: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnumDocument {
+    pub flags: u8,
+    pub size: u8,
+    pub members: Vec<String>,
+    pub unk1: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FunctionDocument {
+    pub visibility: Visibility,
+    pub flags: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<SourceDocument>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub return_type: Option<String>,
+    pub unk1: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_method: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parameters: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub locals: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub operator: Option<u32>,
+    pub cast: u8,
+    /// The body of the function in the assembly format, one line per element.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub code: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unk2: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SourceDocument {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    pub line: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FieldDocument {
+    pub visibility: Visibility,
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub flags: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<Property>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub defaults: Vec<Property>,
+}
+
+/// Converts a bundle into a document with symbolic references.
+pub fn export_bundle(bundle: &ScriptBundle) -> Result<BundleDocument, ExportError> {
+    let pool = &bundle.pool;
+    let exporter = Exporter {
+        pool,
+        symbols: symbols(pool)?,
+    };
+
+    let header = &bundle.header;
+    let header = HeaderDocument {
+        version: header.version.into(),
+        flags: header.flags,
+        timestamp: u64::from_le_bytes(header.timestamp.into_bytes()),
+        unk3: header.unk3,
+        chunks: header.chunks,
+    };
+    let definitions = pool
+        .definitions()
+        .map(|(idx, def)| exporter.definition(idx, def))
+        .collect::<Result<_, _>>()?;
+
+    Ok(BundleDocument {
+        header,
+        names: strings(&pool.names),
+        tweakdb_ids: strings(&pool.tweakdb_ids),
+        resources: strings(&pool.resources),
+        strings: strings(&pool.strings),
+        definitions,
+    })
+}
+
+/// Rebuilds a bundle from a document, resolving the symbolic references.
+pub fn import_bundle(doc: BundleDocument) -> Result<ScriptBundle, ExportError> {
+    let header = Header::new(
+        Version::new(doc.header.version),
+        doc.header.flags,
+        Timestamp::from_bytes(doc.header.timestamp.to_le_bytes()),
+        doc.header.unk3,
+        doc.header.chunks,
+    );
+
+    let mut symbols = HashMap::with_capacity(doc.definitions.len());
+    for (i, def) in doc.definitions.iter().enumerate() {
+        if symbols
+            .insert(def.symbol.as_str(), PoolIndex::new(i as u32 + 1))
+            .is_some()
+        {
+            return Err(ExportError::DuplicateSymbol(def.symbol.clone()));
+        }
+    }
+
+    let mut pool = ConstantPool {
+        names: Strings::from_vec(doc.names.into_iter().map(Ref::from).collect()),
+        tweakdb_ids: Strings::from_vec(doc.tweakdb_ids.into_iter().map(Ref::from).collect()),
+        resources: Strings::from_vec(doc.resources.into_iter().map(Ref::from).collect()),
+        strings: Strings::from_vec(doc.strings.into_iter().map(Ref::from).collect()),
+        definitions: Vec::with_capacity(doc.definitions.len() + 1),
+    };
+    pool.definitions.push(Definition::DEFAULT);
+
+    let importer = Importer { symbols: &symbols };
+    let mut bodies = vec![];
+    for def in &doc.definitions {
+        let (definition, code) = importer.definition(def, &mut pool)?;
+        let index = pool.add_definition(definition);
+        if !code.is_empty() {
+            bodies.push((index, &def.symbol, code));
+        }
+    }
+
+    // bodies can only be assembled once all of the definitions they refer to are in the pool
+    for (index, symbol, code) in bodies {
+        let (code, _) =
+            assemble(&code.join("\n"), index, &mut pool).map_err(|err| ExportError::AsmError(symbol.clone(), err))?;
+        pool.function_mut(index)?.code = code;
+    }
+
+    Ok(ScriptBundle { header, pool })
+}
+
+fn strings<K: DefaultString>(strings: &Strings<K>) -> Vec<String> {
+    strings.iter().map(ToString::to_string).collect()
+}
+
+fn symbols(pool: &ConstantPool) -> Result<Vec<String>, PoolError> {
+    let mut seen = HashSet::new();
+    let mut symbols = vec![String::new()];
+    for (idx, _) in pool.definitions() {
+        let symbol = symbol_name(idx, pool)?;
+        let mut unique = symbol.clone();
+        let mut suffix = 0;
+        while !seen.insert(unique.clone()) {
+            suffix += 1;
+            unique = format!("{symbol}${suffix}");
+        }
+        symbols.push(unique);
+    }
+    Ok(symbols)
+}
+
+struct Exporter<'a> {
+    pool: &'a ConstantPool,
+    symbols: Vec<String>,
+}
+
+impl<'a> Exporter<'a> {
+    fn definition(&self, index: PoolIndex<Definition>, def: &Definition) -> Result<DefinitionDocument, ExportError> {
+        let value = match &def.value {
+            AnyDefinition::Type(type_) => ValueDocument::Type {
+                type_: self.type_(type_)?,
+            },
+            AnyDefinition::Class(class) => ValueDocument::Class(ClassDocument {
+                visibility: class.visibility,
+                flags: u16::from_le_bytes(class.flags.into_bytes()),
+                base: self.reference(class.base)?,
+                functions: self.symbol_list(&class.functions)?,
+                fields: self.symbol_list(&class.fields)?,
+                overrides: self.symbol_list(&class.overrides)?,
+            }),
+            AnyDefinition::EnumValue(value) => ValueDocument::EnumValue { value: *value },
+            AnyDefinition::Enum(enum_) => ValueDocument::Enum(EnumDocument {
+                flags: enum_.flags,
+                size: enum_.size,
+                members: self.symbol_list(&enum_.members)?,
+                unk1: enum_.unk1,
+            }),
+            AnyDefinition::Function(fun) => ValueDocument::Function(self.function(index, fun)?),
+            AnyDefinition::Parameter(param) => ValueDocument::Parameter {
+                type_: self.symbol(param.type_)?,
+                flags: param.flags.into_bytes()[0],
+            },
+            AnyDefinition::Local(local) => ValueDocument::Local {
+                type_: self.symbol(local.type_)?,
+                flags: local.flags.into_bytes()[0],
+            },
+            AnyDefinition::Field(field) => ValueDocument::Field(FieldDocument {
+                visibility: field.visibility,
+                type_: self.symbol(field.type_)?,
+                flags: u16::from_le_bytes(field.flags.into_bytes()),
+                hint: field.hint.clone(),
+                attributes: field.attributes.clone(),
+                defaults: field.defaults.clone(),
+            }),
+            AnyDefinition::SourceFile(file) => ValueDocument::SourceFile {
+                id: file.id,
+                path_hash: file.path_hash,
+                path: file.path.clone(),
+            },
+        };
+
+        Ok(DefinitionDocument {
+            symbol: self.symbol(index)?,
+            name: self.pool.names.get(def.name)?.to_string(),
+            parent: self.reference(def.parent)?,
+            unk1: def.unk1,
+            unk2: def.unk2,
+            unk3: def.unk3,
+            value,
+        })
+    }
+
+    fn function(&self, index: PoolIndex<Definition>, fun: &Function) -> Result<FunctionDocument, ExportError> {
+        let source = fun
+            .source
+            .as_ref()
+            .map(|source| {
+                Ok::<_, ExportError>(SourceDocument {
+                    file: self.reference(source.file)?,
+                    line: source.line,
+                })
+            })
+            .transpose()?;
+        let code = if fun.code.is_empty() {
+            vec![]
+        } else {
+            disassemble(fun, self.pool)
+                .map_err(|err| ExportError::AsmError(self.symbols[u32::from(index) as usize].clone(), err))?
+                .lines()
+                .map(str::to_owned)
+                .collect()
+        };
+
+        Ok(FunctionDocument {
+            visibility: fun.visibility,
+            flags: u32::from_le_bytes(fun.flags.into_bytes()),
+            source,
+            return_type: fun.return_type.map(|idx| self.symbol(idx)).transpose()?,
+            unk1: fun.unk1,
+            base_method: fun.base_method.map(|idx| self.symbol(idx)).transpose()?,
+            parameters: self.symbol_list(&fun.parameters)?,
+            locals: self.symbol_list(&fun.locals)?,
+            operator: fun.operator,
+            cast: fun.cast,
+            code,
+            unk2: self.symbol_list(&fun.unk2)?,
+        })
+    }
+
+    fn type_(&self, type_: &Type) -> Result<TypeDocument, PoolError> {
+        let res = match type_ {
+            Type::Prim => TypeDocument::Prim,
+            Type::Class => TypeDocument::Class,
+            Type::Ref(inner) => TypeDocument::Ref(self.symbol(*inner)?),
+            Type::WeakRef(inner) => TypeDocument::WeakRef(self.symbol(*inner)?),
+            Type::Array(inner) => TypeDocument::Array(self.symbol(*inner)?),
+            Type::StaticArray(inner, size) => TypeDocument::StaticArray(self.symbol(*inner)?, *size),
+            Type::ScriptRef(inner) => TypeDocument::ScriptRef(self.symbol(*inner)?),
+        };
+        Ok(res)
+    }
+
+    fn symbol<A>(&self, index: PoolIndex<A>) -> Result<String, PoolError> {
+        let value = u32::from(index) as usize;
+        match self.symbols.get(value) {
+            Some(symbol) if value != 0 => Ok(symbol.clone()),
+            _ => Err(PoolError::DefinitionNotFound(index.cast())),
+        }
+    }
+
+    fn reference<A>(&self, index: PoolIndex<A>) -> Result<Option<String>, PoolError> {
+        if index.is_undefined() {
+            Ok(None)
+        } else {
+            self.symbol(index).map(Some)
+        }
+    }
+
+    fn symbol_list<A>(&self, indexes: &[PoolIndex<A>]) -> Result<Vec<String>, PoolError> {
+        indexes.iter().map(|idx| self.symbol(*idx)).collect()
+    }
+}
+
+struct Importer<'a> {
+    symbols: &'a HashMap<&'a str, PoolIndex<Definition>>,
+}
+
+impl<'a> Importer<'a> {
+    fn definition<'b>(
+        &self,
+        def: &'b DefinitionDocument,
+        pool: &mut ConstantPool,
+    ) -> Result<(Definition, &'b [String]), ExportError> {
+        let mut code: &[String] = &[];
+        let value = match &def.value {
+            ValueDocument::Type { type_ } => AnyDefinition::Type(self.type_(type_)?),
+            ValueDocument::Class(class) => AnyDefinition::Class(Class {
+                visibility: class.visibility,
+                flags: ClassFlags::from_bytes(class.flags.to_le_bytes()),
+                base: self.reference(class.base.as_deref())?,
+                functions: self.resolve_list(&class.functions)?,
+                fields: self.resolve_list(&class.fields)?,
+                overrides: self.resolve_list(&class.overrides)?,
+            }),
+            ValueDocument::EnumValue { value } => AnyDefinition::EnumValue(*value),
+            ValueDocument::Enum(enum_) => AnyDefinition::Enum(Enum {
+                flags: enum_.flags,
+                size: enum_.size,
+                members: self.resolve_list(&enum_.members)?,
+                unk1: enum_.unk1,
+            }),
+            ValueDocument::Function(fun) => {
+                code = &fun.code;
+                AnyDefinition::Function(self.function(fun)?)
+            }
+            ValueDocument::Parameter { type_, flags } => AnyDefinition::Parameter(Parameter {
+                type_: self.resolve(type_)?,
+                flags: ParameterFlags::from_bytes([*flags]),
+            }),
+            ValueDocument::Local { type_, flags } => AnyDefinition::Local(Local {
+                type_: self.resolve(type_)?,
+                flags: LocalFlags::from_bytes([*flags]),
+            }),
+            ValueDocument::Field(field) => AnyDefinition::Field(Field {
+                visibility: field.visibility,
+                type_: self.resolve(&field.type_)?,
+                flags: FieldFlags::from_bytes(field.flags.to_le_bytes()),
+                hint: field.hint.clone(),
+                attributes: field.attributes.clone(),
+                defaults: field.defaults.clone(),
+            }),
+            ValueDocument::SourceFile { id, path_hash, path } => AnyDefinition::SourceFile(SourceFile {
+                id: *id,
+                path_hash: *path_hash,
+                path: path.clone(),
+            }),
+        };
+
+        let definition = Definition {
+            name: pool.names.add(def.name.as_str().into()),
+            parent: self.reference(def.parent.as_deref())?,
+            unk1: def.unk1,
+            unk2: def.unk2,
+            unk3: def.unk3,
+            value,
+        };
+        Ok((definition, code))
+    }
+
+    fn function(&self, fun: &FunctionDocument) -> Result<Function, ExportError> {
+        let source = fun
+            .source
+            .as_ref()
+            .map(|source| {
+                Ok::<_, ExportError>(SourceReference {
+                    file: self.reference(source.file.as_deref())?,
+                    line: source.line,
+                })
+            })
+            .transpose()?;
+
+        Ok(Function {
+            visibility: fun.visibility,
+            flags: FunctionFlags::from_bytes(fun.flags.to_le_bytes()),
+            source,
+            return_type: fun.return_type.as_deref().map(|sym| self.resolve(sym)).transpose()?,
+            unk1: fun.unk1,
+            base_method: fun.base_method.as_deref().map(|sym| self.resolve(sym)).transpose()?,
+            parameters: self.resolve_list(&fun.parameters)?,
+            locals: self.resolve_list(&fun.locals)?,
+            operator: fun.operator,
+            cast: fun.cast,
+            code: Code::EMPTY,
+            unk2: self.resolve_list(&fun.unk2)?,
+        })
+    }
+
+    fn type_(&self, type_: &TypeDocument) -> Result<Type, ExportError> {
+        let res = match type_ {
+            TypeDocument::Prim => Type::Prim,
+            TypeDocument::Class => Type::Class,
+            TypeDocument::Ref(inner) => Type::Ref(self.resolve(inner)?),
+            TypeDocument::WeakRef(inner) => Type::WeakRef(self.resolve(inner)?),
+            TypeDocument::Array(inner) => Type::Array(self.resolve(inner)?),
+            TypeDocument::StaticArray(inner, size) => Type::StaticArray(self.resolve(inner)?, *size),
+            TypeDocument::ScriptRef(inner) => Type::ScriptRef(self.resolve(inner)?),
+        };
+        Ok(res)
+    }
+
+    fn resolve<A>(&self, symbol: &str) -> Result<PoolIndex<A>, ExportError> {
+        self.symbols
+            .get(symbol)
+            .map(PoolIndex::cast)
+            .ok_or_else(|| ExportError::UnresolvedSymbol(symbol.to_owned()))
+    }
+
+    fn reference<A>(&self, symbol: Option<&str>) -> Result<PoolIndex<A>, ExportError> {
+        symbol.map_or(Ok(PoolIndex::UNDEFINED), |sym| self.resolve(sym))
+    }
+
+    fn resolve_list<A>(&self, symbols: &[String]) -> Result<Vec<PoolIndex<A>>, ExportError> {
+        symbols.iter().map(|sym| self.resolve(sym)).collect()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("pool error: {0}")]
+    PoolError(#[from] PoolError),
+    #[error("assembly error in {0}: {1}")]
+    AsmError(String, AsmError),
+    #[error("unresolved symbol: {0}")]
+    UnresolvedSymbol(String),
+    #[error("duplicate symbol: {0}")]
+    DuplicateSymbol(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::{export_bundle, import_bundle};
+    use crate::bundle::ScriptBundle;
+
+    const PREDEF: &[u8] = include_bytes!("../../resources/predef.redscripts");
+
+    #[test]
+    fn export_and_import_predef() {
+        let bundle = ScriptBundle::load(&mut Cursor::new(PREDEF)).unwrap();
+        let mut expected = Cursor::new(vec![]);
+        bundle.save(&mut expected).unwrap();
+
+        let doc = export_bundle(&bundle).unwrap();
+        assert_eq!(doc.definitions.len(), bundle.pool.definitions.len() - 1);
+
+        let mut actual = Cursor::new(vec![]);
+        import_bundle(doc).unwrap().save(&mut actual).unwrap();
+        assert!(expected.into_inner() == actual.into_inner());
+    }
+}
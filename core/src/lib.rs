@@ -1,11 +1,19 @@
+pub mod asm;
 pub mod ast;
 pub mod bundle;
 pub mod bytecode;
+pub mod compact;
 pub mod decode;
 pub mod definition;
 pub mod encode;
+#[cfg(feature = "serde")]
+pub mod export;
 pub mod io;
 pub mod mapper;
+pub mod package;
+pub mod verify;
+pub mod view;
+pub mod xref;
 
 #[cfg(not(feature = "arc"))]
 pub type Ref<A> = std::rc::Rc<A>;
@@ -3,9 +3,9 @@ use std::marker::PhantomData;
 
 use hashbrown::HashMap;
 
-use crate::bundle::{ConstantPool, PoolIndex};
+use crate::bundle::{CName, ConstantPool, PoolIndex, Resource, TweakDbId};
 use crate::bytecode::Instr;
-use crate::definition::{AnyDefinition, Class, Function};
+use crate::definition::{AnyDefinition, Class, Definition, Function, Type};
 
 pub trait Mapper<A> {
     fn apply(&self, value: A) -> A;
@@ -108,3 +108,191 @@ impl Default for PoolMapper<NoopMapper<PoolIndex<Class>>, NoopMapper<PoolIndex<F
         }
     }
 }
+
+/// A visitor of every definition and string index stored in a definition, see [`visit_indexes`].
+pub trait IndexVisitor {
+    fn visit_definition(&mut self, index: &mut PoolIndex<Definition>);
+    fn visit_name(&mut self, index: &mut PoolIndex<CName>);
+    fn visit_string(&mut self, index: &mut PoolIndex<String>);
+    fn visit_tweakdb_id(&mut self, index: &mut PoolIndex<TweakDbId>);
+    fn visit_resource(&mut self, index: &mut PoolIndex<Resource>);
+}
+
+/// Passes every index a definition refers to to the visitor, including the ones in its bytecode.
+pub fn visit_indexes<V: IndexVisitor>(def: &mut Definition, visitor: &mut V) {
+    visitor.visit_name(&mut def.name);
+    visitor.visit_definition(&mut def.parent);
+    match &mut def.value {
+        AnyDefinition::Type(type_) => match type_ {
+            Type::Ref(inner)
+            | Type::WeakRef(inner)
+            | Type::Array(inner)
+            | Type::StaticArray(inner, _)
+            | Type::ScriptRef(inner) => visit_definition(inner, visitor),
+            Type::Prim | Type::Class => {}
+        },
+        AnyDefinition::Class(class) => {
+            visit_definition(&mut class.base, visitor);
+            visit_definitions(&mut class.functions, visitor);
+            visit_definitions(&mut class.fields, visitor);
+            visit_definitions(&mut class.overrides, visitor);
+        }
+        AnyDefinition::Enum(enum_) => visit_definitions(&mut enum_.members, visitor),
+        AnyDefinition::Function(fun) => {
+            if let Some(source) = &mut fun.source {
+                visit_definition(&mut source.file, visitor);
+            }
+            if let Some(type_) = &mut fun.return_type {
+                visit_definition(type_, visitor);
+            }
+            if let Some(method) = &mut fun.base_method {
+                visit_definition(method, visitor);
+            }
+            visit_definitions(&mut fun.parameters, visitor);
+            visit_definitions(&mut fun.locals, visitor);
+            visit_definitions(&mut fun.unk2, visitor);
+            for instr in &mut fun.code.0 {
+                visit_instr_indexes(instr, visitor);
+            }
+        }
+        AnyDefinition::Parameter(param) => visit_definition(&mut param.type_, visitor),
+        AnyDefinition::Local(local) => visit_definition(&mut local.type_, visitor),
+        AnyDefinition::Field(field) => visit_definition(&mut field.type_, visitor),
+        AnyDefinition::EnumValue(_) | AnyDefinition::SourceFile(_) => {}
+    }
+}
+
+fn visit_instr_indexes<L, V: IndexVisitor>(instr: &mut Instr<L>, visitor: &mut V) {
+    match instr {
+        Instr::NameConst(idx) | Instr::InvokeVirtual(_, _, idx, _) => visitor.visit_name(idx),
+        Instr::StringConst(idx) => visitor.visit_string(idx),
+        Instr::TweakDbIdConst(idx) => visitor.visit_tweakdb_id(idx),
+        Instr::ResourceConst(idx) => visitor.visit_resource(idx),
+        Instr::EnumConst(enum_, member) => {
+            visit_definition(enum_, visitor);
+            visit_definition(member, visitor);
+        }
+        Instr::Local(idx) => visit_definition(idx, visitor),
+        Instr::Param(idx) => visit_definition(idx, visitor),
+        Instr::ObjectField(idx) | Instr::StructField(idx) => visit_definition(idx, visitor),
+        Instr::Construct(_, idx) | Instr::New(idx) | Instr::DynamicCast(idx, _) => visit_definition(idx, visitor),
+        Instr::InvokeStatic(_, _, idx, _) => visit_definition(idx, visitor),
+        Instr::Switch(idx, _)
+        | Instr::Equals(idx)
+        | Instr::RefStringEqualsString(idx)
+        | Instr::StringEqualsRefString(idx)
+        | Instr::NotEquals(idx)
+        | Instr::RefStringNotEqualsString(idx)
+        | Instr::StringNotEqualsRefString(idx)
+        | Instr::ArrayClear(idx)
+        | Instr::ArraySize(idx)
+        | Instr::ArrayResize(idx)
+        | Instr::ArrayFindFirst(idx)
+        | Instr::ArrayFindFirstFast(idx)
+        | Instr::ArrayFindLast(idx)
+        | Instr::ArrayFindLastFast(idx)
+        | Instr::ArrayContains(idx)
+        | Instr::ArrayContainsFast(idx)
+        | Instr::ArrayCount(idx)
+        | Instr::ArrayCountFast(idx)
+        | Instr::ArrayPush(idx)
+        | Instr::ArrayPop(idx)
+        | Instr::ArrayInsert(idx)
+        | Instr::ArrayRemove(idx)
+        | Instr::ArrayRemoveFast(idx)
+        | Instr::ArrayGrow(idx)
+        | Instr::ArrayErase(idx)
+        | Instr::ArrayEraseFast(idx)
+        | Instr::ArrayLast(idx)
+        | Instr::ArrayElement(idx)
+        | Instr::ArraySort(idx)
+        | Instr::ArraySortByPredicate(idx)
+        | Instr::StaticArraySize(idx)
+        | Instr::StaticArrayFindFirst(idx)
+        | Instr::StaticArrayFindFirstFast(idx)
+        | Instr::StaticArrayFindLast(idx)
+        | Instr::StaticArrayFindLastFast(idx)
+        | Instr::StaticArrayContains(idx)
+        | Instr::StaticArrayContainsFast(idx)
+        | Instr::StaticArrayCount(idx)
+        | Instr::StaticArrayCountFast(idx)
+        | Instr::StaticArrayLast(idx)
+        | Instr::StaticArrayElement(idx)
+        | Instr::EnumToI32(idx, _)
+        | Instr::I32ToEnum(idx, _)
+        | Instr::ToString(idx)
+        | Instr::ToVariant(idx)
+        | Instr::FromVariant(idx)
+        | Instr::AsRef(idx)
+        | Instr::Deref(idx) => visit_definition(idx, visitor),
+        _ => {}
+    }
+}
+
+#[inline]
+fn visit_definition<A, V: IndexVisitor>(index: &mut PoolIndex<A>, visitor: &mut V) {
+    let mut def = index.cast();
+    visitor.visit_definition(&mut def);
+    *index = def.cast();
+}
+
+fn visit_definitions<A, V: IndexVisitor>(indexes: &mut [PoolIndex<A>], visitor: &mut V) {
+    for index in indexes {
+        visit_definition(index, visitor);
+    }
+}
+
+/// Rewrites every index in the pool according to the mappings.
+pub struct ReferenceMapper {
+    definitions: MultiMapper<PoolIndex<Definition>>,
+    names: MultiMapper<PoolIndex<CName>>,
+    strings: MultiMapper<PoolIndex<String>>,
+    tweakdb_ids: MultiMapper<PoolIndex<TweakDbId>>,
+    resources: MultiMapper<PoolIndex<Resource>>,
+}
+
+impl ReferenceMapper {
+    pub fn new(
+        definitions: MultiMapper<PoolIndex<Definition>>,
+        names: MultiMapper<PoolIndex<CName>>,
+        strings: MultiMapper<PoolIndex<String>>,
+        tweakdb_ids: MultiMapper<PoolIndex<TweakDbId>>,
+        resources: MultiMapper<PoolIndex<Resource>>,
+    ) -> Self {
+        Self {
+            definitions,
+            names,
+            strings,
+            tweakdb_ids,
+            resources,
+        }
+    }
+
+    pub fn map(&mut self, pool: &mut ConstantPool) {
+        for def in &mut pool.definitions {
+            visit_indexes(def, self);
+        }
+    }
+}
+
+impl IndexVisitor for ReferenceMapper {
+    fn visit_definition(&mut self, index: &mut PoolIndex<Definition>) {
+        *index = self.definitions.apply(*index);
+    }
+
+    fn visit_name(&mut self, index: &mut PoolIndex<CName>) {
+        *index = self.names.apply(*index);
+    }
+
+    fn visit_string(&mut self, index: &mut PoolIndex<String>) {
+        *index = self.strings.apply(*index);
+    }
+
+    fn visit_tweakdb_id(&mut self, index: &mut PoolIndex<TweakDbId>) {
+        *index = self.tweakdb_ids.apply(*index);
+    }
+
+    fn visit_resource(&mut self, index: &mut PoolIndex<Resource>) {
+        *index = self.resources.apply(*index);
+    }
+}
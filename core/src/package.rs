@@ -0,0 +1,661 @@
+//! Precompiled packages of definitions that can be linked into a pool without their sources.
+//!
+//! A package holds the definitions that a compilation has added to or changed in a base pool. It carries
+//! its own string tables and refers to the definitions of the base pool through imports that are resolved
+//! by their [`symbol_name`] at link time, so it can be linked into any pool that provides them.
+//! The definitions are linked with the semantics of the annotations they were compiled from:
+//!
+//! - new definitions are appended to the pool
+//! - patched classes receive the functions, fields and overrides they are missing (`@addMethod`, `@addField`)
+//! - other patched definitions replace their target (`@replaceMethod`, `@replaceGlobal`)
+//! - wrapped functions move the current body of their target to a new function (`@wrapMethod`),
+//!   so packages that wrap the same function are chained in the order they are linked
+use std::io;
+
+use hashbrown::{HashMap, HashSet};
+use thiserror::Error;
+
+use crate::asm::symbol_name;
+use crate::bundle::{
+    CName, ConstantPool, DefaultString, DefinitionType, PoolError, PoolIndex, Resource, Strings, TweakDbId, Version,
+};
+use crate::bytecode::Instr;
+use crate::decode::{Decode, DecodeError, DecodeExt};
+use crate::definition::{AnyDefinition, Class, Definition, Function};
+use crate::encode::{Encode, EncodeExt};
+use crate::mapper::{visit_indexes, IndexVisitor};
+use crate::Ref;
+
+#[derive(Debug, Clone)]
+pub struct Package {
+    names: Vec<String>,
+    strings: Vec<String>,
+    tweakdb_ids: Vec<String>,
+    resources: Vec<String>,
+    imports: Vec<Import>,
+    definitions: Vec<PackageDefinition>,
+    patches: Vec<Patch>,
+}
+
+impl Package {
+    const MAGIC: [u8; 4] = *b"RPKG";
+    const VERSION: u32 = 1;
+
+    /// Creates a package out of the definitions that have been added to or changed in `pool` compared to
+    /// `base`. Each pair of `wrapped` functions consists of a wrapped function and the function that holds
+    /// its original body after compilation.
+    pub fn new(
+        base: &ConstantPool,
+        pool: &ConstantPool,
+        wrapped: &[(PoolIndex<Function>, PoolIndex<Function>)],
+    ) -> Result<Self, PackageError> {
+        let base_len = base.definitions.len();
+        let moved: HashMap<PoolIndex<Definition>, PoolIndex<Definition>> = wrapped
+            .iter()
+            .filter(|(target, _)| (u32::from(*target) as usize) < base_len)
+            .map(|(target, proxy)| (proxy.cast(), target.cast()))
+            .collect();
+
+        // the locals of moved functions are copied from the target when linking
+        let mut skipped = HashSet::new();
+        for proxy in moved.keys() {
+            skipped.extend(pool.function(proxy.cast())?.locals.iter().map(PoolIndex::cast));
+        }
+        let added: Vec<PoolIndex<Definition>> = (base_len..pool.definitions.len())
+            .map(|i| PoolIndex::new(i as u32))
+            .filter(|idx| !skipped.contains(idx))
+            .collect();
+
+        let mut patched = vec![];
+        for (idx, def) in base.definitions() {
+            if encoded(def)? != encoded(pool.definition(idx)?)? {
+                patched.push(idx);
+            }
+        }
+
+        let mut localizer = Localizer {
+            base,
+            pool,
+            base_len,
+            definitions: added
+                .iter()
+                .enumerate()
+                .map(|(i, idx)| (*idx, PoolIndex::new(i as u32 + 1)))
+                .collect(),
+            imports: vec![],
+            import_indexes: HashMap::new(),
+            names: LocalStrings::default(),
+            strings: LocalStrings::default(),
+            tweakdb_ids: LocalStrings::default(),
+            resources: LocalStrings::default(),
+            error: None,
+        };
+
+        let mut definitions = Vec::with_capacity(added.len());
+        for idx in added {
+            let def = pool.definition(idx)?;
+            let entry = match moved.get(&idx) {
+                Some(target) => {
+                    let mut target = *target;
+                    let mut name = def.name;
+                    let mut parent = def.parent;
+                    localizer.visit_definition(&mut target);
+                    localizer.visit_name(&mut name);
+                    localizer.visit_definition(&mut parent);
+                    PackageDefinition::Moved {
+                        target,
+                        name,
+                        parent: parent.cast(),
+                    }
+                }
+                None => PackageDefinition::New(localizer.localize(def)?),
+            };
+            definitions.push(entry);
+        }
+
+        let mut patches = Vec::with_capacity(patched.len());
+        for idx in patched {
+            let mut target = idx;
+            localizer.visit_definition(&mut target);
+            let definition = localizer.localize(pool.definition(idx)?)?;
+            patches.push(Patch { target, definition });
+        }
+        if let Some(err) = localizer.error {
+            return Err(err);
+        }
+
+        Ok(Self {
+            names: localizer.names.strings,
+            strings: localizer.strings.strings,
+            tweakdb_ids: localizer.tweakdb_ids.strings,
+            resources: localizer.resources.strings,
+            imports: localizer.imports,
+            definitions,
+            patches,
+        })
+    }
+
+    pub fn load<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        input.decode()
+    }
+
+    pub fn save<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
+        output.encode(self)
+    }
+
+    /// Returns the symbols of the definitions the package depends on.
+    pub fn imports(&self) -> impl ExactSizeIterator<Item = &str> {
+        self.imports.iter().map(|import| import.symbol.as_str())
+    }
+}
+
+impl Encode for Package {
+    fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
+        output.encode(&Self::MAGIC)?;
+        output.encode(&Self::VERSION)?;
+        encode_strings(output, &self.names)?;
+        encode_strings(output, &self.strings)?;
+        encode_strings(output, &self.tweakdb_ids)?;
+        encode_strings(output, &self.resources)?;
+        output.encode_slice_prefixed::<u32, _>(&self.imports)?;
+        output.encode_slice_prefixed::<u32, _>(&self.definitions)?;
+        output.encode_slice_prefixed::<u32, _>(&self.patches)
+    }
+}
+
+impl Decode for Package {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        let magic: [u8; 4] = input.decode()?;
+        let version: u32 = input.decode()?;
+        if magic != Self::MAGIC || version != Self::VERSION {
+            return Err(DecodeError::InvalidHeader);
+        }
+        Ok(Self {
+            names: input.decode_vec_prefixed::<u32, _>()?,
+            strings: input.decode_vec_prefixed::<u32, _>()?,
+            tweakdb_ids: input.decode_vec_prefixed::<u32, _>()?,
+            resources: input.decode_vec_prefixed::<u32, _>()?,
+            imports: input.decode_vec_prefixed::<u32, _>()?,
+            definitions: input.decode_vec_prefixed::<u32, _>()?,
+            patches: input.decode_vec_prefixed::<u32, _>()?,
+        })
+    }
+}
+
+/// Links packages into a pool. Definitions linked by one package can be imported by the packages
+/// linked after it.
+pub struct Linker<'a> {
+    pool: &'a mut ConstantPool,
+    symbols: HashMap<(DefinitionType, String), PoolIndex<Definition>>,
+}
+
+impl<'a> Linker<'a> {
+    pub fn new(pool: &'a mut ConstantPool) -> Result<Self, PoolError> {
+        let mut linker = Self {
+            pool,
+            symbols: HashMap::new(),
+        };
+        let indexes: Vec<_> = linker.pool.definitions().map(|(idx, _)| idx).collect();
+        linker.register(indexes)?;
+        Ok(linker)
+    }
+
+    pub fn link(&mut self, package: &Package) -> Result<(), PackageError> {
+        let imports = package
+            .imports
+            .iter()
+            .map(|import| {
+                self.symbols
+                    .get(&(import.type_, import.symbol.clone()))
+                    .copied()
+                    .ok_or_else(|| PackageError::UnresolvedImport(import.symbol.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // the slots are reserved only once the imports are resolved, so that a package with missing
+        // dependencies leaves the pool untouched
+        let slots: Vec<PoolIndex<Definition>> = package.definitions.iter().map(|_| self.pool.reserve()).collect();
+        let mut definitions = Vec::with_capacity(slots.len() + imports.len() + 1);
+        definitions.push(PoolIndex::UNDEFINED);
+        definitions.extend(&slots);
+        definitions.extend(imports);
+
+        let mut relocator = Relocator {
+            definitions,
+            names: global_strings(&generated_names(package, &slots), &mut self.pool.names),
+            strings: global_strings(&package.strings, &mut self.pool.strings),
+            tweakdb_ids: global_strings(&package.tweakdb_ids, &mut self.pool.tweakdb_ids),
+            resources: global_strings(&package.resources, &mut self.pool.resources),
+            error: None,
+        };
+
+        // moved functions have to take the bodies of their targets before any patches are applied
+        for (def, slot) in package.definitions.iter().zip(&slots) {
+            if let PackageDefinition::Moved { target, name, parent } = def {
+                let target = relocator.definition(*target)?;
+                let name = relocator.name(*name)?;
+                let parent = relocator.definition(parent.cast())?;
+                self.move_function(target.cast(), slot.cast(), name, parent)?;
+            }
+        }
+        for (def, slot) in package.definitions.iter().zip(&slots) {
+            if let PackageDefinition::New(def) = def {
+                let def = relocator.relocate(def)?;
+                self.pool.put_definition(*slot, def);
+            }
+        }
+        for patch in &package.patches {
+            let target = relocator.definition(patch.target)?;
+            let def = relocator.relocate(&patch.definition)?;
+            match (&mut self.pool.definitions[u32::from(target) as usize].value, def.value) {
+                (AnyDefinition::Class(existing), AnyDefinition::Class(class)) => merge_class(existing, class),
+                (_, value) => self.pool.put_definition(target, Definition { value, ..def }),
+            }
+        }
+
+        self.register(slots)?;
+        Ok(())
+    }
+
+    fn register(&mut self, indexes: Vec<PoolIndex<Definition>>) -> Result<(), PoolError> {
+        for idx in indexes {
+            let type_ = self.pool.definition(idx)?.value.type_();
+            let symbol = symbol_name(idx, self.pool)?;
+            self.symbols.entry((type_, symbol)).or_insert(idx);
+        }
+        Ok(())
+    }
+
+    fn move_function(
+        &mut self,
+        target: PoolIndex<Function>,
+        slot: PoolIndex<Function>,
+        name: PoolIndex<CName>,
+        parent: PoolIndex<Definition>,
+    ) -> Result<(), PackageError> {
+        let def = self.pool.definition(target)?.clone();
+        let mut fun = def
+            .value
+            .into_function()
+            .map_err(|_| PackageError::UnexpectedDefinition(symbol_name(target, self.pool).unwrap_or_default()))?;
+        // wrapped functions should not preserve the callback flag (redscript #63)
+        fun.flags = fun.flags.with_is_callback(false);
+
+        // the locals have to be placed after the function they belong to like the compiler does
+        let mut locals = HashMap::new();
+        for local in &mut fun.locals {
+            let mut def = self.pool.definition(*local)?.clone();
+            def.parent = slot.cast();
+            let copy = self.pool.add_definition(def);
+            locals.insert(*local, copy);
+            *local = copy;
+        }
+        for instr in &mut fun.code.0 {
+            if let Instr::Local(local) = instr {
+                if let Some(copy) = locals.get(local) {
+                    *local = *copy;
+                }
+            }
+        }
+
+        self.pool
+            .put_definition(slot, Definition::function(name, parent.cast(), fun));
+        Ok(())
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PackageError {
+    #[error("pool error: {0}")]
+    PoolError(#[from] PoolError),
+    #[error("unresolved import: {0}")]
+    UnresolvedImport(String),
+    #[error("unexpected definition: {0}")]
+    UnexpectedDefinition(String),
+    #[error("reference to a definition outside of the package: {0}")]
+    DanglingReference(PoolIndex<Definition>),
+    #[error("invalid package index: {0}")]
+    InvalidIndex(u32),
+    #[error("io error: {0}")]
+    IoError(#[from] io::Error),
+}
+
+#[derive(Debug, Clone)]
+struct Import {
+    type_: DefinitionType,
+    symbol: String,
+}
+
+impl Encode for Import {
+    fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
+        output.encode(&self.type_)?;
+        output.encode(&self.symbol.as_str())
+    }
+}
+
+impl Decode for Import {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(Self {
+            type_: input.decode()?,
+            symbol: input.decode()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+enum PackageDefinition {
+    New(Definition),
+    Moved {
+        target: PoolIndex<Definition>,
+        name: PoolIndex<CName>,
+        parent: PoolIndex<Class>,
+    },
+}
+
+impl Encode for PackageDefinition {
+    fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
+        match self {
+            PackageDefinition::New(def) => {
+                output.encode(&0u8)?;
+                encode_definition(output, def)
+            }
+            PackageDefinition::Moved { target, name, parent } => {
+                output.encode(&1u8)?;
+                output.encode(target)?;
+                output.encode(name)?;
+                output.encode(parent)
+            }
+        }
+    }
+}
+
+impl Decode for PackageDefinition {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        match input.decode()? {
+            0u8 => Ok(PackageDefinition::New(decode_definition(input)?)),
+            1u8 => Ok(PackageDefinition::Moved {
+                target: input.decode()?,
+                name: input.decode()?,
+                parent: input.decode()?,
+            }),
+            tag => Err(DecodeError::InvalidTag("package definition", tag)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Patch {
+    target: PoolIndex<Definition>,
+    definition: Definition,
+}
+
+impl Encode for Patch {
+    fn encode<O: io::Write>(&self, output: &mut O) -> io::Result<()> {
+        output.encode(&self.target)?;
+        encode_definition(output, &self.definition)
+    }
+}
+
+impl Decode for Patch {
+    fn decode<I: io::Read>(input: &mut I) -> Result<Self, DecodeError> {
+        Ok(Self {
+            target: input.decode()?,
+            definition: decode_definition(input)?,
+        })
+    }
+}
+
+fn encode_definition<O: io::Write>(output: &mut O, def: &Definition) -> io::Result<()> {
+    output.encode(&def.name)?;
+    output.encode(&def.parent)?;
+    output.encode(&def.unk1)?;
+    output.encode(&def.unk2)?;
+    output.encode(&def.unk3)?;
+    output.encode(&def.value.type_())?;
+    output.encode(&def.value)
+}
+
+fn decode_definition<I: io::Read>(input: &mut I) -> Result<Definition, DecodeError> {
+    let name = input.decode()?;
+    let parent = input.decode()?;
+    let unk1 = input.decode()?;
+    let unk2 = input.decode()?;
+    let unk3 = input.decode()?;
+    let type_ = input.decode()?;
+    Ok(Definition {
+        name,
+        parent,
+        unk1,
+        unk2,
+        unk3,
+        value: AnyDefinition::decode_as(input, type_, Version::LATEST)?,
+    })
+}
+
+fn encoded(def: &Definition) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+    encode_definition(&mut buf, def)?;
+    Ok(buf)
+}
+
+fn encode_strings<O: io::Write>(output: &mut O, strings: &[String]) -> io::Result<()> {
+    output.encode(&(strings.len() as u32))?;
+    for str in strings {
+        output.encode(&str.as_str())?;
+    }
+    Ok(())
+}
+
+fn global_strings<K: DefaultString>(strings: &[String], table: &mut Strings<K>) -> Vec<PoolIndex<K>> {
+    strings.iter().map(|str| table.add(Ref::from(str.as_str()))).collect()
+}
+
+// the names of generated functions include the index they were compiled at, which could clash with
+// the functions of other packages, so they are renamed after the index they are linked at
+fn generated_names(package: &Package, slots: &[PoolIndex<Definition>]) -> Vec<String> {
+    let mut names = package.names.clone();
+    for (def, slot) in package.definitions.iter().zip(slots) {
+        let name = match def {
+            PackageDefinition::New(def) if matches!(def.value, AnyDefinition::Function(_)) => def.name,
+            PackageDefinition::Moved { name, .. } => *name,
+            PackageDefinition::New(_) => continue,
+        };
+        if let Some(str) = names.get_mut(u32::from(name) as usize) {
+            if let Some((prefix @ ("wrapper" | "proxy"), _)) = str.split_once('$') {
+                *str = format!("{prefix}${slot}");
+            }
+        }
+    }
+    names
+}
+
+fn merge_class(existing: &mut Class, class: Class) {
+    for fun in class.functions {
+        if !existing.functions.contains(&fun) {
+            existing.functions.push(fun);
+        }
+    }
+    for field in class.fields {
+        if !existing.fields.contains(&field) {
+            existing.fields.push(field);
+        }
+    }
+    for method in class.overrides {
+        if !existing.overrides.contains(&method) {
+            existing.overrides.push(method);
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct LocalStrings {
+    strings: Vec<String>,
+    indexes: HashMap<u32, u32>,
+}
+
+impl LocalStrings {
+    fn localize<K: DefaultString>(
+        &mut self,
+        table: &Strings<K>,
+        index: PoolIndex<K>,
+    ) -> Result<PoolIndex<K>, PoolError> {
+        if let Some(local) = self.indexes.get(&u32::from(index)) {
+            return Ok(PoolIndex::new(*local));
+        }
+        let local = self.strings.len() as u32;
+        self.strings.push(table.get(index)?.to_string());
+        self.indexes.insert(index.into(), local);
+        Ok(PoolIndex::new(local))
+    }
+}
+
+/// Rewrites the indexes of compiled definitions into the index space of a package.
+struct Localizer<'a> {
+    base: &'a ConstantPool,
+    pool: &'a ConstantPool,
+    base_len: usize,
+    definitions: HashMap<PoolIndex<Definition>, PoolIndex<Definition>>,
+    imports: Vec<Import>,
+    import_indexes: HashMap<PoolIndex<Definition>, PoolIndex<Definition>>,
+    names: LocalStrings,
+    strings: LocalStrings,
+    tweakdb_ids: LocalStrings,
+    resources: LocalStrings,
+    error: Option<PackageError>,
+}
+
+impl<'a> Localizer<'a> {
+    fn localize(&mut self, def: &Definition) -> Result<Definition, PackageError> {
+        let mut def = def.clone();
+        visit_indexes(&mut def, self);
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(def),
+        }
+    }
+
+    fn import(&mut self, index: PoolIndex<Definition>) -> Result<PoolIndex<Definition>, PoolError> {
+        if let Some(local) = self.import_indexes.get(&index) {
+            return Ok(*local);
+        }
+        let local = PoolIndex::new((self.definitions.len() + self.imports.len() + 1) as u32);
+        self.imports.push(Import {
+            type_: self.base.definition(index)?.value.type_(),
+            symbol: symbol_name(index, self.base)?,
+        });
+        self.import_indexes.insert(index, local);
+        Ok(local)
+    }
+}
+
+impl<'a> IndexVisitor for Localizer<'a> {
+    fn visit_definition(&mut self, index: &mut PoolIndex<Definition>) {
+        if index.is_undefined() {
+            return;
+        }
+        let res = if (u32::from(*index) as usize) < self.base_len {
+            self.import(*index).map_err(PackageError::from)
+        } else {
+            self.definitions
+                .get(index)
+                .copied()
+                .ok_or(PackageError::DanglingReference(*index))
+        };
+        match res {
+            Ok(local) => *index = local,
+            Err(err) => self.error = Some(err),
+        }
+    }
+
+    fn visit_name(&mut self, index: &mut PoolIndex<CName>) {
+        match self.names.localize(&self.pool.names, *index) {
+            Ok(local) => *index = local,
+            Err(err) => self.error = Some(err.into()),
+        }
+    }
+
+    fn visit_string(&mut self, index: &mut PoolIndex<String>) {
+        match self.strings.localize(&self.pool.strings, *index) {
+            Ok(local) => *index = local,
+            Err(err) => self.error = Some(err.into()),
+        }
+    }
+
+    fn visit_tweakdb_id(&mut self, index: &mut PoolIndex<TweakDbId>) {
+        match self.tweakdb_ids.localize(&self.pool.tweakdb_ids, *index) {
+            Ok(local) => *index = local,
+            Err(err) => self.error = Some(err.into()),
+        }
+    }
+
+    fn visit_resource(&mut self, index: &mut PoolIndex<Resource>) {
+        match self.resources.localize(&self.pool.resources, *index) {
+            Ok(local) => *index = local,
+            Err(err) => self.error = Some(err.into()),
+        }
+    }
+}
+
+/// Rewrites the indexes of package definitions into the index space of the pool they are linked into.
+struct Relocator {
+    definitions: Vec<PoolIndex<Definition>>,
+    names: Vec<PoolIndex<CName>>,
+    strings: Vec<PoolIndex<String>>,
+    tweakdb_ids: Vec<PoolIndex<TweakDbId>>,
+    resources: Vec<PoolIndex<Resource>>,
+    error: Option<PackageError>,
+}
+
+impl Relocator {
+    fn relocate(&mut self, def: &Definition) -> Result<Definition, PackageError> {
+        let mut def = def.clone();
+        visit_indexes(&mut def, self);
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(def),
+        }
+    }
+
+    fn definition(&self, index: PoolIndex<Definition>) -> Result<PoolIndex<Definition>, PackageError> {
+        Self::get(&self.definitions, index)
+    }
+
+    fn name(&self, index: PoolIndex<CName>) -> Result<PoolIndex<CName>, PackageError> {
+        Self::get(&self.names, index)
+    }
+
+    fn get<A>(table: &[PoolIndex<A>], index: PoolIndex<A>) -> Result<PoolIndex<A>, PackageError> {
+        table
+            .get(u32::from(index) as usize)
+            .copied()
+            .ok_or(PackageError::InvalidIndex(index.into()))
+    }
+
+    fn apply<A>(table: &[PoolIndex<A>], index: &mut PoolIndex<A>, error: &mut Option<PackageError>) {
+        match Self::get(table, *index) {
+            Ok(global) => *index = global,
+            Err(err) => *error = Some(err),
+        }
+    }
+}
+
+impl IndexVisitor for Relocator {
+    fn visit_definition(&mut self, index: &mut PoolIndex<Definition>) {
+        Self::apply(&self.definitions, index, &mut self.error);
+    }
+
+    fn visit_name(&mut self, index: &mut PoolIndex<CName>) {
+        Self::apply(&self.names, index, &mut self.error);
+    }
+
+    fn visit_string(&mut self, index: &mut PoolIndex<String>) {
+        Self::apply(&self.strings, index, &mut self.error);
+    }
+
+    fn visit_tweakdb_id(&mut self, index: &mut PoolIndex<TweakDbId>) {
+        Self::apply(&self.tweakdb_ids, index, &mut self.error);
+    }
+
+    fn visit_resource(&mut self, index: &mut PoolIndex<Resource>) {
+        Self::apply(&self.resources, index, &mut self.error);
+    }
+}
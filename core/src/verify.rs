@@ -0,0 +1,374 @@
+use std::collections::BTreeMap;
+
+use hashbrown::{HashMap, HashSet};
+use thiserror::Error;
+
+use crate::bundle::{CName, ConstantPool, PoolError, PoolIndex};
+use crate::bytecode::{Instr, Location, Offset};
+use crate::definition::{AnyDefinition, Function};
+use crate::Ref;
+
+/// Verifies the bytecode of every function in the pool and returns the first error found in each of them.
+pub fn verify_pool(pool: &ConstantPool) -> Vec<VerifyError> {
+    let verifier = Verifier::new(pool);
+    pool.definitions()
+        .filter(|(_, def)| matches!(def.value, AnyDefinition::Function(_)))
+        .filter_map(|(idx, _)| verifier.verify_function(idx.cast()).err())
+        .collect()
+}
+
+/// Structural checks for function bytecode. This catches malformed code that the game would
+/// otherwise only discover by crashing at runtime.
+pub struct Verifier<'a> {
+    pool: &'a ConstantPool,
+    // parameter counts of all functions sharing a name, used to check virtual calls
+    arg_counts: HashMap<PoolIndex<CName>, HashSet<usize>>,
+}
+
+impl<'a> Verifier<'a> {
+    pub fn new(pool: &'a ConstantPool) -> Self {
+        let mut arg_counts: HashMap<PoolIndex<CName>, HashSet<usize>> = HashMap::new();
+        for (_, def) in pool.definitions() {
+            if let AnyDefinition::Function(fun) = &def.value {
+                arg_counts.entry(def.name).or_default().insert(fun.parameters.len());
+            }
+        }
+        Self { pool, arg_counts }
+    }
+
+    pub fn verify_function(&self, index: PoolIndex<Function>) -> Result<(), VerifyError> {
+        let fun = self
+            .pool
+            .function(index)
+            .map_err(|err| VerifyError::new(index, Location::ZERO, err.into()))?;
+
+        let mut instrs = Vec::with_capacity(fun.code.0.len());
+        let mut boundaries = HashMap::new();
+        let mut end = 0u32;
+        for instr in &fun.code.0 {
+            let location = u16::try_from(end)
+                .map(Location::new)
+                .map_err(|_| VerifyError::new(index, Location::MAX, VerifyCause::CodeTooLarge))?;
+            boundaries.insert(location.value, instrs.len());
+            instrs.push((location, instr));
+            end += u32::from(instr.size());
+        }
+        let end = u16::try_from(end)
+            .map(Location::new)
+            .map_err(|_| VerifyError::new(index, Location::MAX, VerifyCause::CodeTooLarge))?;
+        boundaries.insert(end.value, instrs.len());
+
+        let mut frames: Vec<Frame<'_>> = vec![];
+        let mut switch_labels = BTreeMap::new();
+
+        for &(location, instr) in &instrs {
+            let err = |cause| VerifyError::new(index, location, cause);
+
+            self.check_operands(instr).map_err(|e| err(e.into()))?;
+            for offset in jump_offsets(instr) {
+                let target = resolve(location, offset).filter(|loc| boundaries.contains_key(&loc.value));
+                target.ok_or_else(|| err(VerifyCause::InvalidJumpTarget(offset)))?;
+            }
+
+            match instr {
+                Instr::SwitchLabel(_, _) | Instr::SwitchDefault => {
+                    switch_labels.entry(location).or_insert(false);
+                }
+                Instr::Switch(_, first) => {
+                    let mut current = resolve(location, *first).unwrap_or(end);
+                    while let Some(&(_, instr)) = boundaries.get(&current.value).and_then(|&i| instrs.get(i)) {
+                        match instr {
+                            Instr::SwitchLabel(next, body) => {
+                                switch_labels.insert(current, true);
+                                let next = resolve(current, *next).unwrap_or(end);
+                                let body = resolve(current, *body).unwrap_or(end);
+                                if next <= current || body <= current {
+                                    return Err(err(VerifyCause::MalformedSwitch("label pointing backwards")));
+                                }
+                                current = next;
+                            }
+                            Instr::SwitchDefault => {
+                                switch_labels.insert(current, true);
+                                break;
+                            }
+                            _ => break,
+                        }
+                    }
+                }
+                _ => {}
+            }
+
+            if matches!(instr, Instr::ParamEnd) {
+                match frames.pop() {
+                    Some(Frame::Args(call, call_location, count)) => {
+                        self.check_call(call, count).map_err(err)?;
+                        let exit = resolve(call_location, call_exit(call))
+                            .filter(|exit| exit.value == location.value + instr.size());
+                        exit.ok_or_else(|| err(VerifyCause::InvalidCallExit))?;
+                    }
+                    _ => return Err(err(VerifyCause::UnexpectedInstruction("ParamEnd"))),
+                }
+            } else {
+                match frames.last_mut() {
+                    Some(Frame::Operands(remaining)) => *remaining -= 1,
+                    Some(Frame::Args(_, _, count)) => *count += 1,
+                    None => {}
+                }
+                match instr {
+                    Instr::InvokeStatic(_, _, _, _) | Instr::InvokeVirtual(_, _, _, _) => {
+                        frames.push(Frame::Args(instr, location, 0));
+                    }
+                    _ => match operand_count(instr) {
+                        0 => {}
+                        n => frames.push(Frame::Operands(n)),
+                    },
+                }
+            }
+            while matches!(frames.last(), Some(Frame::Operands(0))) {
+                frames.pop();
+            }
+        }
+
+        if !frames.is_empty() {
+            return Err(VerifyError::new(index, end, VerifyCause::UnexpectedEndOfCode));
+        }
+        if let Some((&location, _)) = switch_labels.iter().find(|(_, owned)| !**owned) {
+            return Err(VerifyError::new(
+                index,
+                location,
+                VerifyCause::MalformedSwitch("label outside of a switch"),
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_operands(&self, instr: &Instr<Offset>) -> Result<(), PoolError> {
+        let pool = self.pool;
+        match instr {
+            Instr::NameConst(idx) => pool.names.get(*idx).map(|_| ()),
+            Instr::StringConst(idx) => pool.strings.get(*idx).map(|_| ()),
+            // undefined indices are used for empty values
+            Instr::TweakDbIdConst(idx) if !idx.is_undefined() => pool.tweakdb_ids.get(*idx).map(|_| ()),
+            Instr::ResourceConst(idx) if !idx.is_undefined() => pool.resources.get(*idx).map(|_| ()),
+            Instr::EnumConst(enum_, member) => pool.enum_(*enum_).and(pool.enum_value(*member)).map(|_| ()),
+            Instr::Local(idx) => pool.local(*idx).map(|_| ()),
+            Instr::Param(idx) => pool.parameter(*idx).map(|_| ()),
+            Instr::ObjectField(idx) | Instr::StructField(idx) => pool.field(*idx).map(|_| ()),
+            Instr::Construct(_, idx) | Instr::New(idx) | Instr::DynamicCast(idx, _) => pool.class(*idx).map(|_| ()),
+            Instr::InvokeStatic(_, _, idx, _) => pool.function(*idx).map(|_| ()),
+            Instr::InvokeVirtual(_, _, idx, _) => pool.names.get(*idx).map(|_| ()),
+            Instr::Switch(idx, _)
+            | Instr::Equals(idx)
+            | Instr::RefStringEqualsString(idx)
+            | Instr::StringEqualsRefString(idx)
+            | Instr::NotEquals(idx)
+            | Instr::RefStringNotEqualsString(idx)
+            | Instr::StringNotEqualsRefString(idx)
+            | Instr::ArrayClear(idx)
+            | Instr::ArraySize(idx)
+            | Instr::ArrayResize(idx)
+            | Instr::ArrayFindFirst(idx)
+            | Instr::ArrayFindFirstFast(idx)
+            | Instr::ArrayFindLast(idx)
+            | Instr::ArrayFindLastFast(idx)
+            | Instr::ArrayContains(idx)
+            | Instr::ArrayContainsFast(idx)
+            | Instr::ArrayCount(idx)
+            | Instr::ArrayCountFast(idx)
+            | Instr::ArrayPush(idx)
+            | Instr::ArrayPop(idx)
+            | Instr::ArrayInsert(idx)
+            | Instr::ArrayRemove(idx)
+            | Instr::ArrayRemoveFast(idx)
+            | Instr::ArrayGrow(idx)
+            | Instr::ArrayErase(idx)
+            | Instr::ArrayEraseFast(idx)
+            | Instr::ArrayLast(idx)
+            | Instr::ArrayElement(idx)
+            | Instr::ArraySort(idx)
+            | Instr::ArraySortByPredicate(idx)
+            | Instr::StaticArraySize(idx)
+            | Instr::StaticArrayFindFirst(idx)
+            | Instr::StaticArrayFindFirstFast(idx)
+            | Instr::StaticArrayFindLast(idx)
+            | Instr::StaticArrayFindLastFast(idx)
+            | Instr::StaticArrayContains(idx)
+            | Instr::StaticArrayContainsFast(idx)
+            | Instr::StaticArrayCount(idx)
+            | Instr::StaticArrayCountFast(idx)
+            | Instr::StaticArrayLast(idx)
+            | Instr::StaticArrayElement(idx)
+            | Instr::EnumToI32(idx, _)
+            | Instr::I32ToEnum(idx, _)
+            | Instr::ToString(idx)
+            | Instr::ToVariant(idx)
+            | Instr::FromVariant(idx)
+            | Instr::AsRef(idx)
+            | Instr::Deref(idx) => pool.type_(*idx).map(|_| ()),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_call(&self, call: &Instr<Offset>, count: usize) -> Result<(), VerifyCause> {
+        match call {
+            Instr::InvokeStatic(_, _, idx, _) => {
+                let expected = self.pool.function(*idx)?.parameters.len();
+                if expected != count {
+                    return Err(VerifyCause::ArgCountMismatch(expected, count));
+                }
+            }
+            Instr::InvokeVirtual(_, _, idx, _)
+                if !self.arg_counts.get(idx).map_or(false, |counts| counts.contains(&count)) =>
+            {
+                return Err(VerifyCause::NoVirtualMatch(self.pool.names.get(*idx)?, count));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+enum Frame<'a> {
+    // number of operand expressions still expected by the current instruction
+    Operands(usize),
+    // a call collecting arguments until the next ParamEnd
+    Args(&'a Instr<Offset>, Location, usize),
+}
+
+fn resolve(location: Location, offset: Offset) -> Option<Location> {
+    let target = i32::from(location.value) + i32::from(offset.value);
+    u16::try_from(target).ok().map(Location::new)
+}
+
+fn call_exit(call: &Instr<Offset>) -> Offset {
+    match call {
+        Instr::InvokeStatic(exit, _, _, _) | Instr::InvokeVirtual(exit, _, _, _) => *exit,
+        _ => Offset::new(0),
+    }
+}
+
+pub(crate) fn jump_offsets(instr: &Instr<Offset>) -> Vec<Offset> {
+    match instr {
+        Instr::Switch(_, offset)
+        | Instr::Jump(offset)
+        | Instr::JumpIfFalse(offset)
+        | Instr::Skip(offset)
+        | Instr::InvokeStatic(offset, _, _, _)
+        | Instr::InvokeVirtual(offset, _, _, _)
+        | Instr::Context(offset) => vec![*offset],
+        Instr::SwitchLabel(first, second) | Instr::Conditional(first, second) => vec![*first, *second],
+        _ => vec![],
+    }
+}
+
+fn operand_count(instr: &Instr<Offset>) -> usize {
+    match instr {
+        Instr::Conditional(_, _) | Instr::ArrayInsert(_) => 3,
+        Instr::Assign
+        | Instr::Context(_)
+        | Instr::Equals(_)
+        | Instr::RefStringEqualsString(_)
+        | Instr::StringEqualsRefString(_)
+        | Instr::NotEquals(_)
+        | Instr::RefStringNotEqualsString(_)
+        | Instr::StringNotEqualsRefString(_)
+        | Instr::ArrayResize(_)
+        | Instr::ArrayFindFirst(_)
+        | Instr::ArrayFindFirstFast(_)
+        | Instr::ArrayFindLast(_)
+        | Instr::ArrayFindLastFast(_)
+        | Instr::ArrayContains(_)
+        | Instr::ArrayContainsFast(_)
+        | Instr::ArrayCount(_)
+        | Instr::ArrayCountFast(_)
+        | Instr::ArrayPush(_)
+        | Instr::ArrayRemove(_)
+        | Instr::ArrayRemoveFast(_)
+        | Instr::ArrayGrow(_)
+        | Instr::ArrayErase(_)
+        | Instr::ArrayEraseFast(_)
+        | Instr::ArrayElement(_)
+        | Instr::ArraySortByPredicate(_)
+        | Instr::StaticArrayFindFirst(_)
+        | Instr::StaticArrayFindFirstFast(_)
+        | Instr::StaticArrayFindLast(_)
+        | Instr::StaticArrayFindLastFast(_)
+        | Instr::StaticArrayContains(_)
+        | Instr::StaticArrayContainsFast(_)
+        | Instr::StaticArrayCount(_)
+        | Instr::StaticArrayCountFast(_)
+        | Instr::StaticArrayElement(_) => 2,
+        Instr::Switch(_, _)
+        | Instr::SwitchLabel(_, _)
+        | Instr::JumpIfFalse(_)
+        | Instr::Skip(_)
+        | Instr::Return
+        | Instr::StructField(_)
+        | Instr::ArrayClear(_)
+        | Instr::ArraySize(_)
+        | Instr::ArrayPop(_)
+        | Instr::ArrayLast(_)
+        | Instr::ArraySort(_)
+        | Instr::StaticArraySize(_)
+        | Instr::StaticArrayLast(_)
+        | Instr::RefToBool
+        | Instr::WeakRefToBool
+        | Instr::EnumToI32(_, _)
+        | Instr::I32ToEnum(_, _)
+        | Instr::DynamicCast(_, _)
+        | Instr::ToString(_)
+        | Instr::ToVariant(_)
+        | Instr::FromVariant(_)
+        | Instr::VariantIsDefined
+        | Instr::VariantIsRef
+        | Instr::VariantIsArray
+        | Instr::VariantTypeName
+        | Instr::VariantToString
+        | Instr::WeakRefToRef
+        | Instr::RefToWeakRef
+        | Instr::AsRef(_)
+        | Instr::Deref(_) => 1,
+        Instr::Construct(n, _) => (*n).into(),
+        _ => 0,
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("{cause} at {location} in function {function}")]
+pub struct VerifyError {
+    pub function: PoolIndex<Function>,
+    pub location: Location,
+    pub cause: VerifyCause,
+}
+
+impl VerifyError {
+    fn new(function: PoolIndex<Function>, location: Location, cause: VerifyCause) -> Self {
+        Self {
+            function,
+            location,
+            cause,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum VerifyCause {
+    #[error("invalid operand: {0}")]
+    InvalidOperand(#[from] PoolError),
+    #[error("jump by {} does not land on an instruction", .0.value)]
+    InvalidJumpTarget(Offset),
+    #[error("call exit does not point past its arguments")]
+    InvalidCallExit,
+    #[error("expected {0} arguments, found {1}")]
+    ArgCountMismatch(usize, usize),
+    #[error("no method named {0} accepts {1} arguments")]
+    NoVirtualMatch(Ref<str>, usize),
+    #[error("malformed switch: {0}")]
+    MalformedSwitch(&'static str),
+    #[error("unexpected {0} instruction")]
+    UnexpectedInstruction(&'static str),
+    #[error("unexpected end of code")]
+    UnexpectedEndOfCode,
+    #[error("code exceeds the maximum size")]
+    CodeTooLarge,
+}
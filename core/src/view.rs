@@ -0,0 +1,277 @@
+//! A read-only view over an encoded script bundle.
+//!
+//! Unlike [`ScriptBundle::load`](crate::bundle::ScriptBundle::load), the view only decodes the header and
+//! the definition headers up front. Strings are read straight from the underlying bytes and definitions
+//! are decoded one by one when they are requested, which makes it suitable for tools that only need
+//! a small part of a large bundle, typically backed by a memory-mapped file.
+use std::cell::OnceCell;
+use std::io;
+
+use byteorder::{ByteOrder, LittleEndian};
+use hashbrown::HashMap;
+use thiserror::Error;
+
+use crate::bundle::{
+    CName, ConstantPool, DefaultString, DefinitionHeader, Header, PoolError, PoolIndex, Resource, Strings, TableHeader,
+    TweakDbId, Version,
+};
+use crate::bytecode::{Code, Offset};
+use crate::decode::{DecodeError, DecodeExt, Table};
+use crate::definition::{AnyDefinition, Definition, Function};
+use crate::mapper::{visit_indexes, IndexVisitor};
+use crate::Ref;
+
+pub struct BundleView<'a> {
+    bytes: &'a [u8],
+    header: Header,
+    definitions: Vec<DefinitionHeader>,
+    lookup: OnceCell<HashMap<&'a str, Vec<PoolIndex<Definition>>>>,
+}
+
+impl<'a> BundleView<'a> {
+    pub fn new(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut input = io::Cursor::new(bytes);
+        let header: Header = input.decode().map_err(|err| err.at(0, Table::Header, None))?;
+
+        let offset = u64::from(header.definitions.offset);
+        input.set_position(offset);
+        let definitions = input
+            .decode_vec(header.definitions.count)
+            .map_err(|err| err.at(offset, Table::DefinitionHeaders, None))?;
+
+        Ok(Self {
+            bytes,
+            header,
+            definitions,
+            lookup: OnceCell::new(),
+        })
+    }
+
+    /// Returns the number of definitions, including the undefined one at index zero.
+    pub fn len(&self) -> usize {
+        self.definitions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.len() <= 1
+    }
+
+    pub fn definition_header<A>(&self, index: PoolIndex<A>) -> Result<&DefinitionHeader, PoolError> {
+        self.definitions
+            .get(u32::from(index) as usize)
+            .filter(|_| !index.is_undefined())
+            .ok_or_else(|| PoolError::DefinitionNotFound(index.cast()))
+    }
+
+    pub fn definition_headers(&self) -> impl Iterator<Item = (PoolIndex<Definition>, &DefinitionHeader)> {
+        self.definitions
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(index, header)| (PoolIndex::new(index as u32), header))
+    }
+
+    /// Decodes a single definition.
+    pub fn definition<A>(&self, index: PoolIndex<A>) -> Result<Definition, ViewError> {
+        let header = self.definition_header(index)?;
+        decode_definition(self.bytes, header, self.header.version, index.into())
+    }
+
+    /// Decodes the definitions one at a time, without keeping them around.
+    pub fn definitions(&self) -> impl Iterator<Item = Result<(PoolIndex<Definition>, Definition), ViewError>> + '_ {
+        // the iterator only borrows what it needs, so that its type does not depend on the lifetime of the bytes
+        let bytes: &[u8] = self.bytes;
+        let version = self.header.version;
+        self.definition_headers().map(move |(index, header)| {
+            let def = decode_definition(bytes, header, version, index.into())?;
+            Ok((index, def))
+        })
+    }
+
+    /// Decodes the body of a function.
+    pub fn code(&self, index: PoolIndex<Function>) -> Result<Code<Offset>, ViewError> {
+        match self.definition(index)?.value {
+            AnyDefinition::Function(fun) => Ok(fun.code),
+            _ => Err(PoolError::UnexpectedEntry("function").into()),
+        }
+    }
+
+    pub fn name(&self, index: PoolIndex<CName>) -> Result<&'a str, ViewError> {
+        self.string_from(&self.header.names, index)
+    }
+
+    pub fn def_name<A>(&self, index: PoolIndex<A>) -> Result<&'a str, ViewError> {
+        self.name(self.definition_header(index)?.name)
+    }
+
+    pub fn string(&self, index: PoolIndex<String>) -> Result<&'a str, ViewError> {
+        self.string_from(&self.header.strings, index)
+    }
+
+    pub fn tweakdb_id(&self, index: PoolIndex<TweakDbId>) -> Result<&'a str, ViewError> {
+        self.string_from(&self.header.tweakdb_indexes, index)
+    }
+
+    pub fn resource(&self, index: PoolIndex<Resource>) -> Result<&'a str, ViewError> {
+        self.string_from(&self.header.resources, index)
+    }
+
+    /// Returns the indexes of all definitions with the given name. The lookup table is built on the first call.
+    pub fn find(&self, name: &str) -> &[PoolIndex<Definition>] {
+        let lookup = self.lookup.get_or_init(|| {
+            let mut lookup: HashMap<&str, Vec<_>> = HashMap::new();
+            for (index, header) in self.definition_headers() {
+                if let Ok(name) = self.name(header.name) {
+                    lookup.entry(name).or_default().push(index);
+                }
+            }
+            lookup
+        });
+        lookup.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Decodes the given definitions into a pool that can stand in for a fully loaded one when working with
+    /// them. The definitions they refer to are decoded too, but the bodies of the functions that do not belong
+    /// to them are left out, and the definitions that are not referred to at all are left as placeholders.
+    pub fn subset(&self, roots: &[PoolIndex<Definition>]) -> Result<ConstantPool, ViewError> {
+        let mut definitions = vec![Definition::DEFAULT; self.definitions.len()];
+        let mut decoded = vec![false; self.definitions.len()];
+        let mut collector = DefinitionCollector::default();
+        collector.queue.extend(roots);
+
+        while let Some(index) = collector.queue.pop() {
+            let position = u32::from(index) as usize;
+            if index.is_undefined() || decoded.get(position) == Some(&true) {
+                continue;
+            }
+            let mut def = self.definition(index)?;
+            if !self.is_owned_by(index, roots) {
+                if let AnyDefinition::Function(fun) = &mut def.value {
+                    fun.code = Code(vec![]);
+                }
+            }
+            visit_indexes(&mut def, &mut collector);
+            definitions[position] = def;
+            decoded[position] = true;
+        }
+
+        Ok(ConstantPool {
+            names: self.strings(&self.header.names)?,
+            tweakdb_ids: self.strings(&self.header.tweakdb_indexes)?,
+            resources: self.strings(&self.header.resources)?,
+            strings: self.strings(&self.header.strings)?,
+            definitions,
+        })
+    }
+
+    fn is_owned_by(&self, index: PoolIndex<Definition>, roots: &[PoolIndex<Definition>]) -> bool {
+        let mut current = index;
+        while !current.is_undefined() {
+            if roots.contains(&current) {
+                return true;
+            }
+            match self.definition_header(current) {
+                Ok(header) => current = header.parent,
+                Err(_) => return false,
+            }
+        }
+        false
+    }
+
+    fn strings<K: DefaultString>(&self, table: &TableHeader) -> Result<Strings<K>, ViewError> {
+        let strings = (0..table.count)
+            .map(|index| Ok(Ref::from(self.raw_string(table, index)?)))
+            .collect::<Result<Vec<_>, ViewError>>()?;
+        Ok(Strings::from_vec(strings))
+    }
+
+    fn string_from<K: DefaultString>(&self, table: &TableHeader, index: PoolIndex<K>) -> Result<&'a str, ViewError> {
+        match K::DEFAULT {
+            Some(default) if index.is_undefined() => Ok(default),
+            _ if u32::from(index) >= table.count => Err(PoolError::StringNotFound(index.cast()).into()),
+            _ => self.raw_string(table, index.into()),
+        }
+    }
+
+    fn raw_string(&self, table: &TableHeader, index: u32) -> Result<&'a str, ViewError> {
+        let position = table.offset as usize + index as usize * 4;
+        let offset = self.bytes.get(position..position + 4).map(LittleEndian::read_u32);
+        let start = self.header.data.offset as usize + offset.ok_or_else(eof)? as usize;
+        let bytes = self.bytes.get(start..).ok_or_else(eof)?;
+        let len = bytes.iter().position(|b| *b == 0).ok_or_else(eof)?;
+        Ok(std::str::from_utf8(&bytes[..len])?)
+    }
+}
+
+fn decode_definition(
+    bytes: &[u8],
+    header: &DefinitionHeader,
+    version: Version,
+    index: u32,
+) -> Result<Definition, ViewError> {
+    Definition::decode(&mut io::Cursor::new(bytes), header, version)
+        .map_err(|err| err.at(header.offset.into(), Table::Definitions, Some(index)).into())
+}
+
+#[derive(Debug, Default)]
+struct DefinitionCollector {
+    queue: Vec<PoolIndex<Definition>>,
+}
+
+impl IndexVisitor for DefinitionCollector {
+    fn visit_definition(&mut self, index: &mut PoolIndex<Definition>) {
+        self.queue.push(*index);
+    }
+
+    fn visit_name(&mut self, _index: &mut PoolIndex<CName>) {}
+
+    fn visit_string(&mut self, _index: &mut PoolIndex<String>) {}
+
+    fn visit_tweakdb_id(&mut self, _index: &mut PoolIndex<TweakDbId>) {}
+
+    fn visit_resource(&mut self, _index: &mut PoolIndex<Resource>) {}
+}
+
+#[derive(Debug, Error)]
+pub enum ViewError {
+    #[error("decode error: {0}")]
+    DecodeError(#[from] DecodeError),
+    #[error("pool error: {0}")]
+    PoolError(#[from] PoolError),
+    #[error("invalid UTF-8 string: {0}")]
+    InvalidString(#[from] std::str::Utf8Error),
+}
+
+fn eof() -> DecodeError {
+    io::Error::from(io::ErrorKind::UnexpectedEof).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::BundleView;
+    use crate::bundle::{PoolIndex, ScriptBundle};
+
+    const PREDEF: &[u8] = include_bytes!("../../resources/predef.redscripts");
+
+    #[test]
+    fn view_matches_loaded_pool() {
+        let pool = ScriptBundle::load(&mut Cursor::new(PREDEF)).unwrap().pool;
+        let view = BundleView::new(PREDEF).unwrap();
+        assert_eq!(view.len(), pool.definitions.len());
+
+        for (index, def) in pool.definitions() {
+            let viewed = view.definition(index).unwrap();
+            assert_eq!(format!("{viewed:?}"), format!("{def:?}"));
+            assert_eq!(view.def_name(index).unwrap(), &*pool.def_name(index).unwrap());
+        }
+        for (i, str) in pool.strings.iter().enumerate() {
+            assert_eq!(view.string(PoolIndex::new(i as u32)).unwrap(), &**str);
+        }
+
+        let (index, _) = pool.definitions().next_back().unwrap();
+        assert!(view.find(&pool.def_name(index).unwrap()).contains(&index));
+        assert!(view.find("NotAName").is_empty());
+    }
+}
@@ -0,0 +1,178 @@
+use std::fmt;
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::bundle::{CName, ConstantPool, PoolIndex};
+use crate::bytecode::{Instr, Location, Offset};
+use crate::definition::{AnyDefinition, Definition, Function};
+
+/// The way a function refers to a definition in its bytecode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReferenceKind {
+    Call,
+    VirtualCall,
+    Read,
+    Write,
+    Construct,
+    EnumValue,
+}
+
+impl ReferenceKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Call => "call",
+            Self::VirtualCall => "virtual call",
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::Construct => "construct",
+            Self::EnumValue => "enum value",
+        }
+    }
+
+    #[inline]
+    pub fn is_call(self) -> bool {
+        matches!(self, Self::Call | Self::VirtualCall)
+    }
+}
+
+impl fmt::Display for ReferenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A reference from the bytecode of a function to a function, field, class or enum member.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reference {
+    pub source: PoolIndex<Function>,
+    pub target: PoolIndex<Definition>,
+    pub kind: ReferenceKind,
+    pub location: Location,
+}
+
+/// An index of the references between the definitions of a pool, built by scanning the bytecode
+/// of every function. Virtual calls are resolved to every method with a matching name, since the
+/// receiver type is not known until runtime.
+#[derive(Debug, Default)]
+pub struct CrossReferences {
+    references: Vec<Reference>,
+    by_source: HashMap<PoolIndex<Function>, Vec<usize>>,
+    by_target: HashMap<PoolIndex<Definition>, Vec<usize>>,
+}
+
+impl CrossReferences {
+    pub fn new(pool: &ConstantPool) -> Self {
+        let mut methods: HashMap<PoolIndex<CName>, Vec<PoolIndex<Definition>>> = HashMap::new();
+        for (idx, def) in pool.definitions() {
+            if matches!(def.value, AnyDefinition::Function(_)) && !def.parent.is_undefined() {
+                methods.entry(def.name).or_default().push(idx);
+            }
+        }
+
+        let mut res = Self::default();
+        for (idx, def) in pool.definitions() {
+            if let AnyDefinition::Function(fun) = &def.value {
+                res.scan_function(idx.cast(), fun, &methods);
+            }
+        }
+        res
+    }
+
+    /// Returns all references in the order of their source functions and locations.
+    pub fn references(&self) -> &[Reference] {
+        &self.references
+    }
+
+    /// Returns the references made by the bytecode of a function.
+    pub fn references_from(&self, function: PoolIndex<Function>) -> impl Iterator<Item = &Reference> {
+        self.indexed(self.by_source.get(&function))
+    }
+
+    /// Returns the references made to a definition from the bytecode of any function.
+    pub fn references_to<A>(&self, definition: PoolIndex<A>) -> impl Iterator<Item = &Reference> {
+        self.indexed(self.by_target.get(&definition.cast()))
+    }
+
+    pub fn callers(&self, function: PoolIndex<Function>) -> impl Iterator<Item = &Reference> {
+        self.references_to(function).filter(|r| r.kind.is_call())
+    }
+
+    pub fn callees(&self, function: PoolIndex<Function>) -> impl Iterator<Item = &Reference> {
+        self.references_from(function).filter(|r| r.kind.is_call())
+    }
+
+    fn indexed<'a>(&'a self, indexes: Option<&'a Vec<usize>>) -> impl Iterator<Item = &'a Reference> {
+        indexes.into_iter().flatten().map(|&i| &self.references[i])
+    }
+
+    fn scan_function(
+        &mut self,
+        source: PoolIndex<Function>,
+        fun: &Function,
+        methods: &HashMap<PoolIndex<CName>, Vec<PoolIndex<Definition>>>,
+    ) {
+        let instrs: Vec<(Location, Instr<Offset>)> = fun.code.iter().collect();
+        let writes = written_fields(&instrs);
+
+        for (i, (location, instr)) in instrs.iter().enumerate() {
+            let mut add = |target: PoolIndex<Definition>, kind| {
+                self.add(Reference {
+                    source,
+                    target,
+                    kind,
+                    location: *location,
+                });
+            };
+            match instr {
+                Instr::InvokeStatic(_, _, idx, _) => add(idx.cast(), ReferenceKind::Call),
+                Instr::InvokeVirtual(_, _, name, _) => {
+                    for &method in methods.get(name).into_iter().flatten() {
+                        add(method, ReferenceKind::VirtualCall);
+                    }
+                }
+                Instr::ObjectField(idx) | Instr::StructField(idx) if writes.contains(&i) => {
+                    add(idx.cast(), ReferenceKind::Write);
+                }
+                Instr::ObjectField(idx) | Instr::StructField(idx) => add(idx.cast(), ReferenceKind::Read),
+                Instr::New(idx) | Instr::Construct(_, idx) => add(idx.cast(), ReferenceKind::Construct),
+                Instr::EnumConst(_, member) => add(member.cast(), ReferenceKind::EnumValue),
+                _ => {}
+            }
+        }
+    }
+
+    fn add(&mut self, reference: Reference) {
+        let index = self.references.len();
+        self.by_source.entry(reference.source).or_default().push(index);
+        self.by_target.entry(reference.target).or_default().push(index);
+        self.references.push(reference);
+    }
+}
+
+// returns the positions of the field instructions that are the target of an assignment,
+// class fields accessed through a context are the last instruction before the exit of the context
+fn written_fields(instrs: &[(Location, Instr<Offset>)]) -> HashSet<usize> {
+    let mut writes = HashSet::new();
+    for (i, window) in instrs.windows(2).enumerate() {
+        let [(_, Instr::Assign), (location, lhs)] = window else {
+            continue;
+        };
+        match lhs {
+            Instr::ObjectField(_) | Instr::StructField(_) => {
+                writes.insert(i + 1);
+            }
+            Instr::Context(exit) => {
+                let exit = exit.absolute(*location);
+                let field = instrs[i + 1..].iter().position(|(loc, instr)| {
+                    matches!(instr, Instr::ObjectField(_))
+                        && u32::from(loc.value) + u32::from(instr.size()) == u32::from(exit.value)
+                });
+                if let Some(pos) = field {
+                    writes.insert(i + 1 + pos);
+                }
+            }
+            _ => {}
+        }
+    }
+    writes
+}
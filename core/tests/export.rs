@@ -0,0 +1,120 @@
+#![cfg(feature = "serde")]
+
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use redscript::bundle::{ConstantPool, PoolIndex, ScriptBundle};
+use redscript::bytecode::{Code, Instr};
+use redscript::definition::{
+    AnyDefinition, Class, ClassFlags, Definition, Field, FieldFlags, Function, FunctionFlags, Parameter,
+    ParameterFlags, SourceFile, SourceReference, Visibility,
+};
+use redscript::export::{export_bundle, import_bundle, ValueDocument};
+
+const PREDEF: &[u8] = include_bytes!("../../resources/predef.redscripts");
+
+fn encoded(bundle: &ScriptBundle) -> Vec<u8> {
+    let mut out = Cursor::new(vec![]);
+    bundle.save(&mut out).unwrap();
+    out.into_inner()
+}
+
+fn add_counter(pool: &mut ConstantPool) {
+    let int32 = pool
+        .definitions()
+        .find(|(_, def)| {
+            matches!(def.value, AnyDefinition::Type(_)) && pool.names.get(def.name).map_or(false, |n| &*n == "Int32")
+        })
+        .map(|(idx, _)| idx.cast())
+        .unwrap();
+
+    let file = pool.add_definition(Definition::source_file(SourceFile {
+        id: 1,
+        path_hash: 0,
+        path: PathBuf::from("counter.reds"),
+    }));
+    let class = pool.reserve();
+    let fun = pool.reserve();
+
+    let name = pool.names.add("max".into());
+    let max = pool.add_definition(Definition::param(
+        name,
+        fun,
+        Parameter {
+            type_: int32,
+            flags: ParameterFlags::new().with_is_optional(true),
+        },
+    ));
+    let name = pool.names.add("count".into());
+    let count = pool.add_definition(Definition::field(
+        name,
+        class,
+        Field {
+            visibility: Visibility::Private,
+            type_: int32,
+            flags: FieldFlags::new(),
+            hint: None,
+            attributes: vec![],
+            defaults: vec![],
+        },
+    ));
+    let name = pool.names.add("Reset;Int32".into());
+    pool.put_definition(
+        fun,
+        Definition::function(
+            name,
+            class,
+            Function {
+                visibility: Visibility::Public,
+                flags: FunctionFlags::new(),
+                source: Some(SourceReference { file, line: 1 }),
+                return_type: Some(int32),
+                unk1: false,
+                base_method: None,
+                parameters: vec![max],
+                locals: vec![],
+                operator: None,
+                cast: 0,
+                code: Code(vec![Instr::Return, Instr::Param(max), Instr::Nop]),
+                unk2: vec![],
+            },
+        ),
+    );
+    let name = pool.names.add("Counter".into());
+    pool.put_definition(
+        class,
+        Definition::class(
+            name,
+            Class {
+                visibility: Visibility::Public,
+                flags: ClassFlags::new().with_has_functions(true).with_has_fields(true),
+                base: PoolIndex::UNDEFINED,
+                functions: vec![fun],
+                fields: vec![count],
+                overrides: vec![],
+            },
+        ),
+    );
+}
+
+#[test]
+fn export_and_import_bundle() {
+    let mut bundle = ScriptBundle::load(&mut Cursor::new(PREDEF)).unwrap();
+    add_counter(&mut bundle.pool);
+    let expected = encoded(&bundle);
+
+    let doc = export_bundle(&bundle).unwrap();
+    let reset = doc
+        .definitions
+        .iter()
+        .find(|def| def.symbol == "Counter::Reset;Int32")
+        .unwrap();
+    assert_eq!(reset.parent.as_deref(), Some("Counter"));
+    assert!(matches!(
+        &reset.value,
+        ValueDocument::Function(fun) if fun.parameters == ["Counter::Reset;Int32/max"] && !fun.code.is_empty()
+    ));
+
+    let actual = encoded(&import_bundle(doc).unwrap());
+    assert!(expected == actual);
+}
@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use itertools::Itertools;
+use redscript::bundle::ConstantPool;
+use redscript::definition::{AnyDefinition, Class, Definition, Function};
+
+use crate::error::Error;
+use crate::print::{format_type, write_function_signature};
+use crate::Decompiler;
+
+/// Compares two pools by the qualified names of their definitions.
+/// When `compare_bodies` is set, functions present in both pools also have their decompiled bodies compared.
+pub fn diff_pools(old: &ConstantPool, new: &ConstantPool, compare_bodies: bool) -> Result<Vec<DiffEntry>, Error> {
+    let old_items = collect_items(old)?;
+    let new_items = collect_items(new)?;
+
+    let mut entries = vec![];
+    let mut removed = vec![];
+    let mut added = vec![];
+
+    for (key, old_item) in &old_items {
+        match new_items.get(key) {
+            Some(new_item) if old_item.desc != new_item.desc => entries.push(DiffEntry {
+                kind: key.0,
+                name: new_item.name.clone(),
+                change: Change::Changed(old_item.desc.clone(), new_item.desc.clone()),
+            }),
+            Some(new_item) if compare_bodies => {
+                if let (Some(old_fun), Some(new_fun)) = (old_item.function, new_item.function) {
+                    if body_changed(old_fun, old, new_fun, new, &new_item.name) {
+                        entries.push(DiffEntry {
+                            kind: key.0,
+                            name: new_item.name.clone(),
+                            change: Change::BodyChanged,
+                        });
+                    }
+                }
+            }
+            Some(_) => {}
+            None => removed.push((key.0, old_item)),
+        }
+    }
+    for (key, new_item) in &new_items {
+        if !old_items.contains_key(key) {
+            added.push((key.0, new_item));
+        }
+    }
+
+    // an overload that was removed and re-added under the same name is reported as a signature change
+    let removed_by_name = removed.iter().into_group_map_by(|(kind, item)| (*kind, &item.name));
+    let added_by_name = added.iter().into_group_map_by(|(kind, item)| (*kind, &item.name));
+    for (kind, item) in &removed {
+        match (
+            &removed_by_name[&(*kind, &item.name)][..],
+            added_by_name.get(&(*kind, &item.name)).map(Vec::as_slice),
+        ) {
+            ([_], Some([(_, new_item)])) if *kind == DiffKind::Function => entries.push(DiffEntry {
+                kind: *kind,
+                name: item.name.clone(),
+                change: Change::Changed(item.desc.clone(), new_item.desc.clone()),
+            }),
+            _ => entries.push(DiffEntry {
+                kind: *kind,
+                name: item.name.clone(),
+                change: Change::Removed(item.desc.clone()),
+            }),
+        }
+    }
+    for (kind, item) in &added {
+        match (
+            removed_by_name.get(&(*kind, &item.name)).map(Vec::as_slice),
+            &added_by_name[&(*kind, &item.name)][..],
+        ) {
+            (Some([_]), [_]) if *kind == DiffKind::Function => {}
+            _ => entries.push(DiffEntry {
+                kind: *kind,
+                name: item.name.clone(),
+                change: Change::Added(item.desc.clone()),
+            }),
+        }
+    }
+
+    entries.sort_by(|a, b| (a.kind, &a.name).cmp(&(b.kind, &b.name)));
+    Ok(entries)
+}
+
+#[derive(Debug)]
+pub struct DiffEntry {
+    pub kind: DiffKind,
+    pub name: String,
+    pub change: Change,
+}
+
+impl fmt::Display for DiffEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.change {
+            Change::Added(desc) => write!(f, "+ {} {}: {}", self.kind, self.name, desc),
+            Change::Removed(desc) => write!(f, "- {} {}: {}", self.kind, self.name, desc),
+            Change::Changed(old, new) => write!(f, "~ {} {}: {} -> {}", self.kind, self.name, old, new),
+            Change::BodyChanged => write!(f, "~ {} {}: body changed", self.kind, self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DiffKind {
+    Class,
+    Field,
+    Enum,
+    EnumMember,
+    Function,
+}
+
+impl fmt::Display for DiffKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let str = match self {
+            DiffKind::Class => "class",
+            DiffKind::Field => "field",
+            DiffKind::Enum => "enum",
+            DiffKind::EnumMember => "enum member",
+            DiffKind::Function => "func",
+        };
+        f.write_str(str)
+    }
+}
+
+#[derive(Debug)]
+pub enum Change {
+    Added(String),
+    Removed(String),
+    Changed(String, String),
+    BodyChanged,
+}
+
+struct Item<'a> {
+    name: String,
+    desc: String,
+    function: Option<&'a Function>,
+}
+
+impl<'a> Item<'a> {
+    fn new(name: &str, desc: String) -> Self {
+        Self {
+            name: name.to_owned(),
+            desc,
+            function: None,
+        }
+    }
+}
+
+type ItemKey = (DiffKind, String);
+
+fn collect_items(pool: &ConstantPool) -> Result<BTreeMap<ItemKey, Item<'_>>, Error> {
+    let mut items = BTreeMap::new();
+    for (_, def) in pool.roots() {
+        match &def.value {
+            AnyDefinition::Class(class) => {
+                let class_name = pool.names.get(def.name)?;
+                let desc = describe_class(&class_name, class, pool)?;
+                items.insert((DiffKind::Class, class_name.to_string()), Item::new(&class_name, desc));
+
+                for field_idx in &class.fields {
+                    let field = pool.field(*field_idx)?;
+                    let name = pool.def_name(*field_idx)?;
+                    let type_name = format_type(pool.definition(field.type_)?, pool)?;
+                    let desc = format!("{} let {}: {}", field.visibility, name, type_name);
+                    let qualified = format!("{class_name}.{name}");
+                    items.insert((DiffKind::Field, qualified.clone()), Item::new(&qualified, desc));
+                }
+                for method_idx in &class.functions {
+                    let definition = pool.definition(*method_idx)?;
+                    let (key, item) = function_item(Some(&class_name), definition, pool)?;
+                    items.insert((DiffKind::Function, key), item);
+                }
+            }
+            AnyDefinition::Enum(enum_) => {
+                let enum_name = pool.names.get(def.name)?;
+                let desc = format!("enum {enum_name}");
+                items.insert((DiffKind::Enum, enum_name.to_string()), Item::new(&enum_name, desc));
+
+                for member_idx in &enum_.members {
+                    let name = pool.def_name(*member_idx)?;
+                    let desc = format!("{} = {}", name, pool.enum_value(*member_idx)?);
+                    let qualified = format!("{enum_name}.{name}");
+                    items.insert((DiffKind::EnumMember, qualified.clone()), Item::new(&qualified, desc));
+                }
+            }
+            AnyDefinition::Function(_) => {
+                let (key, item) = function_item(None, def, pool)?;
+                items.insert((DiffKind::Function, key), item);
+            }
+            _ => {}
+        }
+    }
+    Ok(items)
+}
+
+fn function_item<'a>(
+    class_name: Option<&str>,
+    definition: &'a Definition,
+    pool: &'a ConstantPool,
+) -> Result<(String, Item<'a>), Error> {
+    let fun = definition
+        .value
+        .as_function()
+        .ok_or(Error::DecompileError("Expected a function definition"))?;
+    let mangled = pool.names.get(definition.name)?;
+    let pretty = mangled.split(';').next().unwrap_or_default();
+
+    let mut desc = vec![];
+    write_function_signature(&mut desc, definition, fun, pool)?;
+    let item = Item {
+        name: class_name.map_or_else(|| pretty.to_owned(), |class| format!("{class}::{pretty}")),
+        desc: String::from_utf8_lossy(&desc).into_owned(),
+        function: Some(fun),
+    };
+    let key = class_name.map_or_else(|| mangled.to_string(), |class| format!("{class}::{mangled}"));
+    Ok((key, item))
+}
+
+fn describe_class(name: &str, class: &Class, pool: &ConstantPool) -> Result<String, Error> {
+    let mut desc = format!("{} ", class.visibility);
+    if class.flags.is_abstract() {
+        desc.push_str("abstract ");
+    }
+    if class.flags.is_final() {
+        desc.push_str("final ");
+    }
+    if class.flags.is_native() {
+        desc.push_str("native ");
+    }
+    desc.push_str(if class.flags.is_struct() { "struct " } else { "class " });
+    desc.push_str(name);
+    if !class.base.is_undefined() {
+        desc.push_str(" extends ");
+        desc.push_str(&pool.def_name(class.base)?);
+    }
+    Ok(desc)
+}
+
+fn body_changed(old: &Function, old_pool: &ConstantPool, new: &Function, new_pool: &ConstantPool, name: &str) -> bool {
+    match (
+        Decompiler::decompiled(old, old_pool),
+        Decompiler::decompiled(new, new_pool),
+    ) {
+        (Ok(old_body), Ok(new_body)) => format!("{:?}", old_body.exprs) != format!("{:?}", new_body.exprs),
+        (Err(err), _) | (_, Err(err)) => {
+            log::warn!("Could not compare the body of {name} (caused by {err})");
+            false
+        }
+    }
+}
@@ -6,9 +6,11 @@ use redscript::bundle::{ConstantPool, PoolIndex};
 use redscript::bytecode::{CodeCursor, CursorError, Instr, IntrinsicOp, Location, Offset};
 use redscript::definition::Function;
 
+pub mod diff;
 pub mod error;
 pub mod files;
 pub mod print;
+pub mod stubs;
 
 pub struct Decompiler<'a> {
     code: CodeCursor<'a, Offset>,
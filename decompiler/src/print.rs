@@ -17,6 +17,7 @@ pub enum OutputMode {
     Code { verbose: bool },
     SyntaxTree,
     Bytecode,
+    Declarations,
 }
 
 fn write_indent<W: Write>(out: &mut W, depth: usize) -> Result<(), Error> {
@@ -44,7 +45,8 @@ pub fn write_definition<W: Write>(
             if class.flags.is_final() {
                 write!(out, "final ")?;
             }
-            if class.flags.is_import_only() {
+            let is_declaration = matches!(mode, OutputMode::Declarations);
+            if class.flags.is_import_only() || is_declaration && !class.flags.is_native() {
                 write!(out, "importonly ")?;
             } else if class.flags.is_native() {
                 write!(out, "native ")?;
@@ -54,7 +56,7 @@ pub fn write_definition<W: Write>(
             } else {
                 write!(out, "class ")?;
             }
-            write!(out, "{} ", pool.names.get(definition.name)?)?;
+            write!(out, "{} ", declared_name(&pool.names.get(definition.name)?, mode))?;
             if !class.base.is_undefined() {
                 write!(out, "extends {} ", pool.def_name(class.base)?)?;
             }
@@ -80,7 +82,8 @@ pub fn write_definition<W: Write>(
         }
         AnyDefinition::Enum(enum_) => {
             writeln!(out)?;
-            writeln!(out, "enum {} {{", pool.names.get(definition.name)?)?;
+            let name = pool.names.get(definition.name)?;
+            writeln!(out, "enum {} {{", declared_name(&name, mode))?;
 
             for member in &enum_.members {
                 write_definition(out, pool.definition(*member)?, pool, depth + 1, mode)?;
@@ -89,47 +92,15 @@ pub fn write_definition<W: Write>(
             writeln!(out, "}}")?;
         }
         AnyDefinition::Function(fun) => {
-            let return_type = fun.return_type.map_or_else(
-                || "Void".to_owned(),
-                |idx| format_type(pool.definition(idx).unwrap(), pool).unwrap(),
-            );
-
-            let name = pool.names.get(definition.name)?;
-            let pretty_name = name.split(';').next().expect("Function with empty name");
-
-            let params = fun
-                .parameters
-                .iter()
-                .map(|param| format_param(pool.definition(*param).unwrap(), pool).unwrap())
-                .format(", ");
-
             writeln!(out)?;
             write_indent(out, depth)?;
-            write!(out, "{} ", fun.visibility)?;
-            if fun.flags.is_final() {
-                write!(out, "final ")?;
-            }
-            if fun.flags.is_static() {
-                write!(out, "static ")?;
-            }
-            if fun.flags.is_native() {
-                write!(out, "native ")?;
-            }
-            if fun.flags.is_exec() {
-                write!(out, "exec ")?;
-            }
-            if fun.flags.is_const() {
-                write!(out, "const ")?;
-            }
-            if fun.flags.is_quest() {
-                write!(out, "quest ")?;
-            }
-            if fun.flags.is_callback() {
-                write!(out, "cb ")?;
+            if matches!(mode, OutputMode::Declarations) {
+                write_signature(out, definition, fun, pool, true)?;
+            } else {
+                write_function_signature(out, definition, fun, pool)?;
             }
-            write!(out, "func {}({}) -> {}", pretty_name, params, return_type)?;
 
-            if fun.flags.has_body() {
+            if fun.flags.has_body() && !matches!(mode, OutputMode::Declarations) {
                 write_function_body(out, fun, pool, depth, mode)?;
             } else {
                 write!(out, ";")?;
@@ -190,6 +161,76 @@ pub fn write_definition<W: Write>(
     Ok(())
 }
 
+pub(crate) fn write_function_signature<W: Write>(
+    out: &mut W,
+    definition: &Definition,
+    fun: &Function,
+    pool: &ConstantPool,
+) -> Result<(), Error> {
+    write_signature(out, definition, fun, pool, false)
+}
+
+// declarations have no body, so they're always written as native
+fn write_signature<W: Write>(
+    out: &mut W,
+    definition: &Definition,
+    fun: &Function,
+    pool: &ConstantPool,
+    is_declaration: bool,
+) -> Result<(), Error> {
+    let return_type = match fun.return_type {
+        Some(idx) => format_type(pool.definition(idx)?, pool)?,
+        None => "Void".to_owned(),
+    };
+
+    let name = pool.names.get(definition.name)?;
+    let pretty_name = name.split(';').next().expect("Function with empty name");
+    let pretty_name = if is_declaration {
+        declared_name(pretty_name, OutputMode::Declarations)
+    } else {
+        pretty_name
+    };
+
+    let params = fun
+        .parameters
+        .iter()
+        .map(|param| format_param(pool.definition(*param)?, pool))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    write!(out, "{} ", fun.visibility)?;
+    if fun.flags.is_final() {
+        write!(out, "final ")?;
+    }
+    if fun.flags.is_static() {
+        write!(out, "static ")?;
+    }
+    if fun.flags.is_native() || is_declaration {
+        write!(out, "native ")?;
+    }
+    if fun.flags.is_exec() {
+        write!(out, "exec ")?;
+    }
+    if fun.flags.is_const() {
+        write!(out, "const ")?;
+    }
+    if fun.flags.is_quest() {
+        write!(out, "quest ")?;
+    }
+    if fun.flags.is_callback() {
+        write!(out, "cb ")?;
+    }
+    write!(out, "func {}({}) -> {}", pretty_name, params.join(", "), return_type)?;
+    Ok(())
+}
+
+// declarations are written in a file with a module header, so the module is dropped from their names
+fn declared_name(name: &str, mode: OutputMode) -> &str {
+    match mode {
+        OutputMode::Declarations => name.rsplit_once('.').map_or(name, |(_, name)| name),
+        _ => name,
+    }
+}
+
 fn write_function_body<W: Write>(
     out: &mut W,
     fun: &Function,
@@ -221,6 +262,7 @@ fn write_function_body<W: Write>(
                 writeln!(out, "{}: {}", offset.value, op)?;
             }
         }
+        OutputMode::Declarations => {}
     }
 
     write_indent(out, depth)?;
@@ -452,7 +494,10 @@ fn write_unop<W: Write>(out: &mut W, param: &Expr<SourceAst>, op: UnOp, verbose:
 }
 
 fn format_param(def: &Definition, pool: &ConstantPool) -> Result<String, Error> {
-    let param = def.value.as_parameter().expect("Expected a param definition");
+    let param = def
+        .value
+        .as_parameter()
+        .ok_or(Error::DecompileError("Expected a param definition"))?;
     let type_name = format_type(pool.definition(param.type_)?, pool)?;
     let name = pool.names.get(def.name)?;
     let out = if param.flags.is_out() { "out " } else { "" };
@@ -461,8 +506,11 @@ fn format_param(def: &Definition, pool: &ConstantPool) -> Result<String, Error>
     Ok(format!("{const_}{out}{optional}{name}: {type_name}"))
 }
 
-fn format_type(def: &Definition, pool: &ConstantPool) -> Result<String, Error> {
-    let type_ = def.value.as_type().expect("Expected a type definition");
+pub(crate) fn format_type(def: &Definition, pool: &ConstantPool) -> Result<String, Error> {
+    let type_ = def
+        .value
+        .as_type()
+        .ok_or(Error::DecompileError("Expected a type definition"))?;
     let result = match type_ {
         Type::Prim | Type::Class => pool.names.get(def.name)?.to_string(),
         Type::Ref(nested) => format!("ref<{}>", format_type(pool.definition(*nested)?, pool)?),
@@ -484,6 +532,11 @@ fn format_binop(op: BinOp) -> &'static str {
         BinOp::AssignDivide => "/=",
         BinOp::AssignOr => "|=",
         BinOp::AssignAnd => "&=",
+        BinOp::AssignXor => "^=",
+        BinOp::AssignModulo => "%=",
+        BinOp::AssignShiftLeft => "<<=",
+        BinOp::AssignShiftRight => ">>=",
+        BinOp::Coalesce => "??",
         BinOp::LogicOr => "||",
         BinOp::LogicAnd => "&&",
         BinOp::Or => "|",
@@ -495,6 +548,8 @@ fn format_binop(op: BinOp) -> &'static str {
         BinOp::LessEqual => "<=",
         BinOp::Greater => ">",
         BinOp::GreaterEqual => ">=",
+        BinOp::ShiftLeft => "<<",
+        BinOp::ShiftRight => ">>",
         BinOp::Add => "+",
         BinOp::Subtract => "-",
         BinOp::Multiply => "*",
@@ -0,0 +1,69 @@
+use std::io::Write;
+use std::path::PathBuf;
+
+use hashbrown::HashMap;
+use itertools::Itertools;
+use redscript::bundle::ConstantPool;
+use redscript::definition::{AnyDefinition, Definition};
+
+use crate::error::Error;
+use crate::print::{write_definition, OutputMode};
+
+/// A file with the declarations of a class or an enum, or of the global functions of a module.
+pub struct StubFile<'a> {
+    pub path: PathBuf,
+    pub module: Option<String>,
+    pub definitions: Vec<&'a Definition>,
+}
+
+impl<'a> StubFile<'a> {
+    /// Groups the classes, enums and global functions of a pool into declaration files.
+    /// The files are placed in directories named after the modules of the definitions.
+    pub fn from_pool(pool: &'a ConstantPool) -> Result<Vec<Self>, Error> {
+        let mut files = vec![];
+        let mut functions: HashMap<Option<String>, Vec<&'a Definition>> = HashMap::new();
+
+        for (_, def) in pool.roots() {
+            let name = pool.names.get(def.name)?;
+            let name = name.split(';').next().expect("Definition with empty name");
+            let (module, name) = match name.rsplit_once('.') {
+                Some((module, name)) => (Some(module.to_owned()), name),
+                None => (None, name),
+            };
+            match &def.value {
+                AnyDefinition::Class(_) | AnyDefinition::Enum(_) => files.push(StubFile {
+                    path: module_dir(module.as_deref()).join(format!("{name}.reds")),
+                    module,
+                    definitions: vec![def],
+                }),
+                AnyDefinition::Function(_) => functions.entry(module).or_default().push(def),
+                _ => {}
+            }
+        }
+
+        let functions = functions
+            .into_iter()
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(module, definitions)| StubFile {
+                path: module_dir(module.as_deref()).join("functions.reds"),
+                module,
+                definitions,
+            });
+        files.extend(functions);
+        Ok(files)
+    }
+
+    pub fn write<W: Write>(&self, out: &mut W, pool: &ConstantPool) -> Result<(), Error> {
+        if let Some(module) = &self.module {
+            writeln!(out, "module {module}")?;
+        }
+        for def in &self.definitions {
+            write_definition(out, def, pool, 0, OutputMode::Declarations)?;
+        }
+        Ok(())
+    }
+}
+
+fn module_dir(module: Option<&str>) -> PathBuf {
+    module.into_iter().flat_map(|module| module.split('.')).collect()
+}
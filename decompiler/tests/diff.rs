@@ -0,0 +1,88 @@
+use itertools::Itertools;
+use redscript_decompiler::diff::{diff_pools, Change, DiffKind};
+
+#[allow(unused)]
+mod utils;
+
+use utils::compiled;
+
+const OLD: &str = "
+    class Player {
+        let health: Int32;
+
+        func Heal(amount: Int32) -> Int32 {
+            return amount;
+        }
+
+        func Kill() {}
+    }
+
+    func Removed() {}
+    ";
+
+const NEW: &str = "
+    class Player {
+        let health: Float;
+
+        func Heal(amount: Float) -> Int32 {
+            return 1;
+        }
+
+        func Kill() {
+            this.health = 0.0;
+        }
+    }
+
+    func Added() {}
+    ";
+
+#[test]
+fn diff_definitions() {
+    let old = compiled(vec![OLD]);
+    let new = compiled(vec![NEW]);
+
+    let entries = diff_pools(&old, &new, false).unwrap();
+    let changes = entries
+        .iter()
+        .map(|entry| (entry.kind, entry.name.as_str(), &entry.change))
+        .collect_vec();
+    assert!(matches!(
+        &changes[..],
+        &[
+            (DiffKind::Field, "Player.health", Change::Changed(_, _)),
+            (DiffKind::Function, "Added", Change::Added(_)),
+            (DiffKind::Function, "Player::Heal", Change::Changed(_, _)),
+            (DiffKind::Function, "Removed", Change::Removed(_)),
+        ]
+    ));
+}
+
+#[test]
+fn pair_changed_signatures() {
+    let old = compiled(vec![OLD]);
+    let new = compiled(vec![NEW]);
+
+    let entries = diff_pools(&old, &new, false).unwrap();
+    let heal = entries.iter().find(|entry| entry.name == "Player::Heal").unwrap();
+    match &heal.change {
+        Change::Changed(old, new) => {
+            assert!(old.ends_with("func Heal(amount: Int32) -> Int32"), "{old}");
+            assert!(new.ends_with("func Heal(amount: Float) -> Int32"), "{new}");
+        }
+        change => panic!("Expected a signature change, got {change:?}"),
+    }
+}
+
+#[test]
+fn diff_function_bodies() {
+    let old = compiled(vec![OLD]);
+    let new = compiled(vec![NEW]);
+
+    let entries = diff_pools(&old, &new, true).unwrap();
+    let bodies = entries
+        .iter()
+        .filter(|entry| matches!(entry.change, Change::BodyChanged))
+        .map(|entry| entry.name.as_str())
+        .collect_vec();
+    assert_eq!(bodies, ["Player::Kill"]);
+}
@@ -0,0 +1,61 @@
+use std::io::Cursor;
+
+use redscript::asm::qualified_name;
+use redscript::bundle::ScriptBundle;
+use redscript_decompiler::print::{write_definition, OutputMode};
+
+#[allow(unused)]
+mod utils;
+
+use utils::{compiled, PREDEF};
+
+const SOURCES: &str = "
+    native func OperatorShiftLeft(l: Int32, r: Int32) -> Int32
+    native func OperatorShiftRight(l: Int32, r: Int32) -> Int32
+    native func OperatorAssignShiftLeft(out l: Int32, r: Int32) -> Int32
+    native func OperatorAssignShiftRight(out l: Int32, r: Int32) -> Int32
+    native func OperatorXor(l: Int32, r: Int32) -> Int32
+    native func OperatorAssignXor(out l: Int32, r: Int32) -> Int32
+    native func OperatorAssignModulo(out l: Int32, r: Int32) -> Int32
+
+    func Flags(a: Int32, b: Int32) -> Int32 {
+        a <<= 1;
+        b >>= a;
+        a ^= b;
+        b %= 3;
+        return (a << 2) >> b ^ 1;
+    }
+    ";
+
+fn decompiled(name: &str) -> String {
+    // function bodies are only flagged once the bundle is saved
+    let mut bundle = ScriptBundle::load(&mut Cursor::new(PREDEF)).unwrap();
+    bundle.pool = compiled(vec![SOURCES]);
+    let mut bytes = Cursor::new(vec![]);
+    bundle.save(&mut bytes).unwrap();
+    bytes.set_position(0);
+    let pool = ScriptBundle::load(&mut bytes).unwrap().pool;
+
+    let (_, def) = pool
+        .definitions()
+        .find(|(idx, _)| qualified_name(*idx, &pool).map_or(false, |n| n == name))
+        .unwrap();
+    let mut out = vec![];
+    write_definition(&mut out, def, &pool, 0, OutputMode::Code { verbose: false }).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn bitwise_operators_round_trip() {
+    let code = decompiled("Flags;Int32Int32");
+
+    for line in [
+        "a <<= 1;",
+        "b >>= a;",
+        "a ^= b;",
+        "b %= 3;",
+        "return (a << 2) >> b ^ 1;",
+    ] {
+        assert!(code.contains(line), "{line} not found in:\n{code}");
+    }
+}
@@ -0,0 +1,98 @@
+use std::path::Path;
+
+use redscript::bundle::ConstantPool;
+use redscript_decompiler::stubs::StubFile;
+
+#[allow(unused)]
+mod utils;
+
+use utils::compiled;
+
+const SOURCES: &str = "
+    module Game.Items
+
+    public abstract class Item {
+        public let weight: Float;
+        protected let tags: array<CName>;
+        private let owner: wref<Item>;
+
+        public final func Weight() -> Float {
+            return this.weight;
+        }
+
+        public static func Scale(factor: Float) -> Float {
+            return factor;
+        }
+    }
+
+    enum Rarity {
+        Common = 0,
+        Rare = 1,
+    }
+
+    public struct Stack {
+        public let count: Int32;
+    }
+
+    func Total(items: array<ref<Item>>) -> Int32 {
+        return 0;
+    }
+    ";
+
+fn stub(pool: &ConstantPool, path: &str) -> String {
+    let files = StubFile::from_pool(pool).unwrap();
+    let file = files
+        .iter()
+        .find(|file| file.path == Path::new(path))
+        .unwrap_or_else(|| panic!("no stub file at {path}"));
+    let mut out = vec![];
+    file.write(&mut out, pool).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+#[test]
+fn class_stub() {
+    let pool = compiled(vec![SOURCES]);
+    let stub = stub(&pool, "Game/Items/Item.reds");
+
+    assert!(stub.starts_with("module Game.Items\n"));
+    assert!(stub.contains("\npublic abstract importonly class Item "));
+    assert!(stub.contains("\n  public let weight: Float;\n"));
+    assert!(stub.contains("\n  protected let tags: array<CName>;\n"));
+    assert!(stub.contains("\n  private let owner: wref<Game.Items.Item>;\n"));
+    assert!(stub.contains("\n  public final native func Weight() -> Float;\n"));
+    assert!(stub.contains("\n  public static native func Scale(factor: Float) -> Float;\n"));
+    assert!(!stub.contains("return"));
+}
+
+#[test]
+fn enum_stub() {
+    let pool = compiled(vec![SOURCES]);
+    let stub = stub(&pool, "Game/Items/Rarity.reds");
+
+    assert_eq!(
+        stub,
+        "module Game.Items\n\nenum Rarity {\n  Common = 0,\n  Rare = 1,\n}\n"
+    );
+}
+
+#[test]
+fn struct_stub() {
+    let pool = compiled(vec![SOURCES]);
+    let stub = stub(&pool, "Game/Items/Stack.reds");
+
+    assert_eq!(
+        stub,
+        "module Game.Items\n\npublic importonly struct Stack {\n\n  public let count: Int32;\n}\n"
+    );
+}
+
+#[test]
+fn global_function_stub() {
+    let pool = compiled(vec![SOURCES]);
+    let stub = stub(&pool, "Game/Items/functions.reds");
+
+    assert!(stub.starts_with("module Game.Items\n"));
+    assert!(stub.contains(" native func Total(items: array<ref<Game.Items.Item>>) -> Int32;\n"));
+    assert!(!stub.contains("return"));
+}
@@ -0,0 +1,28 @@
+use std::io::Cursor;
+
+use redscript::bundle::{ConstantPool, ScriptBundle};
+use redscript_compiler::diagnostics::Diagnostic;
+use redscript_compiler::parser;
+use redscript_compiler::source_map::Files;
+use redscript_compiler::unit::CompilationUnit;
+
+pub const PREDEF: &[u8] = include_bytes!("../../resources/predef.redscripts");
+
+pub fn compiled(sources: Vec<&str>) -> ConstantPool {
+    let modules = sources
+        .iter()
+        .map(|source| parser::parse_str(source).unwrap())
+        .collect();
+    let mut scripts = ScriptBundle::load(&mut Cursor::new(PREDEF)).unwrap();
+    let diagnostics = CompilationUnit::new_with_defaults(&mut scripts.pool)
+        .unwrap()
+        .compile(modules, &Files::default())
+        .unwrap()
+        .into_diagnostics();
+    assert!(
+        !diagnostics.iter().any(Diagnostic::is_fatal),
+        "Fatal errors: {:?}",
+        diagnostics
+    );
+    scripts.pool
+}
@@ -8,7 +8,7 @@ use bpaf::ParseFailure;
 use opts::{fix_args, Opts};
 #[cfg(test)]
 use rstest_reuse;
-use scc_lib::api::{SccOutput, SccResult, SccSettings};
+use scc_lib::api::{Profiling, SccOutput, SccResult, SccSettings};
 
 mod opts;
 
@@ -73,6 +73,13 @@ fn run(opts: Opts, r6_dir: &Path) -> anyhow::Result<bool> {
         r6_dir: r6_dir.into(),
         custom_cache_file: Some(custom_cache_file.into()),
         additional_script_paths,
+        emit_breakpoints: opts.breakpoint && !opts.no_breakpoint,
+        profiling: if opts.instrument {
+            Profiling::All
+        } else {
+            Profiling::Disabled
+        },
+        compact: false,
     };
 
     let is_success = SccApi::load()?.compile(settings.into());
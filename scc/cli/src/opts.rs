@@ -58,7 +58,9 @@ pub struct Opts {
     pub warnings: Vec<String>,
     pub no_testonly: bool,
     pub no_breakpoint: bool,
+    pub breakpoint: bool,
     pub profile: bool,
+    pub instrument: bool,
     pub script_paths_file: Option<PathBuf>,
     pub cache_file: Option<PathBuf>,
     pub no_exec: bool,
@@ -66,6 +68,8 @@ pub struct Opts {
 }
 
 impl Opts {
+    pub const DEFAULT_BREAKPOINT: bool = false;
+    pub const DEFAULT_INSTRUMENT: bool = false;
     pub const DEFAULT_NO_BREAKPOINT: bool = false;
     pub const DEFAULT_NO_DEBUG: bool = false;
     pub const DEFAULT_NO_EXEC: bool = false;
@@ -176,6 +180,16 @@ impl Opts {
             "Skips generation of breakpoint opcoes. Off by default",
         )
         .map(|s| s.unwrap_or(Opts::DEFAULT_NO_BREAKPOINT));
+        let breakpoint = toggle_options(
+            "-breakpoint",
+            "Generates breakpoint opcodes for debugging. Off by default",
+        )
+        .map(|s| s.unwrap_or(Opts::DEFAULT_BREAKPOINT));
+        let instrument = toggle_options(
+            "-instrument",
+            "Instruments all functions with profiling opcodes. Off by default",
+        )
+        .map(|s| s.unwrap_or(Opts::DEFAULT_INSTRUMENT));
         let warnings = no_space("-W", "Warnings enabled").many();
         let profile = equals_sign("-profile", "Introduces profiling opcodes. On by default").parse(|s| {
             if let Some(str) = s {
@@ -202,7 +216,9 @@ impl Opts {
             warnings,
             no_testonly,
             no_breakpoint,
+            breakpoint,
             profile,
+            instrument,
             script_paths_file(),
             cache_file,
             no_exec,
@@ -409,9 +425,30 @@ mod test {
         self::assert_eq!(opts.threads, threads.unwrap_or(Opts::DEFAULT_THREADS));
         self::assert_eq!(opts.no_testonly, no_testonly.unwrap_or(Opts::DEFAULT_NO_TESTONLY));
         self::assert_eq!(opts.no_breakpoint, no_breakpoint.unwrap_or(Opts::DEFAULT_NO_BREAKPOINT));
+        self::assert_eq!(opts.breakpoint, Opts::DEFAULT_BREAKPOINT);
         self::assert_eq!(opts.profile, profile.unwrap_or(Opts::DEFAULT_NO_PROFILE));
+        self::assert_eq!(opts.instrument, Opts::DEFAULT_INSTRUMENT);
         self::assert_eq!(opts.optimize, optimize.unwrap_or(Opts::DEFAULT_OPTIMIZE));
         self::assert_eq!(opts.no_exec, no_exec.unwrap_or(Opts::DEFAULT_NO_EXEC));
         self::assert_eq!(opts.no_debug, no_debug.unwrap_or(Opts::DEFAULT_NO_DEBUG));
     }
+
+    #[test]
+    fn breakpoints_are_opt_in() {
+        let opts = Opts::load(&["-compile", SCRIPTS_DIR, "-breakpoint"]).unwrap();
+        assert!(opts.breakpoint);
+        assert!(!opts.no_breakpoint);
+
+        let opts = Opts::load(&["-compile", SCRIPTS_DIR]).unwrap();
+        assert!(!opts.breakpoint);
+    }
+
+    #[test]
+    fn instrumentation_is_opt_in() {
+        let opts = Opts::load(&["-compile", SCRIPTS_DIR, "-profile=on", "-instrument"]).unwrap();
+        assert!(opts.instrument);
+
+        let opts = Opts::load(&["-compile", SCRIPTS_DIR, "-profile=on"]).unwrap();
+        assert!(!opts.instrument);
+    }
 }
@@ -27,6 +27,8 @@ fn api_functions_are_stable() {
         source_ref_parent_name,
         source_ref_path,
         source_ref_line,
+        settings_set_compact,
+        settings_set_emit_breakpoints,
     } = load_api();
 
     let _settings_new: unsafe extern "C" fn(*const i8) -> *mut SccSettings = settings_new.unwrap();
@@ -51,6 +53,9 @@ fn api_functions_are_stable() {
     let _source_ref_path: unsafe extern "C" fn(*mut SccOutput, *mut SccSourceRef) -> StrWithLen =
         source_ref_path.unwrap();
     let _source_ref_line: unsafe extern "C" fn(*mut SccOutput, *mut SccSourceRef) -> usize = source_ref_line.unwrap();
+    let _settings_set_compact: unsafe extern "C" fn(*mut SccSettings, bool) = settings_set_compact.unwrap();
+    let _settings_set_emit_breakpoints: unsafe extern "C" fn(*mut SccSettings, bool) =
+        settings_set_emit_breakpoints.unwrap();
 }
 
 #[test]
@@ -212,6 +217,8 @@ fn load_api() -> SccApi {
             source_ref_parent_name: lib.sym("scc_source_ref_parent_name\0").unwrap(),
             source_ref_path: lib.sym("scc_source_ref_path\0").unwrap(),
             source_ref_line: lib.sym("scc_source_ref_line\0").unwrap(),
+            settings_set_compact: lib.sym("scc_settings_set_compact\0").unwrap(),
+            settings_set_emit_breakpoints: lib.sym("scc_settings_set_emit_breakpoints\0").unwrap(),
         }
     }
 }
@@ -59,6 +59,10 @@ typedef StrWithLen scc_source_ref_path(SccOutput* output, SccSourceRef* ref);
 
 typedef size_t scc_source_ref_line(SccOutput* output, SccSourceRef* ref);
 
+typedef void scc_settings_set_compact(SccSettings* settings, bool compact);
+
+typedef void scc_settings_set_emit_breakpoints(SccSettings* settings, bool emit_breakpoints);
+
 typedef struct SccApi {
     /**
      * Creates new compilation settings.
@@ -121,6 +125,14 @@ typedef struct SccApi {
      * Returns the line in the source code where the entity behind the reference is defined.
      */
     scc_source_ref_line* source_ref_line;
+    /**
+     * Enables the removal of unreachable definitions and strings before the cache file is saved.
+     */
+    scc_settings_set_compact* settings_set_compact;
+    /**
+     * Enables the emission of breakpoint instructions for debugging, they are not emitted by default.
+     */
+    scc_settings_set_emit_breakpoints* settings_set_emit_breakpoints;
 } SccApi;
 
 #if defined(_WIN32) && !defined(BINDING_TEST)
@@ -143,6 +155,8 @@ inline SccApi scc_load_api(HMODULE module)
         (scc_source_ref_parent_name*)GetProcAddress(module, "scc_source_ref_parent_name"),
         (scc_source_ref_path*)GetProcAddress(module, "scc_source_ref_path"),
         (scc_source_ref_line*)GetProcAddress(module, "scc_source_ref_line"),
+        (scc_settings_set_compact*)GetProcAddress(module, "scc_settings_set_compact"),
+        (scc_settings_set_emit_breakpoints*)GetProcAddress(module, "scc_settings_set_emit_breakpoints"),
     };
     return api;
 }
@@ -6,6 +6,7 @@ use std::ptr;
 use redscript::bundle::ScriptBundle;
 use redscript::definition::AnyDefinition;
 use redscript_compiler::source_map::Files;
+pub use redscript_compiler::unit::Profiling;
 use redscript_compiler::unit::{CompilationOutput, SourceRef};
 
 use crate::compile;
@@ -18,6 +19,9 @@ pub unsafe extern "C" fn scc_settings_new(r6_dir: *const i8) -> Box<SccSettings>
         r6_dir: PathBuf::from(CStr::from_ptr(r6_dir).to_string_lossy().as_ref()).into_boxed_path(),
         custom_cache_file: None,
         additional_script_paths: vec![],
+        emit_breakpoints: false,
+        profiling: Profiling::Disabled,
+        compact: false,
     })
 }
 
@@ -39,6 +43,16 @@ pub unsafe extern "C" fn scc_settings_add_script_path(settings: &mut SccSettings
         .push(PathBuf::from(CStr::from_ptr(path).to_string_lossy().as_ref()).into_boxed_path());
 }
 
+#[no_mangle]
+pub extern "C" fn scc_settings_set_compact(settings: &mut SccSettings, compact: bool) {
+    settings.compact = compact;
+}
+
+#[no_mangle]
+pub extern "C" fn scc_settings_set_emit_breakpoints(settings: &mut SccSettings, emit_breakpoints: bool) {
+    settings.emit_breakpoints = emit_breakpoints;
+}
+
 #[no_mangle]
 pub extern "C" fn scc_compile(settings: Box<SccSettings>) -> Box<SccResult> {
     compile(&settings)
@@ -155,6 +169,9 @@ pub struct SccSettings {
     pub r6_dir: Box<Path>,
     pub custom_cache_file: Option<Box<Path>>,
     pub additional_script_paths: Vec<Box<Path>>,
+    pub emit_breakpoints: bool,
+    pub profiling: Profiling,
+    pub compact: bool,
 }
 
 #[derive(Debug)]
@@ -1,7 +1,10 @@
 pub mod api;
 pub mod hints;
+pub mod manifest;
 pub mod timestamp;
 
+use std::borrow::Cow;
+use std::ffi::OsStr;
 use std::fs::{self, File, OpenOptions};
 use std::path::{Path, PathBuf};
 use std::{fmt, io, iter, vec};
@@ -13,19 +16,25 @@ use flexi_logger::{Age, Cleanup, Criterion, Duplicate, FileSpec, LogSpecBuilder,
 use hashbrown::{HashMap, HashSet};
 use hints::UserHints;
 use log::LevelFilter;
+use manifest::{check_manifests, ManifestError};
 use redscript::ast::Span;
 use redscript::bundle::{ConstantPool, ScriptBundle};
+use redscript::compact::compact_pool;
 use redscript::definition::{Definition, Enum};
+use redscript::package::{Linker, Package};
 use redscript_compiler::error::Error;
 use redscript_compiler::source_map::{Files, SourceFilter};
-use redscript_compiler::unit::CompilationUnit;
+use redscript_compiler::unit::{CompilationOutput, CompilationUnit};
 use timestamp::CompileTimestamp;
+use walkdir::WalkDir;
 
 const BUNDLE_FILE_NAME: &str = "final.redscripts";
 const BACKUP_FILE_NAME: &str = "final.redscripts.bk";
 const LEGACY_TIMESTAMP_FILE_NAME: &str = "redscript.ts";
 
 const BACKUP_FILE_EXT: &str = "redscripts.bk";
+const LINE_MAP_FILE_EXT: &str = "redscripts.lines";
+const PACKAGE_FILE_EXT: &str = "redspkg";
 const TIMESTAMP_FILE_EXT: &str = "redscripts.ts";
 
 const USER_HINTS_DIR: &str = "redsUserHints";
@@ -71,8 +80,18 @@ fn try_compile(settings: &SccSettings) -> anyhow::Result<SccResult> {
     }
 
     let files = Files::from_dirs(&script_paths, &SourceFilter::None).context("Could not load script sources")?;
+    let packages = find_packages(&script_paths);
+    let manifest_errors = check_manifests(&script_paths);
 
-    match try_compile_files(&settings.r6_dir, &cache_file, files) {
+    let result = if manifest_errors.is_empty() {
+        try_compile_files(settings, &cache_file, files, &packages)
+    } else {
+        let scripts_dir = settings.r6_dir.join("scripts");
+        let hints = load_hints(&settings.r6_dir);
+        Err(ErrorReport::from_manifest_errors(manifest_errors, scripts_dir, files, hints).into())
+    };
+
+    match result {
         Ok(output) => {
             log::info!("Output successfully saved to {}", cache_file.display());
             Ok(output)
@@ -92,7 +111,12 @@ fn try_compile(settings: &SccSettings) -> anyhow::Result<SccResult> {
     }
 }
 
-fn try_compile_files(r6_dir: &Path, cache_file: &Path, files: Files) -> anyhow::Result<SccResult> {
+fn try_compile_files(
+    settings: &SccSettings,
+    cache_file: &Path,
+    files: Files,
+    packages: &[PathBuf],
+) -> anyhow::Result<SccResult> {
     let backup_path = cache_file.with_extension(BACKUP_FILE_EXT);
     let timestamp_path = cache_file.with_extension(TIMESTAMP_FILE_EXT);
 
@@ -165,7 +189,15 @@ fn try_compile_files(r6_dir: &Path, cache_file: &Path, files: Files) -> anyhow::
         ));
     }
 
-    let default_scripts_dir = r6_dir.join("scripts");
+    let default_scripts_dir = settings.r6_dir.join("scripts");
+    if !packages.is_empty() {
+        let failures = link_packages(&mut bundle.pool, packages)?;
+        if !failures.is_empty() {
+            let hints = load_hints(&settings.r6_dir);
+            return Err(ErrorReport::from_package_failures(failures, default_scripts_dir, files, hints).into());
+        }
+    }
+
     if !files.is_empty() {
         log::info!(
             "Compiling files in {}:\n{}",
@@ -175,6 +207,8 @@ fn try_compile_files(r6_dir: &Path, cache_file: &Path, files: Files) -> anyhow::
     }
     match CompilationUnit::new(&mut bundle.pool, vec![])
         .map_err(|err| anyhow::anyhow!("Failed to create the compilation unit: {err}"))?
+        .with_breakpoints(settings.emit_breakpoints)
+        .with_profiling(settings.profiling.clone())
         .compile_and_report(&files)
     {
         Ok(compilation) => {
@@ -182,6 +216,20 @@ fn try_compile_files(r6_dir: &Path, cache_file: &Path, files: Files) -> anyhow::
 
             add_redscript_signature_def(&mut bundle.pool);
 
+            // the source refs of the output point into the pool from before the compaction
+            let original_pool = if settings.compact {
+                let pool = bundle.pool.clone();
+                let stats = compact_pool(&mut bundle.pool).context("Failed to compact the script cache")?;
+                log::info!(
+                    "Removed {} definitions and {} strings",
+                    stats.definitions,
+                    stats.names + stats.strings + stats.tweakdb_ids + stats.resources
+                );
+                Some(pool)
+            } else {
+                None
+            };
+
             let mut file = File::create(cache_file).map_err(|err| match err.kind() {
                 io::ErrorKind::PermissionDenied => anyhow::anyhow!(
                     "Could not write to '{}', make sure the file is not read-only",
@@ -192,22 +240,41 @@ fn try_compile_files(r6_dir: &Path, cache_file: &Path, files: Files) -> anyhow::
             bundle.save(&mut io::BufWriter::new(&mut file))?;
             file.sync_all()?;
 
+            if let Some(pool) = original_pool {
+                bundle.pool = pool;
+            }
+
             CompileTimestamp::of_cache_file(&file)?.write(&mut *ts_file)?;
 
+            let line_map_path = cache_file.with_extension(LINE_MAP_FILE_EXT);
+            if let Err(err) = save_line_map(&compilation, &bundle.pool, &files, &line_map_path) {
+                log::warn!("Failed to save the line map: {err}");
+            }
+
             let output = SccOutput::new(compilation, bundle, files);
             Ok(SccResult::Success(Box::new(output)))
         }
         Err(err) => {
-            let hints = UserHints::load(r6_dir.join("config").join(USER_HINTS_DIR)).unwrap_or_else(|err| {
-                log::error!("Failed to parse one of the user hints TOML files: {}", err);
-                UserHints::default()
-            });
-
+            let hints = load_hints(&settings.r6_dir);
             Err(ErrorReport::from_error(err, default_scripts_dir.clone(), files, hints)?.into())
         }
     }
 }
 
+fn load_hints(r6_dir: &Path) -> UserHints {
+    UserHints::load(r6_dir.join("config").join(USER_HINTS_DIR)).unwrap_or_else(|err| {
+        log::error!("Failed to parse one of the user hints TOML files: {}", err);
+        UserHints::default()
+    })
+}
+
+fn save_line_map(output: &CompilationOutput, pool: &ConstantPool, files: &Files, path: &Path) -> anyhow::Result<()> {
+    let line_map = output.line_map(pool, files)?;
+    let file = File::create(path)?;
+    line_map.save(&mut io::BufWriter::new(file))?;
+    Ok(())
+}
+
 fn setup_logger(r6_dir: &Path) {
     let file = FileSpec::default().directory(r6_dir.join("logs")).basename("redscript");
     Logger::with(LogSpecBuilder::new().default(LevelFilter::Info).build())
@@ -228,6 +295,44 @@ fn get_base_bundle_path(cache_dir: &Path) -> PathBuf {
     }
 }
 
+fn find_packages(paths: &[Box<Path>]) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .flat_map(|path| {
+            WalkDir::new(path)
+                .follow_links(true)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(Result::ok)
+        })
+        .filter(|entry| entry.path().extension() == Some(OsStr::new(PACKAGE_FILE_EXT)))
+        .map(walkdir::DirEntry::into_path)
+        .collect()
+}
+
+fn link_packages(pool: &mut ConstantPool, packages: &[PathBuf]) -> anyhow::Result<Vec<PackageFailure>> {
+    let mut linker = Linker::new(pool).context("Failed to index the original script cache")?;
+    let mut failures = vec![];
+    for path in packages {
+        log::info!("Linking package {}", path.display());
+        if let Err(error) = link_package(&mut linker, path) {
+            log::error!("Failed to link the package {}: {error:#}", path.display());
+            failures.push(PackageFailure {
+                path: path.clone(),
+                error,
+            });
+        }
+    }
+    Ok(failures)
+}
+
+fn link_package(linker: &mut Linker<'_>, path: &Path) -> anyhow::Result<()> {
+    let file = File::open(path).context("Failed to open the package")?;
+    let package = Package::load(&mut io::BufReader::new(file)).context("Failed to load the package")?;
+    linker.link(&package).context("Failed to link the package")?;
+    Ok(())
+}
+
 fn add_redscript_signature_def(pool: &mut ConstantPool) {
     let name = pool.names.add(REDSCRIPT_SIGNATURE_DEF.into());
     let enum_ = Enum {
@@ -244,12 +349,20 @@ fn check_for_redscript_signature_def(pool: &ConstantPool) -> bool {
     matches!(last_def_name.as_deref(), Some(REDSCRIPT_SIGNATURE_DEF))
 }
 
+#[derive(Debug)]
+struct PackageFailure {
+    path: PathBuf,
+    error: anyhow::Error,
+}
+
 #[derive(Debug)]
 struct ErrorReport {
     scripts_dir: PathBuf,
     files: Files,
     hints: UserHints,
     spans: Vec<(&'static str, Span)>,
+    manifest_errors: Vec<ManifestError>,
+    package_failures: Vec<PackageFailure>,
 }
 
 impl ErrorReport {
@@ -263,6 +376,7 @@ impl ErrorReport {
             Error::MultipleErrors(spans) => spans,
             Error::IoError(err) => anyhow::bail!("There's been an I/O error: {err}"),
             Error::PoolError(err) => anyhow::bail!("There's been a constant pool error: {err}"),
+            Error::VerifyError(err) => anyhow::bail!("The generated bytecode is invalid: {err}"),
         };
 
         Ok(Self {
@@ -270,8 +384,46 @@ impl ErrorReport {
             files,
             hints,
             spans,
+            manifest_errors: vec![],
+            package_failures: vec![],
         })
     }
+
+    fn from_manifest_errors(errors: Vec<ManifestError>, scripts_dir: PathBuf, files: Files, hints: UserHints) -> Self {
+        Self {
+            scripts_dir,
+            files,
+            hints,
+            spans: vec![],
+            manifest_errors: errors,
+            package_failures: vec![],
+        }
+    }
+
+    fn from_package_failures(
+        failures: Vec<PackageFailure>,
+        scripts_dir: PathBuf,
+        files: Files,
+        hints: UserHints,
+    ) -> Self {
+        Self {
+            scripts_dir,
+            files,
+            hints,
+            spans: vec![],
+            manifest_errors: vec![],
+            package_failures: failures,
+        }
+    }
+
+    fn mod_name<'a>(&self, path: &'a Path) -> Cow<'a, str> {
+        path.strip_prefix(&self.scripts_dir)
+            .ok()
+            .and_then(|rel_path| rel_path.iter().next())
+            .or_else(|| path.file_name())
+            .unwrap_or_else(|| path.as_os_str())
+            .to_string_lossy()
+    }
 }
 
 impl fmt::Display for ErrorReport {
@@ -282,13 +434,7 @@ impl fmt::Display for ErrorReport {
         for &(code, span) in &self.spans {
             let loc = self.files.lookup(span).expect("span should point to a source map file");
             let rel_path = loc.file.path().strip_prefix(&self.scripts_dir).ok();
-            let cause = rel_path
-                .and_then(|rel_path| rel_path.iter().next())
-                .or_else(|| loc.file.path().file_name())
-                .unwrap_or_else(|| loc.file.path().as_os_str())
-                .to_string_lossy();
-
-            offending_mods.insert(cause);
+            offending_mods.insert(self.mod_name(loc.file.path()).into_owned());
             if let Some(act) =
                 self.hints
                     .get_by_error(code, rel_path, loc.file.source_slice(span), loc.enclosing_line())
@@ -297,6 +443,13 @@ impl fmt::Display for ErrorReport {
             }
         }
 
+        for err in &self.manifest_errors {
+            offending_mods.insert(err.mod_name());
+        }
+        for failure in &self.package_failures {
+            offending_mods.insert(self.mod_name(&failure.path).into_owned());
+        }
+
         writeln!(f, "REDScript compilation has failed.")?;
 
         if !offending_mods.is_empty() {
@@ -305,6 +458,25 @@ impl fmt::Display for ErrorReport {
                 writeln!(f, "- {}", mod_)?;
             }
         }
+        let mut headings = vec![];
+        for err in &self.manifest_errors {
+            if !headings.contains(&err.heading()) {
+                headings.push(err.heading());
+            }
+        }
+        for heading in headings {
+            writeln!(f, "{heading}:")?;
+            for err in self.manifest_errors.iter().filter(|err| err.heading() == heading) {
+                writeln!(f, "- {err}")?;
+            }
+        }
+        if !self.package_failures.is_empty() {
+            writeln!(f, "Some of the mods have packages that could not be linked:")?;
+            for failure in &self.package_failures {
+                let path = failure.path.strip_prefix(&self.scripts_dir).unwrap_or(&failure.path);
+                writeln!(f, "- {}: {:#}", path.display(), failure.error)?;
+            }
+        }
         if !hints_matched.is_empty() {
             writeln!(
                 f,
@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fmt, fs};
+
+use anyhow::Context;
+use hashbrown::{HashMap, HashSet};
+use semver::{Version, VersionReq};
+use serde::Deserialize;
+
+pub const MANIFEST_FILE_NAME: &str = "redscript.toml";
+
+/// An optional manifest placed in the root directory of a mod, it declares the name and version
+/// of the mod and the versions of other mods it depends on.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Manifest {
+    name: String,
+    version: Version,
+    #[serde(default)]
+    dependencies: BTreeMap<String, VersionReq>,
+    #[serde(default)]
+    optional_dependencies: BTreeMap<String, VersionReq>,
+}
+
+impl Manifest {
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let contents = fs::read_to_string(path).context("Failed to read the manifest file")?;
+        toml::from_str(&contents).context("Failed to parse the manifest file")
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+}
+
+/// Loads the manifests of the mods found in the script directories and checks whether the
+/// dependencies of each of them are satisfied. Mods are the directories placed directly in
+/// the script directories, a script directory with a manifest is treated as a mod too.
+pub fn check_manifests(script_paths: &[Box<Path>]) -> Vec<ManifestError> {
+    let mut manifests = vec![];
+    let mut errors = vec![];
+    for path in manifest_paths(script_paths) {
+        match Manifest::load(&path) {
+            Ok(manifest) => manifests.push(manifest),
+            Err(err) => errors.push(ManifestError::Invalid {
+                path,
+                cause: format!("{err:#}"),
+            }),
+        }
+    }
+
+    let mut versions: HashMap<&str, &Version> = HashMap::new();
+    let mut duplicates: HashSet<&str> = HashSet::new();
+    for manifest in &manifests {
+        if versions.insert(&manifest.name, &manifest.version).is_some() && duplicates.insert(&manifest.name) {
+            errors.push(ManifestError::Duplicate {
+                mod_: manifest.name.clone(),
+            });
+        }
+    }
+
+    for manifest in &manifests {
+        let required = manifest.dependencies.iter().map(|dep| (dep, true));
+        let optional = manifest.optional_dependencies.iter().map(|dep| (dep, false));
+        for ((dependency, requirement), is_required) in required.chain(optional) {
+            // it's unknown which of the copies of a duplicate mod is going to be used
+            if duplicates.contains(dependency.as_str()) {
+                continue;
+            }
+            match versions.get(dependency.as_str()) {
+                None if is_required => errors.push(ManifestError::MissingDependency {
+                    mod_: manifest.name.clone(),
+                    dependency: dependency.clone(),
+                    requirement: requirement.clone(),
+                }),
+                Some(&version) if !requirement.matches(version) => {
+                    errors.push(ManifestError::IncompatibleDependency {
+                        mod_: manifest.name.clone(),
+                        dependency: dependency.clone(),
+                        requirement: requirement.clone(),
+                        version: version.clone(),
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+    errors
+}
+
+fn manifest_paths(script_paths: &[Box<Path>]) -> impl Iterator<Item = PathBuf> + '_ {
+    script_paths
+        .iter()
+        .filter(|path| path.is_dir())
+        .flat_map(|path| {
+            let mod_dirs = fs::read_dir(path)
+                .into_iter()
+                .flatten()
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir());
+            let mut dirs = std::iter::once(path.to_path_buf()).chain(mod_dirs).collect::<Vec<_>>();
+            dirs.sort();
+            dirs
+        })
+        .map(|dir| dir.join(MANIFEST_FILE_NAME))
+        .filter(|path| path.is_file())
+}
+
+#[derive(Debug)]
+pub enum ManifestError {
+    Invalid {
+        path: PathBuf,
+        cause: String,
+    },
+    Duplicate {
+        mod_: String,
+    },
+    MissingDependency {
+        mod_: String,
+        dependency: String,
+        requirement: VersionReq,
+    },
+    IncompatibleDependency {
+        mod_: String,
+        dependency: String,
+        requirement: VersionReq,
+        version: Version,
+    },
+}
+
+impl ManifestError {
+    /// Returns the name of the mod at fault, the name of its directory is used when the manifest
+    /// could not be loaded.
+    pub fn mod_name(&self) -> String {
+        match self {
+            Self::Invalid { path, .. } => path
+                .parent()
+                .and_then(Path::file_name)
+                .unwrap_or(path.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+            Self::Duplicate { mod_ }
+            | Self::MissingDependency { mod_, .. }
+            | Self::IncompatibleDependency { mod_, .. } => mod_.clone(),
+        }
+    }
+
+    /// Returns a heading for the errors of the same kind as this one.
+    pub fn heading(&self) -> &'static str {
+        match self {
+            Self::Invalid { .. } => "Some of the mods have invalid manifests",
+            Self::Duplicate { .. } => "Some of the mods are installed more than once",
+            Self::MissingDependency { .. } | Self::IncompatibleDependency { .. } => {
+                "Some of the mods have missing or incompatible dependencies"
+            }
+        }
+    }
+}
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Invalid { path, cause } => write!(f, "{} is not a valid manifest: {cause}", path.display()),
+            Self::Duplicate { mod_ } => write!(f, "{mod_} is installed more than once"),
+            Self::MissingDependency {
+                mod_,
+                dependency,
+                requirement,
+            } => write!(f, "{mod_} requires {dependency} {requirement}, but it's not installed"),
+            Self::IncompatibleDependency {
+                mod_,
+                dependency,
+                requirement,
+                version,
+            } => write!(
+                f,
+                "{mod_} requires {dependency} {requirement}, but version {version} is installed"
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_fs::prelude::*;
+
+    use super::*;
+
+    fn add_mod(dir: &assert_fs::TempDir, path: &str, manifest: &str) {
+        dir.child(path).child(MANIFEST_FILE_NAME).write_str(manifest).unwrap();
+    }
+
+    #[test]
+    fn report_unsatisfied_dependencies() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        add_mod(&dir, "Core", "name = \"Core\"\nversion = \"1.2.0\"\n");
+        add_mod(
+            &dir,
+            "Addon",
+            "name = \"Addon\"\nversion = \"0.1.0\"\n[dependencies]\nCore = \"^2\"\nLib = \"1\"\n",
+        );
+
+        let errors = check_manifests(&[dir.path().into()]);
+        assert!(matches!(
+            &errors[..],
+            [
+                ManifestError::IncompatibleDependency { dependency: incompatible, .. },
+                ManifestError::MissingDependency { dependency: missing, .. },
+            ] if incompatible == "Core" && missing == "Lib"
+        ));
+        dir.close().unwrap();
+    }
+
+    #[test]
+    fn skip_dependencies_on_duplicates() {
+        let dir = assert_fs::TempDir::new().unwrap();
+        add_mod(&dir, "Core", "name = \"Core\"\nversion = \"1.0.0\"\n");
+        add_mod(&dir, "CoreCopy", "name = \"Core\"\nversion = \"2.0.0\"\n");
+        add_mod(
+            &dir,
+            "Addon",
+            "name = \"Addon\"\nversion = \"0.1.0\"\n[dependencies]\nCore = \"^2\"\n",
+        );
+
+        let errors = check_manifests(&[dir.path().into()]);
+        assert!(matches!(&errors[..], [ManifestError::Duplicate { mod_ }] if mod_ == "Core"));
+        dir.close().unwrap();
+    }
+}
@@ -84,3 +84,36 @@ fn timestamp_migration() -> Result<(), Box<dyn std::error::Error>> {
     temp.close()?;
     Ok(())
 }
+
+#[test]
+fn missing_dependency() -> Result<(), Box<dyn std::error::Error>> {
+    let temp = assert_fs::TempDir::new()?;
+
+    let predef = Path::new("../../resources/predef.redscripts");
+    let bundle_path = temp.child("final.redscripts");
+    fs::copy(predef, &bundle_path).expect("should copy predef.redscripts to bundle path");
+
+    temp.child("scripts/ModA/main.reds").write_str("class TestClass {}")?;
+    temp.child("scripts/ModA/redscript.toml").write_str(
+        r#"
+        name = "ModA"
+        version = "1.0.0"
+
+        [dependencies]
+        ModB = "^1.2"
+        "#,
+    )?;
+    temp.child("scripts/ModB/redscript.toml")
+        .write_str("name = \"ModB\"\nversion = \"1.1.0\"")?;
+
+    let mut cmd = Command::cargo_bin("scc")?;
+    cmd.arg("-compile")
+        .arg(temp.child("scripts").path())
+        .arg(bundle_path.path());
+    cmd.assert().failure().stdout(predicate::str::contains(
+        "- ModA requires ModB ^1.2, but version 1.1.0 is installed",
+    ));
+
+    temp.close()?;
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use redscript::bundle::PoolError;
+use redscript::bytecode::CursorError;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("pool error: {0}")]
+    PoolError(#[from] PoolError),
+    #[error("code cursor error: {0}")]
+    CursorError(#[from] CursorError),
+    #[error("native function {0} is not registered")]
+    MissingNative(String),
+    #[error("function {0} not found")]
+    FunctionNotFound(String),
+    #[error("method {0} not found")]
+    MethodNotFound(String),
+    #[error("variable {0} not found")]
+    VariableNotFound(String),
+    #[error("field {0} not found")]
+    FieldNotFound(String),
+    #[error("null reference")]
+    NullReference,
+    #[error("array index {0} out of bounds")]
+    IndexOutOfBounds(i64),
+    #[error("type mismatch: expected {0}, got {1}")]
+    TypeMismatch(&'static str, &'static str),
+    #[error("unexpected instruction: {0}")]
+    UnexpectedInstr(&'static str),
+    #[error("unsupported instruction: {0}")]
+    Unsupported(&'static str),
+    #[error("stack overflow")]
+    StackOverflow,
+    #[error("assertion failed: {0}")]
+    AssertionFailed(String),
+    #[error("{0}")]
+    Native(String),
+}
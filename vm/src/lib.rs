@@ -0,0 +1,883 @@
+//! An interpreter for compiled script bytecode.
+//!
+//! It executes functions from a [`ConstantPool`] without the game, which makes it possible to unit-test
+//! script logic in isolation. Native functions have no bytecode, so they have to be provided as Rust callbacks
+//! with [`Vm::register_native`], the common operators on primitive types are available through
+//! [`Vm::register_operators`]. Functions annotated with `@test` can be run with [`Vm::run_tests`].
+//!
+//! The `ArraySortByPredicate` instruction and external variables are not supported, executing them fails with
+//! [`Error::Unsupported`]. Values of `script_ref` types hold a copy of the referenced value.
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use error::Error;
+use hashbrown::HashMap;
+use itertools::Itertools;
+use redscript::asm::qualified_name;
+use redscript::bundle::{CName, ConstantPool, PoolError, PoolIndex};
+use redscript::bytecode::{Breakpoint, CodeCursor, Instr, Location, Offset};
+use redscript::definition::{AnyDefinition, Class, Definition, Enum, Field, Function, Local, Parameter, Type};
+use redscript::Ref;
+use value::{Instance, Obj, Value, WeakObj};
+
+pub mod error;
+pub mod operators;
+pub mod testing;
+pub mod value;
+
+pub type Native = Rc<dyn Fn(&mut Vm<'_>, &Value, &mut [Value]) -> Result<Value, Error>>;
+
+const MAX_CALL_DEPTH: usize = 256;
+
+pub struct Vm<'pool> {
+    pool: &'pool ConstantPool,
+    natives: HashMap<String, Native>,
+    types: HashMap<Ref<str>, PoolIndex<Definition>>,
+    breakpoint_handler: Option<Rc<dyn Fn(&Breakpoint)>>,
+    depth: usize,
+}
+
+impl<'pool> Vm<'pool> {
+    pub fn new(pool: &'pool ConstantPool) -> Self {
+        let types = pool
+            .definitions()
+            .filter(|(_, def)| matches!(def.value, AnyDefinition::Class(_) | AnyDefinition::Enum(_)))
+            .filter_map(|(index, def)| Some((pool.names.get(def.name).ok()?, index)))
+            .collect();
+        Self {
+            pool,
+            natives: HashMap::new(),
+            types,
+            breakpoint_handler: None,
+            depth: 0,
+        }
+    }
+
+    #[inline]
+    pub fn pool(&self) -> &'pool ConstantPool {
+        self.pool
+    }
+
+    /// Registers an implementation of a native function. The name can either be the full qualified name
+    /// of the function (e.g. `Class::Method;Int32`) or the qualified name without the signature
+    /// (e.g. `Class::Method`), in which case it's used for all overloads. The callback receives the
+    /// receiver of the call (void for static functions) and the arguments, changes made to the arguments
+    /// are written back for `out` parameters.
+    pub fn register_native<F>(&mut self, name: impl Into<String>, fun: F)
+    where
+        F: Fn(&mut Vm<'_>, &Value, &mut [Value]) -> Result<Value, Error> + 'static,
+    {
+        self.natives.insert(name.into(), Rc::new(fun));
+    }
+
+    /// Sets a callback invoked whenever an enabled breakpoint instruction is reached.
+    pub fn on_breakpoint<F>(&mut self, handler: F)
+    where
+        F: Fn(&Breakpoint) + 'static,
+    {
+        self.breakpoint_handler = Some(Rc::new(handler));
+    }
+
+    /// Finds a function by its qualified name, the signature can be omitted if there's only one overload.
+    pub fn function(&self, name: &str) -> Result<PoolIndex<Function>, Error> {
+        let mut candidates = vec![];
+        for (index, def) in self.pool.definitions() {
+            if let AnyDefinition::Function(_) = def.value {
+                let qualified = qualified_name(index, self.pool)?;
+                if qualified == name {
+                    return Ok(index.cast());
+                } else if strip_signature(&qualified) == name {
+                    candidates.push(index.cast());
+                }
+            }
+        }
+        match candidates[..] {
+            [index] => Ok(index),
+            _ => Err(Error::FunctionNotFound(name.to_owned())),
+        }
+    }
+
+    pub fn class(&self, name: &str) -> Result<PoolIndex<Class>, Error> {
+        match self.types.get(name) {
+            Some(index) if matches!(self.pool.definition(*index)?.value, AnyDefinition::Class(_)) => Ok(index.cast()),
+            _ => Err(PoolError::UnexpectedEntry("class").into()),
+        }
+    }
+
+    /// Calls a function, `this` should be void for static functions.
+    pub fn call(&mut self, function: PoolIndex<Function>, this: Value, args: Vec<Value>) -> Result<Value, Error> {
+        let (res, _) = self.invoke(function, this, args)?;
+        Ok(res)
+    }
+
+    /// Creates an instance of a class with all fields set to their default values.
+    pub fn new_object(&self, class: PoolIndex<Class>) -> Result<Obj, Error> {
+        Ok(Obj::new(self.new_instance(class)?))
+    }
+
+    pub fn default_value(&self, type_: PoolIndex<Type>) -> Result<Value, Error> {
+        let value = match *self.pool.type_(type_)? {
+            Type::Prim | Type::Class => match &*self.pool.def_name(type_)? {
+                "Bool" => Value::Bool(false),
+                "Int8" => Value::I8(0),
+                "Int16" => Value::I16(0),
+                "Int32" => Value::I32(0),
+                "Int64" => Value::I64(0),
+                "Uint8" => Value::U8(0),
+                "Uint16" => Value::U16(0),
+                "Uint32" => Value::U32(0),
+                "Uint64" => Value::U64(0),
+                "Float" => Value::F32(0.),
+                "Double" => Value::F64(0.),
+                "String" => Value::String("".into()),
+                "CName" => Value::Name(self.pool.names.get(PoolIndex::UNDEFINED)?),
+                "TweakDBID" => Value::TweakDbId("".into()),
+                "ResRef" => Value::Resource("".into()),
+                "Variant" => Value::Variant(None),
+                name => match self.types.get(name) {
+                    Some(index) => match &self.pool.definition(*index)?.value {
+                        AnyDefinition::Class(class) if class.flags.is_struct() => {
+                            Value::Struct(Box::new(self.new_instance(index.cast())?))
+                        }
+                        AnyDefinition::Enum(_) => Value::Enum(index.cast(), 0),
+                        _ => Value::NULL,
+                    },
+                    None => Value::Void,
+                },
+            },
+            Type::Ref(_) => Value::NULL,
+            Type::WeakRef(_) => Value::WeakRef(WeakObj::default()),
+            Type::Array(_) => Value::Array(vec![]),
+            Type::StaticArray(inner, size) => Value::Array(vec![self.default_value(inner)?; size as usize]),
+            Type::ScriptRef(inner) => Value::ScriptRef(Rc::new(RefCell::new(self.default_value(inner)?))),
+        };
+        Ok(value)
+    }
+
+    /// Converts a value to a string the same way as the `ToString` intrinsic.
+    pub fn stringify(&self, value: &Value) -> Result<String, Error> {
+        let str = match value {
+            Value::Void | Value::Ref(None) | Value::Variant(None) => String::new(),
+            Value::Bool(b) => b.to_string(),
+            Value::I8(i) => i.to_string(),
+            Value::I16(i) => i.to_string(),
+            Value::I32(i) => i.to_string(),
+            Value::I64(i) => i.to_string(),
+            Value::U8(i) => i.to_string(),
+            Value::U16(i) => i.to_string(),
+            Value::U32(i) => i.to_string(),
+            Value::U64(i) => i.to_string(),
+            Value::F32(f) => f.to_string(),
+            Value::F64(f) => f.to_string(),
+            Value::String(str) | Value::Name(str) | Value::TweakDbId(str) | Value::Resource(str) => str.to_string(),
+            Value::Enum(enum_, val) => {
+                let mut name = val.to_string();
+                for member in &self.pool.enum_(*enum_)?.members {
+                    if self.pool.enum_value(*member)? == *val {
+                        name = self.pool.def_name(*member)?.to_string();
+                        break;
+                    }
+                }
+                name
+            }
+            Value::Ref(Some(obj)) => self.pool.def_name(obj.class())?.to_string(),
+            Value::WeakRef(weak) => return self.stringify(&Value::Ref(weak.upgrade())),
+            Value::Struct(instance) => self.pool.def_name(instance.class)?.to_string(),
+            Value::Array(items) => {
+                let items: Vec<_> = items.iter().map(|item| self.stringify(item)).try_collect()?;
+                format!("[{}]", items.join(", "))
+            }
+            Value::Variant(Some(inner)) => return self.stringify(&inner.1),
+            Value::ScriptRef(inner) => return self.stringify(&inner.borrow()),
+        };
+        Ok(str)
+    }
+
+    fn invoke(
+        &mut self,
+        index: PoolIndex<Function>,
+        this: Value,
+        mut args: Vec<Value>,
+    ) -> Result<(Value, Vec<Value>), Error> {
+        let pool = self.pool;
+        let fun = pool.function(index)?;
+        if fun.flags.is_native() {
+            let native = self.native(index)?;
+            let res = native(self, &this, &mut args)?;
+            return Ok((res, args));
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(Error::StackOverflow);
+        }
+
+        let mut args = args.into_iter();
+        let mut params = HashMap::with_capacity(fun.parameters.len());
+        for param in &fun.parameters {
+            let value = match args.next() {
+                Some(value) => value,
+                None => self.default_value(pool.parameter(*param)?.type_)?,
+            };
+            params.insert(*param, value);
+        }
+        let mut locals = HashMap::with_capacity(fun.locals.len());
+        for local in &fun.locals {
+            locals.insert(*local, self.default_value(pool.local(*local)?.type_)?);
+        }
+        let mut frame = Frame {
+            code: fun.code.cursor(),
+            this,
+            params,
+            locals,
+        };
+
+        self.depth += 1;
+        let res = self.exec(&mut frame);
+        self.depth -= 1;
+
+        let args = fun
+            .parameters
+            .iter()
+            .map(|param| frame.params.remove(param).unwrap_or_default())
+            .collect();
+        Ok((res?, args))
+    }
+
+    fn native(&self, index: PoolIndex<Function>) -> Result<Native, Error> {
+        let name = qualified_name(index, self.pool)?;
+        self.natives
+            .get(&name)
+            .or_else(|| self.natives.get(strip_signature(&name)))
+            .cloned()
+            .ok_or(Error::MissingNative(name))
+    }
+
+    fn exec(&mut self, frame: &mut Frame<'pool>) -> Result<Value, Error> {
+        loop {
+            let position = frame.code.pos();
+            let Some(instr) = frame.code.peek() else {
+                return Ok(Value::Void);
+            };
+            match instr {
+                Instr::Return => {
+                    frame.code.pop()?;
+                    if frame.code.peek() == Some(Instr::Nop) {
+                        frame.code.pop()?;
+                        return Ok(Value::Void);
+                    }
+                    return Ok(self.eval(frame)?.deref());
+                }
+                Instr::Jump(offset) | Instr::Skip(offset) => {
+                    frame.code.seek_abs(offset.absolute(position))?;
+                }
+                Instr::JumpIfFalse(offset) => {
+                    frame.code.pop()?;
+                    if !self.eval(frame)?.as_bool()? {
+                        frame.code.seek_abs(offset.absolute(position))?;
+                    }
+                }
+                Instr::Switch(_, start) => {
+                    frame.code.pop()?;
+                    self.switch(frame, start.absolute(position))?;
+                }
+                // falling through to the next case skips its matcher
+                Instr::SwitchLabel(_, body) => {
+                    frame.code.seek_abs(body.absolute(position))?;
+                }
+                Instr::Breakpoint(breakpoint) => {
+                    frame.code.pop()?;
+                    match &self.breakpoint_handler {
+                        Some(handler) if breakpoint.enabled => handler(&breakpoint),
+                        _ => {}
+                    }
+                }
+                Instr::SwitchDefault | Instr::Nop | Instr::StartProfiling(_) => {
+                    frame.code.pop()?;
+                }
+                _ => {
+                    self.eval(frame)?;
+                }
+            }
+        }
+    }
+
+    fn switch(&mut self, frame: &mut Frame<'pool>, start: Location) -> Result<(), Error> {
+        let subject = self.eval(frame)?;
+        frame.code.seek_abs(start)?;
+        loop {
+            let position = frame.code.pos();
+            match frame.code.peek() {
+                Some(Instr::SwitchLabel(next, body)) => {
+                    frame.code.pop()?;
+                    if self.eval(frame)? == subject {
+                        frame.code.seek_abs(body.absolute(position))?;
+                        return Ok(());
+                    }
+                    frame.code.seek_abs(next.absolute(position))?;
+                }
+                Some(Instr::SwitchDefault) => {
+                    frame.code.pop()?;
+                    return Ok(());
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn eval(&mut self, frame: &mut Frame<'pool>) -> Result<Value, Error> {
+        self.eval_with(frame, None)
+    }
+
+    fn eval_with(&mut self, frame: &mut Frame<'pool>, context: Option<Value>) -> Result<Value, Error> {
+        if let Some(
+            Instr::Local(_)
+            | Instr::Param(_)
+            | Instr::ObjectField(_)
+            | Instr::StructField(_)
+            | Instr::ArrayElement(_)
+            | Instr::StaticArrayElement(_)
+            | Instr::Deref(_),
+        ) = frame.code.peek()
+        {
+            let place = self.place_with(frame, context)?;
+            return self.read(frame, place);
+        }
+
+        let pool = self.pool;
+        let position = frame.code.pos();
+        let value = match frame.code.pop()? {
+            Instr::Nop | Instr::Breakpoint(_) | Instr::StartProfiling(_) => Value::Void,
+            Instr::Null => Value::NULL,
+            Instr::WeakRefNull => Value::WeakRef(WeakObj::default()),
+            Instr::I32One => Value::I32(1),
+            Instr::I32Zero => Value::I32(0),
+            Instr::I8Const(val) => Value::I8(val),
+            Instr::I16Const(val) => Value::I16(val),
+            Instr::I32Const(val) => Value::I32(val),
+            Instr::I64Const(val) => Value::I64(val),
+            Instr::U8Const(val) => Value::U8(val),
+            Instr::U16Const(val) => Value::U16(val),
+            Instr::U32Const(val) => Value::U32(val),
+            Instr::U64Const(val) => Value::U64(val),
+            Instr::F32Const(val) => Value::F32(val),
+            Instr::F64Const(val) => Value::F64(val),
+            Instr::NameConst(idx) => Value::Name(pool.names.get(idx)?),
+            Instr::EnumConst(enum_, member) => Value::Enum(enum_, pool.enum_value(member)?),
+            Instr::StringConst(idx) => Value::String(pool.strings.get(idx)?),
+            Instr::TweakDbIdConst(idx) => Value::TweakDbId(pool.tweakdb_ids.get(idx)?),
+            Instr::ResourceConst(idx) => Value::Resource(pool.resources.get(idx)?),
+            Instr::TrueConst => Value::Bool(true),
+            Instr::FalseConst => Value::Bool(false),
+            Instr::Assign => {
+                let place = self.place(frame)?;
+                let value = self.eval(frame)?;
+                self.write(frame, place, value)?;
+                Value::Void
+            }
+            Instr::Conditional(false_case, exit) => {
+                if self.eval(frame)?.as_bool()? {
+                    let value = self.eval(frame)?;
+                    frame.code.seek_abs(exit.absolute(position))?;
+                    value
+                } else {
+                    frame.code.seek_abs(false_case.absolute(position))?;
+                    self.eval(frame)?
+                }
+            }
+            Instr::Construct(n, class) => {
+                let mut instance = self.new_instance(class)?;
+                for field in self.class_fields(class)?.into_iter().take(n.into()) {
+                    let value = self.eval(frame)?;
+                    instance.fields.insert(field, value);
+                }
+                Value::Struct(Box::new(instance))
+            }
+            Instr::InvokeStatic(_, _, idx, _) => {
+                let this = if pool.function(idx)?.flags.is_static() {
+                    Value::Void
+                } else {
+                    context.unwrap_or_else(|| frame.this.clone())
+                };
+                self.call_with_args(frame, idx, this)?
+            }
+            Instr::InvokeVirtual(_, _, name, _) => {
+                let this = context.unwrap_or_else(|| frame.this.clone());
+                let idx = self.resolve_virtual(this.to_obj()?.class(), name)?;
+                self.call_with_args(frame, idx, this)?
+            }
+            Instr::Context(_) => {
+                let context = self.eval(frame)?.deref();
+                self.eval_with(frame, Some(context))?
+            }
+            Instr::Equals(_) | Instr::RefStringEqualsString(_) | Instr::StringEqualsRefString(_) => {
+                Value::Bool(self.eval(frame)? == self.eval(frame)?)
+            }
+            Instr::NotEquals(_) | Instr::RefStringNotEqualsString(_) | Instr::StringNotEqualsRefString(_) => {
+                Value::Bool(self.eval(frame)? != self.eval(frame)?)
+            }
+            Instr::New(class) => self.new_object(class)?.into(),
+            Instr::Delete => {
+                self.eval(frame)?;
+                Value::Void
+            }
+            Instr::This => frame.this.clone(),
+            Instr::ArrayClear(_) => {
+                let place = self.place(frame)?;
+                self.modify(frame, place, |array| {
+                    array.as_array_mut()?.clear();
+                    Ok(())
+                })?;
+                Value::Void
+            }
+            Instr::ArraySize(_) | Instr::StaticArraySize(_) => Value::I32(self.eval(frame)?.as_array()?.len() as i32),
+            Instr::ArrayResize(type_) => {
+                let place = self.place(frame)?;
+                let size = self.eval(frame)?.as_i64()?;
+                let size = usize::try_from(size).map_err(|_| Error::IndexOutOfBounds(size))?;
+                let default = self.default_value(self.element_type(type_)?)?;
+                self.modify(frame, place, |array| {
+                    array.as_array_mut()?.resize(size, default);
+                    Ok(())
+                })?;
+                Value::Void
+            }
+            Instr::ArrayFindFirst(_)
+            | Instr::ArrayFindFirstFast(_)
+            | Instr::StaticArrayFindFirst(_)
+            | Instr::StaticArrayFindFirstFast(_) => {
+                let array = self.eval(frame)?;
+                let needle = self.eval(frame)?;
+                let index = array.as_array()?.iter().position(|elem| *elem == needle);
+                Value::I32(index.map_or(-1, |i| i as i32))
+            }
+            Instr::ArrayFindLast(_)
+            | Instr::ArrayFindLastFast(_)
+            | Instr::StaticArrayFindLast(_)
+            | Instr::StaticArrayFindLastFast(_) => {
+                let array = self.eval(frame)?;
+                let needle = self.eval(frame)?;
+                let index = array.as_array()?.iter().rposition(|elem| *elem == needle);
+                Value::I32(index.map_or(-1, |i| i as i32))
+            }
+            Instr::ArrayContains(_)
+            | Instr::ArrayContainsFast(_)
+            | Instr::StaticArrayContains(_)
+            | Instr::StaticArrayContainsFast(_) => {
+                let array = self.eval(frame)?;
+                let needle = self.eval(frame)?;
+                Value::Bool(array.as_array()?.contains(&needle))
+            }
+            Instr::ArrayCount(_)
+            | Instr::ArrayCountFast(_)
+            | Instr::StaticArrayCount(_)
+            | Instr::StaticArrayCountFast(_) => {
+                let array = self.eval(frame)?;
+                let needle = self.eval(frame)?;
+                Value::I32(array.as_array()?.iter().filter(|elem| **elem == needle).count() as i32)
+            }
+            Instr::ArrayPush(_) => {
+                let place = self.place(frame)?;
+                let value = self.eval(frame)?;
+                self.modify(frame, place, |array| {
+                    array.as_array_mut()?.push(value);
+                    Ok(())
+                })?;
+                Value::Void
+            }
+            Instr::ArrayPop(type_) => {
+                let place = self.place(frame)?;
+                let default = self.default_value(self.element_type(type_)?)?;
+                self.modify(frame, place, |array| Ok(array.as_array_mut()?.pop().unwrap_or(default)))?
+            }
+            Instr::ArrayInsert(_) => {
+                let place = self.place(frame)?;
+                let index = self.eval(frame)?.as_i64()?;
+                let value = self.eval(frame)?;
+                self.modify(frame, place, |array| {
+                    let array = array.as_array_mut()?;
+                    match usize::try_from(index) {
+                        Ok(i) if i <= array.len() => {
+                            array.insert(i, value);
+                            Ok(())
+                        }
+                        _ => Err(Error::IndexOutOfBounds(index)),
+                    }
+                })?;
+                Value::Void
+            }
+            Instr::ArrayRemove(_) | Instr::ArrayRemoveFast(_) => {
+                let place = self.place(frame)?;
+                let value = self.eval(frame)?;
+                self.modify(frame, place, |array| {
+                    let array = array.as_array_mut()?;
+                    let index = array.iter().position(|elem| *elem == value);
+                    Ok(Value::Bool(index.map(|i| array.remove(i)).is_some()))
+                })?
+            }
+            Instr::ArrayGrow(type_) => {
+                let place = self.place(frame)?;
+                let count = self.eval(frame)?.as_i64()?;
+                let count = usize::try_from(count).map_err(|_| Error::IndexOutOfBounds(count))?;
+                let default = self.default_value(self.element_type(type_)?)?;
+                self.modify(frame, place, |array| {
+                    let array = array.as_array_mut()?;
+                    array.resize(array.len() + count, default);
+                    Ok(())
+                })?;
+                Value::Void
+            }
+            Instr::ArrayErase(_) | Instr::ArrayEraseFast(_) => {
+                let place = self.place(frame)?;
+                let index = self.eval(frame)?.as_i64()?;
+                self.modify(frame, place, |array| {
+                    let array = array.as_array_mut()?;
+                    match usize::try_from(index) {
+                        Ok(i) if i < array.len() => {
+                            array.remove(i);
+                            Ok(Value::Bool(true))
+                        }
+                        _ => Ok(Value::Bool(false)),
+                    }
+                })?
+            }
+            Instr::ArrayLast(type_) | Instr::StaticArrayLast(type_) => {
+                let array = self.eval(frame)?;
+                match array.as_array()?.last() {
+                    Some(last) => last.clone(),
+                    None => self.default_value(self.element_type(type_)?)?,
+                }
+            }
+            Instr::ArraySort(_) => {
+                let place = self.place(frame)?;
+                self.modify(frame, place, |array| {
+                    let array = array.as_array_mut()?;
+                    array.sort_by(|lhs, rhs| lhs.compare(rhs).unwrap_or(Ordering::Equal));
+                    Ok(())
+                })?;
+                Value::Void
+            }
+            Instr::ArraySortByPredicate(_) => return Err(Error::Unsupported("ArraySortByPredicate")),
+            Instr::RefToBool => Value::Bool(matches!(self.eval(frame)?.deref(), Value::Ref(Some(_)))),
+            Instr::WeakRefToBool => Value::Bool(self.eval(frame)?.to_obj().is_ok()),
+            Instr::EnumToI32(_, size) => {
+                let value = self.eval(frame)?.as_i64()?;
+                match size {
+                    1 => Value::I8(value as i8),
+                    2 => Value::I16(value as i16),
+                    8 => Value::I64(value),
+                    _ => Value::I32(value as i32),
+                }
+            }
+            Instr::I32ToEnum(type_, _) => {
+                let value = self.eval(frame)?.as_i64()?;
+                Value::Enum(self.enum_of_type(type_)?, value)
+            }
+            Instr::DynamicCast(class, _) => match self.eval(frame)?.deref() {
+                Value::Ref(Some(obj)) if self.is_subclass(obj.class(), class)? => obj.into(),
+                Value::WeakRef(weak) => match weak.upgrade() {
+                    Some(obj) if self.is_subclass(obj.class(), class)? => Value::WeakRef(weak),
+                    _ => Value::WeakRef(WeakObj::default()),
+                },
+                _ => Value::NULL,
+            },
+            Instr::ToString(_) | Instr::VariantToString => {
+                let value = self.eval(frame)?;
+                Value::String(self.stringify(&value)?.into())
+            }
+            Instr::ToVariant(type_) => Value::Variant(Some(Box::new((type_, self.eval(frame)?.deref())))),
+            Instr::FromVariant(type_) => match self.eval(frame)?.deref() {
+                Value::Variant(Some(inner)) if self.is_same_type(inner.0, type_)? => inner.1,
+                _ => self.default_value(type_)?,
+            },
+            Instr::VariantIsDefined => Value::Bool(matches!(self.eval(frame)?.deref(), Value::Variant(Some(_)))),
+            Instr::VariantIsRef => match self.eval(frame)?.deref() {
+                Value::Variant(Some(inner)) => {
+                    Value::Bool(matches!(pool.type_(inner.0)?, Type::Ref(_) | Type::WeakRef(_)))
+                }
+                _ => Value::Bool(false),
+            },
+            Instr::VariantIsArray => match self.eval(frame)?.deref() {
+                Value::Variant(Some(inner)) => {
+                    Value::Bool(matches!(pool.type_(inner.0)?, Type::Array(_) | Type::StaticArray(_, _)))
+                }
+                _ => Value::Bool(false),
+            },
+            Instr::VariantTypeName => match self.eval(frame)?.deref() {
+                Value::Variant(Some(inner)) => Value::Name(pool.def_name(inner.0)?),
+                _ => Value::Name(pool.names.get(PoolIndex::UNDEFINED)?),
+            },
+            Instr::WeakRefToRef => Value::Ref(self.eval(frame)?.to_obj().ok()),
+            Instr::RefToWeakRef => match self.eval(frame)?.deref() {
+                Value::Ref(Some(obj)) => Value::WeakRef(obj.downgrade()),
+                _ => Value::WeakRef(WeakObj::default()),
+            },
+            Instr::AsRef(_) => Value::ScriptRef(Rc::new(RefCell::new(self.eval(frame)?.deref()))),
+            Instr::ExternalVar => return Err(Error::Unsupported("ExternalVar")),
+            other => return Err(Error::UnexpectedInstr(other.into())),
+        };
+        Ok(value)
+    }
+
+    fn call_with_args(
+        &mut self,
+        frame: &mut Frame<'pool>,
+        index: PoolIndex<Function>,
+        this: Value,
+    ) -> Result<Value, Error> {
+        let pool = self.pool;
+        let fun = pool.function(index)?;
+        let mut args = Vec::with_capacity(fun.parameters.len());
+        let mut out_params = vec![];
+
+        for (i, param_idx) in fun.parameters.iter().enumerate() {
+            let param = pool.parameter(*param_idx)?;
+            let skip = match frame.code.peek() {
+                Some(Instr::Skip(offset)) => {
+                    let target = offset.absolute(frame.code.pos());
+                    frame.code.pop()?;
+                    Some(target)
+                }
+                _ => None,
+            };
+            match (frame.code.peek(), skip) {
+                (Some(Instr::Nop), _) => {
+                    frame.code.pop()?;
+                    args.push(self.default_value(param.type_)?);
+                }
+                (Some(Instr::ParamEnd) | None, _) => args.push(self.default_value(param.type_)?),
+                (_, Some(target)) if self.is_short_circuited(index, &args)? => {
+                    frame.code.seek_abs(target)?;
+                    args.push(self.default_value(param.type_)?);
+                }
+                _ if param.flags.is_out() => {
+                    let place = self.place(frame)?;
+                    args.push(self.read(frame, place.clone())?);
+                    out_params.push((i, place));
+                }
+                _ => args.push(self.eval(frame)?),
+            }
+        }
+        match frame.code.pop()? {
+            Instr::ParamEnd => {}
+            other => return Err(Error::UnexpectedInstr(other.into())),
+        }
+
+        let (res, mut args) = self.invoke(index, this, args)?;
+        for (i, place) in out_params {
+            self.write(frame, place, std::mem::take(&mut args[i]))?;
+        }
+        Ok(res)
+    }
+
+    // the game evaluates short-circuit parameters lazily, this is only relevant to logical operators
+    fn is_short_circuited(&self, index: PoolIndex<Function>, args: &[Value]) -> Result<bool, Error> {
+        let res = match (strip_signature(&self.pool.def_name(index)?), args) {
+            ("OperatorLogicAnd", [lhs]) => !lhs.as_bool()?,
+            ("OperatorLogicOr", [lhs]) => lhs.as_bool()?,
+            _ => false,
+        };
+        Ok(res)
+    }
+
+    fn place(&mut self, frame: &mut Frame<'pool>) -> Result<Place, Error> {
+        self.place_with(frame, None)
+    }
+
+    fn place_with(&mut self, frame: &mut Frame<'pool>, context: Option<Value>) -> Result<Place, Error> {
+        let root = match frame.code.peek() {
+            Some(Instr::Local(idx)) => {
+                frame.code.pop()?;
+                Root::Local(idx)
+            }
+            Some(Instr::Param(idx)) => {
+                frame.code.pop()?;
+                Root::Param(idx)
+            }
+            Some(Instr::ObjectField(idx)) => {
+                frame.code.pop()?;
+                let obj = context.as_ref().unwrap_or(&frame.this).to_obj()?;
+                Root::Field(obj, idx)
+            }
+            Some(Instr::StructField(idx)) => {
+                frame.code.pop()?;
+                let mut place = self.place(frame)?;
+                place.path.push(Step::Field(idx));
+                return Ok(place);
+            }
+            Some(Instr::ArrayElement(_) | Instr::StaticArrayElement(_)) => {
+                frame.code.pop()?;
+                let mut place = self.place(frame)?;
+                let index = self.eval(frame)?.as_i64()?;
+                place.path.push(Step::Element(index));
+                return Ok(place);
+            }
+            Some(Instr::Context(_)) => {
+                frame.code.pop()?;
+                let context = self.eval(frame)?.deref();
+                return self.place_with(frame, Some(context));
+            }
+            Some(Instr::Deref(_)) => {
+                frame.code.pop()?;
+                match self.eval(frame)? {
+                    Value::ScriptRef(inner) => Root::Ref(inner),
+                    other => Root::Temp(other),
+                }
+            }
+            _ => Root::Temp(self.eval_with(frame, context)?),
+        };
+        Ok(Place { root, path: vec![] })
+    }
+
+    fn modify<A, F>(&self, frame: &mut Frame<'pool>, place: Place, f: F) -> Result<A, Error>
+    where
+        F: FnOnce(&mut Value) -> Result<A, Error>,
+    {
+        match place.root {
+            Root::Local(idx) => {
+                let value = frame
+                    .locals
+                    .get_mut(&idx)
+                    .ok_or_else(|| Error::VariableNotFound(idx.to_string()))?;
+                f(resolve_path(value, &place.path)?)
+            }
+            Root::Param(idx) => {
+                let value = frame
+                    .params
+                    .get_mut(&idx)
+                    .ok_or_else(|| Error::VariableNotFound(idx.to_string()))?;
+                f(resolve_path(value, &place.path)?)
+            }
+            Root::Field(obj, idx) => {
+                let mut instance = obj.borrow_mut();
+                f(resolve_path(instance.field_mut(idx)?, &place.path)?)
+            }
+            Root::Ref(inner) => f(resolve_path(&mut inner.borrow_mut(), &place.path)?),
+            Root::Temp(mut value) => f(resolve_path(&mut value, &place.path)?),
+        }
+    }
+
+    fn read(&self, frame: &mut Frame<'pool>, place: Place) -> Result<Value, Error> {
+        self.modify(frame, place, |value| Ok(value.clone()))
+    }
+
+    fn write(&self, frame: &mut Frame<'pool>, place: Place, value: Value) -> Result<(), Error> {
+        self.modify(frame, place, |slot| {
+            *slot = value;
+            Ok(())
+        })
+    }
+
+    fn new_instance(&self, class: PoolIndex<Class>) -> Result<Instance, Error> {
+        let mut instance = Instance::new(class);
+        for field in self.class_fields(class)? {
+            let value = self.default_value(self.pool.field(field)?.type_)?;
+            instance.fields.insert(field, value);
+        }
+        Ok(instance)
+    }
+
+    // returns the fields of a class and its base classes, starting with the base
+    fn class_fields(&self, class: PoolIndex<Class>) -> Result<Vec<PoolIndex<Field>>, Error> {
+        let mut hierarchy = vec![];
+        let mut current = class;
+        while !current.is_undefined() {
+            let class = self.pool.class(current)?;
+            hierarchy.push(class);
+            current = class.base;
+        }
+        Ok(hierarchy
+            .into_iter()
+            .rev()
+            .flat_map(|class| class.fields.iter().copied())
+            .collect())
+    }
+
+    fn resolve_virtual(&self, class: PoolIndex<Class>, name: PoolIndex<CName>) -> Result<PoolIndex<Function>, Error> {
+        let mut current = class;
+        while !current.is_undefined() {
+            let class = self.pool.class(current)?;
+            for fun in &class.functions {
+                if self.pool.definition(*fun)?.name == name {
+                    return Ok(*fun);
+                }
+            }
+            current = class.base;
+        }
+        Err(Error::MethodNotFound(self.pool.names.get(name)?.to_string()))
+    }
+
+    fn is_subclass(&self, class: PoolIndex<Class>, base: PoolIndex<Class>) -> Result<bool, Error> {
+        let mut current = class;
+        while !current.is_undefined() {
+            if current == base {
+                return Ok(true);
+            }
+            current = self.pool.class(current)?.base;
+        }
+        Ok(false)
+    }
+
+    fn is_same_type(&self, lhs: PoolIndex<Type>, rhs: PoolIndex<Type>) -> Result<bool, Error> {
+        Ok(lhs == rhs || self.pool.def_name(lhs)? == self.pool.def_name(rhs)?)
+    }
+
+    fn element_type(&self, type_: PoolIndex<Type>) -> Result<PoolIndex<Type>, Error> {
+        match *self.pool.type_(type_)? {
+            Type::Array(inner) | Type::StaticArray(inner, _) => Ok(inner),
+            _ => Err(PoolError::UnexpectedEntry("array type").into()),
+        }
+    }
+
+    fn enum_of_type(&self, type_: PoolIndex<Type>) -> Result<PoolIndex<Enum>, Error> {
+        match self.types.get(&*self.pool.def_name(type_)?) {
+            Some(index) if matches!(self.pool.definition(*index)?.value, AnyDefinition::Enum(_)) => Ok(index.cast()),
+            _ => Err(PoolError::UnexpectedEntry("enum").into()),
+        }
+    }
+}
+
+struct Frame<'pool> {
+    code: CodeCursor<'pool, Offset>,
+    this: Value,
+    params: HashMap<PoolIndex<Parameter>, Value>,
+    locals: HashMap<PoolIndex<Local>, Value>,
+}
+
+/// A location that can be read and written, like a local, a field or an array element.
+#[derive(Debug, Clone)]
+struct Place {
+    root: Root,
+    path: Vec<Step>,
+}
+
+#[derive(Debug, Clone)]
+enum Root {
+    Local(PoolIndex<Local>),
+    Param(PoolIndex<Parameter>),
+    Field(Obj, PoolIndex<Field>),
+    Ref(Rc<RefCell<Value>>),
+    Temp(Value),
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Field(PoolIndex<Field>),
+    Element(i64),
+}
+
+fn resolve_path<'a>(value: &'a mut Value, path: &[Step]) -> Result<&'a mut Value, Error> {
+    path.iter().try_fold(value, |value, step| match *step {
+        Step::Field(field) => value.as_struct_mut()?.field_mut(field),
+        Step::Element(index) => {
+            let array = value.as_array_mut()?;
+            usize::try_from(index)
+                .ok()
+                .and_then(|i| array.get_mut(i))
+                .ok_or(Error::IndexOutOfBounds(index))
+        }
+    })
+}
+
+fn strip_signature(name: &str) -> &str {
+    name.split_once(';').map_or(name, |(name, _)| name)
+}
@@ -0,0 +1,167 @@
+//! Implementations of the native operators on primitive types.
+use std::cmp::Ordering;
+
+use crate::error::Error;
+use crate::value::Value;
+use crate::Vm;
+
+impl Vm<'_> {
+    /// Registers the arithmetic, comparison, logical and bitwise operators for all primitive types.
+    pub fn register_operators(&mut self) {
+        self.register_native("OperatorAdd", |_, _, args| add(args));
+        self.register_native("OperatorSubtract", |_, _, args| {
+            numeric(args, |l, r| Some(l.wrapping_sub(r)), |l, r| l - r)
+        });
+        self.register_native("OperatorMultiply", |_, _, args| {
+            numeric(args, |l, r| Some(l.wrapping_mul(r)), |l, r| l * r)
+        });
+        self.register_native("OperatorDivide", |_, _, args| {
+            numeric(args, i64::checked_div, |l, r| l / r)
+        });
+        self.register_native("OperatorModulo", |_, _, args| {
+            numeric(args, i64::checked_rem, |l, r| l % r)
+        });
+        self.register_native("OperatorAnd", |_, _, args| bitwise(args, |l, r| l & r));
+        self.register_native("OperatorOr", |_, _, args| bitwise(args, |l, r| l | r));
+        self.register_native("OperatorXor", |_, _, args| bitwise(args, |l, r| l ^ r));
+        self.register_native("OperatorShiftLeft", |_, _, args| {
+            bitwise(args, |l, r| l.wrapping_shl(r as u32))
+        });
+        self.register_native("OperatorShiftRight", |_, _, args| {
+            bitwise(args, |l, r| l.wrapping_shr(r as u32))
+        });
+
+        self.register_native("OperatorEqual", |_, _, args| {
+            let (lhs, rhs) = binary(args)?;
+            Ok(Value::Bool(lhs == rhs))
+        });
+        self.register_native("OperatorNotEqual", |_, _, args| {
+            let (lhs, rhs) = binary(args)?;
+            Ok(Value::Bool(lhs != rhs))
+        });
+        self.register_native("OperatorLess", |_, _, args| compare(args, Ordering::is_lt));
+        self.register_native("OperatorLessEqual", |_, _, args| compare(args, Ordering::is_le));
+        self.register_native("OperatorGreater", |_, _, args| compare(args, Ordering::is_gt));
+        self.register_native("OperatorGreaterEqual", |_, _, args| compare(args, Ordering::is_ge));
+
+        self.register_native("OperatorLogicAnd", |_, _, args| {
+            let (lhs, rhs) = binary(args)?;
+            Ok(Value::Bool(lhs.as_bool()? && rhs.as_bool()?))
+        });
+        self.register_native("OperatorLogicOr", |_, _, args| {
+            let (lhs, rhs) = binary(args)?;
+            Ok(Value::Bool(lhs.as_bool()? || rhs.as_bool()?))
+        });
+        self.register_native("OperatorLogicNot", |_, _, args| {
+            Ok(Value::Bool(!unary(args)?.as_bool()?))
+        });
+        self.register_native("OperatorBitNot", |_, _, args| {
+            let value = unary(args)?;
+            Ok(with_int_type(&value, !value.as_i64()?))
+        });
+        self.register_native("OperatorNeg", |_, _, args| match unary(args)? {
+            Value::F32(f) => Ok(Value::F32(-f)),
+            Value::F64(f) => Ok(Value::F64(-f)),
+            other => Ok(with_int_type(&other, other.as_i64()?.wrapping_neg())),
+        });
+
+        self.register_native("OperatorAssignAdd", |_, _, args| assign(args, add));
+        self.register_native("OperatorAssignSubtract", |_, _, args| {
+            assign(args, |args| numeric(args, |l, r| Some(l.wrapping_sub(r)), |l, r| l - r))
+        });
+        self.register_native("OperatorAssignMultiply", |_, _, args| {
+            assign(args, |args| numeric(args, |l, r| Some(l.wrapping_mul(r)), |l, r| l * r))
+        });
+        self.register_native("OperatorAssignDivide", |_, _, args| {
+            assign(args, |args| numeric(args, i64::checked_div, |l, r| l / r))
+        });
+        self.register_native("OperatorAssignModulo", |_, _, args| {
+            assign(args, |args| numeric(args, i64::checked_rem, |l, r| l % r))
+        });
+        self.register_native("OperatorAssignAnd", |_, _, args| {
+            assign(args, |args| bitwise(args, |l, r| l & r))
+        });
+        self.register_native("OperatorAssignOr", |_, _, args| {
+            assign(args, |args| bitwise(args, |l, r| l | r))
+        });
+        self.register_native("OperatorAssignXor", |_, _, args| {
+            assign(args, |args| bitwise(args, |l, r| l ^ r))
+        });
+    }
+}
+
+fn unary(args: &[Value]) -> Result<Value, Error> {
+    match args {
+        [value, ..] => Ok(value.clone().deref()),
+        _ => Err(Error::Native("expected an operand".to_owned())),
+    }
+}
+
+fn binary(args: &[Value]) -> Result<(Value, Value), Error> {
+    match args {
+        [lhs, rhs, ..] => Ok((lhs.clone().deref(), rhs.clone().deref())),
+        _ => Err(Error::Native("expected two operands".to_owned())),
+    }
+}
+
+fn add(args: &[Value]) -> Result<Value, Error> {
+    match binary(args)? {
+        (Value::String(lhs), Value::String(rhs)) => Ok(Value::String(format!("{lhs}{rhs}").into())),
+        _ => numeric(args, |l, r| Some(l.wrapping_add(r)), |l, r| l + r),
+    }
+}
+
+fn numeric<I, F>(args: &[Value], int: I, float: F) -> Result<Value, Error>
+where
+    I: Fn(i64, i64) -> Option<i64>,
+    F: Fn(f64, f64) -> f64,
+{
+    let (lhs, rhs) = binary(args)?;
+    match (&lhs, &rhs) {
+        (Value::F64(_), _) | (_, Value::F64(_)) => Ok(Value::F64(float(lhs.as_f64()?, rhs.as_f64()?))),
+        (Value::F32(_), _) | (_, Value::F32(_)) => Ok(Value::F32(float(lhs.as_f64()?, rhs.as_f64()?) as f32)),
+        _ => {
+            let res = int(lhs.as_i64()?, rhs.as_i64()?).ok_or_else(|| Error::Native("division by zero".to_owned()))?;
+            Ok(with_int_type(&lhs, res))
+        }
+    }
+}
+
+fn bitwise(args: &[Value], op: impl Fn(i64, i64) -> i64) -> Result<Value, Error> {
+    match binary(args)? {
+        (Value::Bool(lhs), Value::Bool(rhs)) => Ok(Value::Bool(op(lhs.into(), rhs.into()) != 0)),
+        (lhs, rhs) => Ok(with_int_type(&lhs, op(lhs.as_i64()?, rhs.as_i64()?))),
+    }
+}
+
+fn compare(args: &[Value], pred: fn(Ordering) -> bool) -> Result<Value, Error> {
+    let (lhs, rhs) = binary(args)?;
+    let ordering = lhs
+        .compare(&rhs)
+        .ok_or(Error::TypeMismatch("comparable value", lhs.kind()))?;
+    Ok(Value::Bool(pred(ordering)))
+}
+
+fn assign<F>(args: &mut [Value], op: F) -> Result<Value, Error>
+where
+    F: Fn(&[Value]) -> Result<Value, Error>,
+{
+    let res = op(args)?;
+    args[0] = res.clone();
+    Ok(res)
+}
+
+// converts the result of an integer operation back to the type of the operand
+fn with_int_type(operand: &Value, value: i64) -> Value {
+    match operand {
+        Value::I8(_) => Value::I8(value as i8),
+        Value::I16(_) => Value::I16(value as i16),
+        Value::I32(_) => Value::I32(value as i32),
+        Value::U8(_) => Value::U8(value as u8),
+        Value::U16(_) => Value::U16(value as u16),
+        Value::U32(_) => Value::U32(value as u32),
+        Value::U64(_) => Value::U64(value as u64),
+        Value::Enum(enum_, _) => Value::Enum(*enum_, value),
+        _ => Value::I64(value),
+    }
+}
@@ -0,0 +1,102 @@
+//! Running `@test` functions with assertion natives.
+use std::fmt;
+
+use redscript::asm::qualified_name;
+use redscript::bundle::PoolIndex;
+use redscript::definition::Function;
+
+use crate::error::Error;
+use crate::value::Value;
+use crate::{strip_signature, Vm};
+
+/// Declarations of the assertion natives, they have to be compiled along with the tests.
+pub const PRELUDE: &str = "
+native func AssertTrue(condition: Bool)
+native func AssertEqual(actual: Bool, expected: Bool)
+native func AssertEqual(actual: Int32, expected: Int32)
+native func AssertEqual(actual: Int64, expected: Int64)
+native func AssertEqual(actual: Uint32, expected: Uint32)
+native func AssertEqual(actual: Uint64, expected: Uint64)
+native func AssertEqual(actual: Float, expected: Float)
+native func AssertEqual(actual: Double, expected: Double)
+native func AssertEqual(actual: String, expected: String)
+native func AssertEqual(actual: CName, expected: CName)
+";
+
+impl Vm<'_> {
+    /// Registers the implementations of the natives declared in [`PRELUDE`].
+    pub fn register_assertions(&mut self) {
+        self.register_native("AssertTrue", |_, _, args| match args {
+            [condition, ..] if condition.as_bool()? => Ok(Value::Void),
+            _ => Err(Error::AssertionFailed("expected the condition to be true".to_owned())),
+        });
+        self.register_native("AssertEqual", |vm, _, args| match args {
+            [actual, expected, ..] if actual == expected => Ok(Value::Void),
+            [actual, expected, ..] => Err(Error::AssertionFailed(format!(
+                "expected {}, got {}",
+                vm.stringify(expected)?,
+                vm.stringify(actual)?
+            ))),
+            _ => Err(Error::Native("expected two operands".to_owned())),
+        });
+    }
+
+    /// Runs each of the test functions, a test passes when it returns without an error.
+    pub fn run_tests(&mut self, tests: &[PoolIndex<Function>]) -> Result<TestReport, Error> {
+        let mut results = Vec::with_capacity(tests.len());
+        for &test in tests {
+            let name = qualified_name(test, self.pool)?;
+            let outcome = self.call(test, Value::Void, vec![]).map(|_| ());
+            results.push(TestResult {
+                name: strip_signature(&name).to_owned(),
+                outcome,
+            });
+        }
+        Ok(TestReport { results })
+    }
+}
+
+#[derive(Debug)]
+pub struct TestResult {
+    pub name: String,
+    pub outcome: Result<(), Error>,
+}
+
+impl TestResult {
+    #[inline]
+    pub fn is_success(&self) -> bool {
+        self.outcome.is_ok()
+    }
+}
+
+#[derive(Debug)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|res| res.is_success()).count()
+    }
+
+    pub fn failed(&self) -> usize {
+        self.results.len() - self.passed()
+    }
+
+    #[inline]
+    pub fn is_success(&self) -> bool {
+        self.failed() == 0
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for result in &self.results {
+            match &result.outcome {
+                Ok(()) => writeln!(f, "test {} ... ok", result.name)?,
+                Err(err) => writeln!(f, "test {} ... FAILED: {err}", result.name)?,
+            }
+        }
+        write!(f, "{} passed, {} failed", self.passed(), self.failed())
+    }
+}
@@ -0,0 +1,289 @@
+use std::cell::{self, RefCell};
+use std::cmp::Ordering;
+use std::fmt;
+use std::rc::{Rc, Weak};
+
+use hashbrown::HashMap;
+use redscript::bundle::PoolIndex;
+use redscript::definition::{Class, Enum, Field, Type};
+use redscript::Ref;
+use strum::IntoStaticStr;
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Default, IntoStaticStr)]
+pub enum Value {
+    #[default]
+    Void,
+    Bool(bool),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    U8(u8),
+    U16(u16),
+    U32(u32),
+    U64(u64),
+    F32(f32),
+    F64(f64),
+    String(Ref<str>),
+    Name(Ref<str>),
+    TweakDbId(Ref<str>),
+    Resource(Ref<str>),
+    Enum(PoolIndex<Enum>, i64),
+    Ref(Option<Obj>),
+    WeakRef(WeakObj),
+    Struct(Box<Instance>),
+    Array(Vec<Value>),
+    Variant(Option<Box<(PoolIndex<Type>, Value)>>),
+    /// A `script_ref<T>`, it holds a copy of the referenced value.
+    ScriptRef(Rc<RefCell<Value>>),
+}
+
+impl Value {
+    pub const NULL: Self = Value::Ref(None);
+
+    #[inline]
+    pub fn kind(&self) -> &'static str {
+        self.into()
+    }
+
+    pub fn as_bool(&self) -> Result<bool, Error> {
+        match self {
+            Value::Bool(b) => Ok(*b),
+            Value::ScriptRef(inner) => inner.borrow().as_bool(),
+            other => Err(Error::TypeMismatch("Bool", other.kind())),
+        }
+    }
+
+    /// Returns the value of any integer or enum as an `i64`.
+    pub fn as_i64(&self) -> Result<i64, Error> {
+        match *self {
+            Value::I8(i) => Ok(i.into()),
+            Value::I16(i) => Ok(i.into()),
+            Value::I32(i) => Ok(i.into()),
+            Value::I64(i) | Value::Enum(_, i) => Ok(i),
+            Value::U8(i) => Ok(i.into()),
+            Value::U16(i) => Ok(i.into()),
+            Value::U32(i) => Ok(i.into()),
+            Value::U64(i) => Ok(i as i64),
+            Value::ScriptRef(ref inner) => inner.borrow().as_i64(),
+            ref other => Err(Error::TypeMismatch("integer", other.kind())),
+        }
+    }
+
+    /// Returns the value of any number as an `f64`.
+    pub fn as_f64(&self) -> Result<f64, Error> {
+        match *self {
+            Value::F32(f) => Ok(f.into()),
+            Value::F64(f) => Ok(f),
+            Value::ScriptRef(ref inner) => inner.borrow().as_f64(),
+            ref other => other.as_i64().map(|i| i as f64),
+        }
+    }
+
+    /// Returns the contents of a string, a name, a TweakDBID or a resource.
+    pub fn as_str(&self) -> Result<&str, Error> {
+        match self {
+            Value::String(str) | Value::Name(str) | Value::TweakDbId(str) | Value::Resource(str) => Ok(str),
+            other => Err(Error::TypeMismatch("String", other.kind())),
+        }
+    }
+
+    /// Returns the object behind a strong or a weak reference.
+    pub fn to_obj(&self) -> Result<Obj, Error> {
+        match self {
+            Value::Ref(Some(obj)) => Ok(obj.clone()),
+            Value::WeakRef(weak) => weak.upgrade().ok_or(Error::NullReference),
+            Value::Ref(None) => Err(Error::NullReference),
+            Value::ScriptRef(inner) => inner.borrow().to_obj(),
+            other => Err(Error::TypeMismatch("ref", other.kind())),
+        }
+    }
+
+    pub fn as_struct_mut(&mut self) -> Result<&mut Instance, Error> {
+        match self {
+            Value::Struct(instance) => Ok(instance),
+            other => Err(Error::TypeMismatch("struct", other.kind())),
+        }
+    }
+
+    pub fn as_array(&self) -> Result<&[Value], Error> {
+        match self {
+            Value::Array(array) => Ok(array),
+            other => Err(Error::TypeMismatch("array", other.kind())),
+        }
+    }
+
+    pub fn as_array_mut(&mut self) -> Result<&mut Vec<Value>, Error> {
+        match self {
+            Value::Array(array) => Ok(array),
+            other => Err(Error::TypeMismatch("array", other.kind())),
+        }
+    }
+
+    /// Removes a `script_ref` indirection if there is one.
+    pub fn deref(self) -> Value {
+        match self {
+            Value::ScriptRef(inner) => inner.borrow().clone(),
+            other => other,
+        }
+    }
+
+    /// Compares numbers and strings, other values are not ordered.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::F32(_) | Value::F64(_), _) | (_, Value::F32(_) | Value::F64(_)) => {
+                self.as_f64().ok()?.partial_cmp(&other.as_f64().ok()?)
+            }
+            (Value::String(lhs), Value::String(rhs)) | (Value::Name(lhs), Value::Name(rhs)) => Some(lhs.cmp(rhs)),
+            _ => Some(self.as_i64().ok()?.cmp(&other.as_i64().ok()?)),
+        }
+    }
+}
+
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Void, Value::Void) => true,
+            (Value::Bool(lhs), Value::Bool(rhs)) => lhs == rhs,
+            (Value::F32(_) | Value::F64(_), _) | (_, Value::F32(_) | Value::F64(_)) => {
+                matches!((self.as_f64(), other.as_f64()), (Ok(lhs), Ok(rhs)) if lhs == rhs)
+            }
+            (Value::String(lhs), Value::String(rhs))
+            | (Value::Name(lhs), Value::Name(rhs))
+            | (Value::TweakDbId(lhs), Value::TweakDbId(rhs))
+            | (Value::Resource(lhs), Value::Resource(rhs)) => lhs == rhs,
+            (Value::Enum(lhs, lhs_val), Value::Enum(rhs, rhs_val)) => lhs == rhs && lhs_val == rhs_val,
+            (Value::Ref(lhs), Value::Ref(rhs)) => lhs == rhs,
+            (Value::WeakRef(lhs), Value::WeakRef(rhs)) => lhs.upgrade() == rhs.upgrade(),
+            (Value::Ref(lhs), Value::WeakRef(rhs)) | (Value::WeakRef(rhs), Value::Ref(lhs)) => *lhs == rhs.upgrade(),
+            (Value::Struct(lhs), Value::Struct(rhs)) => lhs.class == rhs.class && lhs.fields == rhs.fields,
+            (Value::Array(lhs), Value::Array(rhs)) => lhs == rhs,
+            (Value::Variant(lhs), Value::Variant(rhs)) => lhs == rhs,
+            (Value::ScriptRef(lhs), rhs) => *lhs.borrow() == *rhs,
+            (lhs, Value::ScriptRef(rhs)) => *lhs == *rhs.borrow(),
+            _ => matches!((self.as_i64(), other.as_i64()), (Ok(lhs), Ok(rhs)) if lhs == rhs),
+        }
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(value: i32) -> Self {
+        Value::I32(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::I64(value)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Value::F32(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.into())
+    }
+}
+
+impl From<Obj> for Value {
+    fn from(value: Obj) -> Self {
+        Value::Ref(Some(value))
+    }
+}
+
+/// An instance of a class or a struct.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Instance {
+    pub class: PoolIndex<Class>,
+    pub fields: HashMap<PoolIndex<Field>, Value>,
+}
+
+impl Instance {
+    pub fn new(class: PoolIndex<Class>) -> Self {
+        Self {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    pub fn field(&self, field: PoolIndex<Field>) -> Result<&Value, Error> {
+        self.fields
+            .get(&field)
+            .ok_or_else(|| Error::FieldNotFound(field.to_string()))
+    }
+
+    pub fn field_mut(&mut self, field: PoolIndex<Field>) -> Result<&mut Value, Error> {
+        self.fields
+            .get_mut(&field)
+            .ok_or_else(|| Error::FieldNotFound(field.to_string()))
+    }
+}
+
+/// A shared handle to an object on the heap.
+#[derive(Clone)]
+pub struct Obj(Rc<RefCell<Instance>>);
+
+impl Obj {
+    pub fn new(instance: Instance) -> Self {
+        Self(Rc::new(RefCell::new(instance)))
+    }
+
+    pub fn class(&self) -> PoolIndex<Class> {
+        self.0.borrow().class
+    }
+
+    pub fn borrow(&self) -> cell::Ref<'_, Instance> {
+        self.0.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> cell::RefMut<'_, Instance> {
+        self.0.borrow_mut()
+    }
+
+    pub fn downgrade(&self) -> WeakObj {
+        WeakObj(Rc::downgrade(&self.0))
+    }
+}
+
+impl PartialEq for Obj {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl fmt::Debug for Obj {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Obj({:p}, class {})", Rc::as_ptr(&self.0), self.class())
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct WeakObj(Weak<RefCell<Instance>>);
+
+impl WeakObj {
+    pub fn upgrade(&self) -> Option<Obj> {
+        self.0.upgrade().map(Obj)
+    }
+}
+
+impl fmt::Debug for WeakObj {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.upgrade() {
+            Some(obj) => write!(f, "Weak({obj:?})"),
+            None => write!(f, "Weak(null)"),
+        }
+    }
+}
@@ -0,0 +1,336 @@
+use std::cell::RefCell;
+use std::io::Cursor;
+use std::rc::Rc;
+
+use redscript::bundle::{ConstantPool, ScriptBundle};
+use redscript_compiler::diagnostics::Diagnostic;
+use redscript_compiler::parser;
+use redscript_compiler::source_map::Files;
+use redscript_compiler::unit::CompilationUnit;
+use redscript_vm::error::Error;
+use redscript_vm::value::Value;
+use redscript_vm::{testing, Vm};
+
+const PREDEF: &[u8] = include_bytes!("../../resources/predef.redscripts");
+
+const OPERATORS: &str = "
+    native func OperatorAdd(l: Int32, r: Int32) -> Int32
+    native func OperatorAdd(l: String, r: String) -> String
+    native func OperatorSubtract(l: Int32, r: Int32) -> Int32
+    native func OperatorMultiply(l: Int32, r: Int32) -> Int32
+    native func OperatorAssignAdd(out l: Int32, r: Int32) -> Int32
+    native func OperatorLess(l: Int32, r: Int32) -> Bool
+    native func OperatorLessEqual(l: Int32, r: Int32) -> Bool
+    native func OperatorEqual(l: Int32, r: Int32) -> Bool
+    native func OperatorEqual(l: CName, r: CName) -> Bool
+    native func OperatorNeg(a: Int32) -> Int32
+    native func OperatorLogicAnd(l: Bool, r: Bool) -> Bool
+    ";
+
+fn compiled(sources: &str) -> ConstantPool {
+    let modules = vec![
+        parser::parse_str(sources).unwrap(),
+        parser::parse_str(OPERATORS).unwrap(),
+    ];
+    let mut scripts = ScriptBundle::load(&mut Cursor::new(PREDEF)).unwrap();
+    let res = CompilationUnit::new_with_defaults(&mut scripts.pool)
+        .unwrap()
+        .compile(modules, &Files::default())
+        .unwrap();
+    let diagnostics = res.into_diagnostics();
+    assert!(
+        !diagnostics.iter().any(Diagnostic::is_fatal),
+        "Fatal errors: {:?}",
+        diagnostics
+    );
+    scripts.pool
+}
+
+fn run(pool: &ConstantPool, name: &str, args: Vec<Value>) -> Result<Value, Error> {
+    let mut vm = Vm::new(pool);
+    vm.register_operators();
+    let fun = vm.function(name)?;
+    vm.call(fun, Value::Void, args)
+}
+
+#[test]
+fn run_loops_and_conditionals() {
+    let sources = "
+        func Sum(n: Int32) -> Int32 {
+            let total = 0;
+            let i = 1;
+            while i <= n {
+                total += i;
+                i += 1;
+            }
+            return total;
+        }
+
+        func Max(a: Int32, b: Int32) -> Int32 {
+            return a < b ? b : a;
+        }
+
+        func Sign(a: Int32) -> Int32 {
+            if a < 0 {
+                return -1;
+            } else if a == 0 {
+                return 0;
+            }
+            return 1;
+        }
+        ";
+    let pool = compiled(sources);
+
+    assert_eq!(run(&pool, "Sum", vec![Value::I32(10)]).unwrap(), Value::I32(55));
+    assert_eq!(
+        run(&pool, "Max", vec![Value::I32(3), Value::I32(7)]).unwrap(),
+        Value::I32(7)
+    );
+    assert_eq!(
+        run(&pool, "Max", vec![Value::I32(9), Value::I32(7)]).unwrap(),
+        Value::I32(9)
+    );
+    assert_eq!(run(&pool, "Sign", vec![Value::I32(-4)]).unwrap(), Value::I32(-1));
+    assert_eq!(run(&pool, "Sign", vec![Value::I32(0)]).unwrap(), Value::I32(0));
+    assert_eq!(run(&pool, "Sign", vec![Value::I32(4)]).unwrap(), Value::I32(1));
+}
+
+#[test]
+fn run_virtual_methods_and_fields() {
+    let sources = "
+        class Animal {
+            let legs: Int32;
+
+            func Sound() -> String {
+                return \"...\";
+            }
+
+            func Describe() -> String {
+                return this.Sound() + \" \" + ToString(this.legs);
+            }
+        }
+
+        class Dog extends Animal {
+            func Sound() -> String {
+                return \"woof\";
+            }
+        }
+
+        func Test() -> String {
+            let dog = new Dog();
+            dog.legs = 4;
+            let animal: ref<Animal> = dog;
+            return animal.Describe();
+        }
+
+        func Cast() -> Bool {
+            let animal: ref<Animal> = new Animal();
+            return IsDefined(animal as Dog);
+        }
+        ";
+    let pool = compiled(sources);
+
+    assert_eq!(run(&pool, "Test", vec![]).unwrap(), Value::from("woof 4"));
+    assert_eq!(run(&pool, "Cast", vec![]).unwrap(), Value::Bool(false));
+}
+
+#[test]
+fn run_structs_arrays_and_switch() {
+    let sources = "
+        struct Point {
+            let x: Int32;
+            let y: Int32;
+        }
+
+        enum Direction {
+            North = 0,
+            East = 1,
+            South = 2,
+        }
+
+        func Test() -> Int32 {
+            let points: array<Point>;
+            ArrayPush(points, new Point(1, 2));
+            ArrayPush(points, new Point(3, 4));
+            points[1].x = 10;
+
+            let total = 0;
+            let i = 0;
+            while i < ArraySize(points) {
+                total += points[i].x * points[i].y;
+                i += 1;
+            }
+            return total;
+        }
+
+        func Turn(dir: Direction) -> Direction {
+            switch dir {
+                case Direction.North:
+                    return Direction.East;
+                case Direction.East:
+                case Direction.South:
+                    return Direction.North;
+                default:
+                    return dir;
+            }
+        }
+
+        func Name(value: Int32) -> String {
+            return ToString(Turn(IntEnum<Direction>(value)));
+        }
+        ";
+    let pool = compiled(sources);
+
+    assert_eq!(run(&pool, "Test", vec![]).unwrap(), Value::I32(42));
+    assert_eq!(run(&pool, "Name", vec![Value::I32(0)]).unwrap(), Value::from("East"));
+    assert_eq!(run(&pool, "Name", vec![Value::I32(2)]).unwrap(), Value::from("North"));
+}
+
+#[test]
+fn run_refs_and_variants() {
+    let sources = "
+        class Item {
+            let count: Int32;
+        }
+
+        func Weak() -> Bool {
+            let item = new Item();
+            let weak: wref<Item> = item;
+            return IsDefined(weak);
+        }
+
+        func Variant() -> Int32 {
+            let v: Variant = 5;
+            if VariantTypeName(v) == n\"Int32\" {
+                return FromVariant<Int32>(v);
+            }
+            return -1;
+        }
+
+        func Increment(out count: Int32) {
+            count = count + 1;
+        }
+
+        func Out() -> Int32 {
+            let count = 1;
+            Increment(count);
+            return count;
+        }
+        ";
+    let pool = compiled(sources);
+
+    assert_eq!(run(&pool, "Weak", vec![]).unwrap(), Value::Bool(true));
+    assert_eq!(run(&pool, "Variant", vec![]).unwrap(), Value::I32(5));
+    assert_eq!(run(&pool, "Out", vec![]).unwrap(), Value::I32(2));
+}
+
+#[test]
+fn run_with_mocked_natives() {
+    let sources = "
+        native struct GameInstance {
+            native static func GetPlayerLevel(game: GameInstance) -> Int32
+        }
+
+        native func Log(str: String)
+
+        func Announce(game: GameInstance) {
+            Log(\"level \" + ToString(GameInstance.GetPlayerLevel(game)));
+        }
+        ";
+    let pool = compiled(sources);
+    let logs = Rc::new(RefCell::new(vec![]));
+
+    let mut vm = Vm::new(&pool);
+    vm.register_operators();
+    let announce = vm.function("Announce").unwrap();
+    assert!(matches!(
+        vm.call(announce, Value::Void, vec![Value::NULL]),
+        Err(Error::MissingNative(name)) if name == "GameInstance::GetPlayerLevel"
+    ));
+
+    vm.register_native("GameInstance::GetPlayerLevel", |_, _, _| Ok(Value::I32(50)));
+    let sink = logs.clone();
+    vm.register_native("Log", move |_, _, args| {
+        sink.borrow_mut().push(args[0].as_str()?.to_owned());
+        Ok(Value::Void)
+    });
+    vm.call(announce, Value::Void, vec![Value::NULL]).unwrap();
+
+    assert_eq!(*logs.borrow(), vec!["level 50".to_owned()]);
+}
+
+#[test]
+fn run_test_functions() {
+    let sources = "
+        func Double(a: Int32) -> Int32 {
+            return a * 2;
+        }
+
+        @test
+        func TestDouble() {
+            AssertEqual(Double(2), 4);
+            AssertTrue(Double(0) == 0);
+        }
+
+        @test
+        func TestDoubleFails() {
+            AssertEqual(Double(3), 7);
+        }
+        ";
+    let modules = vec![
+        parser::parse_str(sources).unwrap(),
+        parser::parse_str(OPERATORS).unwrap(),
+        parser::parse_str(testing::PRELUDE).unwrap(),
+    ];
+    let mut scripts = ScriptBundle::load(&mut Cursor::new(PREDEF)).unwrap();
+    let res = CompilationUnit::new_with_defaults(&mut scripts.pool)
+        .unwrap()
+        .with_test_code(true)
+        .compile(modules, &Files::default())
+        .unwrap();
+
+    let mut vm = Vm::new(&scripts.pool);
+    vm.register_operators();
+    vm.register_assertions();
+    let report = vm.run_tests(res.test_functions()).unwrap();
+
+    assert_eq!((report.passed(), report.failed()), (1, 1));
+    assert_eq!(
+        report.to_string(),
+        "test TestDouble ... ok\n\
+         test TestDoubleFails ... FAILED: assertion failed: expected 7, got 6\n\
+         1 passed, 1 failed"
+    );
+}
+
+#[test]
+fn run_with_breakpoints() {
+    let sources = "func Abs(a: Int32) -> Int32 {
+    if a < 0 {
+        return -a;
+    }
+    return a;
+}
+";
+    let mut files = Files::new();
+    files.add("abs.reds".into(), sources.to_owned());
+    files.add("operators.reds".into(), OPERATORS.to_owned());
+    let mut scripts = ScriptBundle::load(&mut Cursor::new(PREDEF)).unwrap();
+    CompilationUnit::new_with_defaults(&mut scripts.pool)
+        .unwrap()
+        .with_breakpoints(true)
+        .compile_files(&files)
+        .unwrap();
+
+    let hits = Rc::new(RefCell::new(vec![]));
+    let mut vm = Vm::new(&scripts.pool);
+    vm.register_operators();
+    vm.on_breakpoint({
+        let hits = hits.clone();
+        move |bp| hits.borrow_mut().push((bp.line, bp.col))
+    });
+    let fun = vm.function("Abs").unwrap();
+    assert_eq!(vm.call(fun, Value::Void, vec![Value::I32(-1)]).unwrap(), Value::I32(1));
+
+    assert_eq!(*hits.borrow(), vec![(2, 5), (3, 9)]);
+}